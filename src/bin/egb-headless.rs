@@ -0,0 +1,9 @@
+//! Runs a ROM without a window, see `egb::headless` for the options. Nothing here needs SDL, so
+//! CI can build it with `cargo build --bin egb-headless --no-default-features`.
+
+use std::{env, process, string::String, vec::Vec};
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    process::exit(egb::headless::run(&args[1..]));
+}
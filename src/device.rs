@@ -1,20 +1,26 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, vec::Vec};
 use embedded_graphics::draw_target::DrawTarget;
 
 use crate::{
     buffer::Buffer,
+    games::Game,
     gui::screen::Screen,
     input::{self, InputStatus},
+    prefs::Prefs,
 };
 
 pub trait Device<D: DrawTarget, B: DrawTarget> {
     fn init(screen: Box<dyn Screen<B>>) -> Self;
+    /// The games and settings the library is shown with again after quitting a game
+    fn set_library(&mut self, games: Vec<Game>, prefs: Prefs);
     fn display(&mut self) -> &mut D;
     fn set_backlight(&mut self, brightness: u16);
     fn set_led_l(&mut self, brightness: u16);
     fn set_led_r(&mut self, brightness: u16);
     fn delay_ms(&mut self, ms: u32);
     fn delay_us(&mut self, us: u32);
+    /// Microseconds since the device started, for pacing
+    fn now_us(&mut self) -> u64;
     fn update_input(&mut self, input: &mut InputStatus) -> InputStatus;
     // TODO: could this be merged into update_input?
     fn update(&mut self, input: &InputStatus);
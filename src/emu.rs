@@ -1,5 +1,30 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
 use embedded_graphics::{draw_target::DrawTarget, pixelcolor::Rgb565};
 
+use crate::{
+    games::{Game, GameConsole, RomHandle},
+    gb::boot::BootMode,
+    input::InputStatus,
+    library::Storage,
+    movie,
+    nes::{
+        cartridge::{self, Rom},
+        emu::NesEmulator,
+    },
+    pacer::Region,
+    sprig::{game::SprigGame, runtime::SprigRuntime},
+};
+#[cfg(target_arch = "x86_64")]
+use crate::{
+    gb::{emu::GbEmulator, link},
+    gba::emu::GbaEmulator,
+};
+
 pub trait Emulator<D>
 where
     D: DrawTarget<Color = Rgb565>,
@@ -7,3 +32,182 @@ where
     fn new(display: &mut D) -> Self;
     fn tick(&mut self, display: &mut D) -> Result<(), D::Error>;
 }
+
+/// A game running on the emulator for its console
+pub enum Machine {
+    Nes(Box<NesEmulator>),
+    #[cfg(target_arch = "x86_64")]
+    Gb(Box<GbEmulator>),
+    #[cfg(target_arch = "x86_64")]
+    Gba(GbaEmulator),
+    Sprig(SprigRuntime),
+}
+
+impl Machine {
+    /// Starts a ROM on the emulator for `console`, `boot` is only used by Game Boy games
+    #[cfg_attr(not(target_arch = "x86_64"), allow(unused_variables))]
+    pub fn from_rom(
+        console: GameConsole,
+        data: Vec<u8>,
+        boot: BootMode,
+    ) -> Result<Machine, String> {
+        match console {
+            GameConsole::NES => {
+                let nes =
+                    NesEmulator::with_rom(Rom::new(&data)?).with_region(cartridge::region(&data));
+                Ok(Machine::Nes(Box::new(nes)))
+            }
+            #[cfg(target_arch = "x86_64")]
+            GameConsole::GameBoy | GameConsole::GameBoyColor => {
+                let mut gb = GbEmulator::with_rom(crate::gb::cartridge::Rom::new(&data)?, boot)?;
+                if let Some(link) = link::from_env().map_err(|e| format!("EGB_LINK: {}", e))? {
+                    gb.bus.serial.set_link(link);
+                }
+                Ok(Machine::Gb(Box::new(gb)))
+            }
+            #[cfg(target_arch = "x86_64")]
+            GameConsole::GameBoyAdvanced => Ok(Machine::Gba(GbaEmulator::with_rom(data))),
+            GameConsole::Sprig => {
+                let src = core::str::from_utf8(&data).map_err(|e| e.to_string())?;
+                Ok(Machine::Sprig(SprigRuntime::with_game(SprigGame::parse(
+                    src,
+                )?)))
+            }
+            #[cfg(not(target_arch = "x86_64"))]
+            _ => Err(format!("{} games can't run yet", console.name())),
+        }
+    }
+
+    /// Starts a game from the library, reading its ROM from `storage` unless it's built in
+    pub fn launch(
+        game: &Game,
+        storage: Option<&mut dyn Storage>,
+        boot: BootMode,
+    ) -> Result<Machine, String> {
+        let data = match &game.rom {
+            Some(RomHandle::Builtin(data)) => data.to_vec(),
+            Some(RomHandle::File(path)) => storage
+                .ok_or_else(|| String::from("No storage to load games from"))?
+                .read(path)?,
+            None => return Err(String::from("There's only cover art for this game, no ROM")),
+        };
+        Machine::from_rom(game.console.clone(), data, boot)
+    }
+
+    /// The frame rate the game runs at
+    pub fn region(&self) -> Region {
+        match self {
+            Machine::Nes(nes) => nes.region(),
+            // the rest run at close to 60Hz wherever they were sold
+            _ => Region::Ntsc,
+        }
+    }
+
+    /// The console's reset button
+    pub fn reset(&mut self) {
+        match self {
+            Machine::Nes(nes) => nes.reset(),
+            #[cfg(target_arch = "x86_64")]
+            Machine::Gb(gb) => gb.reset(),
+            #[cfg(target_arch = "x86_64")]
+            Machine::Gba(gba) => gba.reset(),
+            Machine::Sprig(sprig) => sprig.reset(),
+        }
+    }
+
+    /// Draws the last frame again without running the game
+    pub fn draw<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        match self {
+            Machine::Nes(nes) => nes.draw(display),
+            #[cfg(target_arch = "x86_64")]
+            Machine::Gb(gb) => gb.draw(display),
+            #[cfg(target_arch = "x86_64")]
+            Machine::Gba(gba) => gba.draw(display),
+            Machine::Sprig(sprig) => sprig.draw(display),
+        }
+    }
+
+    /// Runs a frame with the buttons held in `input`, `present` shows it
+    pub fn frame(&mut self, input: &InputStatus) {
+        match self {
+            Machine::Nes(nes) => {
+                nes.set_buttons(movie::buttons(input));
+                nes.run_frame();
+            }
+            #[cfg(target_arch = "x86_64")]
+            Machine::Gb(gb) => {
+                gb.set_input(input);
+                gb.run_frame();
+            }
+            #[cfg(target_arch = "x86_64")]
+            Machine::Gba(gba) => {
+                gba.set_input(input);
+                gba.run_frame();
+            }
+            Machine::Sprig(sprig) => sprig.set_input(input),
+        }
+    }
+
+    /// Draws the frame that was just run, Sprig games only when something changed
+    pub fn present<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        match self {
+            Machine::Sprig(sprig) => sprig.tick(display),
+            machine => machine.draw(display),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::library;
+
+    #[test]
+    fn test_launch() {
+        let games = library::builtin();
+        let crate_push = games
+            .iter()
+            .find(|game| game.title == "Crate Push")
+            .unwrap();
+        assert!(matches!(
+            Machine::launch(crate_push, None, BootMode::Skip),
+            Ok(Machine::Sprig(_))
+        ));
+
+        assert!(matches!(
+            Machine::launch(&games[0], None, BootMode::Skip),
+            Ok(Machine::Nes(_))
+        ));
+        // the other demo entries are only art
+        assert!(Machine::launch(&games[1], None, BootMode::Skip).is_err());
+
+        let mut tetris = Game::new_gameboy("Tetris", &[]);
+        tetris.rom = Some(RomHandle::File("roms/tetris.gb".to_string()));
+        assert_eq!(
+            Machine::launch(&tetris, None, BootMode::Skip)
+                .err()
+                .unwrap(),
+            "No storage to load games from"
+        );
+        tetris.rom = Some(RomHandle::Builtin(&[0; 0x150]));
+        assert!(matches!(
+            Machine::launch(&tetris, None, BootMode::Skip),
+            Ok(Machine::Gb(_))
+        ));
+
+        let mut data = [0; 0x150];
+        data[0x147] = 0xFC;
+        assert_eq!(
+            Machine::from_rom(GameConsole::GameBoy, data.to_vec(), BootMode::Skip)
+                .err()
+                .unwrap(),
+            "Unsupported cartridge type 0xfc"
+        );
+    }
+}
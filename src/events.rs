@@ -1,8 +1,25 @@
-use crate::games::GameConsole;
+use alloc::{boxed::Box, vec::Vec};
+
+use crate::{
+    emu::Machine, games::Game, gui::screens::pause::PauseScreen, nes::emu::NesEmulator,
+    prefs::Prefs,
+};
 
 pub enum Event {
     BacklightBrightness(u16),
     LedL(u16),
     LedR(u16),
-    LaunchGame(GameConsole),
+    LaunchGame(Game),
+    /// Hands a paused game back to the device, from screens that borrowed it
+    ResumeNes(Box<NesEmulator>),
+    /// Hands the game back from the pause menu
+    ResumeGame(Box<Machine>),
+    /// A save state from the pause menu, for the device to keep until the next game is launched
+    SaveState(Vec<u8>),
+    /// Settings from the pause menu, going back to it afterwards
+    OpenSettings(Box<PauseScreen>),
+    /// The game was closed from the pause menu
+    QuitToLibrary,
+    /// The settings store changed and should be written out
+    SavePrefs(Prefs),
 }
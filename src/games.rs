@@ -1,9 +1,18 @@
+use alloc::{
+    rc::Rc,
+    string::{String, ToString},
+};
 use embedded_graphics::{
     geometry::{Point, Size},
     pixelcolor::Rgb565,
 };
 use tinytga::Tga;
 
+/// The start of the Nintendo logo every Game Boy cartridge has at $0104
+const GB_LOGO: [u8; 4] = [0xCE, 0xED, 0x66, 0x66];
+/// The start of the logo GBA cartridges have at $04
+const GBA_LOGO: [u8; 4] = [0x24, 0xFF, 0xAE, 0x51];
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum GameConsole {
     GameBoy,
@@ -11,110 +20,197 @@ pub enum GameConsole {
     GameBoyAdvanced,
     NES,
     Sprig,
-    // TODO: needed?
-    Placeholder,
 }
 
 impl GameConsole {
-    pub fn get_pos(&self, size: &Size, i: u8) -> Point {
+    pub const ALL: [GameConsole; 5] = [
+        GameConsole::GameBoy,
+        GameConsole::GameBoyColor,
+        GameConsole::GameBoyAdvanced,
+        GameConsole::NES,
+        GameConsole::Sprig,
+    ];
+
+    /// A short name that fits next to a title in the game list
+    pub fn name(&self) -> &'static str {
+        match self {
+            GameConsole::GameBoy => "GB",
+            GameConsole::GameBoyColor => "GBC",
+            GameConsole::GameBoyAdvanced => "GBA",
+            GameConsole::NES => "NES",
+            GameConsole::Sprig => "Sprig",
+        }
+    }
+
+    /// Whether the console runs on the Game Boy core and has a boot ROM
+    pub fn is_gameboy(&self) -> bool {
+        matches!(self, GameConsole::GameBoy | GameConsole::GameBoyColor)
+    }
+
+    /// Guesses the console from a ROM's file extension
+    pub fn from_extension(path: &str) -> Option<GameConsole> {
+        let (_, ext) = path.rsplit_once('.')?;
+        match ext.to_ascii_lowercase().as_str() {
+            "gb" => Some(GameConsole::GameBoy),
+            "gbc" => Some(GameConsole::GameBoyColor),
+            "gba" => Some(GameConsole::GameBoyAdvanced),
+            "nes" => Some(GameConsole::NES),
+            "sprig" => Some(GameConsole::Sprig),
+            _ => None,
+        }
+    }
+
+    /// Works out the console from the start of a ROM, falling back to the extension for
+    /// formats without a header like Sprig games. `header` should be the first 0x150 bytes.
+    pub fn detect(header: &[u8], path: &str) -> Option<GameConsole> {
+        let at =
+            |offset: usize, bytes: &[u8]| header.get(offset..offset + bytes.len()) == Some(bytes);
+
+        if at(0, b"NES\x1A") {
+            Some(GameConsole::NES)
+        } else if at(0x104, &GB_LOGO) {
+            match header.get(0x143) {
+                Some(0x80 | 0xC0) => Some(GameConsole::GameBoyColor),
+                _ => Some(GameConsole::GameBoy),
+            }
+        } else if at(0x04, &GBA_LOGO) && header.get(0xB2) == Some(&0x96) {
+            Some(GameConsole::GameBoyAdvanced)
+        } else {
+            GameConsole::from_extension(path)
+        }
+    }
+
+    /// Where the carousel draws this console's cartridge `offset` slots from the selected one,
+    /// which sits in the middle. Neighbours peek in from the edges and anything further out is
+    /// off screen, so any offset works.
+    pub fn get_pos(&self, size: &Size, offset: i32) -> Point {
         let s = match self {
             GameConsole::GameBoy => Size::new(82, 91),
-            GameConsole::GameBoyColor => todo!(),
+            GameConsole::GameBoyColor => Size::new(82, 91),
             GameConsole::GameBoyAdvanced => Size::new(106, 61),
             GameConsole::NES => Size::new(82, 91),
-            GameConsole::Sprig => todo!(),
-            GameConsole::Placeholder => panic!("Placeholder console should not be used"),
+            GameConsole::Sprig => Size::new(96, 70),
         };
+        let step = (size.width as i32 + s.width as i32) / 2 - 16;
 
-        match i {
-            0 => Point::new(
-                (0 - s.width as i32) + 16,
-                (size.height as i32 - s.height as i32) / 2,
-            ),
-            1 => Point::new(
-                (size.width as i32 - s.width as i32) / 2,
-                (size.height as i32 - s.height as i32) / 2,
-            ),
-            2 => Point::new(
-                size.width as i32 - 16,
-                (size.height as i32 - s.height as i32) / 2,
-            ),
-            3 => Point::new(
-                2 * size.width as i32 - 16,
-                (size.height as i32 - s.height as i32) / 2,
-            ),
-            255 => Point::new(
-                -(size.width as i32) + 16,
-                (size.height as i32 - s.height as i32) / 2,
-            ),
-            _ => panic!("Invalid index {:?} with console {:?}", i, self),
+        Point::new(
+            (size.width as i32 - s.width as i32) / 2 + offset * step,
+            (size.height as i32 - s.height as i32) / 2,
+        )
+    }
+}
+
+/// Where a game's ROM comes from
+#[derive(Debug, Clone, PartialEq)]
+pub enum RomHandle {
+    /// Compiled into the firmware
+    Builtin(&'static [u8]),
+    /// A path on the storage the library was scanned from
+    File(String),
+}
+
+/// A game's label art, a TGA
+#[derive(Debug, Clone)]
+pub enum Cover {
+    Builtin(&'static [u8]),
+    /// Read from storage, shared so cloning the library doesn't copy it
+    Loaded(Rc<[u8]>),
+}
+
+impl Cover {
+    pub fn data(&self) -> &[u8] {
+        match self {
+            Cover::Builtin(data) => data,
+            Cover::Loaded(data) => data,
         }
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Game {
-    pub title: &'static str,
+    pub title: String,
     pub console: GameConsole,
-    pub image: Tga<'static, Rgb565>,
+    pub cover: Option<Cover>,
+    /// None for the demo entries that only show art
+    pub rom: Option<RomHandle>,
 }
 
 impl Game {
-    pub fn new_gameboy(title: &'static str, image: Tga<'static, Rgb565>) -> Game {
+    pub fn new(
+        title: String,
+        console: GameConsole,
+        cover: Option<Cover>,
+        rom: Option<RomHandle>,
+    ) -> Game {
         Game {
             title,
-            console: GameConsole::GameBoy,
-            image,
+            console,
+            cover,
+            rom,
         }
     }
 
-    pub fn new_gameboy_color(title: &'static str, image: Tga<'static, Rgb565>) -> Game {
-        Game {
-            title,
-            console: GameConsole::GameBoyColor,
-            image,
-        }
+    pub fn new_gameboy(title: &str, image: &'static [u8]) -> Game {
+        Game::new(
+            title.to_string(),
+            GameConsole::GameBoy,
+            Some(Cover::Builtin(image)),
+            None,
+        )
     }
 
-    pub fn new_gameboy_advanced(title: &'static str, image: Tga<'static, Rgb565>) -> Game {
-        Game {
-            title,
-            console: GameConsole::GameBoyAdvanced,
-            image,
-        }
+    pub fn new_gameboy_color(title: &str, image: &'static [u8]) -> Game {
+        Game::new(
+            title.to_string(),
+            GameConsole::GameBoyColor,
+            Some(Cover::Builtin(image)),
+            None,
+        )
     }
 
-    pub fn new_nes(title: &'static str, image: Tga<'static, Rgb565>) -> Game {
-        Game {
-            title,
-            console: GameConsole::NES,
-            image,
-        }
+    pub fn new_gameboy_advanced(title: &str, image: &'static [u8]) -> Game {
+        Game::new(
+            title.to_string(),
+            GameConsole::GameBoyAdvanced,
+            Some(Cover::Builtin(image)),
+            None,
+        )
     }
 
-    pub fn new_sprig(title: &'static str, image: Tga<'static, Rgb565>) -> Game {
-        Game {
-            title,
-            console: GameConsole::Sprig,
-            image,
-        }
+    pub fn new_nes(title: &str, image: &'static [u8]) -> Game {
+        Game::new(
+            title.to_string(),
+            GameConsole::NES,
+            Some(Cover::Builtin(image)),
+            None,
+        )
     }
 
-    pub fn new_placeholder() -> Game {
-        Game {
-            title: "Placeholder",
-            console: GameConsole::Placeholder,
-            image: Tga::from_slice(include_bytes!("assets/empty.tga")).unwrap(),
+    pub fn new_sprig(title: &str, image: &'static [u8]) -> Game {
+        Game::new(
+            title.to_string(),
+            GameConsole::Sprig,
+            Some(Cover::Builtin(image)),
+            None,
+        )
+    }
+
+    /// What the settings store remembers the game by, its path if it came from storage
+    pub fn key(&self) -> &str {
+        match &self.rom {
+            Some(RomHandle::File(path)) => path,
+            _ => &self.title,
         }
     }
 
     pub fn get_console(&self) -> &GameConsole {
         &self.console
     }
-    pub fn get_title(&self) -> &'static str {
-        self.title
+    pub fn get_title(&self) -> &str {
+        &self.title
     }
-    pub fn get_image(&self) -> Tga<'static, Rgb565> {
-        self.image
+    /// The cover art, None if there isn't any or it isn't a TGA
+    pub fn get_image(&self) -> Option<Tga<'_, Rgb565>> {
+        Tga::from_slice(self.cover.as_ref()?.data()).ok()
     }
 }
@@ -0,0 +1,220 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+pub const BOOT_OFF: u16 = 0xFF50;
+
+const DMG_BOOT_SIZE: usize = 0x100;
+const CGB_BOOT_SIZE: usize = 0x900;
+// the CGB boot ROM is split around the cartridge header at $0100-$01FF
+const CGB_HEADER: u16 = 0x0100;
+const CGB_HEADER_END: u16 = 0x01FF;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Model {
+    Dmg,
+    Cgb,
+}
+
+/// How a Game Boy game gets started, picked per game in the settings
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BootMode {
+    /// Jump straight to $0100 with the registers set up like the boot ROM would have
+    Skip,
+    /// Run a user supplied boot ROM, falls back to `Skip` when there isn't one
+    BootRom,
+}
+
+impl BootMode {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BootMode::Skip => "Skip",
+            BootMode::BootRom => "Boot ROM",
+        }
+    }
+}
+
+/// A boot ROM overlaying the cartridge until a non-zero value is written to $FF50
+pub struct BootRom {
+    data: Vec<u8>,
+    model: Model,
+    mapped: bool,
+}
+
+impl BootRom {
+    pub fn new(raw: &[u8]) -> Result<BootRom, String> {
+        let model = match raw.len() {
+            DMG_BOOT_SIZE => Model::Dmg,
+            CGB_BOOT_SIZE => Model::Cgb,
+            len => return Err(alloc::format!("Invalid boot ROM size {:#x}", len)),
+        };
+
+        Ok(BootRom {
+            data: raw.to_vec(),
+            model,
+            mapped: true,
+        })
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        self.mapped
+    }
+
+    /// Returns the boot ROM byte if it's currently covering `addr`
+    pub fn read(&self, addr: u16) -> Option<u8> {
+        if !self.mapped {
+            return None;
+        }
+
+        match self.model {
+            Model::Dmg if (addr as usize) < DMG_BOOT_SIZE => Some(self.data[addr as usize]),
+            Model::Cgb if (addr as usize) < CGB_BOOT_SIZE => match addr {
+                CGB_HEADER..=CGB_HEADER_END => None,
+                _ => Some(self.data[addr as usize]),
+            },
+            _ => None,
+        }
+    }
+
+    /// Writing anything but 0 to $FF50 unmaps the boot ROM until the next reset
+    pub fn write(&mut self, addr: u16, data: u8) {
+        if addr == BOOT_OFF && data != 0 {
+            self.mapped = false;
+        }
+    }
+
+    pub fn read_boot_off(&self) -> u8 {
+        if self.mapped {
+            0xFE
+        } else {
+            0xFF
+        }
+    }
+}
+
+/// CPU and IO state right after the boot ROM hands over to the cartridge
+/// https://gbdev.io/pandocs/Power_Up_Sequence.html
+pub struct PostBootState {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub io: &'static [(u16, u8)],
+}
+
+#[rustfmt::skip]
+const DMG_IO: &[(u16, u8)] = &[
+    (0xFF00, 0xCF), (0xFF01, 0x00), (0xFF02, 0x7E), (0xFF04, 0xAB),
+    (0xFF05, 0x00), (0xFF06, 0x00), (0xFF07, 0xF8), (0xFF0F, 0xE1),
+    (0xFF10, 0x80), (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF),
+    (0xFF14, 0xBF), (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF),
+    (0xFF19, 0xBF), (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F),
+    (0xFF1D, 0xFF), (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF21, 0x00),
+    (0xFF22, 0x00), (0xFF23, 0xBF), (0xFF24, 0x77), (0xFF25, 0xF3),
+    (0xFF26, 0xF1), (0xFF40, 0x91), (0xFF41, 0x85), (0xFF42, 0x00),
+    (0xFF43, 0x00), (0xFF44, 0x00), (0xFF45, 0x00), (0xFF46, 0xFF),
+    (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00), (0xFF50, 0xFF),
+    (0xFFFF, 0x00),
+];
+
+#[rustfmt::skip]
+const CGB_IO: &[(u16, u8)] = &[
+    (0xFF00, 0xCF), (0xFF01, 0x00), (0xFF02, 0x7F), (0xFF05, 0x00),
+    (0xFF06, 0x00), (0xFF07, 0xF8), (0xFF0F, 0xE1), (0xFF10, 0x80),
+    (0xFF11, 0xBF), (0xFF12, 0xF3), (0xFF13, 0xFF), (0xFF14, 0xBF),
+    (0xFF16, 0x3F), (0xFF17, 0x00), (0xFF18, 0xFF), (0xFF19, 0xBF),
+    (0xFF1A, 0x7F), (0xFF1B, 0xFF), (0xFF1C, 0x9F), (0xFF1D, 0xFF),
+    (0xFF1E, 0xBF), (0xFF20, 0xFF), (0xFF21, 0x00), (0xFF22, 0x00),
+    (0xFF23, 0xBF), (0xFF24, 0x77), (0xFF25, 0xF3), (0xFF26, 0xF1),
+    (0xFF40, 0x91), (0xFF42, 0x00), (0xFF43, 0x00), (0xFF45, 0x00),
+    (0xFF46, 0x00), (0xFF47, 0xFC), (0xFF4A, 0x00), (0xFF4B, 0x00),
+    (0xFF4D, 0x7E), (0xFF4F, 0xFE), (0xFF50, 0xFF), (0xFF55, 0xFF),
+    (0xFF70, 0xF8), (0xFFFF, 0x00),
+];
+
+impl PostBootState {
+    /// `header_checksum` is the byte at $014D, the DMG boot ROM leaves H and C set unless it's 0
+    pub fn new(model: Model, header_checksum: u8) -> Self {
+        match model {
+            Model::Dmg => PostBootState {
+                a: 0x01,
+                f: if header_checksum == 0 { 0x80 } else { 0xB0 },
+                b: 0x00,
+                c: 0x13,
+                d: 0x00,
+                e: 0xD8,
+                h: 0x01,
+                l: 0x4D,
+                sp: 0xFFFE,
+                pc: 0x0100,
+                io: DMG_IO,
+            },
+            Model::Cgb => PostBootState {
+                a: 0x11,
+                f: 0x80,
+                b: 0x00,
+                c: 0x00,
+                d: 0xFF,
+                e: 0x56,
+                h: 0x00,
+                l: 0x0D,
+                sp: 0xFFFE,
+                pc: 0x0100,
+                io: CGB_IO,
+            },
+        }
+    }
+}
+
+/// What a Game Boy core should do on power on
+pub enum Boot {
+    Rom(BootRom),
+    State(PostBootState),
+}
+
+impl Boot {
+    /// Picks between the boot ROM and the built-in state for a game's `BootMode`
+    pub fn resolve(mode: BootMode, model: Model, header_checksum: u8) -> Boot {
+        if mode == BootMode::BootRom {
+            if let Some(rom) = load_boot_rom(model) {
+                return Boot::Rom(rom);
+            }
+        }
+        Boot::State(PostBootState::new(model, header_checksum))
+    }
+}
+
+/// Boot ROMs are copyrighted so we can't ship them, on the simulator they're read from
+/// `EGB_DMG_BOOT`/`EGB_CGB_BOOT` or `dmg_boot.bin`/`cgb_boot.bin` in the working directory
+#[cfg(target_arch = "x86_64")]
+pub fn load_boot_rom(model: Model) -> Option<BootRom> {
+    let (var, default) = match model {
+        Model::Dmg => ("EGB_DMG_BOOT", "dmg_boot.bin"),
+        Model::Cgb => ("EGB_CGB_BOOT", "cgb_boot.bin"),
+    };
+    let path = std::env::var(var).unwrap_or(default.to_string());
+    let raw = std::fs::read(path).ok()?;
+    let rom = BootRom::new(&raw).ok()?;
+    if rom.model() == model {
+        Some(rom)
+    } else {
+        None
+    }
+}
+
+// TODO: read it from the sd card
+#[cfg(not(target_arch = "x86_64"))]
+pub fn load_boot_rom(_model: Model) -> Option<BootRom> {
+    None
+}
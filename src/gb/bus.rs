@@ -0,0 +1,199 @@
+use alloc::string::String;
+
+use crate::nes::cpu::Mem;
+
+use super::{
+    boot::{BootRom, BOOT_OFF},
+    cartridge::Rom,
+    cgb::Cgb,
+    cpu::{INT_JOYPAD, INT_SERIAL, INT_TIMER},
+    mbc::Mbc,
+    ppu::{Ppu, LCDC, WX},
+    serial::{Serial, SB, SC},
+    timer::{Timer, DIV, TAC},
+};
+
+pub const JOYP: u16 = 0xFF00;
+pub const IF: u16 = 0xFF0F;
+pub const DMA: u16 = 0xFF46;
+pub const IE: u16 = 0xFFFF;
+
+const SELECT_DIRECTIONS: u8 = 1 << 4;
+const SELECT_BUTTONS: u8 = 1 << 5;
+
+/// The Game Boy's memory map, https://gbdev.io/pandocs/Memory_Map.html
+pub struct Bus {
+    pub mbc: Mbc,
+    /// Only set for games that ask for the CGB's features, everything else runs as on a DMG
+    pub cgb_mode: bool,
+    /// VRAM and WRAM always live here, the registers are only there in CGB mode
+    pub cgb: Cgb,
+    pub oam: [u8; 0xA0],
+    hram: [u8; 0x7F],
+    pub ppu: Ppu,
+    pub timer: Timer,
+    pub serial: Serial,
+    pub boot: Option<BootRom>,
+    joypad_select: u8,
+    /// Directions in the low nibble and buttons in the high one, set bits are pressed
+    keys: u8,
+    pub interrupt_flag: u8,
+    pub interrupt_enable: u8,
+    /// The sound registers, kept so games can read back what they wrote
+    io: [u8; 0x30],
+    /// Cycles the CPU is paused for by a general purpose DMA
+    pub stall: u32,
+}
+
+impl Bus {
+    pub fn new(rom: &Rom, boot: Option<BootRom>) -> Result<Self, String> {
+        let cgb_mode = rom.is_cgb();
+        Ok(Self {
+            mbc: Mbc::new(rom)?,
+            cgb_mode,
+            cgb: Cgb::new(),
+            oam: [0; 0xA0],
+            hram: [0; 0x7F],
+            ppu: Ppu::new(),
+            timer: Timer::new(),
+            serial: Serial::new(cgb_mode),
+            boot,
+            joypad_select: SELECT_DIRECTIONS | SELECT_BUTTONS,
+            keys: 0,
+            interrupt_flag: 0,
+            interrupt_enable: 0,
+            io: [0; 0x30],
+            stall: 0,
+        })
+    }
+
+    /// Requests the joypad interrupt when a newly pressed key is selected
+    pub fn set_keys(&mut self, keys: u8) {
+        let pressed = keys & !self.keys;
+        self.keys = keys;
+        if pressed & self.selected_keys() != 0 {
+            self.interrupt_flag |= INT_JOYPAD;
+        }
+    }
+
+    fn selected_keys(&self) -> u8 {
+        let mut keys = 0;
+        if self.joypad_select & SELECT_DIRECTIONS == 0 {
+            keys |= self.keys & 0x0F;
+        }
+        if self.joypad_select & SELECT_BUTTONS == 0 {
+            keys |= self.keys >> 4;
+        }
+        keys
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        if let Some(data) = self.boot.as_ref().and_then(|boot| boot.read(addr)) {
+            return data;
+        }
+
+        match addr {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.mbc.read(addr),
+            0x8000..=0x9FFF | 0xC000..=0xFDFF => self.cgb.read(addr),
+            0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize],
+            JOYP => 0xC0 | self.joypad_select | (!self.selected_keys() & 0x0F),
+            SB | SC => self.serial.read(addr),
+            DIV..=TAC => self.timer.read(addr),
+            IF => 0xE0 | self.interrupt_flag,
+            0xFF10..=0xFF3F => self.io[(addr - 0xFF10) as usize],
+            LCDC..=WX => self.ppu.read(addr),
+            BOOT_OFF => self.boot.as_ref().map_or(0xFF, BootRom::read_boot_off),
+            0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize],
+            IE => self.interrupt_enable,
+            _ if self.cgb_mode && Cgb::handles(addr) => self.cgb.read(addr),
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.mbc.write(addr, data),
+            0x8000..=0x9FFF | 0xC000..=0xFDFF => {
+                self.cgb.write(addr, data);
+            }
+            0xFE00..=0xFE9F => self.oam[(addr - 0xFE00) as usize] = data,
+            JOYP => self.joypad_select = data & (SELECT_DIRECTIONS | SELECT_BUTTONS),
+            SB | SC => self.serial.write(addr, data),
+            DIV..=TAC => {
+                let overflow = self.timer.write(addr, data);
+                if overflow {
+                    self.interrupt_flag |= INT_TIMER;
+                }
+            }
+            IF => self.interrupt_flag = data & 0x1F,
+            0xFF10..=0xFF3F => self.io[(addr - 0xFF10) as usize] = data,
+            DMA => self.oam_dma(data),
+            LCDC..=WX => self.ppu.write(addr, data),
+            BOOT_OFF => {
+                if let Some(boot) = &mut self.boot {
+                    boot.write(addr, data);
+                }
+            }
+            0xFF80..=0xFFFE => self.hram[(addr - 0xFF80) as usize] = data,
+            IE => self.interrupt_enable = data,
+            _ if self.cgb_mode && Cgb::handles(addr) => {
+                if let Some(transfer) = self.cgb.write(addr, data) {
+                    transfer.run(self);
+                    self.stall += transfer.cycles(self.cgb.speed.is_double());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Copies a page into OAM. The real DMA takes 160 M-cycles, here it's done at once.
+    fn oam_dma(&mut self, page: u8) {
+        let source = (page as u16) << 8;
+        for i in 0..0xA0 {
+            self.oam[i as usize] = self.read(source + i);
+        }
+    }
+
+    /// The CPU executed `STOP`
+    pub fn stop(&mut self) {
+        if self.cgb_mode {
+            self.cgb.speed.stop();
+        }
+    }
+
+    /// Advances everything but the CPU by `cycles` T-cycles
+    pub fn tick(&mut self, cycles: u32) {
+        if self.timer.tick(cycles) {
+            self.interrupt_flag |= INT_TIMER;
+        }
+        if self.serial.tick(cycles) {
+            self.interrupt_flag |= INT_SERIAL;
+        }
+
+        // the PPU keeps its pace when the CPU runs at double speed
+        let dots = if self.cgb.speed.is_double() {
+            cycles / 2
+        } else {
+            cycles
+        };
+        let (interrupts, hblank) = self.ppu.tick(dots, &self.cgb, &self.oam, self.cgb_mode);
+        self.interrupt_flag |= interrupts;
+
+        if hblank && self.cgb_mode {
+            if let Some(transfer) = self.cgb.hdma.hblank() {
+                transfer.run(self);
+                self.stall += transfer.cycles(self.cgb.speed.is_double());
+            }
+        }
+    }
+}
+
+impl Mem for Bus {
+    fn mem_read(&self, addr: u16) -> u8 {
+        self.read(addr)
+    }
+
+    fn mem_write(&mut self, addr: u16, data: u8) {
+        self.write(addr, data)
+    }
+}
@@ -0,0 +1,68 @@
+use alloc::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+const HEADER_END: usize = 0x150;
+const TITLE_START: usize = 0x134;
+const TITLE_END: usize = 0x144;
+const CGB_FLAG: usize = 0x143;
+const CARTRIDGE_TYPE: usize = 0x147;
+const HEADER_CHECKSUM: usize = 0x14D;
+
+/// How a cartridge wants to be run, taken from the CGB flag at $0143.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgbMode {
+    /// Original Game Boy game, runs in DMG compatibility mode on a CGB.
+    Dmg,
+    /// Works on both, but uses CGB features when available ($80).
+    Compatible,
+    /// Only runs on a CGB ($C0).
+    Exclusive,
+}
+
+pub struct Rom {
+    pub data: Vec<u8>,
+    pub title: String,
+    pub cartridge_type: u8,
+    pub header_checksum: u8,
+    pub cgb_mode: CgbMode,
+}
+
+impl Rom {
+    pub fn new(raw: &[u8]) -> Result<Rom, String> {
+        if raw.len() < HEADER_END {
+            return Err("File is too small to be a Game Boy ROM".to_string());
+        }
+
+        let cgb_mode = match raw[CGB_FLAG] {
+            0x80 => CgbMode::Compatible,
+            0xC0 => CgbMode::Exclusive,
+            _ => CgbMode::Dmg,
+        };
+
+        // CGB titles are only 11/15 characters long, the rest is the manufacturer code and flag
+        let title_end = if cgb_mode == CgbMode::Dmg {
+            TITLE_END
+        } else {
+            CGB_FLAG
+        };
+        let title = raw[TITLE_START..title_end]
+            .iter()
+            .take_while(|c| **c != 0)
+            .map(|c| *c as char)
+            .collect();
+
+        Ok(Rom {
+            data: raw.to_vec(),
+            title,
+            cartridge_type: raw[CARTRIDGE_TYPE],
+            header_checksum: raw[HEADER_CHECKSUM],
+            cgb_mode,
+        })
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.cgb_mode != CgbMode::Dmg
+    }
+}
@@ -0,0 +1,563 @@
+use bitflags::bitflags;
+use embedded_graphics::pixelcolor::Rgb565;
+
+use crate::nes::cpu::Mem;
+
+pub const KEY1: u16 = 0xFF4D;
+pub const VBK: u16 = 0xFF4F;
+pub const HDMA1: u16 = 0xFF51;
+pub const HDMA2: u16 = 0xFF52;
+pub const HDMA3: u16 = 0xFF53;
+pub const HDMA4: u16 = 0xFF54;
+pub const HDMA5: u16 = 0xFF55;
+pub const BCPS: u16 = 0xFF68;
+pub const BCPD: u16 = 0xFF69;
+pub const OCPS: u16 = 0xFF6A;
+pub const OCPD: u16 = 0xFF6B;
+pub const SVBK: u16 = 0xFF70;
+
+const VRAM: u16 = 0x8000;
+const VRAM_END: u16 = 0x9FFF;
+const WRAM: u16 = 0xC000;
+const WRAM_BANK_START: u16 = 0xD000;
+const ECHO_END: u16 = 0xFDFF;
+
+bitflags! {
+    /// # BG Map Attributes (VRAM bank 1) https://gbdev.io/pandocs/Tile_Maps.html
+    ///
+    ///  7 6 5 4 3 2 1 0
+    ///  P Y X _ B C C C
+    ///  | | |   | +-+-+--- Background Palette (0-7)
+    ///  | | |   +--------- Tile VRAM Bank
+    ///  | | +------------- Horizontal Flip
+    ///  | +--------------- Vertical Flip
+    ///  +----------------- BG-to-OAM Priority
+    ///
+    #[derive(Clone, Copy)]
+    pub struct TileAttributes: u8 {
+        const PALETTE  = 0b00000111;
+        const BANK     = 0b00001000;
+        const X_FLIP   = 0b00100000;
+        const Y_FLIP   = 0b01000000;
+        const PRIORITY = 0b10000000;
+    }
+}
+
+impl TileAttributes {
+    pub fn palette(&self) -> u8 {
+        self.bits() & TileAttributes::PALETTE.bits()
+    }
+
+    pub fn bank(&self) -> usize {
+        self.contains(TileAttributes::BANK) as usize
+    }
+}
+
+/// KEY1, the speed switch. The switch only happens once the CPU executes `STOP`.
+pub struct Speed {
+    double: bool,
+    armed: bool,
+}
+
+impl Speed {
+    pub fn new() -> Self {
+        Self {
+            double: false,
+            armed: false,
+        }
+    }
+
+    pub fn is_double(&self) -> bool {
+        self.double
+    }
+
+    pub fn read(&self) -> u8 {
+        ((self.double as u8) << 7) | 0x7E | self.armed as u8
+    }
+
+    pub fn write(&mut self, data: u8) {
+        self.armed = data & 1 != 0;
+    }
+
+    /// Called when the CPU executes `STOP`, returns whether the speed changed
+    pub fn stop(&mut self) -> bool {
+        if self.armed {
+            self.double = !self.double;
+            self.armed = false;
+            return true;
+        }
+        false
+    }
+}
+
+/// Two 8 KB banks at $8000-$9FFF, selected through VBK
+pub struct Vram {
+    banks: [[u8; 0x2000]; 2],
+    bank: usize,
+}
+
+impl Vram {
+    pub fn new() -> Self {
+        Self {
+            banks: [[0; 0x2000]; 2],
+            bank: 0,
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        self.read_bank(self.bank, addr)
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        self.banks[self.bank][(addr - VRAM) as usize] = data;
+    }
+
+    /// The PPU needs both banks regardless of VBK (tile data and attributes)
+    pub fn read_bank(&self, bank: usize, addr: u16) -> u8 {
+        self.banks[bank][(addr - VRAM) as usize]
+    }
+
+    pub fn read_vbk(&self) -> u8 {
+        0xFE | self.bank as u8
+    }
+
+    pub fn write_vbk(&mut self, data: u8) {
+        self.bank = (data & 1) as usize;
+    }
+}
+
+/// Eight 4 KB banks, bank 0 is fixed at $C000 and SVBK selects the one at $D000
+pub struct Wram {
+    banks: [[u8; 0x1000]; 8],
+    bank: usize,
+}
+
+impl Wram {
+    pub fn new() -> Self {
+        Self {
+            banks: [[0; 0x1000]; 8],
+            bank: 1,
+        }
+    }
+
+    fn locate(&self, addr: u16) -> (usize, usize) {
+        // echo RAM at $E000-$FDFF mirrors $C000-$DDFF
+        let addr = if addr > WRAM + 0x1FFF && addr <= ECHO_END {
+            addr - 0x2000
+        } else {
+            addr
+        };
+
+        if addr < WRAM_BANK_START {
+            (0, (addr - WRAM) as usize)
+        } else {
+            (self.bank, (addr - WRAM_BANK_START) as usize)
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        let (bank, offset) = self.locate(addr);
+        self.banks[bank][offset]
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        let (bank, offset) = self.locate(addr);
+        self.banks[bank][offset] = data;
+    }
+
+    pub fn read_svbk(&self) -> u8 {
+        0xF8 | self.bank as u8
+    }
+
+    pub fn write_svbk(&mut self, data: u8) {
+        // bank 0 can't be mapped at $D000, writing 0 selects bank 1
+        self.bank = core::cmp::max(data & 0b111, 1) as usize;
+    }
+}
+
+/// 8 palettes of 4 colours each, accessed through a spec (index) and data register pair
+pub struct ColorPalettes {
+    data: [u8; 64],
+    index: u8,
+    auto_increment: bool,
+}
+
+impl ColorPalettes {
+    pub fn new() -> Self {
+        Self {
+            // the boot ROM leaves the palettes white
+            data: [0xFF; 64],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    pub fn read_spec(&self) -> u8 {
+        ((self.auto_increment as u8) << 7) | 0x40 | self.index
+    }
+
+    pub fn write_spec(&mut self, data: u8) {
+        self.index = data & 0x3F;
+        self.auto_increment = data & 0x80 != 0;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    pub fn write_data(&mut self, data: u8) {
+        self.data[self.index as usize] = data;
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    /// Converts the stored RGB555 colour to the display's RGB565
+    pub fn color(&self, palette: u8, color: u8) -> Rgb565 {
+        let i = (palette as usize & 0b111) * 8 + (color as usize & 0b11) * 2;
+        let raw = self.data[i] as u16 | (self.data[i + 1] as u16) << 8;
+
+        let r = (raw & 0x1F) as u8;
+        let g = ((raw >> 5) & 0x1F) as u8;
+        let b = ((raw >> 10) & 0x1F) as u8;
+
+        // green has an extra bit on the display, repeat the top bit so white stays white
+        Rgb565::new(r, (g << 1) | (g >> 4), b)
+    }
+}
+
+/// A block copy into VRAM requested by the HDMA registers
+#[derive(Debug, Clone, PartialEq)]
+pub struct Transfer {
+    pub source: u16,
+    pub dest: u16,
+    pub len: u16,
+}
+
+impl Transfer {
+    pub fn run<M: Mem>(&self, mem: &mut M) {
+        for i in 0..self.len {
+            let data = mem.mem_read(self.source.wrapping_add(i));
+            mem.mem_write(self.dest.wrapping_add(i), data);
+        }
+    }
+
+    /// Cycles the CPU is halted for, each 16 byte block takes 8 M-cycles (16 in double speed)
+    pub fn cycles(&self, double_speed: bool) -> u32 {
+        let blocks = (self.len / 16) as u32;
+        if double_speed {
+            blocks * 16 * 4
+        } else {
+            blocks * 8 * 4
+        }
+    }
+}
+
+/// VRAM DMA, either all at once (GDMA) or 16 bytes per H-Blank (HDMA)
+pub struct Hdma {
+    source: u16,
+    dest: u16,
+    // blocks left minus one, like the hardware reports it
+    remaining: u8,
+    active: bool,
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Self {
+            source: 0,
+            dest: 0,
+            remaining: 0x7F,
+            active: false,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            HDMA5 => {
+                if self.active {
+                    self.remaining
+                } else {
+                    0x80 | self.remaining
+                }
+            }
+            // the other registers are write only
+            _ => 0xFF,
+        }
+    }
+
+    /// Returns the transfer to perform straight away when a general purpose DMA is started
+    pub fn write(&mut self, addr: u16, data: u8) -> Option<Transfer> {
+        match addr {
+            HDMA1 => self.source = (self.source & 0x00FF) | (data as u16) << 8,
+            HDMA2 => self.source = (self.source & 0xFF00) | (data & 0xF0) as u16,
+            HDMA3 => self.dest = (self.dest & 0x00FF) | ((data & 0x1F) as u16) << 8,
+            HDMA4 => self.dest = (self.dest & 0xFF00) | (data & 0xF0) as u16,
+            HDMA5 => {
+                if self.active && data & 0x80 == 0 {
+                    // writing bit 7 = 0 during an HDMA cancels it
+                    self.active = false;
+                    return None;
+                }
+
+                self.remaining = data & 0x7F;
+                if data & 0x80 != 0 {
+                    self.active = true;
+                } else {
+                    let len = (self.remaining as u16 + 1) * 16;
+                    let transfer = self.next(len);
+                    self.remaining = 0x7F;
+                    return Some(transfer);
+                }
+            }
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Called by the PPU when entering H-Blank on a visible line
+    pub fn hblank(&mut self) -> Option<Transfer> {
+        if !self.active {
+            return None;
+        }
+
+        let transfer = self.next(16);
+        if self.remaining == 0 {
+            self.active = false;
+            self.remaining = 0x7F;
+        } else {
+            self.remaining -= 1;
+        }
+
+        Some(transfer)
+    }
+
+    fn next(&mut self, len: u16) -> Transfer {
+        let transfer = Transfer {
+            source: self.source,
+            dest: VRAM | (self.dest & 0x1FF0),
+            len,
+        };
+        self.source = self.source.wrapping_add(len);
+        self.dest = (self.dest + len) & 0x1FF0;
+        transfer
+    }
+}
+
+/// The hardware a Color Game Boy adds on top of the DMG
+pub struct Cgb {
+    pub speed: Speed,
+    pub vram: Vram,
+    pub wram: Wram,
+    pub bg_palettes: ColorPalettes,
+    pub obj_palettes: ColorPalettes,
+    pub hdma: Hdma,
+}
+
+impl Cgb {
+    pub fn new() -> Self {
+        Self {
+            speed: Speed::new(),
+            vram: Vram::new(),
+            wram: Wram::new(),
+            bg_palettes: ColorPalettes::new(),
+            obj_palettes: ColorPalettes::new(),
+            hdma: Hdma::new(),
+        }
+    }
+
+    /// Whether the address belongs to memory or a register owned by the CGB hardware
+    pub fn handles(addr: u16) -> bool {
+        matches!(
+            addr,
+            VRAM..=VRAM_END | WRAM..=ECHO_END | KEY1 | VBK | HDMA1..=HDMA5 | BCPS..=OCPD | SVBK
+        )
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            VRAM..=VRAM_END => self.vram.read(addr),
+            WRAM..=ECHO_END => self.wram.read(addr),
+            KEY1 => self.speed.read(),
+            VBK => self.vram.read_vbk(),
+            HDMA1..=HDMA5 => self.hdma.read(addr),
+            BCPS => self.bg_palettes.read_spec(),
+            BCPD => self.bg_palettes.read_data(),
+            OCPS => self.obj_palettes.read_spec(),
+            OCPD => self.obj_palettes.read_data(),
+            SVBK => self.wram.read_svbk(),
+            _ => 0xFF,
+        }
+    }
+
+    /// Writes a CGB register, the returned transfer has to be run by the owner of the memory map
+    pub fn write(&mut self, addr: u16, data: u8) -> Option<Transfer> {
+        match addr {
+            VRAM..=VRAM_END => self.vram.write(addr, data),
+            WRAM..=ECHO_END => self.wram.write(addr, data),
+            KEY1 => self.speed.write(data),
+            VBK => self.vram.write_vbk(data),
+            HDMA1..=HDMA5 => return self.hdma.write(addr, data),
+            BCPS => self.bg_palettes.write_spec(data),
+            BCPD => self.bg_palettes.write_data(data),
+            OCPS => self.obj_palettes.write_spec(data),
+            OCPD => self.obj_palettes.write_data(data),
+            SVBK => self.wram.write_svbk(data),
+            _ => {}
+        }
+
+        None
+    }
+
+    /// Resolves the colour of a background pixel given its tile attributes
+    pub fn bg_color(&self, attributes: TileAttributes, color: u8) -> Rgb565 {
+        self.bg_palettes.color(attributes.palette(), color)
+    }
+
+    /// Sprites use bits 0-2 of the OAM flags as their palette number
+    pub fn obj_color(&self, flags: u8, color: u8) -> Rgb565 {
+        self.obj_palettes.color(flags & 0b111, color)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Flat 64K of memory, enough to run transfers against
+    struct Ram([u8; 0x10000]);
+
+    impl Mem for Ram {
+        fn mem_read(&self, addr: u16) -> u8 {
+            self.0[addr as usize]
+        }
+
+        fn mem_write(&mut self, addr: u16, data: u8) {
+            self.0[addr as usize] = data;
+        }
+    }
+
+    #[test]
+    fn test_palettes() {
+        let mut cgb = Cgb::new();
+        assert_eq!(
+            cgb.bg_color(TileAttributes::empty(), 0),
+            Rgb565::new(31, 63, 31)
+        );
+
+        // palette 2 colour 1, auto incrementing from byte 18
+        cgb.write(BCPS, 0x80 | 18);
+        cgb.write(BCPD, 0xFF);
+        cgb.write(BCPD, 0x00);
+        assert_eq!(cgb.read(BCPS), 0x80 | 0x40 | 20);
+        let attributes = TileAttributes::from_bits_truncate(2);
+        assert_eq!(cgb.bg_color(attributes, 1), Rgb565::new(31, 14, 0));
+
+        // without auto increment the index stays put
+        cgb.write(OCPS, 8);
+        cgb.write(OCPD, 0x00);
+        cgb.write(OCPD, 0x1F);
+        assert_eq!(cgb.read(OCPS), 0x40 | 8);
+        assert_eq!(cgb.read(OCPD), 0x1F);
+        assert_eq!(cgb.obj_color(0b1001, 0), Rgb565::new(31, 49, 31));
+    }
+
+    #[test]
+    fn test_vram_banks() {
+        let mut cgb = Cgb::new();
+        cgb.write(0x8000, 1);
+        cgb.write(VBK, 1);
+        assert_eq!(cgb.read(VBK), 0xFF);
+        assert_eq!(cgb.read(0x8000), 0);
+        cgb.write(0x8000, 2);
+
+        assert_eq!(cgb.vram.read_bank(0, 0x8000), 1);
+        assert_eq!(cgb.vram.read_bank(1, 0x8000), 2);
+        cgb.write(VBK, 0xFE);
+        assert_eq!(cgb.read(0x8000), 1);
+    }
+
+    #[test]
+    fn test_wram_banks() {
+        let mut cgb = Cgb::new();
+        cgb.write(0xC000, 0x10);
+        cgb.write(0xD000, 0x11);
+        cgb.write(SVBK, 5);
+        assert_eq!(cgb.read(SVBK), 0xFD);
+        assert_eq!(cgb.read(0xD000), 0);
+        cgb.write(0xD000, 0x15);
+        // bank 0 doesn't move
+        assert_eq!(cgb.read(0xC000), 0x10);
+
+        // 0 picks bank 1
+        cgb.write(SVBK, 0);
+        assert_eq!(cgb.read(SVBK), 0xF9);
+        assert_eq!(cgb.read(0xD000), 0x11);
+        // echo RAM follows the selected bank
+        assert_eq!(cgb.read(0xF000), 0x11);
+        cgb.write(SVBK, 5);
+        assert_eq!(cgb.read(0xF000), 0x15);
+    }
+
+    #[test]
+    fn test_general_dma() {
+        let mut cgb = Cgb::new();
+        cgb.write(HDMA1, 0xC1);
+        cgb.write(HDMA2, 0x2F);
+        cgb.write(HDMA3, 0xE8);
+        cgb.write(HDMA4, 0x0F);
+        // 2 blocks, all at once
+        let transfer = cgb.write(HDMA5, 0x01).unwrap();
+        assert_eq!(
+            transfer,
+            Transfer {
+                source: 0xC120,
+                dest: 0x8800,
+                len: 32,
+            }
+        );
+        assert_eq!(transfer.cycles(false), 64);
+        assert_eq!(transfer.cycles(true), 128);
+        assert_eq!(cgb.read(HDMA5), 0xFF);
+
+        let mut ram = Ram([0; 0x10000]);
+        ram.0[0xC120..0xC140].fill(0xAB);
+        transfer.run(&mut ram);
+        assert!(ram.0[0x8800..0x8820].iter().all(|b| *b == 0xAB));
+        assert_eq!(ram.0[0x8820], 0);
+    }
+
+    #[test]
+    fn test_hblank_dma() {
+        let mut cgb = Cgb::new();
+        cgb.write(HDMA1, 0x40);
+        cgb.write(HDMA2, 0x00);
+        cgb.write(HDMA3, 0x00);
+        cgb.write(HDMA4, 0x00);
+        assert!(cgb.write(HDMA5, 0x82).is_none());
+        assert!(cgb.hdma.is_active());
+        assert_eq!(cgb.read(HDMA5), 0x02);
+
+        // 16 bytes per H-Blank, carrying on from the last block
+        assert_eq!(cgb.hdma.hblank().unwrap().source, 0x4000);
+        let second = cgb.hdma.hblank().unwrap();
+        assert_eq!((second.source, second.dest), (0x4010, 0x8010));
+        assert_eq!(cgb.read(HDMA5), 0x00);
+        assert!(cgb.hdma.hblank().is_some());
+        assert!(!cgb.hdma.is_active());
+        assert_eq!(cgb.read(HDMA5), 0xFF);
+        assert!(cgb.hdma.hblank().is_none());
+
+        // writing bit 7 clear stops one part way
+        cgb.write(HDMA5, 0x85);
+        cgb.hdma.hblank();
+        assert!(cgb.write(HDMA5, 0x00).is_none());
+        assert!(!cgb.hdma.is_active());
+        assert!(cgb.hdma.hblank().is_none());
+    }
+}
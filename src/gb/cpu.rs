@@ -0,0 +1,658 @@
+use super::bus::Bus;
+
+/// # Flags https://gbdev.io/pandocs/CPU_Registers_and_Flags.html
+///
+///  7 6 5 4 3-0
+///  Z N H C 0
+///  | | | +------ Carry
+///  | | +-------- Half Carry (bit 3 to 4)
+///  | +---------- Subtract, only DAA reads it
+///  +------------ Zero
+///
+pub const FLAG_Z: u8 = 0b1000_0000;
+pub const FLAG_N: u8 = 0b0100_0000;
+pub const FLAG_H: u8 = 0b0010_0000;
+pub const FLAG_C: u8 = 0b0001_0000;
+
+/// IF/IE bits, the lowest set bit is serviced first
+pub const INT_VBLANK: u8 = 0b0000_0001;
+pub const INT_STAT: u8 = 0b0000_0010;
+pub const INT_TIMER: u8 = 0b0000_0100;
+pub const INT_SERIAL: u8 = 0b0000_1000;
+pub const INT_JOYPAD: u8 = 0b0001_0000;
+
+/// The SM83, the Game Boy's Z80 cousin
+pub struct Cpu {
+    pub a: u8,
+    pub f: u8,
+    pub b: u8,
+    pub c: u8,
+    pub d: u8,
+    pub e: u8,
+    pub h: u8,
+    pub l: u8,
+    pub sp: u16,
+    pub pc: u16,
+    pub ime: bool,
+    /// EI takes effect after the instruction following it
+    ime_pending: bool,
+    pub halted: bool,
+}
+
+impl Cpu {
+    /// Registers as they are when the boot ROM starts
+    pub fn new() -> Self {
+        Self {
+            a: 0,
+            f: 0,
+            b: 0,
+            c: 0,
+            d: 0,
+            e: 0,
+            h: 0,
+            l: 0,
+            sp: 0,
+            pc: 0,
+            ime: false,
+            ime_pending: false,
+            halted: false,
+        }
+    }
+
+    pub fn flag(&self, flag: u8) -> bool {
+        self.f & flag != 0
+    }
+
+    fn set_flag(&mut self, flag: u8, on: bool) {
+        if on {
+            self.f |= flag;
+        } else {
+            self.f &= !flag;
+        }
+    }
+
+    fn set_flags(&mut self, z: bool, n: bool, h: bool, c: bool) {
+        self.f = (z as u8) << 7 | (n as u8) << 6 | (h as u8) << 5 | (c as u8) << 4;
+    }
+
+    pub fn bc(&self) -> u16 {
+        u16::from_be_bytes([self.b, self.c])
+    }
+
+    pub fn de(&self) -> u16 {
+        u16::from_be_bytes([self.d, self.e])
+    }
+
+    pub fn hl(&self) -> u16 {
+        u16::from_be_bytes([self.h, self.l])
+    }
+
+    pub fn af(&self) -> u16 {
+        u16::from_be_bytes([self.a, self.f])
+    }
+
+    fn set_bc(&mut self, value: u16) {
+        [self.b, self.c] = value.to_be_bytes();
+    }
+
+    fn set_de(&mut self, value: u16) {
+        [self.d, self.e] = value.to_be_bytes();
+    }
+
+    fn set_hl(&mut self, value: u16) {
+        [self.h, self.l] = value.to_be_bytes();
+    }
+
+    fn set_af(&mut self, value: u16) {
+        let [a, f] = value.to_be_bytes();
+        self.a = a;
+        // the low nibble of F doesn't exist
+        self.f = f & 0xF0;
+    }
+
+    fn fetch(&mut self, bus: &Bus) -> u8 {
+        let data = bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        data
+    }
+
+    fn fetch16(&mut self, bus: &Bus) -> u16 {
+        let lo = self.fetch(bus);
+        let hi = self.fetch(bus);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    fn push(&mut self, bus: &mut Bus, value: u16) {
+        let [hi, lo] = value.to_be_bytes();
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, hi);
+        self.sp = self.sp.wrapping_sub(1);
+        bus.write(self.sp, lo);
+    }
+
+    fn pop(&mut self, bus: &Bus) -> u16 {
+        let lo = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        let hi = bus.read(self.sp);
+        self.sp = self.sp.wrapping_add(1);
+        u16::from_le_bytes([lo, hi])
+    }
+
+    /// B, C, D, E, H, L, (HL), A in opcode order
+    fn reg(&self, bus: &Bus, i: u8) -> u8 {
+        match i {
+            0 => self.b,
+            1 => self.c,
+            2 => self.d,
+            3 => self.e,
+            4 => self.h,
+            5 => self.l,
+            6 => bus.read(self.hl()),
+            _ => self.a,
+        }
+    }
+
+    fn set_reg(&mut self, bus: &mut Bus, i: u8, value: u8) {
+        match i {
+            0 => self.b = value,
+            1 => self.c = value,
+            2 => self.d = value,
+            3 => self.e = value,
+            4 => self.h = value,
+            5 => self.l = value,
+            6 => bus.write(self.hl(), value),
+            _ => self.a = value,
+        }
+    }
+
+    /// BC, DE, HL, SP
+    fn rp(&self, i: u8) -> u16 {
+        match i {
+            0 => self.bc(),
+            1 => self.de(),
+            2 => self.hl(),
+            _ => self.sp,
+        }
+    }
+
+    fn set_rp(&mut self, i: u8, value: u16) {
+        match i {
+            0 => self.set_bc(value),
+            1 => self.set_de(value),
+            2 => self.set_hl(value),
+            _ => self.sp = value,
+        }
+    }
+
+    /// NZ, Z, NC, C
+    fn condition(&self, i: u8) -> bool {
+        match i & 0b11 {
+            0 => !self.flag(FLAG_Z),
+            1 => self.flag(FLAG_Z),
+            2 => !self.flag(FLAG_C),
+            _ => self.flag(FLAG_C),
+        }
+    }
+
+    /// ADD, ADC, SUB, SBC, AND, XOR, OR, CP
+    fn alu(&mut self, op: u8, value: u8) {
+        let a = self.a;
+        let carry = (op == 1 || op == 3) && self.flag(FLAG_C);
+        let c = carry as u8;
+        match op {
+            0 | 1 => {
+                let sum = a as u16 + value as u16 + c as u16;
+                self.a = sum as u8;
+                self.set_flags(
+                    self.a == 0,
+                    false,
+                    (a & 0xF) + (value & 0xF) + c > 0xF,
+                    sum > 0xFF,
+                );
+            }
+            2 | 3 | 7 => {
+                let result = a.wrapping_sub(value).wrapping_sub(c);
+                self.set_flags(
+                    result == 0,
+                    true,
+                    (a & 0xF) < (value & 0xF) + c,
+                    (a as u16) < value as u16 + c as u16,
+                );
+                if op != 7 {
+                    self.a = result;
+                }
+            }
+            4 => {
+                self.a &= value;
+                self.set_flags(self.a == 0, false, true, false);
+            }
+            5 => {
+                self.a ^= value;
+                self.set_flags(self.a == 0, false, false, false);
+            }
+            _ => {
+                self.a |= value;
+                self.set_flags(self.a == 0, false, false, false);
+            }
+        }
+    }
+
+    /// The CB prefixed rotates and shifts: RLC, RRC, RL, RR, SLA, SRA, SWAP, SRL
+    fn rotate(&mut self, op: u8, value: u8) -> u8 {
+        let carry = self.flag(FLAG_C) as u8;
+        let (result, out) = match op {
+            0 => (value.rotate_left(1), value >> 7),
+            1 => (value.rotate_right(1), value & 1),
+            2 => (value << 1 | carry, value >> 7),
+            3 => (value >> 1 | carry << 7, value & 1),
+            4 => (value << 1, value >> 7),
+            5 => (value >> 1 | (value & 0x80), value & 1),
+            6 => (value.rotate_left(4), 0),
+            _ => (value >> 1, value & 1),
+        };
+        self.set_flags(result == 0, false, false, out != 0);
+        result
+    }
+
+    /// SP plus a signed byte, flags come from the unsigned low byte add
+    fn sp_offset(&mut self, bus: &Bus) -> u16 {
+        let offset = self.fetch(bus);
+        let sp = self.sp;
+        self.set_flags(
+            false,
+            false,
+            (sp & 0xF) + (offset as u16 & 0xF) > 0xF,
+            (sp & 0xFF) + offset as u16 > 0xFF,
+        );
+        sp.wrapping_add(offset as i8 as u16)
+    }
+
+    fn daa(&mut self) {
+        let mut adjust = 0;
+        let mut carry = self.flag(FLAG_C);
+        if self.flag(FLAG_N) {
+            if self.flag(FLAG_H) {
+                adjust |= 0x06;
+            }
+            if carry {
+                adjust |= 0x60;
+            }
+            self.a = self.a.wrapping_sub(adjust);
+        } else {
+            if self.flag(FLAG_H) || self.a & 0xF > 9 {
+                adjust |= 0x06;
+            }
+            if carry || self.a > 0x99 {
+                adjust |= 0x60;
+                carry = true;
+            }
+            self.a = self.a.wrapping_add(adjust);
+        }
+        self.set_flag(FLAG_Z, self.a == 0);
+        self.set_flag(FLAG_H, false);
+        self.set_flag(FLAG_C, carry);
+    }
+
+    /// Jumps to the highest priority interrupt that's enabled and requested
+    fn interrupt(&mut self, bus: &mut Bus) -> Option<u32> {
+        let pending = bus.interrupt_enable & bus.interrupt_flag & 0x1F;
+        if pending == 0 {
+            return None;
+        }
+        // any pending interrupt ends HALT, even with IME off
+        self.halted = false;
+        if !self.ime {
+            return None;
+        }
+
+        let bit = pending.trailing_zeros() as u16;
+        bus.interrupt_flag &= !(1 << bit);
+        self.ime = false;
+        self.push(bus, self.pc);
+        self.pc = 0x40 + bit * 8;
+        Some(20)
+    }
+
+    /// Runs one instruction (or services an interrupt), returns the T-cycles it took
+    pub fn step(&mut self, bus: &mut Bus) -> u32 {
+        if let Some(cycles) = self.interrupt(bus) {
+            return cycles;
+        }
+        if self.ime_pending {
+            self.ime = true;
+            self.ime_pending = false;
+        }
+        if self.halted {
+            return 4;
+        }
+
+        let opcode = self.fetch(bus);
+        self.execute(bus, opcode)
+    }
+
+    fn execute(&mut self, bus: &mut Bus, opcode: u8) -> u32 {
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 0b111;
+        let z = opcode & 0b111;
+        let p = y >> 1;
+        let q = y & 1;
+
+        match (x, z) {
+            (0, 0) => match y {
+                0 => 4,
+                1 => {
+                    let addr = self.fetch16(bus);
+                    let [hi, lo] = self.sp.to_be_bytes();
+                    bus.write(addr, lo);
+                    bus.write(addr.wrapping_add(1), hi);
+                    20
+                }
+                2 => {
+                    // STOP is two bytes long, and is how the CGB switches speed
+                    self.fetch(bus);
+                    bus.stop();
+                    4
+                }
+                _ => {
+                    let offset = self.fetch(bus) as i8;
+                    if y == 3 || self.condition(y - 4) {
+                        self.pc = self.pc.wrapping_add(offset as u16);
+                        12
+                    } else {
+                        8
+                    }
+                }
+            },
+            (0, 1) => {
+                if q == 0 {
+                    let value = self.fetch16(bus);
+                    self.set_rp(p, value);
+                    12
+                } else {
+                    let hl = self.hl();
+                    let value = self.rp(p);
+                    let (sum, carry) = hl.overflowing_add(value);
+                    self.set_flag(FLAG_N, false);
+                    self.set_flag(FLAG_H, (hl & 0xFFF) + (value & 0xFFF) > 0xFFF);
+                    self.set_flag(FLAG_C, carry);
+                    self.set_hl(sum);
+                    8
+                }
+            }
+            (0, 2) => {
+                let addr = match p {
+                    0 => self.bc(),
+                    1 => self.de(),
+                    _ => self.hl(),
+                };
+                match p {
+                    2 => self.set_hl(addr.wrapping_add(1)),
+                    3 => self.set_hl(addr.wrapping_sub(1)),
+                    _ => {}
+                }
+                if q == 0 {
+                    bus.write(addr, self.a);
+                } else {
+                    self.a = bus.read(addr);
+                }
+                8
+            }
+            (0, 3) => {
+                let value = self.rp(p);
+                let value = if q == 0 {
+                    value.wrapping_add(1)
+                } else {
+                    value.wrapping_sub(1)
+                };
+                self.set_rp(p, value);
+                8
+            }
+            (0, 4) | (0, 5) => {
+                let value = self.reg(bus, y);
+                let result = if z == 4 {
+                    self.set_flag(FLAG_H, value & 0xF == 0xF);
+                    value.wrapping_add(1)
+                } else {
+                    self.set_flag(FLAG_H, value & 0xF == 0);
+                    value.wrapping_sub(1)
+                };
+                self.set_flag(FLAG_Z, result == 0);
+                self.set_flag(FLAG_N, z == 5);
+                self.set_reg(bus, y, result);
+                if y == 6 {
+                    12
+                } else {
+                    4
+                }
+            }
+            (0, 6) => {
+                let value = self.fetch(bus);
+                self.set_reg(bus, y, value);
+                if y == 6 {
+                    12
+                } else {
+                    8
+                }
+            }
+            (0, _) => {
+                match y {
+                    0..=3 => {
+                        // RLCA, RRCA, RLA, RRA are the CB rotates on A, but always clear Z
+                        self.a = self.rotate(y, self.a);
+                        self.set_flag(FLAG_Z, false);
+                    }
+                    4 => self.daa(),
+                    5 => {
+                        self.a = !self.a;
+                        self.set_flag(FLAG_N, true);
+                        self.set_flag(FLAG_H, true);
+                    }
+                    _ => {
+                        let carry = y == 6 || !self.flag(FLAG_C);
+                        self.set_flag(FLAG_N, false);
+                        self.set_flag(FLAG_H, false);
+                        self.set_flag(FLAG_C, carry);
+                    }
+                }
+                4
+            }
+            (1, _) => {
+                if y == 6 && z == 6 {
+                    self.halted = true;
+                    return 4;
+                }
+                let value = self.reg(bus, z);
+                self.set_reg(bus, y, value);
+                if y == 6 || z == 6 {
+                    8
+                } else {
+                    4
+                }
+            }
+            (2, _) => {
+                let value = self.reg(bus, z);
+                self.alu(y, value);
+                if z == 6 {
+                    8
+                } else {
+                    4
+                }
+            }
+            (_, 0) => match y {
+                0..=3 => {
+                    if self.condition(y) {
+                        self.pc = self.pop(bus);
+                        20
+                    } else {
+                        8
+                    }
+                }
+                4 => {
+                    let addr = 0xFF00 | self.fetch(bus) as u16;
+                    bus.write(addr, self.a);
+                    12
+                }
+                5 => {
+                    self.sp = self.sp_offset(bus);
+                    16
+                }
+                6 => {
+                    let addr = 0xFF00 | self.fetch(bus) as u16;
+                    self.a = bus.read(addr);
+                    12
+                }
+                _ => {
+                    let value = self.sp_offset(bus);
+                    self.set_hl(value);
+                    12
+                }
+            },
+            (_, 1) => {
+                if q == 0 {
+                    let value = self.pop(bus);
+                    match p {
+                        3 => self.set_af(value),
+                        _ => self.set_rp(p, value),
+                    }
+                    12
+                } else {
+                    match p {
+                        0 | 1 => {
+                            self.pc = self.pop(bus);
+                            if p == 1 {
+                                self.ime = true;
+                            }
+                            16
+                        }
+                        2 => {
+                            self.pc = self.hl();
+                            4
+                        }
+                        _ => {
+                            self.sp = self.hl();
+                            8
+                        }
+                    }
+                }
+            }
+            (_, 2) => match y {
+                0..=3 => {
+                    let addr = self.fetch16(bus);
+                    if self.condition(y) {
+                        self.pc = addr;
+                        16
+                    } else {
+                        12
+                    }
+                }
+                4 => {
+                    bus.write(0xFF00 | self.c as u16, self.a);
+                    8
+                }
+                5 => {
+                    let addr = self.fetch16(bus);
+                    bus.write(addr, self.a);
+                    16
+                }
+                6 => {
+                    self.a = bus.read(0xFF00 | self.c as u16);
+                    8
+                }
+                _ => {
+                    let addr = self.fetch16(bus);
+                    self.a = bus.read(addr);
+                    16
+                }
+            },
+            (_, 3) => match y {
+                0 => {
+                    self.pc = self.fetch16(bus);
+                    16
+                }
+                1 => {
+                    let opcode = self.fetch(bus);
+                    self.execute_cb(bus, opcode)
+                }
+                6 => {
+                    self.ime = false;
+                    self.ime_pending = false;
+                    4
+                }
+                7 => {
+                    self.ime_pending = true;
+                    4
+                }
+                // the rest lock up real hardware
+                _ => 4,
+            },
+            (_, 4) => {
+                if y > 3 {
+                    return 4;
+                }
+                let addr = self.fetch16(bus);
+                if self.condition(y) {
+                    self.push(bus, self.pc);
+                    self.pc = addr;
+                    24
+                } else {
+                    12
+                }
+            }
+            (_, 5) => {
+                if q == 0 {
+                    let value = match p {
+                        3 => self.af(),
+                        _ => self.rp(p),
+                    };
+                    self.push(bus, value);
+                    16
+                } else if p == 0 {
+                    let addr = self.fetch16(bus);
+                    self.push(bus, self.pc);
+                    self.pc = addr;
+                    24
+                } else {
+                    4
+                }
+            }
+            (_, 6) => {
+                let value = self.fetch(bus);
+                self.alu(y, value);
+                8
+            }
+            _ => {
+                self.push(bus, self.pc);
+                self.pc = y as u16 * 8;
+                16
+            }
+        }
+    }
+
+    fn execute_cb(&mut self, bus: &mut Bus, opcode: u8) -> u32 {
+        let x = opcode >> 6;
+        let y = (opcode >> 3) & 0b111;
+        let z = opcode & 0b111;
+        let value = self.reg(bus, z);
+
+        match x {
+            0 => {
+                let result = self.rotate(y, value);
+                self.set_reg(bus, z, result);
+            }
+            1 => {
+                self.set_flag(FLAG_Z, value & (1 << y) == 0);
+                self.set_flag(FLAG_N, false);
+                self.set_flag(FLAG_H, true);
+                return if z == 6 { 12 } else { 8 };
+            }
+            2 => self.set_reg(bus, z, value & !(1 << y)),
+            _ => self.set_reg(bus, z, value | (1 << y)),
+        }
+
+        if z == 6 {
+            16
+        } else {
+            8
+        }
+    }
+}
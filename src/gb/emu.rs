@@ -0,0 +1,144 @@
+use alloc::{boxed::Box, string::String};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::Rgb565,
+    primitives::Rectangle,
+};
+
+use crate::input::InputStatus;
+
+use super::{
+    boot::{Boot, BootMode, Model, PostBootState, BOOT_OFF},
+    bus::{Bus, DMA},
+    cartridge::Rom,
+    cgb::HDMA5,
+    cpu::Cpu,
+    link::Disconnected,
+    ppu::{HEIGHT, LY, WIDTH},
+};
+
+const SCREEN_WIDTH: u32 = 160;
+const SCREEN_HEIGHT: u32 = 128;
+
+/// The Game Boy is 16 lines taller than the Sprig, so 8 are cut off the top and bottom
+const CROP: usize = (HEIGHT - SCREEN_HEIGHT as usize) / 2;
+
+pub struct GbEmulator {
+    pub cpu: Cpu,
+    pub bus: Bus,
+    rom: Rom,
+    boot: BootMode,
+}
+
+impl GbEmulator {
+    pub fn with_rom(rom: Rom, boot: BootMode) -> Result<Self, String> {
+        let (cpu, bus) = Self::power_on(&rom, boot)?;
+        Ok(Self {
+            cpu,
+            bus,
+            rom,
+            boot,
+        })
+    }
+
+    fn model(rom: &Rom) -> Model {
+        if rom.is_cgb() {
+            Model::Cgb
+        } else {
+            Model::Dmg
+        }
+    }
+
+    fn power_on(rom: &Rom, boot: BootMode) -> Result<(Cpu, Bus), String> {
+        let mut cpu = Cpu::new();
+        match Boot::resolve(boot, Self::model(rom), rom.header_checksum) {
+            // the boot ROM sets everything up itself, starting from $0000
+            Boot::Rom(boot_rom) => Ok((cpu, Bus::new(rom, Some(boot_rom))?)),
+            Boot::State(state) => {
+                let mut bus = Bus::new(rom, None)?;
+                Self::apply(&mut cpu, &mut bus, &state);
+                Ok((cpu, bus))
+            }
+        }
+    }
+
+    fn apply(cpu: &mut Cpu, bus: &mut Bus, state: &PostBootState) {
+        cpu.a = state.a;
+        cpu.f = state.f;
+        cpu.b = state.b;
+        cpu.c = state.c;
+        cpu.d = state.d;
+        cpu.e = state.e;
+        cpu.h = state.h;
+        cpu.l = state.l;
+        cpu.sp = state.sp;
+        cpu.pc = state.pc;
+
+        for &(addr, data) in state.io {
+            // LY is read only, DMA and HDMA5 would start a copy and there is no boot ROM to unmap
+            if !matches!(addr, LY | DMA | HDMA5 | BOOT_OFF) {
+                bus.write(addr, data);
+            }
+        }
+    }
+
+    /// The reset button, the cartridge RAM is kept like it would be on a real cartridge
+    pub fn reset(&mut self) {
+        // a ROM that started is known to be supported, so this can't fail
+        if let Ok((cpu, mut bus)) = Self::power_on(&self.rom, self.boot) {
+            core::mem::swap(&mut bus.mbc, &mut self.bus.mbc);
+            bus.mbc.reset();
+            // and the link cable stays plugged in
+            bus.serial
+                .set_link(self.bus.serial.set_link(Box::new(Disconnected)));
+            self.bus = bus;
+            self.cpu = cpu;
+        }
+    }
+
+    pub fn set_input(&mut self, input: &InputStatus) {
+        // directions in the low nibble, buttons in the high one
+        let buttons = [
+            &input.right,
+            &input.left,
+            &input.up,
+            &input.down,
+            &input.a,
+            &input.b,
+            &input.select,
+            &input.start,
+        ];
+        let keys = buttons
+            .iter()
+            .enumerate()
+            .fold(0, |keys, (i, button)| keys | (button.pressed as u8) << i);
+        self.bus.set_keys(keys);
+    }
+
+    /// Runs until the start of the next vblank
+    pub fn run_frame(&mut self) {
+        self.bus.ppu.frame_ready = false;
+
+        while !self.bus.ppu.frame_ready {
+            let cycles = if self.bus.stall > 0 {
+                core::mem::take(&mut self.bus.stall)
+            } else {
+                self.cpu.step(&mut self.bus)
+            };
+            self.bus.tick(cycles);
+        }
+    }
+
+    /// Draws the last frame, cropped to the Sprig's screen
+    pub fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let rows = &self.bus.ppu.frame[CROP * WIDTH..(HEIGHT - CROP) * WIDTH];
+        display.fill_contiguous(
+            &Rectangle::new(Point::zero(), Size::new(SCREEN_WIDTH, SCREEN_HEIGHT)),
+            rows.iter().copied(),
+        )
+    }
+}
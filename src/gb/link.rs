@@ -0,0 +1,293 @@
+/// The other end of the link cable. Nothing here may block, the emulator calls it between
+/// instructions.
+pub trait Link {
+    /// Called when we start driving the clock, sends `data` to the other side
+    fn start(&mut self, data: u8);
+
+    /// The byte shifted in from the other side for the transfer `start` began, or `None` if it
+    /// hasn't answered yet
+    fn finish(&mut self) -> Option<u8>;
+
+    /// Called while waiting on an external clock. When the other side has started a transfer
+    /// `reply` is sent back and the byte they sent is returned.
+    fn poll(&mut self, reply: u8) -> Option<u8>;
+}
+
+/// Nothing plugged in, the input line is pulled high
+pub struct Disconnected;
+
+impl Link for Disconnected {
+    fn start(&mut self, _data: u8) {}
+
+    fn finish(&mut self) -> Option<u8> {
+        Some(0xFF)
+    }
+
+    fn poll(&mut self, _reply: u8) -> Option<u8> {
+        None
+    }
+}
+
+/// SO wired straight into SI, so everything sent comes straight back
+pub struct Loopback {
+    sent: Option<u8>,
+}
+
+impl Loopback {
+    pub fn new() -> Self {
+        Self { sent: None }
+    }
+}
+
+impl Link for Loopback {
+    fn start(&mut self, data: u8) {
+        self.sent = Some(data);
+    }
+
+    fn finish(&mut self) -> Option<u8> {
+        self.sent.take()
+    }
+
+    fn poll(&mut self, _reply: u8) -> Option<u8> {
+        None
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+pub use host::*;
+
+#[cfg(target_arch = "x86_64")]
+mod host {
+    use std::{
+        boxed::Box,
+        fs::File,
+        io::{self, ErrorKind, Read, Write},
+        net::{TcpListener, TcpStream},
+        os::unix::net::{UnixListener, UnixStream},
+        string::String,
+    };
+
+    use super::{Link, Loopback};
+
+    // message tags, so both sides know who drove the clock
+    const MASTER: u8 = 0x01;
+    const SLAVE: u8 = 0x02;
+
+    /// Loopback which also appends everything sent to a file
+    pub struct FileLink {
+        loopback: Loopback,
+        file: File,
+    }
+
+    impl FileLink {
+        pub fn create(path: &str) -> io::Result<Self> {
+            Ok(Self {
+                loopback: Loopback::new(),
+                file: File::create(path)?,
+            })
+        }
+    }
+
+    impl Link for FileLink {
+        fn start(&mut self, data: u8) {
+            // a failed write shouldn't stop the game
+            let _ = self.file.write_all(&[data]);
+            self.loopback.start(data)
+        }
+
+        fn finish(&mut self) -> Option<u8> {
+            self.loopback.finish()
+        }
+
+        fn poll(&mut self, reply: u8) -> Option<u8> {
+            self.loopback.poll(reply)
+        }
+    }
+
+    pub trait LinkStream: Read + Write + Send {
+        fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()>;
+    }
+
+    impl LinkStream for TcpStream {
+        fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+            TcpStream::set_nonblocking(self, nonblocking)
+        }
+    }
+
+    impl LinkStream for UnixStream {
+        fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+            UnixStream::set_nonblocking(self, nonblocking)
+        }
+    }
+
+    /// Link cable between two emulator processes on the same machine. Every byte is sent as a
+    /// `[tag, data]` pair, the side driving the clock sends `MASTER` and the other side answers
+    /// with `SLAVE` and its own byte.
+    pub struct SocketLink {
+        stream: Box<dyn LinkStream>,
+        /// Both sides started a transfer at once, each gets the other's byte
+        swapped: Option<u8>,
+    }
+
+    impl SocketLink {
+        pub fn new(stream: Box<dyn LinkStream>) -> io::Result<Self> {
+            stream.set_nonblocking(true)?;
+            Ok(Self {
+                stream,
+                swapped: None,
+            })
+        }
+
+        /// Waits for the other emulator to connect
+        pub fn listen_tcp(addr: &str) -> io::Result<Self> {
+            let (stream, _) = TcpListener::bind(addr)?.accept()?;
+            stream.set_nodelay(true)?;
+            Self::new(Box::new(stream))
+        }
+
+        pub fn connect_tcp(addr: &str) -> io::Result<Self> {
+            let stream = TcpStream::connect(addr)?;
+            stream.set_nodelay(true)?;
+            Self::new(Box::new(stream))
+        }
+
+        /// Waits for the other emulator to connect, replacing a stale socket file
+        pub fn listen_unix(path: &str) -> io::Result<Self> {
+            let _ = std::fs::remove_file(path);
+            let (stream, _) = UnixListener::bind(path)?.accept()?;
+            Self::new(Box::new(stream))
+        }
+
+        pub fn connect_unix(path: &str) -> io::Result<Self> {
+            Self::new(Box::new(UnixStream::connect(path)?))
+        }
+
+        fn try_read(&mut self) -> Option<(u8, u8)> {
+            let mut buf = [0; 2];
+            match self.stream.read(&mut buf[..1]) {
+                Ok(1) => {}
+                _ => return None,
+            }
+            // the data byte is sent right behind the tag, so block until it's there
+            self.stream.set_nonblocking(false).ok()?;
+            let result = self.stream.read_exact(&mut buf[1..]);
+            self.stream.set_nonblocking(true).ok()?;
+            result.ok().map(|_| (buf[0], buf[1]))
+        }
+
+        fn send(&mut self, tag: u8, data: u8) {
+            // the stream is non blocking, but two bytes will always fit in the socket buffer
+            let _ = self.stream.write_all(&[tag, data]);
+            let _ = self.stream.flush();
+        }
+    }
+
+    impl Link for SocketLink {
+        fn start(&mut self, data: u8) {
+            // a reply that came in after the last transfer gave up on it belongs to that one,
+            // taking it as the answer to this one would put every byte after it one behind
+            self.swapped = None;
+            while let Some((tag, received)) = self.try_read() {
+                if tag == MASTER {
+                    self.swapped = Some(received);
+                }
+            }
+            self.send(MASTER, data);
+        }
+
+        fn finish(&mut self) -> Option<u8> {
+            if let Some(received) = self.swapped.take() {
+                return Some(received);
+            }
+            match self.try_read() {
+                // a MASTER here means both sides started a transfer at once, treat it as a swap
+                Some((SLAVE | MASTER, received)) => Some(received),
+                _ => None,
+            }
+        }
+
+        fn poll(&mut self, reply: u8) -> Option<u8> {
+            let received = match self.try_read() {
+                Some((MASTER, received)) => received,
+                _ => return None,
+            };
+
+            self.send(SLAVE, reply);
+            Some(received)
+        }
+    }
+
+    /// Builds a link from a spec like `tcp-listen:127.0.0.1:7777`, `tcp:127.0.0.1:7777`,
+    /// `unix-listen:/tmp/egb.sock`, `unix:/tmp/egb.sock`, `file:serial.txt` or `loopback`
+    pub fn from_spec(spec: &str) -> io::Result<Box<dyn Link>> {
+        let (kind, arg) = spec.split_once(':').unwrap_or((spec, ""));
+        Ok(match kind {
+            "loopback" => Box::new(Loopback::new()),
+            "file" => Box::new(FileLink::create(arg)?),
+            "tcp-listen" => Box::new(SocketLink::listen_tcp(arg)?),
+            "tcp" => Box::new(SocketLink::connect_tcp(arg)?),
+            "unix-listen" => Box::new(SocketLink::listen_unix(arg)?),
+            "unix" => Box::new(SocketLink::connect_unix(arg)?),
+            _ => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    String::from("unknown link type ") + kind,
+                ))
+            }
+        })
+    }
+
+    /// Reads the link spec from `EGB_LINK`, defaulting to an unplugged cable
+    pub fn from_env() -> io::Result<Option<Box<dyn Link>>> {
+        match std::env::var("EGB_LINK") {
+            Ok(spec) => from_spec(&spec).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use super::*;
+
+        #[test]
+        fn test_socket_exchange() {
+            let (a, b) = UnixStream::pair().unwrap();
+            let mut master = SocketLink::new(Box::new(a)).unwrap();
+            let mut slave = SocketLink::new(Box::new(b)).unwrap();
+
+            master.start(0xAA);
+            // nothing waits for the answer
+            assert_eq!(master.finish(), None);
+            assert_eq!(slave.poll(0x55), Some(0xAA));
+            assert_eq!(master.finish(), Some(0x55));
+        }
+
+        #[test]
+        fn test_late_reply() {
+            let (a, b) = UnixStream::pair().unwrap();
+            let mut master = SocketLink::new(Box::new(a)).unwrap();
+            let mut slave = SocketLink::new(Box::new(b)).unwrap();
+
+            // the master gives up on the first byte before the slave gets to it
+            master.start(0x01);
+            assert_eq!(master.finish(), None);
+            assert_eq!(slave.poll(0x10), Some(0x01));
+
+            master.start(0x02);
+            assert_eq!(slave.poll(0x20), Some(0x02));
+            assert_eq!(master.finish(), Some(0x20));
+        }
+
+        #[test]
+        fn test_both_masters() {
+            let (a, b) = UnixStream::pair().unwrap();
+            let mut left = SocketLink::new(Box::new(a)).unwrap();
+            let mut right = SocketLink::new(Box::new(b)).unwrap();
+
+            left.start(0x0A);
+            right.start(0x0B);
+            assert_eq!(left.finish(), Some(0x0B));
+            assert_eq!(right.finish(), Some(0x0A));
+        }
+    }
+}
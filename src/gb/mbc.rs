@@ -0,0 +1,175 @@
+use alloc::{format, string::String, vec, vec::Vec};
+
+use super::cartridge::Rom;
+
+const RAM_SIZE: usize = 0x149;
+
+/// The memory bank controller on the cartridge, https://gbdev.io/pandocs/MBCs.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Kind {
+    None,
+    Mbc1,
+    Mbc2,
+    Mbc3,
+    Mbc5,
+}
+
+pub struct Mbc {
+    kind: Kind,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rom_bank: usize,
+    /// RAM bank, or the upper ROM bank bits in MBC1's second mode
+    ram_bank: usize,
+    ram_enabled: bool,
+    /// MBC1's banking mode, 1 lets the upper bits bank RAM and the $0000 area
+    mode: u8,
+}
+
+impl Mbc {
+    pub fn new(rom: &Rom) -> Result<Self, String> {
+        let kind = match rom.cartridge_type {
+            0x00 | 0x08 | 0x09 => Kind::None,
+            0x01..=0x03 => Kind::Mbc1,
+            0x05 | 0x06 => Kind::Mbc2,
+            0x0F..=0x13 => Kind::Mbc3,
+            0x19..=0x1E => Kind::Mbc5,
+            kind => return Err(format!("Unsupported cartridge type {:#04x}", kind)),
+        };
+
+        let ram = match kind {
+            // MBC2 has 512 half bytes built in
+            Kind::Mbc2 => 0x200,
+            _ => match rom.data[RAM_SIZE] {
+                0x02 => 0x2000,
+                0x03 => 0x8000,
+                0x04 => 0x20000,
+                0x05 => 0x10000,
+                _ => 0,
+            },
+        };
+
+        // pad to a whole number of banks so out of range banks can just wrap
+        let mut data = rom.data.clone();
+        let banks = data.len().div_ceil(0x4000).next_power_of_two().max(2);
+        data.resize(banks * 0x4000, 0xFF);
+
+        Ok(Self {
+            kind,
+            rom: data,
+            ram: vec![0; ram],
+            rom_bank: 1,
+            ram_bank: 0,
+            // without an MBC the RAM is always there
+            ram_enabled: kind == Kind::None,
+            mode: 0,
+        })
+    }
+
+    /// Back to the power on banks, the RAM is battery backed so it stays
+    pub fn reset(&mut self) {
+        self.rom_bank = 1;
+        self.ram_bank = 0;
+        self.ram_enabled = self.kind == Kind::None;
+        self.mode = 0;
+    }
+
+    fn rom_banks(&self) -> usize {
+        self.rom.len() / 0x4000
+    }
+
+    fn read_rom(&self, bank: usize, addr: u16) -> u8 {
+        let bank = bank % self.rom_banks();
+        self.rom[bank * 0x4000 + (addr as usize & 0x3FFF)]
+    }
+
+    fn is_clock(&self) -> bool {
+        self.kind == Kind::Mbc3 && self.ram_enabled && self.ram_bank >= 8
+    }
+
+    fn ram_offset(&self, addr: u16) -> Option<usize> {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return None;
+        }
+        let offset = match self.kind {
+            Kind::Mbc2 => addr as usize & 0x1FF,
+            Kind::Mbc1 if self.mode == 0 => addr as usize & 0x1FFF,
+            _ => self.ram_bank * 0x2000 + (addr as usize & 0x1FFF),
+        };
+        Some(offset % self.ram.len())
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x3FFF => {
+                let bank = match self.kind {
+                    Kind::Mbc1 if self.mode == 1 => self.ram_bank << 5,
+                    _ => 0,
+                };
+                self.read_rom(bank, addr)
+            }
+            0x4000..=0x7FFF => {
+                let bank = match self.kind {
+                    Kind::Mbc1 => self.ram_bank << 5 | self.rom_bank,
+                    _ => self.rom_bank,
+                };
+                self.read_rom(bank, addr)
+            }
+            // the MBC3 clock registers aren't emulated, they read as 0
+            0xA000..=0xBFFF if self.is_clock() => 0,
+            0xA000..=0xBFFF => match self.ram_offset(addr) {
+                // MBC2 RAM is only 4 bits wide
+                Some(offset) if self.kind == Kind::Mbc2 => 0xF0 | self.ram[offset],
+                Some(offset) => self.ram[offset],
+                None => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match (self.kind, addr) {
+            (Kind::None, 0xA000..=0xBFFF) => {
+                if let Some(offset) = self.ram_offset(addr) {
+                    self.ram[offset] = data;
+                }
+            }
+            (Kind::None, _) => {}
+
+            // MBC2 uses bit 8 of the address to pick between RAM enable and the ROM bank
+            (Kind::Mbc2, 0x0000..=0x3FFF) => {
+                if addr & 0x100 == 0 {
+                    self.ram_enabled = data & 0x0F == 0x0A;
+                } else {
+                    self.rom_bank = (data as usize & 0x0F).max(1);
+                }
+            }
+
+            (_, 0x0000..=0x1FFF) => self.ram_enabled = data & 0x0F == 0x0A,
+            (Kind::Mbc1, 0x2000..=0x3FFF) => self.rom_bank = (data as usize & 0x1F).max(1),
+            (Kind::Mbc3, 0x2000..=0x3FFF) => self.rom_bank = (data as usize & 0x7F).max(1),
+            (Kind::Mbc5, 0x2000..=0x2FFF) => {
+                self.rom_bank = (self.rom_bank & 0x100) | data as usize;
+            }
+            (Kind::Mbc5, 0x3000..=0x3FFF) => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (data as usize & 1) << 8;
+            }
+            (Kind::Mbc1, 0x4000..=0x5FFF) => self.ram_bank = data as usize & 0b11,
+            // 8-$C select the clock registers instead of a RAM bank
+            (Kind::Mbc3, 0x4000..=0x5FFF) => self.ram_bank = data as usize & 0x0F,
+            (Kind::Mbc5, 0x4000..=0x5FFF) => self.ram_bank = data as usize & 0x0F,
+            (Kind::Mbc1, 0x6000..=0x7FFF) => self.mode = data & 1,
+            (_, 0xA000..=0xBFFF) if self.is_clock() => {}
+            (_, 0xA000..=0xBFFF) => {
+                if let Some(offset) = self.ram_offset(addr) {
+                    self.ram[offset] = if self.kind == Kind::Mbc2 {
+                        data & 0x0F
+                    } else {
+                        data
+                    };
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,169 @@
+pub mod boot;
+#[cfg(target_arch = "x86_64")]
+pub mod bus;
+pub mod cartridge;
+pub mod cgb;
+#[cfg(target_arch = "x86_64")]
+pub mod cpu;
+#[cfg(target_arch = "x86_64")]
+pub mod emu;
+pub mod link;
+#[cfg(target_arch = "x86_64")]
+pub mod mbc;
+#[cfg(target_arch = "x86_64")]
+pub mod ppu;
+pub mod serial;
+#[cfg(target_arch = "x86_64")]
+pub mod timer;
+
+#[cfg(test)]
+mod test {
+    use alloc::{vec, vec::Vec};
+    use embedded_graphics::pixelcolor::Rgb565;
+
+    use super::{
+        boot::{BootMode, BootRom, BOOT_OFF},
+        bus::Bus,
+        cartridge::Rom,
+        cpu::Cpu,
+        emu::GbEmulator,
+    };
+
+    /// A 32K cartridge with no MBC, `code` at the entry point and `handlers` at their address
+    fn rom(cgb: bool, code: &[u8], handlers: &[(usize, &[u8])]) -> Rom {
+        let mut data: Vec<u8> = vec![0; 0x8000];
+        data[0x100..0x100 + code.len()].copy_from_slice(code);
+        for (addr, handler) in handlers {
+            data[*addr..*addr + handler.len()].copy_from_slice(handler);
+        }
+        if cgb {
+            data[0x143] = 0x80;
+        }
+        Rom::new(&data).unwrap()
+    }
+
+    fn emu(cgb: bool, code: &[u8], handlers: &[(usize, &[u8])]) -> GbEmulator {
+        GbEmulator::with_rom(rom(cgb, code, handlers), BootMode::Skip).unwrap()
+    }
+
+    #[test]
+    fn test_program() {
+        let mut emu = emu(
+            false,
+            &[
+                0x3E, 0x42, // ld a, $42
+                0xEA, 0x00, 0xC0, // ld ($C000), a
+                0x06, 0x05, // ld b, 5
+                0x3C, // inc a
+                0x05, // dec b
+                0x20, 0xFC, // jr nz, -4
+                0xEA, 0x01, 0xC0, // ld ($C001), a
+                0x18, 0xFE, // jr -2
+            ],
+            &[],
+        );
+        emu.run_frame();
+        assert_eq!(emu.bus.read(0xC000), 0x42);
+        assert_eq!(emu.bus.read(0xC001), 0x47);
+        // echo RAM
+        assert_eq!(emu.bus.read(0xE001), 0x47);
+    }
+
+    #[test]
+    fn test_timer_interrupt() {
+        let mut emu = emu(
+            false,
+            &[
+                0x3E, 0x04, // ld a, 4
+                0xE0, 0xFF, // ldh (IE), a
+                0x3E, 0x05, // ld a, 5
+                0xE0, 0x07, // ldh (TAC), a
+                0xFB, // ei
+                0x76, // halt
+                0x18, 0xFD, // jr -3
+            ],
+            // ld hl, $C000; inc (hl); reti
+            &[(0x50, &[0x21, 0x00, 0xC0, 0x34, 0xD9])],
+        );
+        emu.run_frame();
+        // TIMA overflows every 4096 cycles at 262144 Hz, 17 times a frame
+        let count = emu.bus.read(0xC000);
+        assert!((16..=18).contains(&count), "{} interrupts", count);
+    }
+
+    #[test]
+    fn test_dmg_background() {
+        let mut emu = emu(false, &[0x18, 0xFE], &[]);
+        // tile 0 all colour 3, which BGP shows as black
+        for addr in 0x8000..0x8010 {
+            emu.bus.write(addr, 0xFF);
+        }
+        emu.run_frame();
+        emu.run_frame();
+        assert_eq!(emu.bus.ppu.frame[0], Rgb565::new(0, 0, 0));
+
+        emu.bus.write(super::ppu::BGP, 0x00);
+        emu.run_frame();
+        assert_eq!(emu.bus.ppu.frame[0], Rgb565::new(31, 63, 31));
+    }
+
+    #[test]
+    fn test_cgb_palettes() {
+        let mut emu = emu(true, &[0x18, 0xFE], &[]);
+        assert!(emu.bus.cgb_mode);
+        // palette 0 colour 0 red, through the auto incrementing data register
+        emu.bus.write(super::cgb::BCPS, 0x80);
+        emu.bus.write(super::cgb::BCPD, 0x1F);
+        emu.bus.write(super::cgb::BCPD, 0x00);
+        emu.run_frame();
+        emu.run_frame();
+        assert_eq!(emu.bus.ppu.frame[0], Rgb565::new(31, 0, 0));
+
+        // a DMG game can't reach the CGB registers
+        let mut dmg = self::emu(false, &[0x18, 0xFE], &[]);
+        dmg.bus.write(super::cgb::SVBK, 3);
+        assert_eq!(dmg.bus.read(super::cgb::SVBK), 0xFF);
+    }
+
+    #[test]
+    fn test_boot_rom() {
+        let mut boot = vec![0; 0x100];
+        // ld a, 0; ldh ($50), a; inc a; ldh ($50), a, then NOPs up to the cartridge
+        boot[..7].copy_from_slice(&[0x3E, 0x00, 0xE0, 0x50, 0x3C, 0xE0, 0x50]);
+        let rom = rom(false, &[0x18, 0xFE], &[]);
+        let mut bus = Bus::new(&rom, Some(BootRom::new(&boot).unwrap())).unwrap();
+        let mut cpu = Cpu::new();
+
+        assert_eq!(bus.read(0x0000), 0x3E);
+        for _ in 0..3 {
+            cpu.step(&mut bus);
+        }
+        // writing 0 leaves it mapped
+        assert_eq!(bus.read(BOOT_OFF), 0xFE);
+        assert_eq!(bus.read(0x0000), 0x3E);
+
+        while cpu.pc < 0x100 {
+            cpu.step(&mut bus);
+        }
+        assert_eq!(bus.read(BOOT_OFF), 0xFF);
+        assert_eq!(bus.read(0x0000), 0x00);
+        assert_eq!(bus.read(0x0100), 0x18);
+    }
+
+    #[test]
+    fn test_reset_keeps_ram() {
+        let mut data: Vec<u8> = vec![0; 0x8000];
+        data[0x100..0x102].copy_from_slice(&[0x18, 0xFE]);
+        // MBC1+RAM+BATTERY with 8K of RAM
+        data[0x147] = 0x03;
+        data[0x149] = 0x02;
+        let mut emu = GbEmulator::with_rom(Rom::new(&data).unwrap(), BootMode::Skip).unwrap();
+        emu.bus.write(0x0000, 0x0A);
+        emu.bus.write(0xA000, 0x99);
+        emu.reset();
+        assert_eq!(emu.bus.read(0xA000), 0xFF, "RAM is disabled again");
+        emu.bus.write(0x0000, 0x0A);
+        assert_eq!(emu.bus.read(0xA000), 0x99);
+        assert_eq!(emu.cpu.pc, 0x100);
+    }
+}
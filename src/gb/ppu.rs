@@ -0,0 +1,396 @@
+use alloc::{vec, vec::Vec};
+use embedded_graphics::pixelcolor::Rgb565;
+
+use super::{
+    cgb::{Cgb, TileAttributes},
+    cpu::{INT_STAT, INT_VBLANK},
+};
+
+pub const WIDTH: usize = 160;
+pub const HEIGHT: usize = 144;
+
+pub const LCDC: u16 = 0xFF40;
+pub const STAT: u16 = 0xFF41;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const LY: u16 = 0xFF44;
+pub const LYC: u16 = 0xFF45;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+
+/// # LCD Control https://gbdev.io/pandocs/LCDC.html
+const LCDC_BG: u8 = 1 << 0;
+const LCDC_OBJ: u8 = 1 << 1;
+const LCDC_OBJ_TALL: u8 = 1 << 2;
+const LCDC_BG_MAP: u8 = 1 << 3;
+const LCDC_TILES: u8 = 1 << 4;
+const LCDC_WINDOW: u8 = 1 << 5;
+const LCDC_WINDOW_MAP: u8 = 1 << 6;
+const LCDC_ON: u8 = 1 << 7;
+
+const STAT_LYC: u8 = 1 << 2;
+const STAT_SELECT: u8 = 0b0111_1000;
+
+const OBJ_PRIORITY: u8 = 1 << 7;
+const OBJ_Y_FLIP: u8 = 1 << 6;
+const OBJ_X_FLIP: u8 = 1 << 5;
+const OBJ_PALETTE: u8 = 1 << 4;
+const OBJ_BANK: u8 = 1 << 3;
+
+const OAM_DOTS: u32 = 80;
+const DRAW_DOTS: u32 = 172;
+const LINE_DOTS: u32 = 456;
+const LINES: u8 = 154;
+pub const FRAME_DOTS: u32 = LINE_DOTS * LINES as u32;
+
+const MAX_SPRITES: usize = 10;
+
+/// The DMG's four shades of grey, lightest first
+const SHADES: [Rgb565; 4] = [
+    Rgb565::new(31, 63, 31),
+    Rgb565::new(21, 42, 21),
+    Rgb565::new(10, 21, 10),
+    Rgb565::new(0, 0, 0),
+];
+
+fn shade(palette: u8, color: u8) -> Rgb565 {
+    SHADES[((palette >> (color * 2)) & 0b11) as usize]
+}
+
+/// A scanline renderer, each line is drawn whole when it enters H-Blank
+pub struct Ppu {
+    lcdc: u8,
+    stat: u8,
+    scy: u8,
+    scx: u8,
+    ly: u8,
+    lyc: u8,
+    bgp: u8,
+    obp0: u8,
+    obp1: u8,
+    wy: u8,
+    wx: u8,
+    /// Dots into the current line
+    dots: u32,
+    /// The window has its own line counter that only moves on lines it's drawn on
+    window_line: u8,
+    /// STAT interrupts fire on the rising edge of all the selected sources ORed together
+    stat_line: bool,
+    /// Counts frames while the LCD is off so the emulator still gets a frame every 70224 dots
+    off_dots: u32,
+    pub frame: Vec<Rgb565>,
+    pub frame_ready: bool,
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            lcdc: 0,
+            stat: 0,
+            scy: 0,
+            scx: 0,
+            ly: 0,
+            lyc: 0,
+            bgp: 0,
+            obp0: 0,
+            obp1: 0,
+            wy: 0,
+            wx: 0,
+            dots: 0,
+            window_line: 0,
+            stat_line: false,
+            off_dots: 0,
+            frame: vec![SHADES[0]; WIDTH * HEIGHT],
+            frame_ready: false,
+        }
+    }
+
+    fn is_on(&self) -> bool {
+        self.lcdc & LCDC_ON != 0
+    }
+
+    /// 2 while searching OAM, 3 while drawing, 0 in H-Blank and 1 in V-Blank
+    pub fn mode(&self) -> u8 {
+        if !self.is_on() {
+            0
+        } else if self.ly >= HEIGHT as u8 {
+            1
+        } else if self.dots < OAM_DOTS {
+            2
+        } else if self.dots < OAM_DOTS + DRAW_DOTS {
+            3
+        } else {
+            0
+        }
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            LCDC => self.lcdc,
+            STAT => {
+                let lyc = if self.ly == self.lyc { STAT_LYC } else { 0 };
+                0x80 | self.stat | lyc | self.mode()
+            }
+            SCY => self.scy,
+            SCX => self.scx,
+            LY => self.ly,
+            LYC => self.lyc,
+            BGP => self.bgp,
+            OBP0 => self.obp0,
+            OBP1 => self.obp1,
+            WY => self.wy,
+            WX => self.wx,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            LCDC => {
+                if self.is_on() && data & LCDC_ON == 0 {
+                    // the screen goes blank while the LCD is off
+                    self.ly = 0;
+                    self.dots = 0;
+                    self.off_dots = 0;
+                    self.frame.fill(SHADES[0]);
+                }
+                self.lcdc = data;
+            }
+            STAT => self.stat = data & STAT_SELECT,
+            SCY => self.scy = data,
+            SCX => self.scx = data,
+            // LY is read only
+            LYC => self.lyc = data,
+            BGP => self.bgp = data,
+            OBP0 => self.obp0 = data,
+            OBP1 => self.obp1 = data,
+            WY => self.wy = data,
+            WX => self.wx = data,
+            _ => {}
+        }
+    }
+
+    fn update_stat(&mut self) -> u8 {
+        let mode = self.mode();
+        let line = (self.ly == self.lyc && self.stat & (1 << 6) != 0)
+            || (mode == 0 && self.stat & (1 << 3) != 0)
+            || (mode == 1 && self.stat & (1 << 4) != 0)
+            || (mode == 2 && self.stat & (1 << 5) != 0);
+        let rising = line && !self.stat_line;
+        self.stat_line = line;
+        if rising {
+            INT_STAT
+        } else {
+            0
+        }
+    }
+
+    /// Advances by `dots` (T-cycles at normal speed). Returns the interrupts to request, and
+    /// whether a visible line entered H-Blank, which is when HDMA copies a block.
+    pub fn tick(&mut self, dots: u32, cgb: &Cgb, oam: &[u8], cgb_mode: bool) -> (u8, bool) {
+        if !self.is_on() {
+            self.off_dots += dots;
+            if self.off_dots >= FRAME_DOTS {
+                self.off_dots -= FRAME_DOTS;
+                self.frame_ready = true;
+            }
+            return (0, false);
+        }
+
+        let mut interrupts = 0;
+        let mut hblank = false;
+        let mut remaining = dots;
+        while remaining > 0 {
+            let boundary = match self.mode() {
+                2 => OAM_DOTS,
+                3 => OAM_DOTS + DRAW_DOTS,
+                _ => LINE_DOTS,
+            };
+            let step = remaining.min(boundary - self.dots);
+            self.dots += step;
+            remaining -= step;
+
+            if self.dots == OAM_DOTS + DRAW_DOTS && self.ly < HEIGHT as u8 {
+                self.render_line(cgb, oam, cgb_mode);
+                hblank = true;
+            } else if self.dots == LINE_DOTS {
+                self.dots = 0;
+                self.ly += 1;
+                if self.ly == HEIGHT as u8 {
+                    interrupts |= INT_VBLANK;
+                    self.frame_ready = true;
+                } else if self.ly == LINES {
+                    self.ly = 0;
+                    self.window_line = 0;
+                }
+            }
+            interrupts |= self.update_stat();
+        }
+        (interrupts, hblank)
+    }
+
+    /// The colour index of a pixel in a tile row, `bit` 0 being the leftmost pixel
+    fn tile_pixel(cgb: &Cgb, bank: usize, addr: u16, bit: u8) -> u8 {
+        let lo = cgb.vram.read_bank(bank, addr);
+        let hi = cgb.vram.read_bank(bank, addr + 1);
+        let shift = 7 - bit;
+        ((hi >> shift) & 1) << 1 | ((lo >> shift) & 1)
+    }
+
+    fn render_line(&mut self, cgb: &Cgb, oam: &[u8], cgb_mode: bool) {
+        let ly = self.ly;
+        let row = &mut self.frame[ly as usize * WIDTH..(ly as usize + 1) * WIDTH];
+        // the colour index and BG-to-OAM priority of each background pixel, for the sprites
+        let mut bg = [(0u8, false); WIDTH];
+
+        // on the CGB LCDC bit 0 only takes away the background's priority
+        let bg_on = cgb_mode || self.lcdc & LCDC_BG != 0;
+        let window_on = bg_on && self.lcdc & LCDC_WINDOW != 0 && self.wy <= ly && self.wx <= 166;
+        let mut window_drawn = false;
+
+        for x in 0..WIDTH {
+            if !bg_on {
+                row[x] = shade(self.bgp, 0);
+                continue;
+            }
+
+            let in_window = window_on && x as u16 + 7 >= self.wx as u16;
+            let (map, px, py) = if in_window {
+                window_drawn = true;
+                let map = if self.lcdc & LCDC_WINDOW_MAP != 0 {
+                    0x9C00
+                } else {
+                    0x9800
+                };
+                (map, (x as u16 + 7 - self.wx as u16) as u8, self.window_line)
+            } else {
+                let map = if self.lcdc & LCDC_BG_MAP != 0 {
+                    0x9C00
+                } else {
+                    0x9800
+                };
+                (
+                    map,
+                    (x as u8).wrapping_add(self.scx),
+                    ly.wrapping_add(self.scy),
+                )
+            };
+
+            let map_addr = map + (py as u16 / 8) * 32 + px as u16 / 8;
+            let tile = cgb.vram.read_bank(0, map_addr);
+            let attributes = if cgb_mode {
+                TileAttributes::from_bits_truncate(cgb.vram.read_bank(1, map_addr))
+            } else {
+                TileAttributes::empty()
+            };
+
+            let tile_addr = if self.lcdc & LCDC_TILES != 0 {
+                0x8000 + tile as u16 * 16
+            } else {
+                0x9000u16.wrapping_add((tile as i8 as i16 * 16) as u16)
+            };
+            let mut y = py % 8;
+            if attributes.contains(TileAttributes::Y_FLIP) {
+                y = 7 - y;
+            }
+            let mut bit = px % 8;
+            if attributes.contains(TileAttributes::X_FLIP) {
+                bit = 7 - bit;
+            }
+            let color = Self::tile_pixel(cgb, attributes.bank(), tile_addr + y as u16 * 2, bit);
+
+            row[x] = if cgb_mode {
+                cgb.bg_color(attributes, color)
+            } else {
+                shade(self.bgp, color)
+            };
+            bg[x] = (color, attributes.contains(TileAttributes::PRIORITY));
+        }
+        if window_drawn {
+            self.window_line += 1;
+        }
+
+        if self.lcdc & LCDC_OBJ == 0 {
+            return;
+        }
+
+        let height = if self.lcdc & LCDC_OBJ_TALL != 0 {
+            16
+        } else {
+            8
+        };
+        let mut sprites: Vec<usize> = (0..40)
+            .filter(|i| {
+                let y = oam[i * 4] as i16 - 16;
+                (y..y + height).contains(&(ly as i16))
+            })
+            .take(MAX_SPRITES)
+            .collect();
+        if !cgb_mode {
+            // the DMG draws the leftmost sprite on top, the CGB goes by OAM order
+            sprites.sort_by_key(|i| oam[i * 4 + 1]);
+        }
+
+        let mut drawn = [false; WIDTH];
+        for i in sprites {
+            let sprite = &oam[i * 4..i * 4 + 4];
+            let (sy, sx, mut tile, flags) = (
+                sprite[0] as i16 - 16,
+                sprite[1] as i16 - 8,
+                sprite[2],
+                sprite[3],
+            );
+            let mut y = ly as i16 - sy;
+            if flags & OBJ_Y_FLIP != 0 {
+                y = height - 1 - y;
+            }
+            if height == 16 {
+                tile &= 0xFE;
+            }
+            let addr = 0x8000 + tile as u16 * 16 + y as u16 * 2;
+            let bank = if cgb_mode && flags & OBJ_BANK != 0 {
+                1
+            } else {
+                0
+            };
+
+            for px in 0..8 {
+                let x = sx + px;
+                if !(0..WIDTH as i16).contains(&x) || drawn[x as usize] {
+                    continue;
+                }
+                let bit = if flags & OBJ_X_FLIP != 0 { 7 - px } else { px };
+                let color = Self::tile_pixel(cgb, bank, addr, bit as u8);
+                if color == 0 {
+                    continue;
+                }
+                // the first sprite with a pixel here takes it, even if the background hides it
+                let x = x as usize;
+                drawn[x] = true;
+
+                let (bg_color, bg_priority) = bg[x];
+                let hidden = if cgb_mode {
+                    self.lcdc & LCDC_BG != 0
+                        && bg_color != 0
+                        && (bg_priority || flags & OBJ_PRIORITY != 0)
+                } else {
+                    flags & OBJ_PRIORITY != 0 && bg_color != 0
+                };
+                if hidden {
+                    continue;
+                }
+
+                row[x] = if cgb_mode {
+                    cgb.obj_color(flags, color)
+                } else if flags & OBJ_PALETTE != 0 {
+                    shade(self.obp1, color)
+                } else {
+                    shade(self.obp0, color)
+                };
+            }
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use alloc::boxed::Box;
+
+use super::link::{Disconnected, Link};
+
+pub const SB: u16 = 0xFF01;
+pub const SC: u16 = 0xFF02;
+
+const TRANSFER_START: u8 = 0b1000_0000;
+const CLOCK_SPEED: u8 = 0b0000_0010;
+const INTERNAL_CLOCK: u8 = 0b0000_0001;
+
+// 8192 Hz, or 262144 Hz with the CGB fast clock bit set
+const CYCLES_PER_BIT: u32 = 512;
+const FAST_CYCLES_PER_BIT: u32 = 16;
+
+/// How long past the end of a transfer we wait for the other side to answer, a frame
+const LINK_TIMEOUT: u32 = 70_224;
+
+/// The serial port, shifting SB out to (and in from) whatever is on the other end of the link
+pub struct Serial {
+    data: u8,
+    control: u8,
+    counter: u32,
+    cgb: bool,
+    link: Box<dyn Link>,
+}
+
+impl Serial {
+    pub fn new(cgb: bool) -> Self {
+        Self::with_link(cgb, Box::new(Disconnected))
+    }
+
+    pub fn with_link(cgb: bool, link: Box<dyn Link>) -> Self {
+        Self {
+            data: 0,
+            control: 0,
+            counter: 0,
+            cgb,
+            link,
+        }
+    }
+
+    /// Plugs in another link, returning the one that was there
+    pub fn set_link(&mut self, link: Box<dyn Link>) -> Box<dyn Link> {
+        core::mem::replace(&mut self.link, link)
+    }
+
+    pub fn is_transferring(&self) -> bool {
+        self.control & TRANSFER_START != 0
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            SB => self.data,
+            // the clock speed bit only exists on the CGB
+            SC if self.cgb => self.control | 0b0111_1100,
+            SC => self.control | 0b0111_1110,
+            _ => 0xFF,
+        }
+    }
+
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            SB => self.data = data,
+            SC => {
+                self.control = if self.cgb {
+                    data & (TRANSFER_START | CLOCK_SPEED | INTERNAL_CLOCK)
+                } else {
+                    data & (TRANSFER_START | INTERNAL_CLOCK)
+                };
+                self.counter = 0;
+                if self.is_transferring() && self.control & INTERNAL_CLOCK != 0 {
+                    self.link.start(self.data);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn cycles_per_transfer(&self) -> u32 {
+        let per_bit = if self.control & CLOCK_SPEED != 0 {
+            FAST_CYCLES_PER_BIT
+        } else {
+            CYCLES_PER_BIT
+        };
+        per_bit * 8
+    }
+
+    /// Advances the port by `cycles` T-cycles, returns true when the serial interrupt should be requested
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        if !self.is_transferring() {
+            return false;
+        }
+
+        let received = if self.control & INTERNAL_CLOCK != 0 {
+            // we drive the clock, so the byte is swapped once all 8 bits have been shifted
+            self.counter += cycles;
+            if self.counter < self.cycles_per_transfer() {
+                return false;
+            }
+            match self.link.finish() {
+                Some(received) => received,
+                // the other side is a bit behind, give it a frame before reading an open line
+                None if self.counter < self.cycles_per_transfer() + LINK_TIMEOUT => return false,
+                None => 0xFF,
+            }
+        } else {
+            // external clock, nothing happens until the other side starts a transfer
+            match self.link.poll(self.data) {
+                Some(received) => received,
+                None => return false,
+            }
+        };
+
+        self.data = received;
+        self.control &= !TRANSFER_START;
+        self.counter = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::String;
+
+    use super::*;
+    use crate::gb::link::Loopback;
+
+    #[test]
+    fn test_internal_clock_transfer() {
+        let mut serial = Serial::with_link(false, Box::new(Loopback::new()));
+        serial.write(SB, b'P');
+        serial.write(SC, 0x81);
+
+        assert!(!serial.tick(4095));
+        assert!(serial.is_transferring());
+        assert!(serial.tick(1));
+        assert!(!serial.is_transferring());
+        assert_eq!(serial.read(SB), b'P');
+        assert_eq!(serial.read(SC), 0x7F);
+    }
+
+    #[test]
+    fn test_blargg_output() {
+        let mut serial = Serial::with_link(false, Box::new(Loopback::new()));
+        let mut output = String::new();
+        for c in "Passed".bytes() {
+            serial.write(SB, c);
+            serial.write(SC, 0x81);
+            while !serial.tick(64) {}
+            output.push(serial.read(SB) as char);
+        }
+
+        assert_eq!(output, "Passed");
+    }
+
+    /// Plugged in, but the other side never answers
+    struct Silent;
+
+    impl Link for Silent {
+        fn start(&mut self, _data: u8) {}
+
+        fn finish(&mut self) -> Option<u8> {
+            None
+        }
+
+        fn poll(&mut self, _reply: u8) -> Option<u8> {
+            None
+        }
+    }
+
+    #[test]
+    fn test_link_timeout() {
+        let mut serial = Serial::with_link(false, Box::new(Silent));
+        serial.write(SB, 0x42);
+        serial.write(SC, 0x81);
+
+        assert!(!serial.tick(4096));
+        assert!(!serial.tick(LINK_TIMEOUT - 1));
+        assert!(serial.is_transferring());
+        assert!(serial.tick(1));
+        assert_eq!(serial.read(SB), 0xFF);
+    }
+
+    #[test]
+    fn test_external_clock_waits() {
+        let mut serial = Serial::new(true);
+        serial.write(SB, 0x42);
+        serial.write(SC, 0x80);
+
+        assert!(!serial.tick(100_000));
+        assert!(serial.is_transferring());
+    }
+}
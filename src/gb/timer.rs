@@ -0,0 +1,91 @@
+pub const DIV: u16 = 0xFF04;
+pub const TIMA: u16 = 0xFF05;
+pub const TMA: u16 = 0xFF06;
+pub const TAC: u16 = 0xFF07;
+
+const TAC_ENABLE: u8 = 0b100;
+
+/// DIV and TIMA. Both count off one 16 bit counter, DIV is its top byte and TIMA ticks when the
+/// bit TAC picks falls from 1 to 0.
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Self {
+            counter: 0,
+            tima: 0,
+            tma: 0,
+            tac: 0,
+        }
+    }
+
+    /// The counter bit TIMA watches: 4096, 262144, 65536 or 16384 Hz
+    fn bit(&self) -> u16 {
+        match self.tac & 0b11 {
+            0 => 1 << 9,
+            1 => 1 << 3,
+            2 => 1 << 5,
+            _ => 1 << 7,
+        }
+    }
+
+    fn input(&self) -> bool {
+        self.tac & TAC_ENABLE != 0 && self.counter & self.bit() != 0
+    }
+
+    /// Sets the counter, which ticks TIMA if the watched bit falls. Returns true on overflow.
+    fn set_counter(&mut self, counter: u16) -> bool {
+        let before = self.input();
+        self.counter = counter;
+        before && !self.input() && self.tick_tima()
+    }
+
+    pub fn read(&self, addr: u16) -> u8 {
+        match addr {
+            DIV => (self.counter >> 8) as u8,
+            TIMA => self.tima,
+            TMA => self.tma,
+            TAC => 0xF8 | self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    /// Returns true when the write made TIMA overflow
+    pub fn write(&mut self, addr: u16, data: u8) -> bool {
+        match addr {
+            // any write clears the whole counter, which can tick TIMA
+            DIV => return self.set_counter(0),
+            TIMA => self.tima = data,
+            TMA => self.tma = data,
+            TAC => {
+                let before = self.input();
+                self.tac = data & 0b111;
+                // turning it off or picking another bit can look like a falling edge too
+                return before && !self.input() && self.tick_tima();
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn tick_tima(&mut self) -> bool {
+        let (tima, overflow) = self.tima.overflowing_add(1);
+        self.tima = if overflow { self.tma } else { tima };
+        overflow
+    }
+
+    /// Advances by `cycles` T-cycles, returns true when the timer interrupt should be requested
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        let mut interrupt = false;
+        // the counter goes up once per M-cycle
+        for _ in 0..cycles / 4 {
+            interrupt |= self.set_counter(self.counter.wrapping_add(4));
+        }
+        interrupt
+    }
+}
@@ -0,0 +1,434 @@
+use super::{
+    bios,
+    bus::Bus,
+    cpu::{Cpu, ShiftKind, FLAG_C, FLAG_N, FLAG_T, FLAG_Z, MODE_UND, MODE_USR, VECTOR_UNDEFINED},
+};
+
+impl Cpu {
+    pub(super) fn execute_arm(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        if op & 0x0FFF_FFF0 == 0x012F_FF10 {
+            self.arm_bx(op)
+        } else if op & 0x0FB0_0FF0 == 0x0100_0090 {
+            self.arm_swap(bus, op)
+        } else if op & 0x0FC0_00F0 == 0x0000_0090 {
+            self.arm_multiply(op)
+        } else if op & 0x0F80_00F0 == 0x0080_0090 {
+            self.arm_multiply_long(op)
+        } else if op & 0x0E00_0090 == 0x0000_0090 {
+            self.arm_halfword_transfer(bus, op)
+        } else if op & 0x0FBF_0FFF == 0x010F_0000 {
+            self.arm_mrs(op)
+        } else if op & 0x0DB0_F000 == 0x0120_F000 {
+            self.arm_msr(op)
+        } else if op & 0x0C00_0000 == 0x0000_0000 {
+            self.arm_data_processing(op)
+        } else if op & 0x0E00_0010 == 0x0600_0010 {
+            self.arm_undefined()
+        } else if op & 0x0C00_0000 == 0x0400_0000 {
+            self.arm_single_transfer(bus, op)
+        } else if op & 0x0E00_0000 == 0x0800_0000 {
+            self.arm_block_transfer(bus, op)
+        } else if op & 0x0E00_0000 == 0x0A00_0000 {
+            self.arm_branch(op)
+        } else if op & 0x0F00_0000 == 0x0F00_0000 {
+            // the comment field holds the function number in bits 16-23
+            bios::swi(self, bus, ((op >> 16) & 0xFF) as u8)
+        } else {
+            // no coprocessors on the GBA
+            self.arm_undefined()
+        }
+    }
+
+    fn arm_undefined(&mut self) -> u32 {
+        let next = self.next_instruction();
+        self.exception(VECTOR_UNDEFINED, MODE_UND, next);
+        3
+    }
+
+    fn arm_bx(&mut self, op: u32) -> u32 {
+        let target = self.regs[(op & 0xF) as usize];
+        self.set_flag(FLAG_T, target & 1 != 0);
+        self.set_pc(target);
+        3
+    }
+
+    fn arm_branch(&mut self, op: u32) -> u32 {
+        // 24 bit signed word offset
+        let offset = (((op & 0x00FF_FFFF) << 8) as i32 >> 6) as u32;
+        if op & (1 << 24) != 0 {
+            self.regs[14] = self.next_instruction();
+        }
+        self.set_pc(self.regs[15].wrapping_add(offset));
+        3
+    }
+
+    /// Operand 2 of a data processing instruction, with the shifter carry out
+    fn arm_operand2(&self, op: u32) -> (u32, bool) {
+        if op & (1 << 25) != 0 {
+            let imm = op & 0xFF;
+            let rotate = ((op >> 8) & 0xF) * 2;
+            if rotate == 0 {
+                (imm, self.flag(FLAG_C))
+            } else {
+                let value = imm.rotate_right(rotate);
+                (value, value & 0x8000_0000 != 0)
+            }
+        } else {
+            let rm = (op & 0xF) as usize;
+            let kind = ShiftKind::from_bits(op >> 5);
+            if op & (1 << 4) != 0 {
+                // shift by register, the extra cycle makes r15 read 4 further ahead
+                let value = self.regs[rm].wrapping_add(if rm == 15 { 4 } else { 0 });
+                let amount = self.regs[((op >> 8) & 0xF) as usize] & 0xFF;
+                self.shift(kind, value, amount, false)
+            } else {
+                self.shift(kind, self.regs[rm], (op >> 7) & 0x1F, true)
+            }
+        }
+    }
+
+    fn arm_data_processing(&mut self, op: u32) -> u32 {
+        let opcode = (op >> 21) & 0xF;
+        let set_flags = op & (1 << 20) != 0;
+        let rn = ((op >> 16) & 0xF) as usize;
+        let rd = ((op >> 12) & 0xF) as usize;
+        let register_shift = op & (1 << 25) == 0 && op & (1 << 4) != 0;
+
+        let (operand, shifter_carry) = self.arm_operand2(op);
+        let a = self.regs[rn].wrapping_add(if rn == 15 && register_shift { 4 } else { 0 });
+        let carry = self.flag(FLAG_C);
+
+        // writing r15 with S set restores the CPSR instead of setting flags
+        let flags = set_flags && rd != 15;
+
+        let (result, write) = match opcode {
+            0x0 => (a & operand, true),
+            0x1 => (a ^ operand, true),
+            0x2 => (self.sub(a, operand, true, flags), true),
+            0x3 => (self.sub(operand, a, true, flags), true),
+            0x4 => (self.add(a, operand, false, flags), true),
+            0x5 => (self.add(a, operand, carry, flags), true),
+            0x6 => (self.sub(a, operand, carry, flags), true),
+            0x7 => (self.sub(operand, a, carry, flags), true),
+            0x8 => (a & operand, false),
+            0x9 => (a ^ operand, false),
+            0xA => (self.sub(a, operand, true, set_flags), false),
+            0xB => (self.add(a, operand, false, set_flags), false),
+            0xC => (a | operand, true),
+            0xD => (operand, true),
+            0xE => (a & !operand, true),
+            _ => (!operand, true),
+        };
+
+        let logical = matches!(opcode, 0x0 | 0x1 | 0x8 | 0x9 | 0xC | 0xD | 0xE | 0xF);
+        if logical && (flags || (set_flags && !write)) {
+            self.set_nz(result);
+            self.set_flag(FLAG_C, shifter_carry);
+        }
+
+        if write {
+            if rd == 15 && set_flags {
+                let spsr = self.spsr();
+                self.set_cpsr(spsr);
+            }
+            self.set_reg(rd, result);
+        }
+
+        if rd == 15 && write {
+            3
+        } else if register_shift {
+            2
+        } else {
+            1
+        }
+    }
+
+    fn arm_mrs(&mut self, op: u32) -> u32 {
+        let rd = ((op >> 12) & 0xF) as usize;
+        self.regs[rd] = if op & (1 << 22) != 0 {
+            self.spsr()
+        } else {
+            self.cpsr
+        };
+        1
+    }
+
+    fn arm_msr(&mut self, op: u32) -> u32 {
+        let value = if op & (1 << 25) != 0 {
+            (op & 0xFF).rotate_right(((op >> 8) & 0xF) * 2)
+        } else {
+            self.regs[(op & 0xF) as usize]
+        };
+
+        let mut mask = 0;
+        if op & (1 << 19) != 0 {
+            mask |= 0xFF00_0000;
+        }
+        if op & (1 << 16) != 0 && self.mode() != MODE_USR {
+            mask |= 0x0000_00FF;
+        }
+
+        if op & (1 << 22) != 0 {
+            let spsr = self.spsr();
+            self.set_spsr((spsr & !mask) | (value & mask));
+        } else {
+            // the T bit can't be changed through MSR
+            let mask = mask & !FLAG_T;
+            let cpsr = (self.cpsr & !mask) | (value & mask);
+            self.set_cpsr(cpsr);
+        }
+        1
+    }
+
+    fn arm_multiply(&mut self, op: u32) -> u32 {
+        let rd = ((op >> 16) & 0xF) as usize;
+        let rn = ((op >> 12) & 0xF) as usize;
+        let rs = ((op >> 8) & 0xF) as usize;
+        let rm = (op & 0xF) as usize;
+
+        let mut result = self.regs[rm].wrapping_mul(self.regs[rs]);
+        if op & (1 << 21) != 0 {
+            result = result.wrapping_add(self.regs[rn]);
+        }
+        self.regs[rd] = result;
+
+        if op & (1 << 20) != 0 {
+            self.set_nz(result);
+        }
+        3
+    }
+
+    fn arm_multiply_long(&mut self, op: u32) -> u32 {
+        let rd_hi = ((op >> 16) & 0xF) as usize;
+        let rd_lo = ((op >> 12) & 0xF) as usize;
+        let rs = ((op >> 8) & 0xF) as usize;
+        let rm = (op & 0xF) as usize;
+
+        let mut result = if op & (1 << 22) != 0 {
+            (self.regs[rm] as i32 as i64).wrapping_mul(self.regs[rs] as i32 as i64) as u64
+        } else {
+            (self.regs[rm] as u64).wrapping_mul(self.regs[rs] as u64)
+        };
+        if op & (1 << 21) != 0 {
+            let acc = ((self.regs[rd_hi] as u64) << 32) | self.regs[rd_lo] as u64;
+            result = result.wrapping_add(acc);
+        }
+
+        self.regs[rd_lo] = result as u32;
+        self.regs[rd_hi] = (result >> 32) as u32;
+
+        if op & (1 << 20) != 0 {
+            self.set_flag(FLAG_N, result & (1 << 63) != 0);
+            self.set_flag(FLAG_Z, result == 0);
+        }
+        4
+    }
+
+    fn arm_swap(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let rn = ((op >> 16) & 0xF) as usize;
+        let rd = ((op >> 12) & 0xF) as usize;
+        let rm = (op & 0xF) as usize;
+        let addr = self.regs[rn];
+        let source = self.regs[rm];
+
+        if op & (1 << 22) != 0 {
+            let value = bus.read8(addr);
+            bus.write8(addr, source as u8);
+            self.set_reg(rd, value as u32);
+        } else {
+            let value = bus.read32_rotated(addr);
+            bus.write32(addr, source);
+            self.set_reg(rd, value);
+        }
+        4
+    }
+
+    fn arm_halfword_transfer(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let pre = op & (1 << 24) != 0;
+        let up = op & (1 << 23) != 0;
+        let writeback = op & (1 << 21) != 0;
+        let load = op & (1 << 20) != 0;
+        let rn = ((op >> 16) & 0xF) as usize;
+        let rd = ((op >> 12) & 0xF) as usize;
+
+        let offset = if op & (1 << 22) != 0 {
+            ((op >> 4) & 0xF0) | (op & 0xF)
+        } else {
+            self.regs[(op & 0xF) as usize]
+        };
+
+        let base = self.regs[rn];
+        let offset_addr = if up {
+            base.wrapping_add(offset)
+        } else {
+            base.wrapping_sub(offset)
+        };
+        let addr = if pre { offset_addr } else { base };
+
+        if !pre || writeback {
+            self.set_reg(rn, offset_addr);
+        }
+
+        match ((op >> 5) & 0b11, load) {
+            (1, false) => {
+                let value = self.regs[rd].wrapping_add(if rd == 15 { 4 } else { 0 });
+                bus.write16(addr, value as u16);
+            }
+            (1, true) => {
+                // misaligned halfwords are rotated like words
+                let value = (bus.read16(addr) as u32).rotate_right((addr & 1) * 8);
+                self.set_reg(rd, value);
+            }
+            (2, true) => {
+                let value = bus.read8(addr) as i8 as i32 as u32;
+                self.set_reg(rd, value);
+            }
+            (3, true) => {
+                // a misaligned LDRSH only loads the byte
+                let value = if addr & 1 != 0 {
+                    bus.read8(addr) as i8 as i32 as u32
+                } else {
+                    bus.read16(addr) as i16 as i32 as u32
+                };
+                self.set_reg(rd, value);
+            }
+            _ => {}
+        }
+
+        if load {
+            3
+        } else {
+            2
+        }
+    }
+
+    fn arm_single_transfer(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let pre = op & (1 << 24) != 0;
+        let up = op & (1 << 23) != 0;
+        let byte = op & (1 << 22) != 0;
+        let writeback = op & (1 << 21) != 0;
+        let load = op & (1 << 20) != 0;
+        let rn = ((op >> 16) & 0xF) as usize;
+        let rd = ((op >> 12) & 0xF) as usize;
+
+        let offset = if op & (1 << 25) != 0 {
+            let kind = ShiftKind::from_bits(op >> 5);
+            self.shift(kind, self.regs[(op & 0xF) as usize], (op >> 7) & 0x1F, true)
+                .0
+        } else {
+            op & 0xFFF
+        };
+
+        let base = self.regs[rn];
+        let offset_addr = if up {
+            base.wrapping_add(offset)
+        } else {
+            base.wrapping_sub(offset)
+        };
+        let addr = if pre { offset_addr } else { base };
+
+        if load {
+            if !pre || writeback {
+                self.set_reg(rn, offset_addr);
+            }
+            let value = if byte {
+                bus.read8(addr) as u32
+            } else {
+                bus.read32_rotated(addr)
+            };
+            self.set_reg(rd, value);
+            if rd == 15 {
+                5
+            } else {
+                3
+            }
+        } else {
+            // r15 is stored 12 ahead
+            let value = self.regs[rd].wrapping_add(if rd == 15 { 4 } else { 0 });
+            if byte {
+                bus.write8(addr, value as u8);
+            } else {
+                bus.write32(addr, value);
+            }
+            if !pre || writeback {
+                self.set_reg(rn, offset_addr);
+            }
+            2
+        }
+    }
+
+    fn arm_block_transfer(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let pre = op & (1 << 24) != 0;
+        let up = op & (1 << 23) != 0;
+        let user = op & (1 << 22) != 0;
+        let writeback = op & (1 << 21) != 0;
+        let load = op & (1 << 20) != 0;
+        let rn = ((op >> 16) & 0xF) as usize;
+        let mut list = op & 0xFFFF;
+
+        let base = self.regs[rn];
+        // an empty list transfers r15 and moves the base by 16 words
+        let (count, size) = if list == 0 {
+            list = 1 << 15;
+            (1, 0x40)
+        } else {
+            (list.count_ones(), list.count_ones() * 4)
+        };
+
+        let (mut addr, new_base) = if up {
+            (if pre { base + 4 } else { base }, base.wrapping_add(size))
+        } else {
+            let start = base.wrapping_sub(size);
+            (if pre { start } else { start + 4 }, start)
+        };
+
+        // S with r15 in an LDM restores the CPSR, otherwise it transfers the user bank
+        let restore = user && load && list & (1 << 15) != 0;
+        let user_bank = user && !restore;
+
+        if load {
+            if writeback {
+                self.set_reg(rn, new_base);
+            }
+            for reg in 0..16 {
+                if list & (1 << reg) == 0 {
+                    continue;
+                }
+                let value = bus.read32(addr);
+                if user_bank {
+                    self.set_user_reg(reg, value);
+                } else {
+                    self.set_reg(reg, value);
+                }
+                addr = addr.wrapping_add(4);
+            }
+            if restore {
+                let spsr = self.spsr();
+                self.set_cpsr(spsr);
+            }
+        } else {
+            let first = list.trailing_zeros() as usize;
+            for reg in 0..16 {
+                if list & (1 << reg) == 0 {
+                    continue;
+                }
+                let value = if reg == rn && writeback && reg != first {
+                    // the base is already updated when it isn't the first register stored
+                    new_base
+                } else if user_bank {
+                    self.user_reg(reg)
+                } else {
+                    self.regs[reg]
+                };
+                let value = value.wrapping_add(if reg == 15 { 4 } else { 0 });
+                bus.write32(addr, value);
+                addr = addr.wrapping_add(4);
+            }
+            if writeback {
+                self.set_reg(rn, new_base);
+            }
+        }
+
+        count + 2
+    }
+}
@@ -0,0 +1,357 @@
+use alloc::{vec, vec::Vec};
+
+use super::{bus::Bus, cpu::Cpu};
+
+const BIOS_SIZE: usize = 0x4000;
+/// Where interrupt handlers acknowledge interrupts for `IntrWait`
+pub const BIOS_IF: u32 = 0x03007FF8;
+
+const IO_IME: u32 = 0x04000208;
+
+// The only code that runs from our BIOS is the IRQ dispatcher, everything else is done in
+// `swi` instead of emulating the real BIOS. At $18 is `b $128`, then at $128:
+//   stmfd sp!, {r0-r3, r12, lr}
+//   mov   r0, #0x04000000
+//   add   lr, pc, #0
+//   ldr   pc, [r0, #-4]     ; handler pointer at $03007FFC
+//   ldmfd sp!, {r0-r3, r12, lr}
+//   subs  pc, lr, #4
+const IRQ_VECTOR: (usize, u32) = (0x18, 0xEA000042);
+const IRQ_DISPATCH: (usize, [u32; 6]) = (
+    0x128,
+    [
+        0xE92D500F, 0xE3A00301, 0xE28FE000, 0xE510F004, 0xE8BD500F, 0xE25EF004,
+    ],
+);
+
+/// A stand-in for the real BIOS which isn't ours to ship
+pub fn image() -> Vec<u8> {
+    let mut bios = vec![0; BIOS_SIZE];
+    bios[IRQ_VECTOR.0..IRQ_VECTOR.0 + 4].copy_from_slice(&IRQ_VECTOR.1.to_le_bytes());
+    for (i, op) in IRQ_DISPATCH.1.iter().enumerate() {
+        let addr = IRQ_DISPATCH.0 + i * 4;
+        bios[addr..addr + 4].copy_from_slice(&op.to_le_bytes());
+    }
+    bios
+}
+
+/// High level emulation of the BIOS calls, returns roughly how many cycles it took.
+/// https://problemkaputt.de/gbatek.htm#biosfunctions
+pub fn swi(cpu: &mut Cpu, bus: &mut Bus, num: u8) -> u32 {
+    let r = cpu.regs;
+    match num {
+        0x00 => soft_reset(cpu, bus),
+        0x01 => register_ram_reset(bus, r[0]),
+        0x02 | 0x03 => bus.halted = true,
+        0x04 => intr_wait(cpu, bus, r[0] != 0, r[1] as u16),
+        0x05 => intr_wait(cpu, bus, true, 1),
+        0x06 => div(cpu, r[0] as i32, r[1] as i32),
+        0x07 => div(cpu, r[1] as i32, r[0] as i32),
+        0x08 => cpu.regs[0] = isqrt(r[0]),
+        0x09 => {
+            let tan = (r[0] as i16) as f64 / 16384.0;
+            cpu.regs[0] = (tan.atan() * (0x8000 as f64 / core::f64::consts::PI)) as i32 as u32;
+        }
+        0x0A => {
+            let (x, y) = ((r[0] as i16) as f64, (r[1] as i16) as f64);
+            let mut angle = y.atan2(x);
+            if angle < 0.0 {
+                angle += 2.0 * core::f64::consts::PI;
+            }
+            cpu.regs[0] = ((angle * (0x8000 as f64 / core::f64::consts::PI)) as u32) & 0xFFFF;
+        }
+        0x0B => cpu_set(bus, r[0], r[1], r[2]),
+        0x0C => cpu_fast_set(bus, r[0], r[1], r[2]),
+        0x0D => cpu.regs[0] = 0xBAAE187F,
+        0x0E => bg_affine_set(bus, r[0], r[1], r[2]),
+        0x0F => obj_affine_set(bus, r[0], r[1], r[2], r[3]),
+        0x10 => bit_unpack(bus, r[0], r[1], r[2]),
+        0x11 => write_bytes(bus, r[1], &lz77(bus, r[0]), false),
+        0x12 => write_bytes(bus, r[1], &lz77(bus, r[0]), true),
+        0x14 => write_bytes(bus, r[1], &run_length(bus, r[0]), false),
+        0x15 => write_bytes(bus, r[1], &run_length(bus, r[0]), true),
+        0x16 => write_bytes(bus, r[1], &diff8(bus, r[0]), false),
+        0x17 => write_bytes(bus, r[1], &diff8(bus, r[0]), true),
+        0x18 => write_bytes(bus, r[1], &diff16(bus, r[0]), true),
+        // SoundBias, and the sound calls we don't need without sound
+        _ => {}
+    }
+    20
+}
+
+fn soft_reset(cpu: &mut Cpu, bus: &mut Bus) {
+    bus.clear(0x03007E00, 0x200);
+    cpu.reset();
+}
+
+fn register_ram_reset(bus: &mut Bus, flags: u32) {
+    if flags & 1 != 0 {
+        bus.clear(0x02000000, 0x40000);
+    }
+    // the top of IWRAM holds the stacks and the BIOS variables
+    if flags & 2 != 0 {
+        bus.clear(0x03000000, 0x7E00);
+    }
+    if flags & 4 != 0 {
+        bus.clear(0x05000000, 0x400);
+    }
+    if flags & 8 != 0 {
+        bus.clear(0x06000000, 0x18000);
+    }
+    if flags & 16 != 0 {
+        bus.clear(0x07000000, 0x400);
+    }
+}
+
+fn intr_wait(cpu: &mut Cpu, bus: &mut Bus, discard: bool, mask: u16) {
+    bus.write16(IO_IME, 1);
+    if discard {
+        let flags = bus.read16(BIOS_IF);
+        bus.write16(BIOS_IF, flags & !mask);
+    }
+    cpu.wait_mask = Some(mask);
+    bus.halted = true;
+}
+
+fn div(cpu: &mut Cpu, num: i32, den: i32) {
+    if den == 0 {
+        // the real BIOS loops forever, give something sane back instead
+        cpu.regs[0] = if num < 0 { u32::MAX } else { 1 };
+        cpu.regs[1] = num as u32;
+        cpu.regs[3] = 1;
+        return;
+    }
+    let quot = num.wrapping_div(den);
+    cpu.regs[0] = quot as u32;
+    cpu.regs[1] = num.wrapping_rem(den) as u32;
+    cpu.regs[3] = quot.unsigned_abs();
+}
+
+fn isqrt(value: u32) -> u32 {
+    let mut root = (value as f64).sqrt() as u32;
+    // fix up any rounding from the float sqrt
+    while (root as u64) * (root as u64) > value as u64 {
+        root -= 1;
+    }
+    while ((root + 1) as u64) * ((root + 1) as u64) <= value as u64 {
+        root += 1;
+    }
+    root
+}
+
+fn cpu_set(bus: &mut Bus, mut source: u32, mut dest: u32, control: u32) {
+    let count = control & 0x1FFFFF;
+    let fill = control & (1 << 24) != 0;
+
+    if control & (1 << 26) != 0 {
+        let value = bus.read32(source);
+        for _ in 0..count {
+            bus.write32(dest, if fill { value } else { bus.read32(source) });
+            source += 4;
+            dest += 4;
+        }
+    } else {
+        let value = bus.read16(source);
+        for _ in 0..count {
+            bus.write16(dest, if fill { value } else { bus.read16(source) });
+            source += 2;
+            dest += 2;
+        }
+    }
+}
+
+fn cpu_fast_set(bus: &mut Bus, source: u32, dest: u32, control: u32) {
+    // always words, in blocks of 8
+    let count = ((control & 0x1FFFFF) + 7) & !7;
+    cpu_set(bus, source, dest, (control & (1 << 24)) | (1 << 26) | count);
+}
+
+fn rotation(angle: u16) -> (f64, f64) {
+    let theta = (angle >> 8) as f64 * 2.0 * core::f64::consts::PI / 256.0;
+    (theta.sin(), theta.cos())
+}
+
+fn bg_affine_set(bus: &mut Bus, mut source: u32, mut dest: u32, count: u32) {
+    for _ in 0..count {
+        let origin_x = bus.read32(source) as i32 as f64;
+        let origin_y = bus.read32(source + 4) as i32 as f64;
+        let display_x = bus.read16(source + 8) as i16 as f64;
+        let display_y = bus.read16(source + 10) as i16 as f64;
+        let scale_x = bus.read16(source + 12) as i16 as f64;
+        let scale_y = bus.read16(source + 14) as i16 as f64;
+        let (sin, cos) = rotation(bus.read16(source + 16));
+
+        let pa = scale_x * cos;
+        let pb = -scale_x * sin;
+        let pc = scale_y * sin;
+        let pd = scale_y * cos;
+
+        bus.write16(dest, pa as i16 as u16);
+        bus.write16(dest + 2, pb as i16 as u16);
+        bus.write16(dest + 4, pc as i16 as u16);
+        bus.write16(dest + 6, pd as i16 as u16);
+        bus.write32(
+            dest + 8,
+            (origin_x - (pa * display_x + pb * display_y)) as i32 as u32,
+        );
+        bus.write32(
+            dest + 12,
+            (origin_y - (pc * display_x + pd * display_y)) as i32 as u32,
+        );
+
+        source += 20;
+        dest += 16;
+    }
+}
+
+fn obj_affine_set(bus: &mut Bus, mut source: u32, mut dest: u32, count: u32, stride: u32) {
+    for _ in 0..count {
+        let scale_x = bus.read16(source) as i16 as f64;
+        let scale_y = bus.read16(source + 2) as i16 as f64;
+        let (sin, cos) = rotation(bus.read16(source + 4));
+
+        let params = [scale_x * cos, -scale_x * sin, scale_y * sin, scale_y * cos];
+        for param in params {
+            bus.write16(dest, param as i16 as u16);
+            dest += stride;
+        }
+        source += 8;
+    }
+}
+
+fn bit_unpack(bus: &mut Bus, mut source: u32, mut dest: u32, info: u32) {
+    let len = bus.read16(info) as u32;
+    let source_width = bus.read8(info + 2) as u32;
+    let dest_width = bus.read8(info + 3) as u32;
+    let offset = bus.read32(info + 4);
+    let zero_flag = offset & (1 << 31) != 0;
+    let offset = offset & 0x7FFFFFFF;
+
+    if !matches!(source_width, 1 | 2 | 4 | 8) || !matches!(dest_width, 1 | 2 | 4 | 8 | 16 | 32) {
+        return;
+    }
+
+    let mut out = 0u32;
+    let mut out_bits = 0;
+    for _ in 0..len {
+        let byte = bus.read8(source) as u32;
+        source += 1;
+
+        for shift in (0..8).step_by(source_width as usize) {
+            let mut value = (byte >> shift) & ((1 << source_width) - 1);
+            if value != 0 || zero_flag {
+                value = value.wrapping_add(offset);
+            }
+            if dest_width < 32 {
+                value &= (1 << dest_width) - 1;
+            }
+            out |= value << out_bits;
+            out_bits += dest_width;
+
+            if out_bits >= 32 {
+                bus.write32(dest, out);
+                dest += 4;
+                out = 0;
+                out_bits = 0;
+            }
+        }
+    }
+}
+
+fn lz77(bus: &Bus, mut source: u32) -> Vec<u8> {
+    let size = (bus.read32(source) >> 8) as usize;
+    source += 4;
+    let mut out = Vec::with_capacity(size);
+
+    while out.len() < size {
+        let flags = bus.read8(source);
+        source += 1;
+
+        for bit in (0..8).rev() {
+            if out.len() >= size {
+                break;
+            }
+            if flags & (1 << bit) == 0 {
+                out.push(bus.read8(source));
+                source += 1;
+            } else {
+                let hi = bus.read8(source) as usize;
+                let lo = bus.read8(source + 1) as usize;
+                source += 2;
+
+                let len = (hi >> 4) + 3;
+                let disp = (((hi & 0xF) << 8) | lo) + 1;
+                for _ in 0..len {
+                    let byte = out.get(out.len().wrapping_sub(disp)).copied().unwrap_or(0);
+                    out.push(byte);
+                }
+            }
+        }
+    }
+
+    out.truncate(size);
+    out
+}
+
+fn run_length(bus: &Bus, mut source: u32) -> Vec<u8> {
+    let size = (bus.read32(source) >> 8) as usize;
+    source += 4;
+    let mut out = Vec::with_capacity(size);
+
+    while out.len() < size {
+        let flag = bus.read8(source);
+        source += 1;
+
+        if flag & 0x80 != 0 {
+            let byte = bus.read8(source);
+            source += 1;
+            for _ in 0..(flag & 0x7F) as usize + 3 {
+                out.push(byte);
+            }
+        } else {
+            for _ in 0..(flag & 0x7F) as usize + 1 {
+                out.push(bus.read8(source));
+                source += 1;
+            }
+        }
+    }
+
+    out.truncate(size);
+    out
+}
+
+fn diff8(bus: &Bus, source: u32) -> Vec<u8> {
+    let size = bus.read32(source) >> 8;
+    let mut out = Vec::with_capacity(size as usize);
+    let mut value = 0u8;
+    for i in 0..size {
+        value = value.wrapping_add(bus.read8(source + 4 + i));
+        out.push(value);
+    }
+    out
+}
+
+fn diff16(bus: &Bus, source: u32) -> Vec<u8> {
+    let size = bus.read32(source) >> 8;
+    let mut out = Vec::with_capacity(size as usize);
+    let mut value = 0u16;
+    for i in (0..size).step_by(2) {
+        value = value.wrapping_add(bus.read16(source + 4 + i));
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out
+}
+
+/// VRAM can't take byte writes, so the VRAM variants write halfwords
+fn write_bytes(bus: &mut Bus, dest: u32, data: &[u8], vram: bool) {
+    if vram {
+        for (i, pair) in data.chunks(2).enumerate() {
+            let value = pair[0] as u16 | (*pair.get(1).unwrap_or(&0) as u16) << 8;
+            bus.write16(dest + i as u32 * 2, value);
+        }
+    } else {
+        for (i, byte) in data.iter().enumerate() {
+            bus.write8(dest + i as u32, *byte);
+        }
+    }
+}
@@ -0,0 +1,436 @@
+use alloc::{vec, vec::Vec};
+
+use super::{bios, ppu::Ppu};
+
+const IO_DISPSTAT: u32 = 0x004;
+const IO_VCOUNT: u32 = 0x006;
+const IO_DMA: u32 = 0x0B0;
+const IO_DMA_END: u32 = 0x0DF;
+const IO_TIMERS: u32 = 0x100;
+const IO_TIMERS_END: u32 = 0x10F;
+const IO_KEYINPUT: u32 = 0x130;
+const IO_IE: u32 = 0x200;
+const IO_IF: u32 = 0x202;
+const IO_IME: u32 = 0x208;
+const IO_HALTCNT: u32 = 0x301;
+
+const IRQ_TIMER0: u16 = 1 << 3;
+const IRQ_DMA0: u16 = 1 << 8;
+
+const TIMER_PRESCALERS: [u32; 4] = [1, 64, 256, 1024];
+
+#[derive(Default, Clone, Copy)]
+struct Timer {
+    counter: u16,
+    reload: u16,
+    control: u16,
+    cycles: u32,
+}
+
+impl Timer {
+    fn enabled(&self) -> bool {
+        self.control & (1 << 7) != 0
+    }
+
+    fn cascade(&self) -> bool {
+        self.control & (1 << 2) != 0
+    }
+}
+
+/// Internal DMA registers, latched from IO when a channel gets enabled
+#[derive(Default, Clone, Copy)]
+struct Dma {
+    source: u32,
+    dest: u32,
+    count: u32,
+}
+
+#[derive(PartialEq)]
+enum DmaTiming {
+    Immediate,
+    VBlank,
+    HBlank,
+}
+
+/// Everything on the GBA's address bus apart from the CPU
+pub struct Bus {
+    bios: Vec<u8>,
+    ewram: Vec<u8>,
+    iwram: Vec<u8>,
+    io: Vec<u8>,
+    palette: Vec<u8>,
+    vram: Vec<u8>,
+    oam: Vec<u8>,
+    rom: Vec<u8>,
+    sram: Vec<u8>,
+    pub ppu: Ppu,
+    timers: [Timer; 4],
+    dma: [Dma; 4],
+    /// Pressed buttons, in KEYINPUT bit order
+    keys: u16,
+    pub halted: bool,
+}
+
+impl Bus {
+    /// Back to how the console powers on, keeping the cartridge and its save
+    pub fn reset(&mut self) {
+        let rom = core::mem::take(&mut self.rom);
+        let sram = core::mem::take(&mut self.sram);
+        *self = Bus::new(rom);
+        self.sram = sram;
+    }
+
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            bios: bios::image(),
+            ewram: vec![0; 0x40000],
+            iwram: vec![0; 0x8000],
+            io: vec![0; 0x400],
+            palette: vec![0; 0x400],
+            vram: vec![0; 0x18000],
+            oam: vec![0; 0x400],
+            rom,
+            sram: vec![0xFF; 0x10000],
+            ppu: Ppu::new(),
+            timers: [Timer::default(); 4],
+            dma: [Dma::default(); 4],
+            keys: 0,
+            halted: false,
+        }
+    }
+
+    /// Sets the pressed buttons: A, B, Select, Start, Right, Left, Up, Down, R, L from bit 0 up
+    pub fn set_keys(&mut self, keys: u16) {
+        self.keys = keys & 0x3FF;
+    }
+
+    pub fn irq_pending(&self) -> bool {
+        self.io[IO_IME as usize] & 1 != 0 && self.io16(IO_IE) & self.io16(IO_IF) != 0
+    }
+
+    /// Halt ends on any enabled interrupt, even with IME off
+    pub fn irq_wake(&self) -> bool {
+        self.io16(IO_IE) & self.io16(IO_IF) != 0
+    }
+
+    pub fn raise_irq(&mut self, irq: u16) {
+        let flags = self.io16(IO_IF) | irq;
+        self.set_io16(IO_IF, flags);
+    }
+
+    fn io16(&self, addr: u32) -> u16 {
+        u16::from_le_bytes([self.io[addr as usize], self.io[addr as usize + 1]])
+    }
+
+    fn set_io16(&mut self, addr: u32, value: u16) {
+        self.io[addr as usize..addr as usize + 2].copy_from_slice(&value.to_le_bytes());
+    }
+
+    fn vram_index(addr: u32) -> usize {
+        // 96K mirrored in 128K blocks, the last 32K repeats the sprite area
+        let addr = addr & 0x1FFFF;
+        if addr >= 0x18000 {
+            (addr - 0x8000) as usize
+        } else {
+            addr as usize
+        }
+    }
+
+    pub fn read8(&self, addr: u32) -> u8 {
+        match addr >> 24 {
+            0x00 => self.bios.get(addr as usize).copied().unwrap_or(0),
+            0x02 => self.ewram[(addr & 0x3FFFF) as usize],
+            0x03 => self.iwram[(addr & 0x7FFF) as usize],
+            0x04 => self.io_read8(addr & 0xFFFFFF),
+            0x05 => self.palette[(addr & 0x3FF) as usize],
+            0x06 => self.vram[Self::vram_index(addr)],
+            0x07 => self.oam[(addr & 0x3FF) as usize],
+            0x08..=0x0D => {
+                let offset = (addr & 0x1FFFFFF) as usize;
+                match self.rom.get(offset) {
+                    Some(byte) => *byte,
+                    // past the end of the cartridge the bus floats with the address
+                    None => ((offset / 2) >> ((offset & 1) * 8)) as u8,
+                }
+            }
+            0x0E | 0x0F => self.sram[(addr & 0xFFFF) as usize],
+            _ => 0,
+        }
+    }
+
+    pub fn read16(&self, addr: u32) -> u16 {
+        let addr = addr & !1;
+        u16::from_le_bytes([self.read8(addr), self.read8(addr + 1)])
+    }
+
+    pub fn read32(&self, addr: u32) -> u32 {
+        let addr = addr & !3;
+        u32::from_le_bytes([
+            self.read8(addr),
+            self.read8(addr + 1),
+            self.read8(addr + 2),
+            self.read8(addr + 3),
+        ])
+    }
+
+    /// Misaligned word loads rotate the aligned word, like `LDR` does
+    pub fn read32_rotated(&self, addr: u32) -> u32 {
+        self.read32(addr).rotate_right((addr & 3) * 8)
+    }
+
+    fn io_read8(&self, addr: u32) -> u8 {
+        match addr {
+            IO_TIMERS..=IO_TIMERS_END if addr & 3 < 2 => {
+                let counter = self.timers[((addr - IO_TIMERS) / 4) as usize].counter;
+                (counter >> ((addr & 1) * 8)) as u8
+            }
+            IO_KEYINPUT => !self.keys as u8,
+            0x131 => (!self.keys >> 8) as u8 & 3,
+            _ if addr < 0x400 => self.io[addr as usize],
+            _ => 0,
+        }
+    }
+
+    fn store8(&mut self, addr: u32, data: u8) {
+        match addr >> 24 {
+            0x02 => self.ewram[(addr & 0x3FFFF) as usize] = data,
+            0x03 => self.iwram[(addr & 0x7FFF) as usize] = data,
+            0x04 => self.io_write8(addr & 0xFFFFFF, data),
+            0x05 => self.palette[(addr & 0x3FF) as usize] = data,
+            0x06 => self.vram[Self::vram_index(addr)] = data,
+            0x07 => self.oam[(addr & 0x3FF) as usize] = data,
+            0x0E | 0x0F => self.sram[(addr & 0xFFFF) as usize] = data,
+            _ => {}
+        }
+    }
+
+    pub fn write8(&mut self, addr: u32, data: u8) {
+        match addr >> 24 {
+            // video memory is 16 bits wide, a byte gets written to both halves
+            0x05 | 0x06 => {
+                self.store8(addr & !1, data);
+                self.store8(addr | 1, data);
+            }
+            0x07 => {}
+            _ => self.store8(addr, data),
+        }
+    }
+
+    pub fn write16(&mut self, addr: u32, data: u16) {
+        let addr = addr & !1;
+        let [lo, hi] = data.to_le_bytes();
+        self.store8(addr, lo);
+        self.store8(addr + 1, hi);
+    }
+
+    pub fn write32(&mut self, addr: u32, data: u32) {
+        let addr = addr & !3;
+        for (i, byte) in data.to_le_bytes().into_iter().enumerate() {
+            self.store8(addr + i as u32, byte);
+        }
+    }
+
+    fn io_write8(&mut self, addr: u32, data: u8) {
+        if addr >= 0x400 {
+            return;
+        }
+
+        match addr {
+            // the status bits are read only
+            IO_DISPSTAT => self.io[addr as usize] = (self.io[addr as usize] & 7) | (data & !7),
+            IO_VCOUNT | 0x007 | IO_KEYINPUT | 0x131 => {}
+            // writing 1 acknowledges an interrupt
+            IO_IF | 0x203 => self.io[addr as usize] &= !data,
+            IO_HALTCNT => self.halted = true,
+            IO_TIMERS..=IO_TIMERS_END => {
+                let timer = &mut self.timers[((addr - IO_TIMERS) / 4) as usize];
+                let shift = (addr & 1) * 8;
+                match addr & 3 {
+                    0 | 1 => {
+                        timer.reload = (timer.reload & !(0xFF << shift)) | (data as u16) << shift
+                    }
+                    2 => {
+                        if !timer.enabled() && data & (1 << 7) != 0 {
+                            timer.counter = timer.reload;
+                            timer.cycles = 0;
+                        }
+                        timer.control = data as u16;
+                    }
+                    _ => {}
+                }
+                self.io[addr as usize] = data;
+            }
+            IO_DMA..=IO_DMA_END => {
+                let channel = ((addr - IO_DMA) / 12) as usize;
+                let control = IO_DMA + channel as u32 * 12 + 10;
+                let was_enabled = self.io16(control) & (1 << 15) != 0;
+                self.io[addr as usize] = data;
+
+                if addr == control + 1 && !was_enabled && data & 0x80 != 0 {
+                    self.start_dma(channel);
+                }
+            }
+            _ => self.io[addr as usize] = data,
+        }
+    }
+
+    fn start_dma(&mut self, channel: usize) {
+        let base = IO_DMA + channel as u32 * 12;
+        let io32 = |bus: &Bus, addr: u32| bus.io16(addr) as u32 | (bus.io16(addr + 2) as u32) << 16;
+
+        let source_mask = if channel == 0 { 0x07FFFFFF } else { 0x0FFFFFFF };
+        let dest_mask = if channel == 3 { 0x0FFFFFFF } else { 0x07FFFFFF };
+        self.dma[channel] = Dma {
+            source: io32(self, base) & source_mask,
+            dest: io32(self, base + 4) & dest_mask,
+            count: self.dma_count(channel),
+        };
+
+        if self.dma_timing(channel) == Some(DmaTiming::Immediate) {
+            self.run_dma(channel);
+        }
+    }
+
+    fn dma_count(&self, channel: usize) -> u32 {
+        let count = self.io16(IO_DMA + channel as u32 * 12 + 8) as u32;
+        match (count, channel) {
+            (0, 3) => 0x10000,
+            (0, _) => 0x4000,
+            (count, _) => count,
+        }
+    }
+
+    fn dma_timing(&self, channel: usize) -> Option<DmaTiming> {
+        let control = self.io16(IO_DMA + channel as u32 * 12 + 10);
+        if control & (1 << 15) == 0 {
+            return None;
+        }
+        match (control >> 12) & 3 {
+            0 => Some(DmaTiming::Immediate),
+            1 => Some(DmaTiming::VBlank),
+            2 => Some(DmaTiming::HBlank),
+            // sound FIFO and video capture, no sound yet
+            _ => None,
+        }
+    }
+
+    fn run_dma(&mut self, channel: usize) {
+        let control_addr = IO_DMA + channel as u32 * 12 + 10;
+        let control = self.io16(control_addr);
+        let word = control & (1 << 10) != 0;
+        let unit: i32 = if word { 4 } else { 2 };
+
+        let step = |mode: u16| match mode {
+            0 | 3 => unit,
+            1 => -unit,
+            _ => 0,
+        };
+        let dest_step = step((control >> 5) & 3);
+        let source_step = step((control >> 7) & 3);
+
+        let Dma {
+            mut source,
+            mut dest,
+            count,
+        } = self.dma[channel];
+
+        for _ in 0..count {
+            if word {
+                let value = self.read32(source);
+                self.write32(dest, value);
+            } else {
+                let value = self.read16(source);
+                self.write16(dest, value);
+            }
+            source = source.wrapping_add(source_step as u32);
+            dest = dest.wrapping_add(dest_step as u32);
+        }
+
+        self.dma[channel].source = source;
+        self.dma[channel].dest = dest;
+
+        let repeat = control & (1 << 9) != 0;
+        if repeat && (control >> 12) & 3 != 0 {
+            self.dma[channel].count = self.dma_count(channel);
+            if (control >> 5) & 3 == 3 {
+                let base = IO_DMA + channel as u32 * 12 + 4;
+                self.dma[channel].dest =
+                    self.io16(base) as u32 | (self.io16(base + 2) as u32) << 16;
+            }
+        } else {
+            self.set_io16(control_addr, control & !(1 << 15));
+        }
+
+        if control & (1 << 14) != 0 {
+            self.raise_irq(IRQ_DMA0 << channel);
+        }
+    }
+
+    fn trigger_dma(&mut self, timing: DmaTiming) {
+        for channel in 0..4 {
+            if self.dma_timing(channel).as_ref() == Some(&timing) {
+                self.run_dma(channel);
+            }
+        }
+    }
+
+    fn increment_timer(&mut self, index: usize) {
+        let timer = &mut self.timers[index];
+        let (counter, overflow) = timer.counter.overflowing_add(1);
+        timer.counter = counter;
+        if !overflow {
+            return;
+        }
+
+        timer.counter = timer.reload;
+        if timer.control & (1 << 6) != 0 {
+            self.raise_irq(IRQ_TIMER0 << index);
+        }
+        if index < 3 && self.timers[index + 1].enabled() && self.timers[index + 1].cascade() {
+            self.increment_timer(index + 1);
+        }
+    }
+
+    fn tick_timers(&mut self, cycles: u32) {
+        for index in 0..4 {
+            let timer = &mut self.timers[index];
+            if !timer.enabled() || (index > 0 && timer.cascade()) {
+                continue;
+            }
+
+            timer.cycles += cycles;
+            let prescaler = TIMER_PRESCALERS[(timer.control & 3) as usize];
+            let ticks = timer.cycles / prescaler;
+            timer.cycles %= prescaler;
+            for _ in 0..ticks {
+                self.increment_timer(index);
+            }
+        }
+    }
+
+    /// Advances the rest of the system by `cycles` CPU cycles
+    pub fn tick(&mut self, cycles: u32) {
+        let events = self
+            .ppu
+            .tick(cycles, &mut self.io, &self.palette, &self.vram, &self.oam);
+
+        if events.irq != 0 {
+            self.raise_irq(events.irq);
+        }
+        if events.vblank {
+            self.trigger_dma(DmaTiming::VBlank);
+        }
+        if events.hblank {
+            self.trigger_dma(DmaTiming::HBlank);
+        }
+
+        self.tick_timers(cycles);
+    }
+
+    /// Zeroes `len` bytes starting at `addr`, used by the BIOS reset calls
+    pub fn clear(&mut self, addr: u32, len: u32) {
+        for offset in (0..len).step_by(4) {
+            self.write32(addr + offset, 0);
+        }
+    }
+}
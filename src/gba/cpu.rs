@@ -0,0 +1,374 @@
+use super::bus::Bus;
+
+pub const MODE_USR: u32 = 0x10;
+pub const MODE_FIQ: u32 = 0x11;
+pub const MODE_IRQ: u32 = 0x12;
+pub const MODE_SVC: u32 = 0x13;
+pub const MODE_ABT: u32 = 0x17;
+pub const MODE_UND: u32 = 0x1B;
+pub const MODE_SYS: u32 = 0x1F;
+
+/// # Program Status Register https://problemkaputt.de/gbatek.htm#armcpuflagsconditionfieldcond
+///
+///  31 30 29 28 .. 7 6 5 4-0
+///   N  Z  C  V     I F T Mode
+///   |  |  |  |     | | | +--- Processor Mode
+///   |  |  |  |     | | +----- Thumb State
+///   |  |  |  |     | +------- FIQ Disable
+///   |  |  |  |     +--------- IRQ Disable
+///   |  |  |  +--------------- Overflow Flag
+///   |  |  +------------------ Carry Flag
+///   |  +--------------------- Zero Flag
+///   +------------------------ Negative Flag
+///
+pub const FLAG_N: u32 = 1 << 31;
+pub const FLAG_Z: u32 = 1 << 30;
+pub const FLAG_C: u32 = 1 << 29;
+pub const FLAG_V: u32 = 1 << 28;
+pub const FLAG_I: u32 = 1 << 7;
+pub const FLAG_T: u32 = 1 << 5;
+const MODE_MASK: u32 = 0x1F;
+
+pub const VECTOR_UNDEFINED: u32 = 0x04;
+pub const VECTOR_IRQ: u32 = 0x18;
+
+const ROM_START: u32 = 0x0800_0000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShiftKind {
+    Lsl,
+    Lsr,
+    Asr,
+    Ror,
+}
+
+impl ShiftKind {
+    pub fn from_bits(bits: u32) -> Self {
+        match bits & 0b11 {
+            0 => ShiftKind::Lsl,
+            1 => ShiftKind::Lsr,
+            2 => ShiftKind::Asr,
+            _ => ShiftKind::Ror,
+        }
+    }
+}
+
+pub struct Cpu {
+    /// r0-r15 of the current mode. While an instruction executes r15 reads as its address
+    /// plus 8 (ARM) or 4 (Thumb), like the real pipeline.
+    pub regs: [u32; 16],
+    pub cpsr: u32,
+    // indexed by `bank`, usr/sys has no spsr
+    spsr: [u32; 6],
+    banked_sp_lr: [[u32; 2]; 6],
+    usr_r8_r12: [u32; 5],
+    fiq_r8_r12: [u32; 5],
+    // set when the executing instruction wrote to r15
+    branched: bool,
+    /// Interrupt flags a BIOS `IntrWait` is waiting for
+    pub wait_mask: Option<u16>,
+}
+
+fn bank(mode: u32) -> usize {
+    match mode {
+        MODE_FIQ => 1,
+        MODE_IRQ => 2,
+        MODE_SVC => 3,
+        MODE_ABT => 4,
+        MODE_UND => 5,
+        _ => 0,
+    }
+}
+
+impl Cpu {
+    /// State the BIOS leaves behind when it jumps to the cartridge
+    pub fn new() -> Self {
+        let mut cpu = Cpu {
+            regs: [0; 16],
+            cpsr: MODE_SYS,
+            spsr: [0; 6],
+            banked_sp_lr: [[0; 2]; 6],
+            usr_r8_r12: [0; 5],
+            fiq_r8_r12: [0; 5],
+            branched: false,
+            wait_mask: None,
+        };
+        cpu.reset();
+        cpu
+    }
+
+    pub fn reset(&mut self) {
+        self.regs = [0; 16];
+        self.spsr = [0; 6];
+        self.banked_sp_lr = [[0; 2]; 6];
+        self.banked_sp_lr[bank(MODE_SVC)][0] = 0x0300_7FE0;
+        self.banked_sp_lr[bank(MODE_IRQ)][0] = 0x0300_7FA0;
+        self.cpsr = MODE_SYS;
+        self.regs[13] = 0x0300_7F00;
+        self.regs[15] = ROM_START;
+        self.wait_mask = None;
+    }
+
+    pub fn mode(&self) -> u32 {
+        self.cpsr & MODE_MASK
+    }
+
+    pub fn is_thumb(&self) -> bool {
+        self.cpsr & FLAG_T != 0
+    }
+
+    pub fn flag(&self, flag: u32) -> bool {
+        self.cpsr & flag != 0
+    }
+
+    pub fn set_flag(&mut self, flag: u32, value: bool) {
+        if value {
+            self.cpsr |= flag;
+        } else {
+            self.cpsr &= !flag;
+        }
+    }
+
+    pub fn set_nz(&mut self, result: u32) {
+        self.set_flag(FLAG_N, result & 0x8000_0000 != 0);
+        self.set_flag(FLAG_Z, result == 0);
+    }
+
+    pub fn spsr(&self) -> u32 {
+        match bank(self.mode()) {
+            0 => self.cpsr,
+            b => self.spsr[b],
+        }
+    }
+
+    pub fn set_spsr(&mut self, value: u32) {
+        let b = bank(self.mode());
+        if b != 0 {
+            self.spsr[b] = value;
+        }
+    }
+
+    /// Writes r15, aligned for the current state
+    pub fn set_pc(&mut self, value: u32) {
+        self.regs[15] = if self.is_thumb() {
+            value & !1
+        } else {
+            value & !3
+        };
+        self.branched = true;
+    }
+
+    pub fn set_reg(&mut self, reg: usize, value: u32) {
+        if reg == 15 {
+            self.set_pc(value);
+        } else {
+            self.regs[reg] = value;
+        }
+    }
+
+    pub fn switch_mode(&mut self, mode: u32) {
+        let old = self.mode();
+        let (old_bank, new_bank) = (bank(old), bank(mode));
+
+        if old_bank != new_bank {
+            self.banked_sp_lr[old_bank] = [self.regs[13], self.regs[14]];
+            [self.regs[13], self.regs[14]] = self.banked_sp_lr[new_bank];
+
+            if old == MODE_FIQ {
+                self.fiq_r8_r12.copy_from_slice(&self.regs[8..13]);
+                self.regs[8..13].copy_from_slice(&self.usr_r8_r12);
+            } else if mode == MODE_FIQ {
+                self.usr_r8_r12.copy_from_slice(&self.regs[8..13]);
+                self.regs[8..13].copy_from_slice(&self.fiq_r8_r12);
+            }
+        }
+
+        self.cpsr = (self.cpsr & !MODE_MASK) | mode;
+    }
+
+    /// Replaces the whole CPSR, switching register banks if the mode changed
+    pub fn set_cpsr(&mut self, value: u32) {
+        self.switch_mode(value & MODE_MASK);
+        self.cpsr = value;
+    }
+
+    /// Registers as seen from user mode, for `LDM`/`STM` with the S bit set
+    pub fn user_reg(&self, reg: usize) -> u32 {
+        let mode = self.mode();
+        match reg {
+            8..=12 if mode == MODE_FIQ => self.usr_r8_r12[reg - 8],
+            13 | 14 if bank(mode) != 0 => self.banked_sp_lr[0][reg - 13],
+            _ => self.regs[reg],
+        }
+    }
+
+    pub fn set_user_reg(&mut self, reg: usize, value: u32) {
+        let mode = self.mode();
+        match reg {
+            8..=12 if mode == MODE_FIQ => self.usr_r8_r12[reg - 8] = value,
+            13 | 14 if bank(mode) != 0 => self.banked_sp_lr[0][reg - 13] = value,
+            _ => self.set_reg(reg, value),
+        }
+    }
+
+    /// Enters an exception, `return_addr` ends up in the new mode's LR
+    pub fn exception(&mut self, vector: u32, mode: u32, return_addr: u32) {
+        let cpsr = self.cpsr;
+        self.switch_mode(mode);
+        self.set_spsr(cpsr);
+        self.regs[14] = return_addr;
+        self.cpsr &= !FLAG_T;
+        self.cpsr |= FLAG_I;
+        self.set_pc(vector);
+    }
+
+    /// Takes the IRQ exception if one is pending and not masked
+    pub fn check_irq(&mut self, bus: &Bus) -> bool {
+        if self.flag(FLAG_I) || !bus.irq_pending() {
+            return false;
+        }
+        // r15 holds the next instruction, the handler returns with `SUBS PC, LR, #4`
+        let next = self.regs[15];
+        self.exception(VECTOR_IRQ, MODE_IRQ, next + 4);
+        true
+    }
+
+    pub fn condition(&self, cond: u32) -> bool {
+        let (n, z, c, v) = (
+            self.flag(FLAG_N),
+            self.flag(FLAG_Z),
+            self.flag(FLAG_C),
+            self.flag(FLAG_V),
+        );
+        match cond {
+            0x0 => z,
+            0x1 => !z,
+            0x2 => c,
+            0x3 => !c,
+            0x4 => n,
+            0x5 => !n,
+            0x6 => v,
+            0x7 => !v,
+            0x8 => c && !z,
+            0x9 => !c || z,
+            0xA => n == v,
+            0xB => n != v,
+            0xC => !z && n == v,
+            0xD => z || n != v,
+            0xE => true,
+            // NV, never on ARMv4
+            _ => false,
+        }
+    }
+
+    /// The barrel shifter, returns the result and the carry out. `immediate` selects the
+    /// encoding quirks of shift-by-immediate (LSR/ASR #0 mean #32, ROR #0 means RRX).
+    pub fn shift(&self, kind: ShiftKind, value: u32, amount: u32, immediate: bool) -> (u32, bool) {
+        let carry = self.flag(FLAG_C);
+        if amount == 0 && !(immediate && kind != ShiftKind::Lsl) {
+            return (value, carry);
+        }
+
+        match kind {
+            ShiftKind::Lsl => match amount {
+                1..=31 => (value << amount, value & (1 << (32 - amount)) != 0),
+                32 => (0, value & 1 != 0),
+                _ => (0, false),
+            },
+            ShiftKind::Lsr => {
+                let amount = if amount == 0 { 32 } else { amount };
+                match amount {
+                    1..=31 => (value >> amount, value & (1 << (amount - 1)) != 0),
+                    32 => (0, value & 0x8000_0000 != 0),
+                    _ => (0, false),
+                }
+            }
+            ShiftKind::Asr => {
+                let amount = if amount == 0 { 32 } else { amount };
+                match amount {
+                    1..=31 => (
+                        ((value as i32) >> amount) as u32,
+                        value & (1 << (amount - 1)) != 0,
+                    ),
+                    _ => (((value as i32) >> 31) as u32, value & 0x8000_0000 != 0),
+                }
+            }
+            ShiftKind::Ror => {
+                if amount == 0 {
+                    // RRX
+                    (((carry as u32) << 31) | (value >> 1), value & 1 != 0)
+                } else if amount & 31 == 0 {
+                    (value, value & 0x8000_0000 != 0)
+                } else {
+                    let amount = amount & 31;
+                    (value.rotate_right(amount), value & (1 << (amount - 1)) != 0)
+                }
+            }
+        }
+    }
+
+    /// `a + b + carry`, returns the result, carry and overflow
+    pub fn add_with_carry(a: u32, b: u32, carry: bool) -> (u32, bool, bool) {
+        let wide = a as u64 + b as u64 + carry as u64;
+        let result = wide as u32;
+        let overflow = (!(a ^ b) & (a ^ result)) & 0x8000_0000 != 0;
+        (result, wide > 0xFFFF_FFFF, overflow)
+    }
+
+    pub fn add(&mut self, a: u32, b: u32, carry: bool, set_flags: bool) -> u32 {
+        let (result, c, v) = Cpu::add_with_carry(a, b, carry);
+        if set_flags {
+            self.set_nz(result);
+            self.set_flag(FLAG_C, c);
+            self.set_flag(FLAG_V, v);
+        }
+        result
+    }
+
+    /// `a - b - !carry`, the ARM carry flag is an inverted borrow
+    pub fn sub(&mut self, a: u32, b: u32, carry: bool, set_flags: bool) -> u32 {
+        self.add(a, !b, carry, set_flags)
+    }
+
+    /// Executes one instruction and returns roughly how many cycles it took
+    pub fn step(&mut self, bus: &mut Bus) -> u32 {
+        let addr = self.regs[15];
+        self.branched = false;
+
+        if self.is_thumb() {
+            let op = bus.read16(addr);
+            self.regs[15] = addr.wrapping_add(4);
+            let cycles = self.execute_thumb(bus, op);
+            if !self.branched {
+                self.regs[15] = addr.wrapping_add(2);
+            }
+            cycles
+        } else {
+            let op = bus.read32(addr);
+            self.regs[15] = addr.wrapping_add(8);
+            let cycles = if self.condition(op >> 28) {
+                self.execute_arm(bus, op)
+            } else {
+                1
+            };
+            if !self.branched {
+                self.regs[15] = addr.wrapping_add(4);
+            }
+            cycles
+        }
+    }
+
+    /// Whether the last executed instruction changed r15
+    pub fn branched(&self) -> bool {
+        self.branched
+    }
+
+    /// Address of the instruction after the one executing
+    pub fn next_instruction(&self) -> u32 {
+        if self.is_thumb() {
+            self.regs[15].wrapping_sub(2)
+        } else {
+            self.regs[15].wrapping_sub(4)
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use alloc::vec::Vec;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::Rgb565,
+    primitives::Rectangle,
+};
+
+use crate::{emu::Emulator, input::InputStatus};
+
+use super::{
+    bios::BIOS_IF,
+    bus::Bus,
+    cpu::{Cpu, MODE_IRQ},
+    ppu::{HEIGHT, WIDTH},
+};
+
+const SCREEN_WIDTH: u32 = 160;
+const SCREEN_HEIGHT: u32 = 128;
+
+// cycles to skip forward while halted
+const HALT_CYCLES: u32 = 16;
+
+fn color(bgr: u16) -> Rgb565 {
+    let r = (bgr & 0x1F) as u8;
+    let g = ((bgr >> 5) & 0x1F) as u8;
+    let b = ((bgr >> 10) & 0x1F) as u8;
+    Rgb565::new(r, g << 1 | g >> 4, b)
+}
+
+pub struct GbaEmulator {
+    pub cpu: Cpu,
+    pub bus: Bus,
+}
+
+impl GbaEmulator {
+    pub fn with_rom(rom: Vec<u8>) -> Self {
+        Self {
+            cpu: Cpu::new(),
+            bus: Bus::new(rom),
+        }
+    }
+
+    /// The reset button
+    pub fn reset(&mut self) {
+        self.cpu = Cpu::new();
+        self.bus.reset();
+    }
+
+    /// The Sprig has no shoulder buttons so L and R are never pressed
+    pub fn set_input(&mut self, input: &InputStatus) {
+        let buttons = [
+            &input.a,
+            &input.b,
+            &input.select,
+            &input.start,
+            &input.right,
+            &input.left,
+            &input.up,
+            &input.down,
+        ];
+        let keys = buttons
+            .iter()
+            .enumerate()
+            .fold(0, |keys, (i, button)| keys | (button.pressed as u16) << i);
+        self.bus.set_keys(keys);
+    }
+
+    /// `IntrWait` keeps halting until the game's handler acknowledges one of the interrupts
+    fn check_intr_wait(&mut self) {
+        let Some(mask) = self.cpu.wait_mask else {
+            return;
+        };
+        if self.cpu.mode() == MODE_IRQ {
+            return;
+        }
+
+        let flags = self.bus.read16(BIOS_IF);
+        if flags & mask != 0 {
+            self.bus.write16(BIOS_IF, flags & !mask);
+            self.cpu.wait_mask = None;
+        } else {
+            self.bus.halted = true;
+        }
+    }
+
+    /// Runs until the start of the next vblank
+    pub fn run_frame(&mut self) {
+        self.bus.ppu.frame_ready = false;
+
+        while !self.bus.ppu.frame_ready {
+            let cycles = if self.bus.halted {
+                HALT_CYCLES
+            } else {
+                self.cpu.step(&mut self.bus)
+            };
+            self.bus.tick(cycles);
+
+            if self.bus.halted && self.bus.irq_wake() {
+                self.bus.halted = false;
+            }
+            if !self.bus.halted {
+                self.cpu.check_irq(&self.bus);
+                self.check_intr_wait();
+            }
+        }
+    }
+
+    pub fn frame(&self) -> &[u16] {
+        &self.bus.ppu.frame
+    }
+
+    /// Draws the last frame, scaled down to the Sprig's screen
+    pub fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        // the screen is smaller than the GBA's, so skip every third column and fifth row
+        let frame = self.frame();
+        let pixels = (0..SCREEN_HEIGHT).flat_map(|y| {
+            let src_y = (y * HEIGHT as u32 / SCREEN_HEIGHT) as usize;
+            (0..SCREEN_WIDTH).map(move |x| {
+                let src_x = (x * WIDTH as u32 / SCREEN_WIDTH) as usize;
+                color(frame[src_y * WIDTH + src_x])
+            })
+        });
+
+        display.fill_contiguous(
+            &Rectangle::new(Point::zero(), Size::new(SCREEN_WIDTH, SCREEN_HEIGHT)),
+            pixels,
+        )
+    }
+}
+
+impl<D> Emulator<D> for GbaEmulator
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    fn new(_display: &mut D) -> Self {
+        // no cartridge, the BIOS would sit on its splash screen
+        Self::with_rom(Vec::new())
+    }
+
+    fn tick(&mut self, display: &mut D) -> Result<(), D::Error> {
+        self.run_frame();
+        self.draw(display)
+    }
+}
@@ -0,0 +1,96 @@
+mod arm;
+pub mod bios;
+pub mod bus;
+pub mod cpu;
+pub mod emu;
+pub mod ppu;
+mod thumb;
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::{bus::Bus, cpu::Cpu, emu::GbaEmulator};
+
+    fn run(program: &[u32], steps: usize) -> Cpu {
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_le_bytes()).collect();
+        let mut bus = Bus::new(rom);
+        let mut cpu = Cpu::new();
+        for _ in 0..steps {
+            cpu.step(&mut bus);
+        }
+        cpu
+    }
+
+    #[test]
+    fn test_arm_data_processing() {
+        let cpu = run(
+            &[
+                0xE3A00005, // mov r0, #5
+                0xE0801100, // add r1, r0, r0, lsl #2
+                0xE2512019, // subs r2, r1, #25
+            ],
+            3,
+        );
+        assert_eq!(cpu.regs[1], 25);
+        assert_eq!(cpu.regs[2], 0);
+        assert!(cpu.flag(super::cpu::FLAG_Z));
+        assert!(cpu.flag(super::cpu::FLAG_C));
+    }
+
+    #[test]
+    fn test_thumb_switch_and_stack() {
+        let cpu = run(
+            &[
+                0xE28F0001, // add r0, pc, #1
+                0xE12FFF10, // bx r0
+                0x00CA210A, // movs r1, #10; lsls r2, r1, #3
+                0xBC18B406, // push {r1, r2}; pop {r3, r4}
+            ],
+            6,
+        );
+        assert!(cpu.is_thumb());
+        assert_eq!(cpu.regs[3], 10);
+        assert_eq!(cpu.regs[4], 80);
+        assert_eq!(cpu.regs[13], 0x03007F00);
+        assert_eq!(cpu.regs[15], 0x08000010);
+    }
+
+    #[test]
+    fn test_bios_div() {
+        let cpu = run(
+            &[
+                0xE3E00063, // mvn r0, #99 (-100)
+                0xE3A01007, // mov r1, #7
+                0xEF060000, // swi 0x06
+            ],
+            3,
+        );
+        assert_eq!(cpu.regs[0] as i32, -14);
+        assert_eq!(cpu.regs[1] as i32, -2);
+        assert_eq!(cpu.regs[3], 14);
+    }
+
+    /// jsmolka's gba-tests, put any of them in `tests/roms/gba` and run the ignored tests to
+    /// run them. Failing tests leave their number in r12.
+    #[test]
+    #[ignore = "needs test ROMs in tests/roms/gba"]
+    fn test_roms() {
+        let dir = std::fs::read_dir("tests/roms/gba").unwrap();
+        let mut ran = 0;
+        for entry in dir.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|ext| ext != "gba") {
+                continue;
+            }
+
+            let mut emu = GbaEmulator::with_rom(std::fs::read(&path).unwrap());
+            for _ in 0..60 {
+                emu.run_frame();
+            }
+            assert_eq!(emu.cpu.regs[12], 0, "{} failed", path.display());
+            ran += 1;
+        }
+        assert!(ran > 0, "no test ROMs in tests/roms/gba");
+    }
+}
@@ -0,0 +1,379 @@
+use alloc::{vec, vec::Vec};
+
+pub const WIDTH: usize = 240;
+pub const HEIGHT: usize = 160;
+
+const HDRAW_CYCLES: u32 = 960;
+const LINE_CYCLES: u32 = 1232;
+const LINES: u16 = 228;
+
+const DISPCNT: usize = 0x00;
+const DISPSTAT: usize = 0x04;
+const VCOUNT: usize = 0x06;
+const BG0CNT: usize = 0x08;
+const BG0HOFS: usize = 0x10;
+
+const STAT_VBLANK: u16 = 1 << 0;
+const STAT_HBLANK: u16 = 1 << 1;
+const STAT_VCOUNT: u16 = 1 << 2;
+const STAT_VBLANK_IRQ: u16 = 1 << 3;
+const STAT_HBLANK_IRQ: u16 = 1 << 4;
+const STAT_VCOUNT_IRQ: u16 = 1 << 5;
+
+pub const IRQ_VBLANK: u16 = 1 << 0;
+pub const IRQ_HBLANK: u16 = 1 << 1;
+pub const IRQ_VCOUNT: u16 = 1 << 2;
+
+const OBJ_VRAM: usize = 0x10000;
+const OBJ_PALETTE: usize = 0x200;
+
+// [shape][size] in pixels
+const OBJ_SIZES: [[(u32, u32); 4]; 3] = [
+    [(8, 8), (16, 16), (32, 32), (64, 64)],
+    [(16, 8), (32, 8), (32, 16), (64, 32)],
+    [(8, 16), (8, 32), (16, 32), (32, 64)],
+];
+
+/// What happened during a `Ppu::tick`, the bus uses it to raise interrupts and start DMAs
+#[derive(Default)]
+pub struct Events {
+    pub irq: u16,
+    pub hblank: bool,
+    pub vblank: bool,
+}
+
+#[derive(Clone, Copy)]
+struct Pixel {
+    color: u16,
+    priority: u8,
+}
+
+/// Display timing and a scanline renderer. Only the text backgrounds, bitmap modes and regular
+/// sprites are drawn; affine layers, windows and blending are not handled yet.
+pub struct Ppu {
+    cycles: u32,
+    line: u16,
+    in_hblank: bool,
+    /// The last finished frame as BGR555
+    pub frame: Vec<u16>,
+    pub frame_ready: bool,
+}
+
+fn read16(mem: &[u8], addr: usize) -> u16 {
+    match mem.get(addr..addr + 2) {
+        Some(bytes) => u16::from_le_bytes([bytes[0], bytes[1]]),
+        None => 0,
+    }
+}
+
+fn write16(mem: &mut [u8], addr: usize, value: u16) {
+    mem[addr..addr + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+impl Ppu {
+    pub fn new() -> Self {
+        Self {
+            cycles: 0,
+            line: 0,
+            in_hblank: false,
+            frame: vec![0; WIDTH * HEIGHT],
+            frame_ready: false,
+        }
+    }
+
+    pub fn tick(
+        &mut self,
+        cycles: u32,
+        io: &mut [u8],
+        palette: &[u8],
+        vram: &[u8],
+        oam: &[u8],
+    ) -> Events {
+        let mut events = Events::default();
+        self.cycles += cycles;
+
+        loop {
+            let stat = read16(io, DISPSTAT);
+            if !self.in_hblank && self.cycles >= HDRAW_CYCLES {
+                self.in_hblank = true;
+                if (self.line as usize) < HEIGHT {
+                    self.render_line(io, palette, vram, oam);
+                    events.hblank = true;
+                }
+                write16(io, DISPSTAT, stat | STAT_HBLANK);
+                if stat & STAT_HBLANK_IRQ != 0 {
+                    events.irq |= IRQ_HBLANK;
+                }
+            } else if self.cycles >= LINE_CYCLES {
+                self.cycles -= LINE_CYCLES;
+                self.in_hblank = false;
+                self.line = (self.line + 1) % LINES;
+                write16(io, VCOUNT, self.line);
+
+                let mut stat = stat & !(STAT_HBLANK | STAT_VCOUNT);
+                if self.line as usize == HEIGHT {
+                    stat |= STAT_VBLANK;
+                    events.vblank = true;
+                    self.frame_ready = true;
+                    if stat & STAT_VBLANK_IRQ != 0 {
+                        events.irq |= IRQ_VBLANK;
+                    }
+                } else if self.line == LINES - 1 {
+                    // the flag drops on the last line, not line 0
+                    stat &= !STAT_VBLANK;
+                }
+                if self.line == stat >> 8 {
+                    stat |= STAT_VCOUNT;
+                    if stat & STAT_VCOUNT_IRQ != 0 {
+                        events.irq |= IRQ_VCOUNT;
+                    }
+                }
+                write16(io, DISPSTAT, stat);
+            } else {
+                return events;
+            }
+        }
+    }
+
+    fn render_line(&mut self, io: &[u8], palette: &[u8], vram: &[u8], oam: &[u8]) {
+        let line = self.line as usize;
+        let dispcnt = read16(io, DISPCNT);
+        let out = &mut self.frame[line * WIDTH..(line + 1) * WIDTH];
+
+        // forced blank
+        if dispcnt & (1 << 7) != 0 {
+            out.fill(0x7FFF);
+            return;
+        }
+
+        let backdrop = read16(palette, 0);
+        let mut bgs = [[None; WIDTH]; 4];
+
+        match dispcnt & 7 {
+            0 => {
+                for (bg, out) in bgs.iter_mut().enumerate() {
+                    if dispcnt & (1 << (8 + bg)) != 0 {
+                        text_bg(bg, line, io, palette, vram, out);
+                    }
+                }
+            }
+            1 => {
+                // 2 is affine, which isn't drawn yet
+                for (bg, out) in bgs.iter_mut().enumerate().take(2) {
+                    if dispcnt & (1 << (8 + bg)) != 0 {
+                        text_bg(bg, line, io, palette, vram, out);
+                    }
+                }
+            }
+            mode @ 3..=5 if dispcnt & (1 << 10) != 0 => {
+                let priority = (read16(io, BG0CNT + 4) & 3) as u8;
+                let page = if dispcnt & (1 << 4) != 0 { 0xA000 } else { 0 };
+                for (x, pixel) in bgs[2].iter_mut().enumerate() {
+                    let color = match mode {
+                        3 => Some(read16(vram, (line * WIDTH + x) * 2)),
+                        4 => match vram[page + line * WIDTH + x] {
+                            0 => None,
+                            index => Some(read16(palette, index as usize * 2)),
+                        },
+                        // mode 5 is a smaller 160x128 frame
+                        _ if x < 160 && line < 128 => {
+                            Some(read16(vram, page + (line * 160 + x) * 2))
+                        }
+                        _ => None,
+                    };
+                    *pixel = color.map(|color| Pixel { color, priority });
+                }
+            }
+            _ => {}
+        }
+
+        let mut objs = [None; WIDTH];
+        if dispcnt & (1 << 12) != 0 {
+            sprites(line, dispcnt, palette, vram, oam, &mut objs);
+        }
+
+        for (x, out) in out.iter_mut().enumerate() {
+            let mut color = backdrop;
+            'priority: for priority in 0..4 {
+                if let Some(obj) = objs[x] {
+                    if obj.priority == priority {
+                        color = obj.color;
+                        break 'priority;
+                    }
+                }
+                for bg in &bgs {
+                    if let Some(pixel) = bg[x] {
+                        if pixel.priority == priority {
+                            color = pixel.color;
+                            break 'priority;
+                        }
+                    }
+                }
+            }
+            *out = color & 0x7FFF;
+        }
+    }
+}
+
+fn text_bg(
+    bg: usize,
+    line: usize,
+    io: &[u8],
+    palette: &[u8],
+    vram: &[u8],
+    out: &mut [Option<Pixel>; WIDTH],
+) {
+    let cnt = read16(io, BG0CNT + bg * 2);
+    let hofs = (read16(io, BG0HOFS + bg * 4) & 0x1FF) as usize;
+    let vofs = (read16(io, BG0HOFS + bg * 4 + 2) & 0x1FF) as usize;
+
+    let priority = (cnt & 3) as u8;
+    let char_base = ((cnt >> 2) & 3) as usize * 0x4000;
+    let color_256 = cnt & (1 << 7) != 0;
+    let screen_base = ((cnt >> 8) & 0x1F) as usize * 0x800;
+    let (width, height) = match cnt >> 14 {
+        0 => (256, 256),
+        1 => (512, 256),
+        2 => (256, 512),
+        _ => (512, 512),
+    };
+
+    let y = (line + vofs) % height;
+    for (screen_x, pixel) in out.iter_mut().enumerate() {
+        let x = (screen_x + hofs) % width;
+
+        let block = (x / 256) + (y / 256) * (width / 256);
+        let entry_addr = screen_base + block * 0x800 + ((y % 256) / 8) * 64 + ((x % 256) / 8) * 2;
+        let entry = read16(vram, entry_addr) as usize;
+
+        let tile = entry & 0x3FF;
+        let mut px = x % 8;
+        let mut py = y % 8;
+        if entry & (1 << 10) != 0 {
+            px = 7 - px;
+        }
+        if entry & (1 << 11) != 0 {
+            py = 7 - py;
+        }
+
+        let index = if color_256 {
+            let addr = char_base + tile * 64 + py * 8 + px;
+            // tiles can't come from sprite VRAM
+            if addr >= OBJ_VRAM {
+                continue;
+            }
+            vram[addr] as usize
+        } else {
+            let addr = char_base + tile * 32 + py * 4 + px / 2;
+            if addr >= OBJ_VRAM {
+                continue;
+            }
+            match (vram[addr] >> ((px & 1) * 4)) & 0xF {
+                0 => 0,
+                index => (entry >> 12) * 16 + index as usize,
+            }
+        };
+
+        if index != 0 {
+            *pixel = Some(Pixel {
+                color: read16(palette, index * 2),
+                priority,
+            });
+        }
+    }
+}
+
+fn sprites(
+    line: usize,
+    dispcnt: u16,
+    palette: &[u8],
+    vram: &[u8],
+    oam: &[u8],
+    out: &mut [Option<Pixel>; WIDTH],
+) {
+    let mapping_1d = dispcnt & (1 << 6) != 0;
+
+    for obj in oam.chunks_exact(8).take(128) {
+        let attr0 = u16::from_le_bytes([obj[0], obj[1]]);
+        let attr1 = u16::from_le_bytes([obj[2], obj[3]]);
+        let attr2 = u16::from_le_bytes([obj[4], obj[5]]);
+
+        // affine sprites aren't supported, bit 9 on a regular sprite hides it
+        if attr0 & (1 << 8) != 0 || attr0 & (1 << 9) != 0 {
+            continue;
+        }
+        // object window
+        if (attr0 >> 10) & 3 == 2 {
+            continue;
+        }
+
+        let shape = (attr0 >> 14) as usize;
+        if shape == 3 {
+            continue;
+        }
+        let (width, height) = OBJ_SIZES[shape][(attr1 >> 14) as usize];
+
+        let y = (attr0 & 0xFF) as u32;
+        let row = (line as u32).wrapping_sub(y) & 0xFF;
+        if row >= height {
+            continue;
+        }
+
+        let x = (attr1 & 0x1FF) as i32;
+        let x = if x >= 256 { x - 512 } else { x };
+
+        let color_256 = attr0 & (1 << 13) != 0;
+        let tile = (attr2 & 0x3FF) as u32;
+        let priority = ((attr2 >> 10) & 3) as u8;
+        let bank = (attr2 >> 12) as usize;
+
+        let py = if attr1 & (1 << 13) != 0 {
+            height - 1 - row
+        } else {
+            row
+        };
+
+        for col in 0..width {
+            let screen_x = x + col as i32;
+            if !(0..WIDTH as i32).contains(&screen_x) {
+                continue;
+            }
+            let screen_x = screen_x as usize;
+            if let Some(existing) = out[screen_x] {
+                if existing.priority <= priority {
+                    continue;
+                }
+            }
+
+            let px = if attr1 & (1 << 12) != 0 {
+                width - 1 - col
+            } else {
+                col
+            };
+
+            // tile numbers count 32 byte blocks, 256 colour tiles take two
+            let step = if color_256 { 2 } else { 1 };
+            let row_stride = if mapping_1d { width / 8 * step } else { 32 };
+            let tile = (tile + (py / 8) * row_stride + (px / 8) * step) & 0x3FF;
+            let base = OBJ_VRAM + tile as usize * 32;
+            let (px, py) = ((px % 8) as usize, (py % 8) as usize);
+
+            let index = if color_256 {
+                vram.get(base + py * 8 + px).copied().unwrap_or(0) as usize
+            } else {
+                let byte = vram.get(base + py * 4 + px / 2).copied().unwrap_or(0);
+                match (byte >> ((px & 1) * 4)) & 0xF {
+                    0 => 0,
+                    index => bank * 16 + index as usize,
+                }
+            };
+
+            if index != 0 {
+                out[screen_x] = Some(Pixel {
+                    color: read16(palette, OBJ_PALETTE + index * 2),
+                    priority,
+                });
+            }
+        }
+    }
+}
@@ -0,0 +1,386 @@
+use super::{
+    bios,
+    bus::Bus,
+    cpu::{Cpu, ShiftKind, FLAG_C, FLAG_T, MODE_UND, VECTOR_UNDEFINED},
+};
+
+impl Cpu {
+    pub(super) fn execute_thumb(&mut self, bus: &mut Bus, op: u16) -> u32 {
+        let op = op as u32;
+        match op >> 8 {
+            0x00..=0x17 => self.thumb_move_shifted(op),
+            0x18..=0x1F => self.thumb_add_sub(op),
+            0x20..=0x3F => self.thumb_immediate(op),
+            0x40..=0x43 => self.thumb_alu(op),
+            0x44..=0x47 => self.thumb_hi_register(op),
+            0x48..=0x4F => self.thumb_pc_load(bus, op),
+            0x50..=0x5F if op & (1 << 9) == 0 => self.thumb_register_offset(bus, op),
+            0x50..=0x5F => self.thumb_signed_transfer(bus, op),
+            0x60..=0x7F => self.thumb_immediate_offset(bus, op),
+            0x80..=0x8F => self.thumb_halfword(bus, op),
+            0x90..=0x9F => self.thumb_sp_relative(bus, op),
+            0xA0..=0xAF => self.thumb_load_address(op),
+            0xB0 => self.thumb_sp_offset(op),
+            0xB4 | 0xB5 | 0xBC | 0xBD => self.thumb_push_pop(bus, op),
+            0xC0..=0xCF => self.thumb_multiple(bus, op),
+            0xDF => bios::swi(self, bus, op as u8),
+            0xD0..=0xDD => self.thumb_conditional_branch(op),
+            0xE0..=0xE7 => self.thumb_branch(op),
+            0xF0..=0xFF => self.thumb_long_branch(op),
+            _ => {
+                let next = self.next_instruction();
+                self.exception(VECTOR_UNDEFINED, MODE_UND, next);
+                3
+            }
+        }
+    }
+
+    fn thumb_move_shifted(&mut self, op: u32) -> u32 {
+        let kind = ShiftKind::from_bits(op >> 11);
+        let amount = (op >> 6) & 0x1F;
+        let rs = ((op >> 3) & 7) as usize;
+        let rd = (op & 7) as usize;
+
+        let (result, carry) = self.shift(kind, self.regs[rs], amount, true);
+        self.regs[rd] = result;
+        self.set_nz(result);
+        self.set_flag(FLAG_C, carry);
+        1
+    }
+
+    fn thumb_add_sub(&mut self, op: u32) -> u32 {
+        let rn_or_imm = (op >> 6) & 7;
+        let rs = ((op >> 3) & 7) as usize;
+        let rd = (op & 7) as usize;
+
+        let operand = if op & (1 << 10) != 0 {
+            rn_or_imm
+        } else {
+            self.regs[rn_or_imm as usize]
+        };
+
+        self.regs[rd] = if op & (1 << 9) != 0 {
+            self.sub(self.regs[rs], operand, true, true)
+        } else {
+            self.add(self.regs[rs], operand, false, true)
+        };
+        1
+    }
+
+    fn thumb_immediate(&mut self, op: u32) -> u32 {
+        let rd = ((op >> 8) & 7) as usize;
+        let imm = op & 0xFF;
+
+        match (op >> 11) & 3 {
+            0 => {
+                self.regs[rd] = imm;
+                self.set_nz(imm);
+            }
+            1 => {
+                self.sub(self.regs[rd], imm, true, true);
+            }
+            2 => self.regs[rd] = self.add(self.regs[rd], imm, false, true),
+            _ => self.regs[rd] = self.sub(self.regs[rd], imm, true, true),
+        }
+        1
+    }
+
+    fn thumb_alu(&mut self, op: u32) -> u32 {
+        let rs = ((op >> 3) & 7) as usize;
+        let rd = (op & 7) as usize;
+        let (a, b) = (self.regs[rd], self.regs[rs]);
+        let carry = self.flag(FLAG_C);
+
+        let logical = |cpu: &mut Cpu, result: u32| {
+            cpu.set_nz(result);
+            result
+        };
+        let shifted = |cpu: &mut Cpu, kind: ShiftKind| {
+            let (result, carry) = cpu.shift(kind, a, b & 0xFF, false);
+            cpu.set_nz(result);
+            cpu.set_flag(FLAG_C, carry);
+            result
+        };
+
+        let (result, write) = match (op >> 6) & 0xF {
+            0x0 => (logical(self, a & b), true),
+            0x1 => (logical(self, a ^ b), true),
+            0x2 => (shifted(self, ShiftKind::Lsl), true),
+            0x3 => (shifted(self, ShiftKind::Lsr), true),
+            0x4 => (shifted(self, ShiftKind::Asr), true),
+            0x5 => (self.add(a, b, carry, true), true),
+            0x6 => (self.sub(a, b, carry, true), true),
+            0x7 => (shifted(self, ShiftKind::Ror), true),
+            0x8 => (logical(self, a & b), false),
+            0x9 => (self.sub(0, b, true, true), true),
+            0xA => (self.sub(a, b, true, true), false),
+            0xB => (self.add(a, b, false, true), false),
+            0xC => (logical(self, a | b), true),
+            0xD => (logical(self, a.wrapping_mul(b)), true),
+            0xE => (logical(self, a & !b), true),
+            _ => (logical(self, !b), true),
+        };
+
+        if write {
+            self.regs[rd] = result;
+        }
+
+        match (op >> 6) & 0xF {
+            0x2 | 0x3 | 0x4 | 0x7 => 2,
+            0xD => 3,
+            _ => 1,
+        }
+    }
+
+    fn thumb_hi_register(&mut self, op: u32) -> u32 {
+        let rs = (((op >> 3) & 7) | ((op >> 3) & 8)) as usize;
+        let rd = ((op & 7) | ((op >> 4) & 8)) as usize;
+        let value = self.regs[rs];
+
+        match (op >> 8) & 3 {
+            0 => {
+                let result = self.regs[rd].wrapping_add(value);
+                self.set_reg(rd, result);
+            }
+            1 => {
+                self.sub(self.regs[rd], value, true, true);
+            }
+            2 => self.set_reg(rd, value),
+            _ => {
+                self.set_flag(FLAG_T, value & 1 != 0);
+                self.set_pc(value);
+            }
+        }
+
+        if self.branched() {
+            3
+        } else {
+            1
+        }
+    }
+
+    fn thumb_pc_load(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let rd = ((op >> 8) & 7) as usize;
+        let addr = (self.regs[15] & !2).wrapping_add((op & 0xFF) << 2);
+        self.regs[rd] = bus.read32(addr);
+        3
+    }
+
+    fn thumb_register_offset(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let ro = ((op >> 6) & 7) as usize;
+        let rb = ((op >> 3) & 7) as usize;
+        let rd = (op & 7) as usize;
+        let addr = self.regs[rb].wrapping_add(self.regs[ro]);
+
+        match (op >> 10) & 3 {
+            0 => bus.write32(addr, self.regs[rd]),
+            1 => bus.write8(addr, self.regs[rd] as u8),
+            2 => self.regs[rd] = bus.read32_rotated(addr),
+            _ => self.regs[rd] = bus.read8(addr) as u32,
+        }
+
+        if op & (1 << 11) != 0 {
+            3
+        } else {
+            2
+        }
+    }
+
+    fn thumb_signed_transfer(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let ro = ((op >> 6) & 7) as usize;
+        let rb = ((op >> 3) & 7) as usize;
+        let rd = (op & 7) as usize;
+        let addr = self.regs[rb].wrapping_add(self.regs[ro]);
+
+        match (op >> 10) & 3 {
+            0 => bus.write16(addr, self.regs[rd] as u16),
+            1 => self.regs[rd] = bus.read8(addr) as i8 as i32 as u32,
+            2 => self.regs[rd] = (bus.read16(addr) as u32).rotate_right((addr & 1) * 8),
+            _ => {
+                self.regs[rd] = if addr & 1 != 0 {
+                    bus.read8(addr) as i8 as i32 as u32
+                } else {
+                    bus.read16(addr) as i16 as i32 as u32
+                }
+            }
+        }
+        3
+    }
+
+    fn thumb_immediate_offset(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let offset = (op >> 6) & 0x1F;
+        let rb = ((op >> 3) & 7) as usize;
+        let rd = (op & 7) as usize;
+
+        match (op >> 11) & 3 {
+            0 => bus.write32(self.regs[rb].wrapping_add(offset << 2), self.regs[rd]),
+            1 => self.regs[rd] = bus.read32_rotated(self.regs[rb].wrapping_add(offset << 2)),
+            2 => bus.write8(self.regs[rb].wrapping_add(offset), self.regs[rd] as u8),
+            _ => self.regs[rd] = bus.read8(self.regs[rb].wrapping_add(offset)) as u32,
+        }
+        3
+    }
+
+    fn thumb_halfword(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let offset = ((op >> 6) & 0x1F) << 1;
+        let rb = ((op >> 3) & 7) as usize;
+        let rd = (op & 7) as usize;
+        let addr = self.regs[rb].wrapping_add(offset);
+
+        if op & (1 << 11) != 0 {
+            self.regs[rd] = (bus.read16(addr) as u32).rotate_right((addr & 1) * 8);
+        } else {
+            bus.write16(addr, self.regs[rd] as u16);
+        }
+        3
+    }
+
+    fn thumb_sp_relative(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let rd = ((op >> 8) & 7) as usize;
+        let addr = self.regs[13].wrapping_add((op & 0xFF) << 2);
+
+        if op & (1 << 11) != 0 {
+            self.regs[rd] = bus.read32_rotated(addr);
+        } else {
+            bus.write32(addr, self.regs[rd]);
+        }
+        3
+    }
+
+    fn thumb_load_address(&mut self, op: u32) -> u32 {
+        let rd = ((op >> 8) & 7) as usize;
+        let offset = (op & 0xFF) << 2;
+
+        self.regs[rd] = if op & (1 << 11) != 0 {
+            self.regs[13].wrapping_add(offset)
+        } else {
+            (self.regs[15] & !2).wrapping_add(offset)
+        };
+        1
+    }
+
+    fn thumb_sp_offset(&mut self, op: u32) -> u32 {
+        let offset = (op & 0x7F) << 2;
+        self.regs[13] = if op & (1 << 7) != 0 {
+            self.regs[13].wrapping_sub(offset)
+        } else {
+            self.regs[13].wrapping_add(offset)
+        };
+        1
+    }
+
+    fn thumb_push_pop(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let extra = op & (1 << 8) != 0;
+        let list = op & 0xFF;
+        let count = list.count_ones() + extra as u32;
+
+        if op & (1 << 11) != 0 {
+            // POP {list, pc}
+            let mut addr = self.regs[13];
+            for reg in 0..8 {
+                if list & (1 << reg) != 0 {
+                    self.regs[reg] = bus.read32(addr);
+                    addr = addr.wrapping_add(4);
+                }
+            }
+            if extra {
+                let pc = bus.read32(addr);
+                self.set_pc(pc);
+                addr = addr.wrapping_add(4);
+            }
+            self.regs[13] = addr;
+        } else {
+            // PUSH {list, lr}
+            let mut addr = self.regs[13].wrapping_sub(count * 4);
+            self.regs[13] = addr;
+            for reg in 0..8 {
+                if list & (1 << reg) != 0 {
+                    bus.write32(addr, self.regs[reg]);
+                    addr = addr.wrapping_add(4);
+                }
+            }
+            if extra {
+                bus.write32(addr, self.regs[14]);
+            }
+        }
+        count + 2
+    }
+
+    fn thumb_multiple(&mut self, bus: &mut Bus, op: u32) -> u32 {
+        let rb = ((op >> 8) & 7) as usize;
+        let list = op & 0xFF;
+        let base = self.regs[rb];
+
+        if list == 0 {
+            // like ARM, an empty list transfers r15 and moves the base by 16 words
+            if op & (1 << 11) != 0 {
+                let pc = bus.read32(base);
+                self.set_pc(pc);
+            } else {
+                bus.write32(base, self.regs[15].wrapping_add(2));
+            }
+            self.regs[rb] = base.wrapping_add(0x40);
+            return 3;
+        }
+
+        let new_base = base.wrapping_add(list.count_ones() * 4);
+        let mut addr = base;
+
+        if op & (1 << 11) != 0 {
+            self.regs[rb] = new_base;
+            for reg in 0..8 {
+                if list & (1 << reg) != 0 {
+                    self.regs[reg] = bus.read32(addr);
+                    addr = addr.wrapping_add(4);
+                }
+            }
+        } else {
+            let first = list.trailing_zeros() as usize;
+            for reg in 0..8 {
+                if list & (1 << reg) != 0 {
+                    let value = if reg == rb && reg != first {
+                        new_base
+                    } else {
+                        self.regs[reg]
+                    };
+                    bus.write32(addr, value);
+                    addr = addr.wrapping_add(4);
+                }
+            }
+            self.regs[rb] = new_base;
+        }
+        list.count_ones() + 2
+    }
+
+    fn thumb_conditional_branch(&mut self, op: u32) -> u32 {
+        if !self.condition((op >> 8) & 0xF) {
+            return 1;
+        }
+        let offset = ((op & 0xFF) as i8 as i32 as u32) << 1;
+        self.set_pc(self.regs[15].wrapping_add(offset));
+        3
+    }
+
+    fn thumb_branch(&mut self, op: u32) -> u32 {
+        // 11 bit signed halfword offset
+        let offset = ((((op & 0x7FF) << 21) as i32) >> 20) as u32;
+        self.set_pc(self.regs[15].wrapping_add(offset));
+        3
+    }
+
+    fn thumb_long_branch(&mut self, op: u32) -> u32 {
+        let offset = op & 0x7FF;
+        if op & (1 << 11) == 0 {
+            // first half, the upper bits of the offset go into lr
+            let upper = ((((offset << 21) as i32) >> 9) as u32).wrapping_add(self.regs[15]);
+            self.regs[14] = upper;
+            1
+        } else {
+            let next = self.next_instruction();
+            let target = self.regs[14].wrapping_add(offset << 1);
+            self.regs[14] = next | 1;
+            self.set_pc(target);
+            3
+        }
+    }
+}
@@ -16,7 +16,7 @@ use crate::input::{Button, InputStatus};
 
 use super::screen;
 
-const PICO_FONT: MonoFont = MonoFont {
+pub const PICO_FONT: MonoFont = MonoFont {
     image: ImageRaw::new(include_bytes!("../assets/font.raw"), 128),
     glyph_mapping: &StrGlyphMapping::new(
         "  ! \" # $ % & ' ( ) * + , - . / 0 1 2 3 4 5 6 7 8 9 : ; < = > ? @ A B C D E F G H I J K L M N O P Q R S T U V W X Y Z [ \\ ] ^ _ ` a b c d e f g h i j k l m n o p q r s t u v w x y z { | } ~ \u{80} \u{81}\u{82}\u{83}\u{84}\u{85}\u{86}\u{87}\u{88}\u{89}\u{8A}\u{8B}\u{8C}\u{8D}\u{8E}\u{8F}\u{90}\u{91}\u{92}\u{93}\u{94}\u{95}\u{96}\u{97}\u{98}\u{99}\u{9A}\u{9B}\u{9C}\u{9D}\u{9E}\u{9F}\u{A0}\u{A1}\u{A2}\u{A3}\u{A4}\u{A5}\u{A6}\u{A7}\u{A8}\u{A9}\u{AA}\u{AB}\u{AC}\u{AD}\u{AE}\u{AF}\u{B0}\u{B1}\u{B2}\u{B3}\u{B4}\u{B5}\u{B6}\u{B7}\u{B8}\u{B9}\u{BA}\u{BB}\u{BC}",
@@ -92,7 +92,13 @@ where
         Ok(())
     }
 
-    pub fn update(&mut self, input: &InputStatus, display: &mut D) -> Result<(), D::Error> {
+    pub fn update(
+        &mut self,
+        input: &InputStatus,
+        now_us: u64,
+        display: &mut D,
+    ) -> Result<(), D::Error> {
+        self.screen.set_time(now_us);
         let result = self.screen.update(display, input)?;
         if result.is_some() {
             self.change_screen(result.unwrap(), display)?;
@@ -104,6 +110,11 @@ where
     pub fn events(&mut self) -> Vec<crate::events::Event> {
         self.screen.events()
     }
+
+    /// Takes back the screen being shown, e.g. to return to it from another one
+    pub fn into_screen(self) -> Box<dyn screen::Screen<D>> {
+        self.screen
+    }
 }
 
 // TODO: add x and y
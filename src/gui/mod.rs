@@ -1,3 +1,4 @@
 pub mod core;
+pub mod overlay;
 pub mod screen;
 pub mod screens;
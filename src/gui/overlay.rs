@@ -0,0 +1,241 @@
+use alloc::{collections::VecDeque, format, string::String};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::{Rgb565, RgbColor},
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    text::Text,
+    Drawable,
+};
+
+use crate::{
+    input::InputStatus,
+    nes::{cpu::CPU, emu::NesEmulator, trace::trace},
+    pacer::Speed,
+};
+
+use super::core::{BACKGROUND, GREY_CHAR, NORMAL_TEXT, WHITE_CHAR};
+
+const TOP: i32 = 64;
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 64;
+const LINE_HEIGHT: i32 = 7;
+/// 160 pixels of 4 pixel wide characters
+const LINE_CHARS: usize = 40;
+
+pub const TRACE_LINES: usize = 5;
+/// Redraw at most this often, drawing text over SPI isn't free
+const REFRESH_US: u64 = 250_000;
+
+/// Diagnostics drawn over the bottom half of the screen while a game runs, toggled with
+/// Select+B. The Sprig has no debug probe, so this is the easiest way to see what a game is up
+/// to.
+pub struct DebugOverlay {
+    pub visible: bool,
+    /// Shown when it isn't normal
+    pub speed: Speed,
+    trace: VecDeque<String>,
+    /// Averaged time between frames
+    frame_time_us: u64,
+    /// Emulated frames per second, times 10
+    fps: u64,
+    last_update_us: Option<u64>,
+    last_frame: u64,
+    last_draw_us: u64,
+    window_start_us: u64,
+    window_start_frame: u64,
+}
+
+/// `NV-BDIZC` with clear flags as dots
+pub fn flags(status: u8) -> String {
+    "NV-BDIZC"
+        .chars()
+        .enumerate()
+        .map(
+            |(i, flag)| {
+                if status & (0x80 >> i) != 0 {
+                    flag
+                } else {
+                    '.'
+                }
+            },
+        )
+        .collect()
+}
+
+impl Default for DebugOverlay {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DebugOverlay {
+    pub fn new() -> Self {
+        Self {
+            visible: false,
+            speed: Speed::Normal,
+            trace: VecDeque::with_capacity(TRACE_LINES),
+            frame_time_us: 0,
+            fps: 0,
+            last_update_us: None,
+            last_frame: 0,
+            last_draw_us: 0,
+            window_start_us: 0,
+            window_start_frame: 0,
+        }
+    }
+
+    /// Keeps the instruction about to run, only while the overlay is up since tracing is slow
+    pub fn record(&mut self, cpu: &CPU) {
+        if !self.visible {
+            return;
+        }
+        if self.trace.len() == TRACE_LINES {
+            self.trace.pop_front();
+        }
+        self.trace.push_back(trace(cpu));
+    }
+
+    /// Works out the frame time and FPS, `frame` is the emulated frame number
+    pub fn time(&mut self, now_us: u64, frame: u64) {
+        // updates come between frames too while the pacer waits
+        if frame != self.last_frame || self.last_update_us.is_none() {
+            if let Some(last) = self.last_update_us {
+                let delta = now_us.saturating_sub(last) / frame.abs_diff(self.last_frame).max(1);
+                self.frame_time_us = if self.frame_time_us == 0 {
+                    delta
+                } else {
+                    (self.frame_time_us * 7 + delta) / 8
+                };
+            }
+            self.last_update_us = Some(now_us);
+            self.last_frame = frame;
+        }
+
+        if frame < self.window_start_frame {
+            // rewound or a state was loaded, count from here
+            self.window_start_us = now_us;
+            self.window_start_frame = frame;
+        }
+
+        let elapsed = now_us.saturating_sub(self.window_start_us);
+        if elapsed >= 1_000_000 {
+            self.fps = (frame - self.window_start_frame) * 10_000_000 / elapsed;
+            self.window_start_us = now_us;
+            self.window_start_frame = frame;
+        }
+    }
+
+    /// Call after every emulator tick, handles the toggle and redraws when it's due
+    pub fn update<D>(
+        &mut self,
+        display: &mut D,
+        input: &InputStatus,
+        now_us: u64,
+        emu: &NesEmulator,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        self.time(now_us, emu.frame());
+
+        if input.select.pressed && input.b.should_trigger() {
+            self.visible = !self.visible;
+            self.trace.clear();
+            if !self.visible {
+                return Rectangle::new(Point::new(0, TOP), Size::new(WIDTH, HEIGHT))
+                    .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+                    .draw(display);
+            }
+        } else if !self.visible || now_us.saturating_sub(self.last_draw_us) < REFRESH_US {
+            return Ok(());
+        }
+
+        self.last_draw_us = now_us;
+        self.draw(display, emu)
+    }
+
+    fn draw<D>(&self, display: &mut D, emu: &NesEmulator) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        Rectangle::new(Point::new(0, TOP), Size::new(WIDTH, HEIGHT))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        let cpu = emu.cpu();
+        let status = [
+            format!(
+                "FPS {}.{} HOST {}.{}MS LINE {} {}",
+                self.fps / 10,
+                self.fps % 10,
+                self.frame_time_us / 1000,
+                self.frame_time_us / 100 % 10,
+                emu.scanline(),
+                if self.speed == Speed::Normal {
+                    String::new()
+                } else {
+                    self.speed.label()
+                }
+            ),
+            format!(
+                "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} SP:{:02X}",
+                cpu.program_counter,
+                cpu.register_a,
+                cpu.register_x,
+                cpu.register_y,
+                cpu.stack_pointer
+            ),
+            format!(
+                "P:{:02X} {} CYC:{}",
+                cpu.status.bits(),
+                flags(cpu.status.bits()),
+                cpu.cycles
+            ),
+        ];
+
+        let lines = status
+            .iter()
+            .map(|line| (line, WHITE_CHAR))
+            .chain(self.trace.iter().map(|line| (line, GREY_CHAR)));
+        for (i, (line, style)) in lines.enumerate() {
+            let line = line.get(..LINE_CHARS).unwrap_or(line);
+            Text::with_text_style(
+                line,
+                Point::new(1, TOP + 1 + i as i32 * LINE_HEIGHT),
+                style,
+                NORMAL_TEXT,
+            )
+            .draw(display)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(flags(0b1010_0101), "N.-..I.C");
+
+        let mut overlay = DebugOverlay::new();
+        overlay.time(0, 0);
+        overlay.time(16_000, 1);
+        // the pacer waiting
+        overlay.time(20_000, 1);
+        overlay.time(32_000, 2);
+        assert_eq!(overlay.frame_time_us, 16_000);
+        overlay.time(1_000_000, 60);
+        assert_eq!(overlay.fps, 600);
+
+        // going back in time starts the count again instead of underflowing
+        overlay.time(1_500_000, 90);
+        overlay.time(2_100_000, 20);
+        assert_eq!(overlay.fps, 600);
+        overlay.time(3_100_000, 80);
+        assert_eq!(overlay.fps, 600);
+    }
+}
@@ -15,4 +15,6 @@ where
         input: &InputStatus,
     ) -> Result<Option<Box<dyn Screen<D>>>, D::Error>;
     fn events(&mut self) -> Vec<Event>;
+    /// The device's clock in microseconds, given before every update to screens that keep time
+    fn set_time(&mut self, _now_us: u64) {}
 }
@@ -0,0 +1,259 @@
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::Rgb565,
+    primitives::{Primitive, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::Text,
+    Drawable,
+};
+
+use crate::{
+    events::Event,
+    gui::{
+        core::{
+            draw_inputs, BACKGROUND, BLACK_CHAR, CENTERED_TEXT, GREY_CHAR, INNER_BORDER,
+            INNER_BORDER_CLR, NORMAL_TEXT, OUTER_BORDER_CLR, WHITE_CHAR,
+        },
+        screen::Screen,
+    },
+    input::{Button, InputStatus},
+    nes::emu::NesEmulator,
+};
+
+const TOP: i32 = 22;
+const ROW_HEIGHT: i32 = 8;
+const ROWS: usize = 11;
+/// 160 pixels of 4 pixel wide characters, less the margins
+const LINE_CHARS: usize = 38;
+
+const CURSOR: PrimitiveStyle<Rgb565> = PrimitiveStyleBuilder::new()
+    .stroke_width(1)
+    .stroke_color(INNER_BORDER_CLR)
+    .build();
+
+/// The cheats loaded for the running NES game. The game is paused while it's open, A turns the
+/// selected cheat on or off and B goes back.
+pub struct CheatsScreen {
+    emu: Option<NesEmulator>,
+    selected: usize,
+    /// The first row shown
+    scroll: usize,
+    events: Vec<Event>,
+}
+
+impl CheatsScreen {
+    pub fn new(emu: NesEmulator) -> Self {
+        Self {
+            emu: Some(emu),
+            selected: 0,
+            scroll: 0,
+            events: vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.emu.as_ref().map_or(0, |emu| emu.cheats().list.len())
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + ROWS {
+            self.scroll = self.selected + 1 - ROWS;
+        }
+    }
+
+    fn draw_list<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+        Rectangle::new(
+            Point::new(0, TOP - 2),
+            Size::new(size.width, ROWS as u32 * ROW_HEIGHT as u32 + 2),
+        )
+        .into_styled(BACKGROUND)
+        .draw(display)?;
+
+        let Some(emu) = &self.emu else {
+            return Ok(());
+        };
+        let cheats = &emu.cheats().list;
+        if cheats.is_empty() {
+            return Text::with_text_style(
+                "No cheats",
+                Point::new(size.width as i32 / 2, TOP + 2 * ROW_HEIGHT),
+                GREY_CHAR,
+                CENTERED_TEXT,
+            )
+            .draw(display)
+            .map(|_| ());
+        }
+
+        for (row, (i, cheat)) in cheats
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(ROWS)
+            .enumerate()
+        {
+            let point = Point::new(4, TOP + row as i32 * ROW_HEIGHT);
+            let name = if cheat.name.is_empty() {
+                &cheat.code
+            } else {
+                &cheat.name
+            };
+            let line = format!(
+                "{} {:8} {}",
+                if cheat.enabled { "ON " } else { "OFF" },
+                cheat.code,
+                name
+            );
+            let line = line.get(..LINE_CHARS).unwrap_or(&line);
+            let style = if cheat.enabled { WHITE_CHAR } else { GREY_CHAR };
+            Text::with_text_style(line, point, style, NORMAL_TEXT).draw(display)?;
+
+            if i == self.selected {
+                Rectangle::new(
+                    point - Point::new(2, 1),
+                    Size::new(size.width - 4, ROW_HEIGHT as u32),
+                )
+                .into_styled(CURSOR)
+                .draw(display)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<D> Screen<D> for CheatsScreen
+where
+    D: DrawTarget<Color = Rgb565> + OriginDimensions,
+{
+    fn draw(&mut self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(OUTER_BORDER_CLR)?;
+        let size = display.size();
+
+        Rectangle::new(Point::new(0, 8), Size::new(size.width, size.height - 18))
+            .into_styled(INNER_BORDER)
+            .draw(display)?;
+
+        Rectangle::new(Point::new(0, 18), Size::new(size.width, size.height - 36))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        Text::with_text_style(
+            "Cheats",
+            Point::new(size.width as i32 / 2, 12),
+            BLACK_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        self.draw_list(display)?;
+
+        draw_inputs(
+            vec![(Button::A, "Toggle"), (Button::B, "Back")],
+            display,
+            WHITE_CHAR,
+        )?;
+
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        display: &mut D,
+        input: &InputStatus,
+    ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
+        if input.b.should_trigger() {
+            if let Some(emu) = self.emu.take() {
+                self.events.push(Event::ResumeNes(Box::new(emu)));
+            }
+            return Ok(None);
+        }
+
+        let len = self.len();
+        if len == 0 {
+            return Ok(None);
+        }
+
+        let mut dirty = true;
+        if input.up.should_trigger() {
+            self.select((self.selected + len - 1) % len);
+        } else if input.down.should_trigger() {
+            self.select((self.selected + 1) % len);
+        } else if input.a.should_trigger() {
+            if let Some(emu) = self.emu.as_mut() {
+                emu.toggle_cheat(self.selected);
+            }
+        } else {
+            dirty = false;
+        }
+
+        if dirty {
+            self.draw_list(display)?;
+        }
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffer::Buffer,
+        nes::{
+            cartridge::{Mirroring, Rom},
+            cheats::Cheats,
+            cpu::Mem,
+        },
+    };
+
+    fn press(screen: &mut CheatsScreen, display: &mut Buffer, button: fn(&mut InputStatus)) {
+        let mut input = InputStatus::default();
+        button(&mut input);
+        Screen::<Buffer>::update(screen, display, &input).unwrap();
+    }
+
+    #[test]
+    fn test_toggle() {
+        // GOSSIP patches $D1DD, which mirrors $91DD in a 16K ROM
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[0x11DD] = 0x99;
+        let rom = Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        };
+        let mut emu = NesEmulator::with_rom(rom);
+        emu.set_cheats(Cheats::parse("-GOSSIP Patch\n0010=42 Freeze\n").unwrap());
+        assert_eq!(emu.cpu().mem_read(0xD1DD), 0x99);
+
+        let mut screen = CheatsScreen::new(emu);
+        let mut display = Buffer::new();
+        press(&mut screen, &mut display, |input| input.a.update(true));
+        assert_eq!(screen.emu.as_ref().unwrap().cpu().mem_read(0xD1DD), 0x14);
+
+        // up wraps to the freeze, which is on
+        press(&mut screen, &mut display, |input| input.up.update(true));
+        press(&mut screen, &mut display, |input| input.a.update(true));
+        assert!(!screen.emu.as_ref().unwrap().cheats().list[1].enabled);
+
+        press(&mut screen, &mut display, |input| input.b.update(true));
+        let mut events = Screen::<Buffer>::events(&mut screen);
+        let Some(Event::ResumeNes(mut emu)) = events.pop() else {
+            panic!("expected the game back");
+        };
+        emu.run_frame();
+        assert_eq!(emu.cpu().mem_read(0x10), 0);
+    }
+}
@@ -0,0 +1,144 @@
+use alloc::{
+    boxed::Box,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::Rgb565,
+    primitives::{Primitive, Rectangle},
+    text::Text,
+    Drawable,
+};
+
+use crate::{
+    events::Event,
+    gui::{
+        core::{
+            draw_inputs, BACKGROUND, BLACK_CHAR, CENTERED_TEXT, GREY_CHAR, INNER_BORDER,
+            OUTER_BORDER_CLR, WHITE_CHAR,
+        },
+        screen::Screen,
+    },
+    input::{Button, InputStatus},
+};
+
+/// Characters that fit across the screen with a margin
+const LINE_LEN: usize = 36;
+
+/// Splits `text` into lines short enough for the screen, breaking between words
+fn wrap(text: &str) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > LINE_LEN {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+/// Why a game couldn't be started. B goes back to the screen it was launched from.
+pub struct ErrorScreen<D> {
+    title: String,
+    message: String,
+    back: Option<Box<dyn Screen<D>>>,
+}
+
+impl<D> ErrorScreen<D> {
+    pub fn new(title: &str, message: &str, back: Box<dyn Screen<D>>) -> Self {
+        Self {
+            title: title.to_string(),
+            message: message.to_string(),
+            back: Some(back),
+        }
+    }
+}
+
+impl<D> Screen<D> for ErrorScreen<D>
+where
+    D: DrawTarget<Color = Rgb565> + OriginDimensions,
+{
+    fn draw(&mut self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(OUTER_BORDER_CLR)?;
+        let size = display.size();
+        let center = size.width as i32 / 2;
+
+        Rectangle::new(Point::new(0, 8), Size::new(size.width, size.height - 18))
+            .into_styled(INNER_BORDER)
+            .draw(display)?;
+
+        Rectangle::new(Point::new(0, 18), Size::new(size.width, size.height - 36))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        Text::with_text_style(
+            &self.title,
+            Point::new(center, 12),
+            BLACK_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        Text::with_text_style(
+            "Couldn't start this game",
+            Point::new(center, 30),
+            WHITE_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        for (i, line) in wrap(&self.message).iter().enumerate() {
+            Text::with_text_style(
+                line,
+                Point::new(center, 44 + i as i32 * 8),
+                GREY_CHAR,
+                CENTERED_TEXT,
+            )
+            .draw(display)?;
+        }
+
+        draw_inputs(vec![(Button::B, "Back")], display, WHITE_CHAR)
+    }
+
+    fn update(
+        &mut self,
+        _display: &mut D,
+        input: &InputStatus,
+    ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
+        if input.b.should_trigger() {
+            return Ok(self.back.take());
+        }
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<Event> {
+        vec![]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wrap() {
+        let lines = wrap("roms/gb/Tetris.gb: Game Boy games can't run yet, try again later");
+        assert!(lines.iter().all(|line| line.len() <= LINE_LEN));
+        assert_eq!(
+            lines.join(" "),
+            "roms/gb/Tetris.gb: Game Boy games can't run yet, try again later"
+        );
+        assert!(wrap("").is_empty());
+    }
+}
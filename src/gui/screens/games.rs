@@ -1,11 +1,15 @@
-use core::cmp::{max, min};
-
-use alloc::{boxed::Box, string::ToString, vec, vec::Vec};
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
 use embedded_graphics::{
     draw_target::DrawTarget,
     geometry::{OriginDimensions, Point, Size},
     image::Image,
-    pixelcolor::{Rgb565, RgbColor},
+    pixelcolor::Rgb565,
     primitives::{Primitive, PrimitiveStyleBuilder, Rectangle},
     text::Text,
     Drawable,
@@ -23,14 +27,22 @@ use crate::{
         screen::Screen,
     },
     input::{Button, InputStatus},
+    prefs::{Filter, Prefs, View},
 };
 
 const TOTAL_FRAMES: i32 = 4;
+/// Repeats of a held Left/Right before it starts jumping a letter at a time
+const JUMP_AFTER: u32 = 8;
+/// How many recently played games the list view shows above the rest
+const SHELF: usize = 3;
+const ROW_HEIGHT: u32 = 8;
 
 // TODO: could these be moved to the sd card to save space for the emulators?
 const GB_CARTRIDGE: &'static [u8; 4193] = include_bytes!("../../assets/cartridges/gb.tga");
+const GBC_CARTRIDGE: &'static [u8; 3910] = include_bytes!("../../assets/cartridges/gbc.tga");
 const GBA_CARTRIDGE: &'static [u8; 3813] = include_bytes!("../../assets/cartridges/gba.tga");
 const NES_CARTRIDGE: &'static [u8; 4709] = include_bytes!("../../assets/cartridges/nes.tga");
+const SPRIG_CONSOLE: &'static [u8; 1914] = include_bytes!("../../assets/cartridges/sprig.tga");
 
 pub(crate) enum Direction {
     Left,
@@ -48,31 +60,368 @@ impl Direction {
     }
 }
 
+/// A line of the list view
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Row {
+    Header(&'static str),
+    /// An index into `games`
+    Game(usize),
+}
+
 pub struct GamesScreen {
     pub games: Vec<Game>,
-    // TODO: this could probably be a different type
+    prefs: Prefs,
+    /// What's shown, in order. The carousel only has games, the list view starts with the
+    /// recently played shelf.
+    rows: Vec<Row>,
     frame: i32,
-    previous_game: u8,
     dir: Direction,
-    // TODO: could be a bigger type but maybe not necessary
-    selected_game: u8,
+    /// An index into `rows`, always a game unless there aren't any
+    selected: usize,
+    /// The first row on screen in the list view
+    top: usize,
+    /// How many repeats Left/Right has been held for, past `JUMP_AFTER` it jumps by letter
+    held: u32,
+    /// The games need drawing even though nothing moved
+    redraw: bool,
     events: Vec<Event>,
 }
 
 impl GamesScreen {
-    pub fn new(games: Vec<Game>) -> Self {
-        Self::with_game(games, 255)
+    pub fn new(games: Vec<Game>, prefs: Prefs) -> Self {
+        Self::with_game(games, prefs, None)
     }
 
-    pub fn with_game(games: Vec<Game>, selected_game: u8) -> Self {
-        Self {
+    /// Starts on `game`, an index into `games`, or the first game shown if it's filtered out
+    pub fn with_game(games: Vec<Game>, prefs: Prefs, game: Option<usize>) -> Self {
+        let mut screen = Self {
             games,
+            prefs,
+            rows: vec![],
             frame: 0,
             dir: Direction::None,
-            selected_game,
-            previous_game: 0,
+            selected: 0,
+            top: 0,
+            held: 0,
+            redraw: true,
             events: vec![],
+        };
+        screen.build_rows();
+        screen.select(game);
+        screen
+    }
+
+    fn build_rows(&mut self) {
+        self.rows.clear();
+        let order = self.prefs.order(&self.games);
+
+        if self.prefs.view == View::List {
+            let recent = self.prefs.recent(&self.games);
+            if !recent.is_empty() {
+                self.rows.push(Row::Header("Recently played"));
+                self.rows
+                    .extend(recent.into_iter().take(SHELF).map(Row::Game));
+                self.rows.push(Row::Header(match self.prefs.filter {
+                    Filter::All => "All games",
+                    _ => self.prefs.filter.label(),
+                }));
+            }
+        }
+        self.rows.extend(order.into_iter().map(Row::Game));
+    }
+
+    /// Moves to the row with `game`, staying put if it's already there
+    fn select(&mut self, game: Option<usize>) {
+        let is_game = |row: &Row| game.is_some_and(|game| *row == Row::Game(game));
+        if self.rows.get(self.selected).is_some_and(is_game) {
+            return;
+        }
+        self.selected = self
+            .rows
+            .iter()
+            .position(is_game)
+            .or_else(|| self.rows.iter().position(|row| matches!(row, Row::Game(_))))
+            .unwrap_or(0);
+    }
+
+    /// Rebuilds the rows after the settings store changed, keeping the same game selected
+    fn refresh(&mut self) {
+        let game = self.selected_index();
+        self.build_rows();
+        self.select(game);
+        self.redraw = true;
+    }
+
+    /// The selected game as an index into `games`
+    fn selected_index(&self) -> Option<usize> {
+        match self.rows.get(self.selected) {
+            Some(Row::Game(i)) => Some(*i),
+            _ => None,
+        }
+    }
+
+    pub fn selected_game(&self) -> Option<&Game> {
+        self.selected_index().map(|i| &self.games[i])
+    }
+
+    fn row_game(&self, row: usize) -> Option<&Game> {
+        match self.rows[row] {
+            Row::Game(i) => Some(&self.games[i]),
+            Row::Header(_) => None,
+        }
+    }
+
+    /// The game `offset` slots from the selected one, wrapping around the ends of the list
+    fn game_at(&self, offset: i32) -> &Game {
+        let row = (self.selected as i32 + offset).rem_euclid(self.rows.len() as i32);
+        self.row_game(row as usize).unwrap()
+    }
+
+    /// The title bar, with a star for favourites
+    fn heading(&self) -> String {
+        match self.selected_game() {
+            Some(game) if self.prefs.is_favourite(game) => format!("* {}", game.get_title()),
+            Some(game) => game.get_title().to_string(),
+            None => "No games".to_string(),
+        }
+    }
+
+    /// Moves `by` one game forwards or backwards, skipping headers and wrapping around
+    fn step(&mut self, by: i32) {
+        let n = self.rows.len() as i32;
+        for k in 1..=n {
+            let row = (self.selected as i32 + k * by).rem_euclid(n) as usize;
+            if self.row_game(row).is_some() {
+                self.selected = row;
+                return;
+            }
+        }
+    }
+
+    /// Moves one game left or right, or to the next letter once the button has been held a while
+    fn scroll(&mut self, dir: Direction, repeat: bool) {
+        if self.rows.len() < 2 {
+            return;
         }
+        self.held = if repeat { self.held + 1 } else { 0 };
+
+        let offset = dir.get_offset();
+        if self.held >= JUMP_AFTER {
+            self.selected = self.jump(offset);
+        } else {
+            self.step(offset);
+        }
+        self.dir = dir;
+    }
+
+    /// The first game of the next (or previous) letter, for lists sorted by title
+    fn jump(&self, offset: i32) -> usize {
+        let n = self.rows.len();
+        let letter = |row: usize| self.row_game(row).map(initial);
+        let step = |row: usize, by: i32| (row as i32 + by).rem_euclid(n as i32) as usize;
+
+        let current = letter(self.selected);
+        let Some(found) = (1..n as i32)
+            .map(|k| step(self.selected, k * offset))
+            .find(|&row| letter(row).is_some_and(|l| Some(l) != current))
+        else {
+            // every game starts with the same letter
+            return self.selected;
+        };
+        if offset > 0 {
+            return found;
+        }
+
+        // going left lands on the last game of the letter, walk back to its first
+        let target = letter(found);
+        let mut first = found;
+        for _ in 1..n {
+            let previous = step(first, -1);
+            if letter(previous) != target {
+                break;
+            }
+            first = previous;
+        }
+        first
+    }
+
+    fn toggle_favourite(&mut self) {
+        let Some(i) = self.selected_index() else {
+            return;
+        };
+        self.prefs.toggle_favourite(&self.games[i]);
+        self.events.push(Event::SavePrefs(self.prefs.clone()));
+        self.refresh();
+    }
+
+    fn launch(&mut self) {
+        let Some(i) = self.selected_index() else {
+            return;
+        };
+        self.prefs.played(&self.games[i]);
+        self.events.push(Event::SavePrefs(self.prefs.clone()));
+        self.events.push(Event::LaunchGame(self.games[i].clone()));
+        self.refresh();
+    }
+
+    fn settings<D>(&self) -> Box<dyn Screen<D>>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        Box::new(crate::gui::screens::settings::Settings::new(
+            self.games.clone(),
+            self.prefs.clone(),
+            self.selected_index(),
+        ))
+    }
+
+    fn update_list<D>(
+        &mut self,
+        display: &mut D,
+        input: &InputStatus,
+    ) -> Result<Option<Box<dyn Screen<D>>>, D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let mut dirty = self.redraw;
+        if input.up.should_trigger() {
+            self.step(-1);
+            dirty = true;
+        } else if input.down.should_trigger() {
+            self.step(1);
+            dirty = true;
+        } else if input.left.should_trigger() {
+            self.selected = self.jump(-1);
+            dirty = true;
+        } else if input.right.should_trigger() {
+            self.selected = self.jump(1);
+            dirty = true;
+        }
+        if input.b.should_trigger() {
+            return Ok(Some(self.settings()));
+        }
+        if input.a.should_trigger() {
+            self.launch();
+            dirty = true;
+        }
+        if input.select.should_trigger() {
+            self.toggle_favourite();
+            dirty = true;
+        }
+
+        if !dirty {
+            return Ok(None);
+        }
+        self.redraw = false;
+        self.draw_list(display)?;
+
+        Ok(None)
+    }
+
+    fn draw_list<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+        let visible = ((size.height - 48) / ROW_HEIGHT) as usize;
+        // characters across the screen, less the star and the console
+        let width = (size.width as usize - 8) / 4 - 8;
+
+        // scroll so the selected game is on screen, with its header if it's the first
+        if self.selected < self.top {
+            self.top = self.selected;
+            if self.top > 0 && self.row_game(self.top - 1).is_none() {
+                self.top -= 1;
+            }
+        } else if self.selected >= self.top + visible {
+            self.top = self.selected + 1 - visible;
+        }
+
+        Rectangle::new(Point::new(0, 18), Size::new(size.width, size.height - 36))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        Rectangle::new(Point::new(0, 8), Size::new(size.width, 10))
+            .into_styled(INNER_BORDER)
+            .draw(display)?;
+
+        Text::with_text_style(
+            &self.heading(),
+            Point::new(size.width as i32 / 2, 12),
+            BLACK_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        for (i, row) in self.rows.iter().enumerate().skip(self.top).take(visible) {
+            let y = 20 + ((i - self.top) as u32 * ROW_HEIGHT) as i32;
+            let game = match row {
+                Row::Header(text) => {
+                    Text::with_text_style(text, Point::new(4, y + 1), GREY_CHAR, NORMAL_TEXT)
+                        .draw(display)?;
+                    continue;
+                }
+                Row::Game(game) => &self.games[*game],
+            };
+
+            let text = if i == self.selected {
+                Rectangle::new(Point::new(2, y), Size::new(size.width - 4, ROW_HEIGHT))
+                    .into_styled(INNER_BORDER)
+                    .draw(display)?;
+                BLACK_CHAR
+            } else {
+                WHITE_CHAR
+            };
+
+            let star = if self.prefs.is_favourite(game) {
+                "*"
+            } else {
+                ""
+            };
+            let title: String = game.get_title().chars().take(width).collect();
+            Text::with_text_style(star, Point::new(4, y + 1), text, NORMAL_TEXT).draw(display)?;
+            Text::with_text_style(&title, Point::new(12, y + 1), text, NORMAL_TEXT)
+                .draw(display)?;
+
+            let console = game.get_console().name();
+            Text::with_text_style(
+                console,
+                Point::new(size.width as i32 - 4 - console.len() as i32 * 4, y + 1),
+                text,
+                NORMAL_TEXT,
+            )
+            .draw(display)?;
+        }
+
+        draw_inputs(
+            vec![
+                (Button::A, "Launch"),
+                (Button::B, "Settings"),
+                (Button::Select, "Fav"),
+            ],
+            display,
+            WHITE_CHAR,
+        )
+    }
+}
+
+/// What the alphabetical jump groups a game by, anything that isn't a letter is lumped together
+fn initial(game: &Game) -> char {
+    match game.title.chars().next() {
+        Some(c) if c.is_alphabetic() => c.to_ascii_uppercase(),
+        _ => '#',
+    }
+}
+
+/// The cartridge art for a console and where the game's cover goes on it
+fn cartridge(console: &GameConsole) -> (&'static [u8], Point) {
+    match console {
+        GameConsole::GameBoy => (GB_CARTRIDGE, Point::new(10, 26)),
+        GameConsole::GameBoyColor => (GBC_CARTRIDGE, Point::new(10, 26)),
+        GameConsole::GameBoyAdvanced => (GBA_CARTRIDGE, Point::new(15, 14)),
+        GameConsole::NES => (NES_CARTRIDGE, Point::new(38, 1)),
+        // the game's art goes on the console's screen
+        GameConsole::Sprig => (SPRIG_CONSOLE, Point::new(20, 8)),
     }
 }
 
@@ -102,6 +451,7 @@ where
         .draw(display)?;
 
         // draw games
+        self.redraw = true;
         self.update(display, &InputStatus::default())?;
 
         Ok(())
@@ -112,58 +462,42 @@ where
         display: &mut D,
         input: &InputStatus,
     ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
-        let mut dirty = true;
+        if self.prefs.view == View::List {
+            return self.update_list(display, input);
+        }
+
         let mut pressed = false;
-        //std::println!("{:?} {:?}", input.left.pressed, input.left.timer);
         if self.frame == 0 {
             if input.left.should_trigger() {
-                self.selected_game = max(self.selected_game.saturating_sub(1), 0);
-                self.dir = Direction::Left;
+                self.scroll(Direction::Left, input.left.repeat);
                 pressed = true;
             } else if input.right.should_trigger() {
-                self.selected_game = min(self.selected_game + 1, self.games.len() as u8 - 1);
-                self.dir = Direction::Right;
+                self.scroll(Direction::Right, input.right.repeat);
                 pressed = true;
+            } else if !input.left.pressed && !input.right.pressed {
+                self.held = 0;
             }
             if input.b.should_trigger() {
-                return Ok(Some(Box::new(
-                    crate::gui::screens::settings::Settings::new(
-                        self.games.clone(),
-                        if self.selected_game == 0 {
-                            255
-                        } else {
-                            self.selected_game
-                        },
-                    ),
-                )));
+                return Ok(Some(self.settings()));
             }
 
             if input.a.should_trigger() {
-                self.events.push(Event::LaunchGame(GameConsole::NES));
+                self.launch();
+            }
+            if input.select.should_trigger() {
+                self.toggle_favourite();
             }
         }
 
-        if self.frame == 0 && self.previous_game == self.selected_game {
-            dirty = false;
-        }
-        if self.frame == TOTAL_FRAMES && !pressed {
-            dirty = false;
-        }
-
-        if !dirty {
+        if self.frame == TOTAL_FRAMES || (self.frame == 0 && !pressed && !self.redraw) {
             self.frame = 0;
-            self.previous_game = self.selected_game;
             self.dir = Direction::None;
+            self.redraw = false;
             return Ok(None);
         }
 
-        if self.selected_game == 255 {
-            self.selected_game = 0;
-        }
-
         self.frame += 1;
         let multiplier: f32 = self.frame as f32 / TOTAL_FRAMES as f32;
-        //std::println!("{:?} {:?}", self.frame, multiplier);
 
         let size = display.size();
 
@@ -186,102 +520,50 @@ where
             .draw(display)?;
 
         Text::with_text_style(
-            self.games[self.selected_game as usize].title,
+            &self.heading(),
             Point::new(size.width as i32 / 2, 12),
             BLACK_CHAR,
             CENTERED_TEXT,
         )
         .draw(display)?;
 
-        let mut to_draw = vec![];
-        let placeholder = Game::new_placeholder();
-        if self.selected_game == 0 {
-            to_draw.push(&placeholder);
-        }
-
-        for (i, game) in self.games.iter().enumerate() {
-            let i = i as i32;
-            if i + 1 == self.selected_game.into()
-                || i == self.selected_game.into()
-                || i - 1 == self.selected_game.into()
-            {
-                to_draw.push(game);
+        // only as many slots as there are different games, so short lists don't repeat
+        let slots: &[i32] = match self.rows.len() {
+            0 => &[],
+            1 => &[0],
+            2 => &[0, 1],
+            _ => &[-1, 0, 1],
+        };
+
+        for &slot in slots {
+            let game = self.game_at(slot);
+            let console = game.get_console();
+            let old = console.get_pos(&size, slot + self.dir.get_offset());
+            let current = console.get_pos(&size, slot);
+            let (diff_x, diff_y) = (current.x - old.x, current.y - old.y);
+            let (x, y) = (
+                (diff_x as f32 * multiplier) as i32 + old.x,
+                (diff_y as f32 * multiplier) as i32 + old.y,
+            );
+
+            let (art, cover) = cartridge(console);
+            let cartridge: Tga<Rgb565> = Tga::from_slice(art).unwrap();
+            Image::new(&cartridge, Point::new(x, y)).draw(display)?;
+
+            if let Some(tga) = game.get_image() {
+                Image::new(&tga, Point::new(x, y) + cover).draw(display)?;
             }
         }
 
-        /*let mut out = alloc::string::String::new();
-        out += "Selected: ";
-        for (i, game) in to_draw.iter().enumerate() {
-            out += &i.to_string();
-            out += ". ";
-            out += game.title;
-            out += ", ";
-        }
-        std::println!("{:?}", &out);*/
-
-        for (i, game) in to_draw.iter().enumerate() {
-            match game.get_console() {
-                crate::games::GameConsole::GameBoy => {
-                    let old = GameConsole::GameBoy
-                        .get_pos(&size, (i as i32 + self.dir.get_offset()) as u8);
-                    let current = GameConsole::GameBoy.get_pos(&size, i as u8);
-                    let (diff_x, diff_y) = (current.x - old.x, current.y - old.y);
-                    let (x, y) = (
-                        (diff_x as f32 * multiplier) as i32 + old.x,
-                        (diff_y as f32 * multiplier) as i32 + old.y,
-                    );
-
-                    let cartridge: Tga<Rgb565> = Tga::from_slice(GB_CARTRIDGE).unwrap();
-                    Image::new(&cartridge, Point::new(x, y)).draw(display)?;
-
-                    let tga = game.get_image();
-
-                    Image::new(&tga, Point::new(x + 10, y + 26)).draw(display)?;
-                }
-                crate::games::GameConsole::GameBoyColor => todo!(),
-                crate::games::GameConsole::GameBoyAdvanced => {
-                    let old = GameConsole::GameBoyAdvanced
-                        .get_pos(&size, (i as i32 + self.dir.get_offset()) as u8);
-                    let current = GameConsole::GameBoyAdvanced.get_pos(&size, i as u8);
-                    let (diff_x, diff_y) = (current.x - old.x, current.y - old.y);
-                    let (x, y) = (
-                        (diff_x as f32 * multiplier) as i32 + old.x,
-                        (diff_y as f32 * multiplier) as i32 + old.y,
-                    );
-
-                    let cartridge: Tga<Rgb565> = Tga::from_slice(GBA_CARTRIDGE).unwrap();
-                    Image::new(&cartridge, Point::new(x, y)).draw(display)?;
-
-                    let tga = game.get_image();
-
-                    Image::new(&tga, Point::new(x + 15, y + 14)).draw(display)?;
-                }
-                crate::games::GameConsole::NES => {
-                    let old =
-                        GameConsole::NES.get_pos(&size, (i as i32 + self.dir.get_offset()) as u8);
-                    let current = GameConsole::NES.get_pos(&size, i as u8);
-                    let (diff_x, diff_y) = (current.x - old.x, current.y - old.y);
-                    let (x, y) = (
-                        (diff_x as f32 * multiplier) as i32 + old.x,
-                        (diff_y as f32 * multiplier) as i32 + old.y,
-                    );
-
-                    let cartridge: Tga<Rgb565> = Tga::from_slice(NES_CARTRIDGE).unwrap();
-                    Image::new(&cartridge, Point::new(x, y)).draw(display)?;
-
-                    let tga = game.get_image();
-
-                    Image::new(&tga, Point::new(x + 38, y + 1)).draw(display)?;
-                }
-                crate::games::GameConsole::Sprig => todo!(),
-                crate::games::GameConsole::Placeholder => (),
-            }
-            let mut inputs = vec![];
-            inputs.push((Button::A, "Launch"));
-            inputs.push((Button::B, "Settings"));
-
-            draw_inputs(inputs, display, WHITE_CHAR)?;
-        }
+        draw_inputs(
+            vec![
+                (Button::A, "Launch"),
+                (Button::B, "Settings"),
+                (Button::Select, "Fav"),
+            ],
+            display,
+            WHITE_CHAR,
+        )?;
 
         Ok(None)
     }
@@ -290,3 +572,104 @@ where
         self.events.drain(..).collect()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::buffer::Buffer;
+    use alloc::string::String;
+
+    fn screen(titles: &[&str]) -> GamesScreen {
+        let games = titles
+            .iter()
+            .map(|title| Game::new(String::from(*title), GameConsole::NES, None, None))
+            .collect();
+        GamesScreen::new(games, Prefs::default())
+    }
+
+    fn press(screen: &mut GamesScreen, display: &mut Buffer, button: fn(&mut InputStatus)) {
+        let mut input = InputStatus::default();
+        button(&mut input);
+        Screen::<Buffer>::update(screen, display, &input).unwrap();
+        // let the slide finish
+        while screen.frame != 0 {
+            Screen::<Buffer>::update(screen, display, &InputStatus::default()).unwrap();
+        }
+    }
+
+    fn title(screen: &GamesScreen) -> &str {
+        screen.selected_game().unwrap().get_title()
+    }
+
+    #[test]
+    fn test_wrap() {
+        let mut screen = screen(&["Alpha", "Bravo", "Charlie"]);
+        let mut display = Buffer::new();
+        press(&mut screen, &mut display, |input| input.left.update(true));
+        assert_eq!(title(&screen), "Charlie");
+        press(&mut screen, &mut display, |input| input.right.update(true));
+        assert_eq!(title(&screen), "Alpha");
+
+        let pos = GameConsole::NES.get_pos(&display.size(), 0);
+        assert_eq!(GameConsole::NES.get_pos(&display.size(), 5).y, pos.y);
+        assert!(GameConsole::NES.get_pos(&display.size(), 2).x > display.size().width as i32);
+    }
+
+    #[test]
+    fn test_jump() {
+        let mut screen = screen(&["Apple", "Avocado", "Banana", "Blueberry", "Cherry", "1942"]);
+
+        // sorted by title, 1942 comes first
+        screen.selected = 1;
+        assert_eq!(screen.jump(1), 3);
+        assert_eq!(screen.jump(-1), 0);
+        screen.selected = 4;
+        assert_eq!(screen.jump(1), 5);
+        assert_eq!(screen.jump(-1), 1);
+
+        // holding right steps one at a time, then speeds up into jumps
+        screen.selected = 1;
+        screen.scroll(Direction::Right, true);
+        assert_eq!(title(&screen), "Avocado");
+        screen.held = JUMP_AFTER - 1;
+        screen.scroll(Direction::Right, true);
+        assert_eq!(title(&screen), "Banana");
+        screen.scroll(Direction::Right, false);
+        assert_eq!(title(&screen), "Blueberry");
+    }
+
+    #[test]
+    fn test_list() {
+        let mut screen = screen(&["Metroid", "Zelda", "Kirby"]);
+        screen.prefs.view = View::List;
+        screen.refresh();
+        let mut display = Buffer::new();
+        assert_eq!(title(&screen), "Kirby");
+
+        press(&mut screen, &mut display, |input| input.down.update(true));
+        press(&mut screen, &mut display, |input| input.a.update(true));
+        assert!(matches!(
+            Screen::<Buffer>::events(&mut screen).as_slice(),
+            [Event::SavePrefs(_), Event::LaunchGame(_)]
+        ));
+
+        // the game just played is on the shelf at the top, and stays selected
+        assert_eq!(
+            screen.rows[..3],
+            [
+                Row::Header("Recently played"),
+                Row::Game(0),
+                Row::Header("All games")
+            ]
+        );
+        assert_eq!(screen.selected, 1);
+        press(&mut screen, &mut display, |input| input.up.update(true));
+        assert_eq!(title(&screen), "Zelda");
+
+        press(&mut screen, &mut display, |input| input.select.update(true));
+        assert!(screen.prefs.is_favourite(screen.selected_game().unwrap()));
+        screen.prefs.filter = Filter::Favourites;
+        screen.refresh();
+        assert_eq!(screen.rows, [Row::Game(1)]);
+    }
+}
@@ -0,0 +1,385 @@
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    mono_font::MonoTextStyle,
+    pixelcolor::{Rgb565, RgbColor},
+    primitives::{Primitive, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::Text,
+    Drawable,
+};
+
+use crate::{
+    events::Event,
+    gui::{
+        core::{
+            draw_inputs, BACKGROUND, BLACK_CHAR, CENTERED_TEXT, GREY_CHAR, INNER_BORDER,
+            INNER_BORDER_CLR, NORMAL_TEXT, OUTER_BORDER_CLR, PICO_FONT, WHITE_CHAR,
+        },
+        screen::Screen,
+    },
+    input::{Button, InputStatus},
+    nes::{cpu::Mem, emu::NesEmulator},
+};
+
+const COLUMNS: u16 = 8;
+const PAGE_SIZE: u16 = 0x40;
+const TOP: i32 = 22;
+const ROW_HEIGHT: i32 = 8;
+/// Where the first byte of a row starts, after "XXXX "
+const BYTES_LEFT: i32 = 4 + 5 * 4;
+const BYTE_WIDTH: i32 = 12;
+/// Select + up/down jumps this far
+const BIG_STEP: u16 = 0x1000;
+
+const CHANGED_CHAR: MonoTextStyle<Rgb565> = MonoTextStyle::new(&PICO_FONT, Rgb565::YELLOW);
+
+const CURSOR: PrimitiveStyle<Rgb565> = PrimitiveStyleBuilder::new()
+    .stroke_width(1)
+    .stroke_color(INNER_BORDER_CLR)
+    .build();
+
+const EDIT_CURSOR: PrimitiveStyle<Rgb565> = PrimitiveStyleBuilder::new()
+    .stroke_width(1)
+    .stroke_color(Rgb565::YELLOW)
+    .build();
+
+/// Reading the PPU's registers has side effects, so they're left alone
+fn readable(addr: u16) -> bool {
+    !(0x2000..=0x3FFF).contains(&addr)
+}
+
+/// Pages through the NES's address space a frame at a time, showing bytes that changed in the
+/// last frame in yellow. A starts editing the byte under the cursor: up/down change it by 1,
+/// left/right by $10, A writes it and B cancels. Select with the D-pad moves a page at a time.
+pub struct MemoryScreen {
+    emu: Option<NesEmulator>,
+    page: u16,
+    cursor: u16,
+    values: [Option<u8>; PAGE_SIZE as usize],
+    changed: [bool; PAGE_SIZE as usize],
+    /// The new value while editing, the game is paused until it's written or dropped
+    editing: Option<u8>,
+    events: Vec<Event>,
+}
+
+impl MemoryScreen {
+    pub fn new(emu: NesEmulator) -> Self {
+        let mut screen = Self {
+            emu: Some(emu),
+            page: 0,
+            cursor: 0,
+            values: [None; PAGE_SIZE as usize],
+            changed: [false; PAGE_SIZE as usize],
+            editing: None,
+            events: vec![],
+        };
+        screen.read_page();
+        screen
+    }
+
+    fn addr(&self) -> u16 {
+        self.page.wrapping_add(self.cursor)
+    }
+
+    /// Reads the page in, returns whether anything changed
+    fn read_page(&mut self) -> bool {
+        let Some(emu) = &self.emu else {
+            return false;
+        };
+        let mut any = false;
+        for i in 0..PAGE_SIZE {
+            let addr = self.page.wrapping_add(i);
+            let value = readable(addr).then(|| emu.cpu().mem_read(addr));
+            let changed = self.values[i as usize].is_some() && value != self.values[i as usize];
+            any |= changed || self.changed[i as usize] != changed;
+            self.values[i as usize] = value;
+            self.changed[i as usize] = changed;
+        }
+        any
+    }
+
+    fn change_page(&mut self, page: u16) {
+        self.page = page;
+        self.values = [None; PAGE_SIZE as usize];
+        self.changed = [false; PAGE_SIZE as usize];
+        self.read_page();
+    }
+
+    /// Moves the cursor, flipping pages at the edges
+    fn move_cursor(&mut self, by: i32) {
+        let cursor = self.cursor as i32 + by;
+        if cursor < 0 || cursor >= PAGE_SIZE as i32 {
+            let page = self
+                .page
+                .wrapping_add((cursor.div_euclid(PAGE_SIZE as i32) * PAGE_SIZE as i32) as u16);
+            self.change_page(page);
+        }
+        self.cursor = cursor.rem_euclid(PAGE_SIZE as i32) as u16;
+    }
+
+    fn write(&mut self, value: u8) {
+        let addr = self.addr();
+        if let Some(emu) = self.emu.as_mut() {
+            emu.cpu_mut().mem_write(addr, value);
+        }
+        self.read_page();
+    }
+
+    fn byte_point(i: u16) -> Point {
+        Point::new(
+            BYTES_LEFT + (i % COLUMNS) as i32 * BYTE_WIDTH,
+            TOP + (i / COLUMNS) as i32 * ROW_HEIGHT,
+        )
+    }
+
+    fn draw_page<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+        Rectangle::new(
+            Point::new(0, TOP - 2),
+            Size::new(
+                size.width,
+                (PAGE_SIZE / COLUMNS) as u32 * ROW_HEIGHT as u32 + 12,
+            ),
+        )
+        .into_styled(BACKGROUND)
+        .draw(display)?;
+
+        for row in 0..PAGE_SIZE / COLUMNS {
+            Text::with_text_style(
+                &format!("{:04X}", self.page.wrapping_add(row * COLUMNS)),
+                Point::new(4, TOP + row as i32 * ROW_HEIGHT),
+                GREY_CHAR,
+                NORMAL_TEXT,
+            )
+            .draw(display)?;
+        }
+
+        for i in 0..PAGE_SIZE {
+            let selected = i == self.cursor;
+            let (text, style) = match (self.values[i as usize], selected, self.editing) {
+                (_, true, Some(value)) => (format!("{:02X}", value), CHANGED_CHAR),
+                (Some(value), _, _) if self.changed[i as usize] => {
+                    (format!("{:02X}", value), CHANGED_CHAR)
+                }
+                (Some(value), _, _) => (format!("{:02X}", value), WHITE_CHAR),
+                (None, _, _) => ("--".into(), GREY_CHAR),
+            };
+            let point = Self::byte_point(i);
+            Text::with_text_style(&text, point, style, NORMAL_TEXT).draw(display)?;
+
+            if selected {
+                let cursor = if self.editing.is_some() {
+                    EDIT_CURSOR
+                } else {
+                    CURSOR
+                };
+                Rectangle::new(point - Point::new(2, 1), Size::new(12, 8))
+                    .into_styled(cursor)
+                    .draw(display)?;
+            }
+        }
+
+        let status = match (self.editing, self.values[self.cursor as usize]) {
+            (Some(value), _) => format!("${:04X} <- {:02X}", self.addr(), value),
+            (None, Some(value)) => format!("${:04X} = {:02X} ({})", self.addr(), value, value),
+            (None, None) => format!("${:04X} is a PPU register", self.addr()),
+        };
+        Text::with_text_style(
+            &status,
+            Point::new(4, TOP + (PAGE_SIZE / COLUMNS) as i32 * ROW_HEIGHT + 2),
+            GREY_CHAR,
+            NORMAL_TEXT,
+        )
+        .draw(display)?;
+
+        Ok(())
+    }
+}
+
+impl<D> Screen<D> for MemoryScreen
+where
+    D: DrawTarget<Color = Rgb565> + OriginDimensions,
+{
+    fn draw(&mut self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(OUTER_BORDER_CLR)?;
+        let size = display.size();
+
+        Rectangle::new(Point::new(0, 8), Size::new(size.width, size.height - 18))
+            .into_styled(INNER_BORDER)
+            .draw(display)?;
+
+        Rectangle::new(Point::new(0, 18), Size::new(size.width, size.height - 36))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        Text::with_text_style(
+            "Memory",
+            Point::new(size.width as i32 / 2, 12),
+            BLACK_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        self.draw_page(display)?;
+
+        draw_inputs(
+            vec![(Button::A, "Edit"), (Button::B, "Back")],
+            display,
+            WHITE_CHAR,
+        )?;
+
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        display: &mut D,
+        input: &InputStatus,
+    ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
+        let mut dirty = false;
+
+        if let Some(value) = self.editing {
+            let change: i16 = if input.up.should_trigger() {
+                1
+            } else if input.down.should_trigger() {
+                -1
+            } else if input.right.should_trigger() {
+                0x10
+            } else if input.left.should_trigger() {
+                -0x10
+            } else {
+                0
+            };
+            if change != 0 {
+                self.editing = Some((value as i16 + change) as u8);
+                dirty = true;
+            }
+            if input.a.should_trigger() {
+                self.editing = None;
+                self.write(value);
+                dirty = true;
+            } else if input.b.should_trigger() {
+                self.editing = None;
+                dirty = true;
+            }
+        } else if input.select.pressed {
+            let page = if input.up.should_trigger() {
+                Some(self.page.wrapping_sub(BIG_STEP))
+            } else if input.down.should_trigger() {
+                Some(self.page.wrapping_add(BIG_STEP))
+            } else if input.left.should_trigger() {
+                Some(self.page.wrapping_sub(PAGE_SIZE))
+            } else if input.right.should_trigger() {
+                Some(self.page.wrapping_add(PAGE_SIZE))
+            } else {
+                None
+            };
+            if let Some(page) = page {
+                self.change_page(page);
+                dirty = true;
+            }
+        } else {
+            if input.b.should_trigger() {
+                if let Some(emu) = self.emu.take() {
+                    self.events.push(Event::ResumeNes(Box::new(emu)));
+                }
+                return Ok(None);
+            }
+            let by = if input.left.should_trigger() {
+                -1
+            } else if input.right.should_trigger() {
+                1
+            } else if input.up.should_trigger() {
+                -(COLUMNS as i32)
+            } else if input.down.should_trigger() {
+                COLUMNS as i32
+            } else {
+                0
+            };
+            if by != 0 {
+                self.move_cursor(by);
+                dirty = true;
+            }
+            if input.a.should_trigger() && self.values[self.cursor as usize].is_some() {
+                self.editing = self.values[self.cursor as usize];
+                dirty = true;
+            }
+        }
+
+        if self.editing.is_none() {
+            if let Some(emu) = self.emu.as_mut() {
+                emu.run_frame();
+                dirty |= self.read_page();
+            }
+        }
+
+        if dirty {
+            self.draw_page(display)?;
+        }
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffer::Buffer,
+        nes::cartridge::{Mirroring, Rom},
+    };
+
+    fn press(screen: &mut MemoryScreen, display: &mut Buffer, button: fn(&mut InputStatus)) {
+        let mut input = InputStatus::default();
+        button(&mut input);
+        Screen::<Buffer>::update(screen, display, &input).unwrap();
+    }
+
+    #[test]
+    fn test_edit() {
+        // INC $00 forever
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..4].copy_from_slice(&[0xE6, 0x00, 0x4C, 0x00]);
+        prg_rom[3] = 0x80;
+        prg_rom[0x3FFD] = 0x80;
+        let rom = Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        };
+        let mut screen = MemoryScreen::new(NesEmulator::with_rom(rom));
+        let mut display = Buffer::new();
+
+        // a frame runs with every update
+        press(&mut screen, &mut display, |_| {});
+        assert!(screen.changed[0]);
+        assert!(!screen.changed[1]);
+
+        // $01: edit, +1, +$10, write
+        press(&mut screen, &mut display, |input| input.right.update(true));
+        press(&mut screen, &mut display, |input| input.a.update(true));
+        press(&mut screen, &mut display, |input| input.up.update(true));
+        press(&mut screen, &mut display, |input| input.right.update(true));
+        assert_eq!(screen.editing, Some(0x11));
+        press(&mut screen, &mut display, |input| input.a.update(true));
+        assert_eq!(screen.emu.as_ref().unwrap().cpu().mem_read(0x01), 0x11);
+
+        // moving up off the page goes back one
+        press(&mut screen, &mut display, |input| input.up.update(true));
+        assert_eq!(screen.addr(), 0xFFF9);
+
+        press(&mut screen, &mut display, |input| input.b.update(true));
+        assert!(matches!(
+            Screen::<Buffer>::events(&mut screen).as_slice(),
+            [Event::ResumeNes(_)]
+        ));
+    }
+}
@@ -1,2 +1,8 @@
+pub mod cheats;
+pub mod error;
 pub mod games;
+pub mod memory;
+pub mod nsf;
+pub mod pause;
+pub mod search;
 pub mod settings;
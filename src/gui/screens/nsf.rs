@@ -0,0 +1,266 @@
+use alloc::{boxed::Box, format, string::String, vec, vec::Vec};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::Rgb565,
+    primitives::{Primitive, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::Text,
+    Drawable,
+};
+
+use crate::{
+    games::Game,
+    gui::{
+        core::{
+            draw_inputs, BACKGROUND, BLACK_CHAR, CENTERED_TEXT, GREY_CHAR, INNER_BORDER,
+            INNER_BORDER_CLR, OUTER_BORDER_CLR, WHITE_CHAR,
+        },
+        screen::Screen,
+    },
+    input::{Button, InputStatus},
+    nes::{apu::SAMPLE_RATE, nsf::NsfPlayer},
+    pacer::{Pace, Pacer, Region},
+    prefs::Prefs,
+};
+
+use super::games::GamesScreen;
+
+const METER_TOP: i32 = 66;
+const METER_HEIGHT: u32 = 30;
+const METER_WIDTH: u32 = 16;
+const METER_LABELS: [&str; 5] = ["P1", "P2", "TRI", "NOI", "DMC"];
+/// How much sound to keep queued, 50ms
+const AUDIO_TARGET: usize = SAMPLE_RATE as usize / 20;
+
+const METER_FILL: PrimitiveStyle<Rgb565> = PrimitiveStyleBuilder::new()
+    .stroke_width(0)
+    .fill_color(INNER_BORDER_CLR)
+    .build();
+
+/// Plays an NSF tune with the track info and a level meter for each APU channel
+pub struct NsfScreen {
+    player: NsfPlayer,
+    games: Vec<Game>,
+    prefs: Prefs,
+    levels: [u8; 5],
+    error: Option<String>,
+    /// Calls play as often as the tune asks, not every update
+    pacer: Pacer,
+    now: u64,
+    /// When the queued sound started playing, and how many samples have been queued since
+    audio_start: Option<u64>,
+    produced: u64,
+}
+
+impl NsfScreen {
+    pub fn new(player: NsfPlayer, games: Vec<Game>, prefs: Prefs) -> Self {
+        Self {
+            pacer: Pacer::new(if player.nsf.is_pal() {
+                Region::Pal
+            } else {
+                Region::Ntsc
+            }),
+            player,
+            games,
+            prefs,
+            levels: [0; 5],
+            error: None,
+            now: 0,
+            audio_start: None,
+            produced: 0,
+        }
+    }
+
+    /// Samples waiting to be played. There's no audio output yet, so it's what one playing
+    /// them at `SAMPLE_RATE` would have left.
+    fn queued(&mut self) -> u64 {
+        let start = *self.audio_start.get_or_insert(self.now);
+        let played = (self.now - start) * SAMPLE_RATE as u64 / 1_000_000;
+        if played >= self.produced {
+            // ran dry, whatever comes next plays straight away
+            self.audio_start = Some(self.now);
+            self.produced = 0;
+        }
+        self.produced.saturating_sub(played)
+    }
+
+    fn draw_track<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+
+        Rectangle::new(Point::new(0, 54), Size::new(size.width, 8))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        let text = match &self.error {
+            Some(error) => error.clone(),
+            None => format!("Track {}/{}", self.player.song + 1, self.player.nsf.songs),
+        };
+        Text::with_text_style(
+            &text,
+            Point::new(size.width as i32 / 2, 58),
+            WHITE_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        Ok(())
+    }
+
+    fn draw_meters<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+        let spacing = size.width as i32 / 5;
+
+        for (i, level) in self.levels.iter().enumerate() {
+            // the DMC is 7 bit, the rest 4
+            let max = if i == 4 { 127 } else { 15 };
+            let height = *level as u32 * METER_HEIGHT / max;
+            let x = spacing * i as i32 + (spacing - METER_WIDTH as i32) / 2;
+
+            Rectangle::new(
+                Point::new(x, METER_TOP),
+                Size::new(METER_WIDTH, METER_HEIGHT - height),
+            )
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+            Rectangle::new(
+                Point::new(x, METER_TOP + (METER_HEIGHT - height) as i32),
+                Size::new(METER_WIDTH, height),
+            )
+            .into_styled(METER_FILL)
+            .draw(display)?;
+        }
+
+        Ok(())
+    }
+
+    fn change_song(&mut self, next: bool) {
+        let result = if next {
+            self.player.next_song()
+        } else {
+            self.player.previous_song()
+        };
+        self.error = result.err();
+    }
+}
+
+impl<D> Screen<D> for NsfScreen
+where
+    D: DrawTarget<Color = Rgb565> + OriginDimensions,
+{
+    fn draw(&mut self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(OUTER_BORDER_CLR)?;
+        let size = display.size();
+        let center = size.width as i32 / 2;
+
+        Rectangle::new(Point::new(0, 8), Size::new(size.width, size.height - 18))
+            .into_styled(INNER_BORDER)
+            .draw(display)?;
+
+        Rectangle::new(Point::new(0, 18), Size::new(size.width, size.height - 36))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        Text::with_text_style(
+            "NSF Player",
+            Point::new(center, 12),
+            BLACK_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        let nsf = &self.player.nsf;
+        for (text, y, style) in [
+            (&nsf.title, 24, WHITE_CHAR),
+            (&nsf.artist, 34, GREY_CHAR),
+            (&nsf.copyright, 44, GREY_CHAR),
+        ] {
+            Text::with_text_style(text, Point::new(center, y), style, CENTERED_TEXT)
+                .draw(display)?;
+        }
+
+        let spacing = size.width as i32 / 5;
+        for (i, label) in METER_LABELS.iter().enumerate() {
+            Text::with_text_style(
+                label,
+                Point::new(spacing * i as i32 + spacing / 2, METER_TOP - 4),
+                GREY_CHAR,
+                CENTERED_TEXT,
+            )
+            .draw(display)?;
+        }
+
+        self.draw_track(display)?;
+        self.draw_meters(display)?;
+
+        draw_inputs(
+            vec![
+                (Button::Left, "Prev"),
+                (Button::Right, "Next"),
+                (Button::B, "Back"),
+            ],
+            display,
+            WHITE_CHAR,
+        )?;
+
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        display: &mut D,
+        input: &InputStatus,
+    ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
+        if input.b.should_trigger() {
+            return Ok(Some(Box::new(GamesScreen::new(
+                self.games.clone(),
+                self.prefs.clone(),
+            ))));
+        }
+        if input.left.should_trigger() {
+            self.change_song(false);
+            self.draw_track(display)?;
+        }
+        if input.right.should_trigger() {
+            self.change_song(true);
+            self.draw_track(display)?;
+        }
+
+        let due = matches!(self.pacer.poll(self.now), Pace::Run { .. });
+        if self.error.is_none() && due {
+            if let Err(error) = self.player.frame() {
+                self.error = Some(error);
+                self.draw_track(display)?;
+            }
+            // TODO: there's no audio output yet, the backlog is kept as if there was
+            let samples = self.player.take_samples();
+            if !self.pacer.mute_audio() {
+                self.produced += samples.len() as u64;
+            }
+            let queued = self.queued();
+            self.pacer.audio_backlog(queued as usize, AUDIO_TARGET);
+        }
+
+        let levels = self.player.cpu.bus.apu.levels();
+        if levels != self.levels {
+            self.levels = levels;
+            self.draw_meters(display)?;
+        }
+
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<crate::events::Event> {
+        vec![]
+    }
+
+    fn set_time(&mut self, now_us: u64) {
+        self.now = now_us;
+    }
+}
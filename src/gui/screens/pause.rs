@@ -0,0 +1,350 @@
+use alloc::{
+    boxed::Box,
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::{Rgb565, RgbColor},
+    primitives::{Primitive, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::Text,
+    Drawable, Pixel,
+};
+
+use crate::{
+    emu::Machine,
+    events::Event,
+    gui::{
+        core::{
+            BACKGROUND, BLACK_CHAR, CENTERED_TEXT, GREY_CHAR, INNER_BORDER, INNER_BORDER_CLR,
+            WHITE_CHAR,
+        },
+        screen::Screen,
+    },
+    input::InputStatus,
+};
+
+const PANEL_WIDTH: u32 = 96;
+const ROW_HEIGHT: i32 = 9;
+/// The title, the items and a line for messages
+const PANEL_HEIGHT: u32 = 12 + ITEMS.len() as u32 * ROW_HEIGHT as u32 + 12;
+
+const PANEL: PrimitiveStyle<Rgb565> = PrimitiveStyleBuilder::new()
+    .stroke_width(1)
+    .stroke_color(INNER_BORDER_CLR)
+    .fill_color(Rgb565::new(0, 1, 6))
+    .build();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    Resume,
+    Reset,
+    SaveState,
+    LoadState,
+    Settings,
+    Quit,
+}
+
+const ITEMS: [Item; 6] = [
+    Item::Resume,
+    Item::Reset,
+    Item::SaveState,
+    Item::LoadState,
+    Item::Settings,
+    Item::Quit,
+];
+
+impl Item {
+    fn label(&self) -> &'static str {
+        match self {
+            Item::Resume => "Resume",
+            Item::Reset => "Reset",
+            Item::SaveState => "Save State",
+            Item::LoadState => "Load State",
+            Item::Settings => "Settings",
+            Item::Quit => "Quit to Library",
+        }
+    }
+}
+
+/// The in-game menu. The game is frozen while it's open, with its last frame dimmed behind the
+/// menu. B (or Resume) hands the game back to the device.
+pub struct PauseScreen {
+    machine: Option<Machine>,
+    title: String,
+    /// The state Load State goes back to, kept by the device between pauses
+    slot: Option<Vec<u8>>,
+    selected: usize,
+    /// Why the last choice didn't do anything
+    message: Option<String>,
+    events: Vec<Event>,
+}
+
+impl PauseScreen {
+    pub fn new(machine: Machine, title: &str, slot: Option<Vec<u8>>) -> Self {
+        Self {
+            machine: Some(machine),
+            title: title.to_string(),
+            slot,
+            selected: 0,
+            message: None,
+            events: vec![],
+        }
+    }
+
+    /// Moves the paused game into a new screen, for coming back to from settings
+    fn take(&mut self) -> PauseScreen {
+        PauseScreen {
+            machine: self.machine.take(),
+            title: self.title.clone(),
+            slot: self.slot.take(),
+            selected: self.selected,
+            message: None,
+            events: vec![],
+        }
+    }
+
+    fn resume(&mut self) {
+        if let Some(machine) = self.machine.take() {
+            self.events.push(Event::ResumeGame(Box::new(machine)));
+        }
+    }
+
+    fn save_state(&mut self) {
+        let Some(Machine::Nes(nes)) = &self.machine else {
+            self.message = Some("Only NES games have states".to_string());
+            return;
+        };
+        let mut state = vec![];
+        nes.save_state(&mut state);
+        self.slot = Some(state.clone());
+        self.events.push(Event::SaveState(state));
+        self.message = Some("State saved".to_string());
+    }
+
+    fn load_state(&mut self) {
+        let Some(Machine::Nes(nes)) = &mut self.machine else {
+            self.message = Some("Only NES games have states".to_string());
+            return;
+        };
+        let Some(state) = &self.slot else {
+            self.message = Some("No state saved yet".to_string());
+            return;
+        };
+        match nes.load_state(state) {
+            Ok(()) => self.resume(),
+            Err(e) => self.message = Some(format!("Can't load: {}", e)),
+        }
+    }
+
+    fn choose(&mut self) {
+        self.message = None;
+        match ITEMS[self.selected] {
+            Item::Resume => self.resume(),
+            Item::Reset => {
+                if let Some(machine) = self.machine.as_mut() {
+                    machine.reset();
+                }
+                self.resume();
+            }
+            Item::SaveState => self.save_state(),
+            Item::LoadState => self.load_state(),
+            Item::Settings => {
+                let pause = Box::new(self.take());
+                self.events.push(Event::OpenSettings(pause));
+            }
+            Item::Quit => {
+                self.machine = None;
+                self.events.push(Event::QuitToLibrary);
+            }
+        }
+    }
+
+    fn draw_menu<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+        let left = (size.width as i32 - PANEL_WIDTH as i32) / 2;
+        let top = (size.height as i32 - PANEL_HEIGHT as i32) / 2;
+        let center = size.width as i32 / 2;
+
+        Rectangle::new(Point::new(left, top), Size::new(PANEL_WIDTH, PANEL_HEIGHT))
+            .into_styled(PANEL)
+            .draw(display)?;
+
+        Rectangle::new(Point::new(left, top), Size::new(PANEL_WIDTH, 10))
+            .into_styled(INNER_BORDER)
+            .draw(display)?;
+
+        let title: String = self
+            .title
+            .chars()
+            .take(PANEL_WIDTH as usize / 4 - 2)
+            .collect();
+        Text::with_text_style(
+            &title,
+            Point::new(center, top + 4),
+            BLACK_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        for (i, item) in ITEMS.iter().enumerate() {
+            let y = top + 12 + i as i32 * ROW_HEIGHT;
+            let text = if i == self.selected {
+                Rectangle::new(
+                    Point::new(left + 2, y),
+                    Size::new(PANEL_WIDTH - 4, ROW_HEIGHT as u32),
+                )
+                .into_styled(INNER_BORDER)
+                .draw(display)?;
+                BLACK_CHAR
+            } else {
+                Rectangle::new(
+                    Point::new(left + 2, y),
+                    Size::new(PANEL_WIDTH - 4, ROW_HEIGHT as u32),
+                )
+                .into_styled(BACKGROUND)
+                .draw(display)?;
+                WHITE_CHAR
+            };
+            Text::with_text_style(item.label(), Point::new(center, y + 4), text, CENTERED_TEXT)
+                .draw(display)?;
+        }
+
+        let y = top + PANEL_HEIGHT as i32 - 10;
+        Rectangle::new(Point::new(left + 2, y), Size::new(PANEL_WIDTH - 4, 8))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+        if let Some(message) = &self.message {
+            Text::with_text_style(message, Point::new(center, y + 4), GREY_CHAR, CENTERED_TEXT)
+                .draw(display)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<D> Screen<D> for PauseScreen
+where
+    D: DrawTarget<Color = Rgb565> + OriginDimensions,
+{
+    fn draw(&mut self, display: &mut D) -> Result<(), D::Error> {
+        if let Some(machine) = self.machine.as_mut() {
+            machine.draw(display)?;
+        }
+
+        // dim the game by blacking out every other pixel
+        let size = display.size();
+        display.draw_iter((0..size.height as i32).flat_map(|y| {
+            (y % 2..size.width as i32)
+                .step_by(2)
+                .map(move |x| Pixel(Point::new(x, y), Rgb565::BLACK))
+        }))?;
+
+        self.draw_menu(display)
+    }
+
+    fn update(
+        &mut self,
+        display: &mut D,
+        input: &InputStatus,
+    ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
+        if self.machine.is_none() {
+            // handed back to the device already
+            return Ok(None);
+        }
+
+        let mut dirty = false;
+        if input.up.should_trigger() {
+            self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len();
+            dirty = true;
+        }
+        if input.down.should_trigger() {
+            self.selected = (self.selected + 1) % ITEMS.len();
+            dirty = true;
+        }
+        if input.b.should_trigger() {
+            self.resume();
+            return Ok(None);
+        }
+        if input.a.should_trigger() {
+            self.choose();
+            dirty = true;
+        }
+
+        if dirty && self.machine.is_some() {
+            self.draw_menu(display)?;
+        }
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffer::Buffer,
+        nes::{
+            cartridge::{Mirroring, Rom},
+            cpu::Mem,
+            emu::NesEmulator,
+        },
+    };
+
+    fn press(screen: &mut PauseScreen, display: &mut Buffer, button: fn(&mut InputStatus)) {
+        let mut input = InputStatus::default();
+        button(&mut input);
+        Screen::<Buffer>::update(screen, display, &input).unwrap();
+    }
+
+    fn choose(screen: &mut PauseScreen, display: &mut Buffer, item: Item) {
+        screen.selected = ITEMS.iter().position(|i| *i == item).unwrap();
+        press(screen, display, |input| input.a.update(true));
+    }
+
+    #[test]
+    fn test_states() {
+        let rom = Rom {
+            prg_rom: vec![0xEA; 0x4000],
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        };
+        let mut nes = NesEmulator::with_rom(rom);
+        nes.cpu_mut().mem_write(0x0010, 1);
+
+        let mut screen = PauseScreen::new(Machine::Nes(Box::new(nes)), "Test", None);
+        let mut display = Buffer::new();
+        Screen::<Buffer>::draw(&mut screen, &mut display).unwrap();
+
+        choose(&mut screen, &mut display, Item::LoadState);
+        assert_eq!(screen.message.as_deref(), Some("No state saved yet"));
+
+        choose(&mut screen, &mut display, Item::SaveState);
+        let events = Screen::<Buffer>::events(&mut screen);
+        assert!(matches!(events.as_slice(), [Event::SaveState(_)]));
+
+        if let Some(Machine::Nes(nes)) = &mut screen.machine {
+            nes.cpu_mut().mem_write(0x0010, 2);
+        }
+        choose(&mut screen, &mut display, Item::LoadState);
+        let events = Screen::<Buffer>::events(&mut screen);
+        let [Event::ResumeGame(machine)] = events.as_slice() else {
+            panic!("the game wasn't resumed");
+        };
+        let Machine::Nes(nes) = machine.as_ref() else {
+            panic!("a different game was resumed");
+        };
+        assert_eq!(nes.cpu().mem_read(0x0010), 1);
+        assert!(screen.machine.is_none());
+    }
+}
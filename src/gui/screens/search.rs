@@ -0,0 +1,339 @@
+use alloc::{boxed::Box, format, vec, vec::Vec};
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{OriginDimensions, Point, Size},
+    pixelcolor::Rgb565,
+    primitives::{Primitive, PrimitiveStyle, PrimitiveStyleBuilder, Rectangle},
+    text::Text,
+    Drawable,
+};
+
+use crate::{
+    events::Event,
+    gui::{
+        core::{
+            draw_inputs, BACKGROUND, BLACK_CHAR, CENTERED_TEXT, GREY_CHAR, INNER_BORDER,
+            INNER_BORDER_CLR, NORMAL_TEXT, OUTER_BORDER_CLR, WHITE_CHAR,
+        },
+        screen::Screen,
+    },
+    input::{Button, InputStatus},
+    nes::{
+        cheats::{Cheat, Code},
+        emu::NesEmulator,
+        search::Filter,
+    },
+};
+
+const TOP: i32 = 22;
+const ROW_HEIGHT: i32 = 8;
+/// Candidate rows, under the filter and the column headings
+const ROWS: usize = 7;
+
+const CURSOR: PrimitiveStyle<Rgb565> = PrimitiveStyleBuilder::new()
+    .stroke_width(1)
+    .stroke_color(INNER_BORDER_CLR)
+    .build();
+
+/// Narrows down work RAM to find a cheat. The game is paused while it's open: left/right pick
+/// a filter, A keeps the bytes that match it since the last search and B goes back to play some
+/// more. Select+up/down changes the amount for "changed by", Select+A starts over and Select+B
+/// freezes the selected byte at its value.
+pub struct RamSearchScreen {
+    emu: Option<NesEmulator>,
+    filter: Filter,
+    selected: usize,
+    /// The first row shown
+    scroll: usize,
+    events: Vec<Event>,
+}
+
+impl RamSearchScreen {
+    pub fn new(emu: NesEmulator) -> Self {
+        Self {
+            emu: Some(emu),
+            filter: Filter::Equal,
+            selected: 0,
+            scroll: 0,
+            events: vec![],
+        }
+    }
+
+    fn len(&mut self) -> usize {
+        self.emu
+            .as_mut()
+            .map_or(0, |emu| emu.ram_search().candidates().len())
+    }
+
+    fn select(&mut self, selected: usize) {
+        self.selected = selected;
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if self.selected >= self.scroll + ROWS {
+            self.scroll = self.selected + 1 - ROWS;
+        }
+    }
+
+    fn search(&mut self) {
+        if let Some(emu) = self.emu.as_mut() {
+            let ram = *emu.cpu().bus.ram();
+            emu.ram_search().filter(self.filter, &ram);
+        }
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    fn reset(&mut self) {
+        if let Some(emu) = self.emu.as_mut() {
+            let ram = *emu.cpu().bus.ram();
+            emu.ram_search().reset(&ram);
+        }
+        self.selected = 0;
+        self.scroll = 0;
+    }
+
+    /// Turns the selected candidate into a freeze at its current value
+    fn freeze(&mut self) {
+        let selected = self.selected;
+        let Some(emu) = self.emu.as_mut() else {
+            return;
+        };
+        if let Some(&addr) = emu.ram_search().candidates().get(selected) {
+            let value = emu.cpu().bus.ram()[addr as usize];
+            emu.add_cheat(Cheat::freeze(addr, value));
+        }
+    }
+
+    fn draw_list<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+        Rectangle::new(
+            Point::new(0, TOP - 2),
+            Size::new(size.width, (ROWS as u32 + 2) * ROW_HEIGHT as u32 + 2),
+        )
+        .into_styled(BACKGROUND)
+        .draw(display)?;
+
+        let Some(emu) = self.emu.as_mut() else {
+            return Ok(());
+        };
+        let ram = *emu.cpu().bus.ram();
+        let frozen: Vec<u16> = emu
+            .cheats()
+            .list
+            .iter()
+            .filter_map(|cheat| match cheat.kind {
+                Code::Freeze { addr, .. } if cheat.enabled => Some(addr),
+                _ => None,
+            })
+            .collect();
+        let search = emu.ram_search();
+
+        Text::with_text_style(
+            &format!(
+                "< {} >  {} LEFT",
+                self.filter.label(),
+                search.candidates().len()
+            ),
+            Point::new(4, TOP),
+            WHITE_CHAR,
+            NORMAL_TEXT,
+        )
+        .draw(display)?;
+        Text::with_text_style(
+            "ADDR  WAS  NOW",
+            Point::new(4, TOP + ROW_HEIGHT),
+            GREY_CHAR,
+            NORMAL_TEXT,
+        )
+        .draw(display)?;
+
+        for (row, (i, addr)) in search
+            .candidates()
+            .iter()
+            .enumerate()
+            .skip(self.scroll)
+            .take(ROWS)
+            .enumerate()
+        {
+            let point = Point::new(4, TOP + (row as i32 + 2) * ROW_HEIGHT);
+            let line = format!(
+                "{:04X}  {:02X}   {:02X}  {}",
+                addr,
+                search.previous(*addr),
+                ram[*addr as usize],
+                if frozen.contains(addr) { "FROZEN" } else { "" }
+            );
+            Text::with_text_style(&line, point, WHITE_CHAR, NORMAL_TEXT).draw(display)?;
+
+            if i == self.selected {
+                Rectangle::new(
+                    point - Point::new(2, 1),
+                    Size::new(size.width - 4, ROW_HEIGHT as u32),
+                )
+                .into_styled(CURSOR)
+                .draw(display)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl<D> Screen<D> for RamSearchScreen
+where
+    D: DrawTarget<Color = Rgb565> + OriginDimensions,
+{
+    fn draw(&mut self, display: &mut D) -> Result<(), D::Error> {
+        display.clear(OUTER_BORDER_CLR)?;
+        let size = display.size();
+
+        Rectangle::new(Point::new(0, 8), Size::new(size.width, size.height - 18))
+            .into_styled(INNER_BORDER)
+            .draw(display)?;
+
+        Rectangle::new(Point::new(0, 18), Size::new(size.width, size.height - 36))
+            .into_styled(BACKGROUND)
+            .draw(display)?;
+
+        Text::with_text_style(
+            "RAM Search",
+            Point::new(size.width as i32 / 2, 12),
+            BLACK_CHAR,
+            CENTERED_TEXT,
+        )
+        .draw(display)?;
+
+        self.draw_list(display)?;
+
+        draw_inputs(
+            vec![(Button::A, "Search"), (Button::B, "Back")],
+            display,
+            WHITE_CHAR,
+        )?;
+
+        Ok(())
+    }
+
+    fn update(
+        &mut self,
+        display: &mut D,
+        input: &InputStatus,
+    ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
+        let mut dirty = true;
+
+        if input.select.pressed {
+            if input.a.should_trigger() {
+                self.reset();
+            } else if input.b.should_trigger() {
+                self.freeze();
+            } else if let Filter::ChangedBy(by) = self.filter {
+                if input.up.should_trigger() {
+                    self.filter = Filter::ChangedBy(by.saturating_add(1));
+                } else if input.down.should_trigger() {
+                    self.filter = Filter::ChangedBy(by.saturating_sub(1));
+                } else {
+                    dirty = false;
+                }
+            } else {
+                dirty = false;
+            }
+        } else if input.b.should_trigger() {
+            if let Some(emu) = self.emu.take() {
+                self.events.push(Event::ResumeNes(Box::new(emu)));
+            }
+            return Ok(None);
+        } else if input.a.should_trigger() {
+            self.search();
+        } else if input.left.should_trigger() {
+            self.filter = self.filter.previous();
+        } else if input.right.should_trigger() {
+            self.filter = self.filter.next();
+        } else if input.up.should_trigger() {
+            self.select(self.selected.saturating_sub(1));
+        } else if input.down.should_trigger() {
+            let last = self.len().saturating_sub(1);
+            self.select((self.selected + 1).min(last));
+        } else {
+            dirty = false;
+        }
+
+        if dirty {
+            self.draw_list(display)?;
+        }
+        Ok(None)
+    }
+
+    fn events(&mut self) -> Vec<Event> {
+        self.events.drain(..).collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        buffer::Buffer,
+        nes::{
+            cartridge::{Mirroring, Rom},
+            cpu::Mem,
+        },
+    };
+
+    fn press(screen: &mut RamSearchScreen, display: &mut Buffer, button: fn(&mut InputStatus)) {
+        let mut input = InputStatus::default();
+        button(&mut input);
+        Screen::<Buffer>::update(screen, display, &input).unwrap();
+    }
+
+    fn poke(screen: &mut RamSearchScreen, addr: u16, value: u8) {
+        screen
+            .emu
+            .as_mut()
+            .unwrap()
+            .cpu_mut()
+            .mem_write(addr, value);
+    }
+
+    #[test]
+    fn test_search() {
+        let rom = Rom {
+            prg_rom: vec![0; 0x4000],
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        };
+        let mut screen = RamSearchScreen::new(NesEmulator::with_rom(rom));
+        let mut display = Buffer::new();
+        // opening it takes the first snapshot
+        screen.len();
+
+        // two bytes go up by one, then only one of them again
+        poke(&mut screen, 0x42, 1);
+        poke(&mut screen, 0x43, 1);
+        press(&mut screen, &mut display, |input| input.left.update(true));
+        assert_eq!(screen.filter, Filter::ChangedBy(1));
+        press(&mut screen, &mut display, |input| input.a.update(true));
+        assert_eq!(screen.len(), 2);
+
+        poke(&mut screen, 0x43, 2);
+        press(&mut screen, &mut display, |input| input.a.update(true));
+        let emu = screen.emu.as_mut().unwrap();
+        assert_eq!(emu.ram_search().candidates(), &[0x43]);
+
+        press(&mut screen, &mut display, |input| {
+            input.select.update(true);
+            input.b.update(true);
+        });
+        let emu = screen.emu.as_mut().unwrap();
+        assert_eq!(emu.cheats().list[0], Cheat::freeze(0x43, 2));
+
+        press(&mut screen, &mut display, |input| {
+            input.select.update(true);
+            input.a.update(true);
+        });
+        assert_eq!(screen.len(), 2048);
+    }
+}
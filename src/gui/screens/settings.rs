@@ -24,10 +24,11 @@ use crate::{
         screen::Screen,
     },
     input::{Button, InputStatus},
+    prefs::Prefs,
     rp2040::Sprig,
 };
 
-use super::games::GamesScreen;
+use super::{games::GamesScreen, pause::PauseScreen};
 
 const TOTAL_FRAMES: i32 = 4;
 
@@ -54,27 +55,46 @@ const SELECTED_FILL: PrimitiveStyle<Rgb565> = PrimitiveStyleBuilder::new()
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Selection {
     Brightness,
+    BootRom,
+    View,
+    Sort,
+    Filter,
     None,
 }
 
 impl Selection {
-    pub fn next(&self) -> Self {
+    // the boot rom entry only exists for game boy games
+    pub fn next(&self, has_boot: bool) -> Self {
         match self {
-            Selection::Brightness => Selection::None,
+            Selection::Brightness if has_boot => Selection::BootRom,
+            Selection::Brightness => Selection::View,
+            Selection::BootRom => Selection::View,
+            Selection::View => Selection::Sort,
+            Selection::Sort => Selection::Filter,
+            Selection::Filter => Selection::None,
             Selection::None => Selection::Brightness,
         }
     }
 
-    pub fn previous(&self) -> Self {
+    pub fn previous(&self, has_boot: bool) -> Self {
         match self {
             Selection::Brightness => Selection::None,
-            Selection::None => Selection::Brightness,
+            Selection::BootRom => Selection::Brightness,
+            Selection::View if has_boot => Selection::BootRom,
+            Selection::View => Selection::Brightness,
+            Selection::Sort => Selection::View,
+            Selection::Filter => Selection::Sort,
+            Selection::None => Selection::Filter,
         }
     }
 
     pub fn point(&self) -> Point {
         match self {
             Selection::Brightness => Point::new(4, 31),
+            Selection::BootRom => Point::new(4, 51),
+            Selection::View => Point::new(44, 63),
+            Selection::Sort => Point::new(44, 75),
+            Selection::Filter => Point::new(44, 87),
             Selection::None => Point::new(0, 0),
         }
     }
@@ -84,21 +104,117 @@ impl Selection {
 pub struct Settings {
     events: Vec<Event>,
     games: Vec<Game>,
-    selected_game: u8,
+    prefs: Prefs,
+    selected_game: Option<usize>,
+    /// The game these were opened from, B goes back to it instead of the library
+    pause: Option<Box<PauseScreen>>,
     brightness: u16,
     selection: Selection,
 }
 
 impl Settings {
-    pub fn new(games: Vec<Game>, selected_game: u8) -> Self {
+    pub fn new(games: Vec<Game>, prefs: Prefs, selected_game: Option<usize>) -> Self {
         Self {
             events: vec![],
             games,
+            prefs,
             selected_game,
+            pause: None,
             brightness: u16::MAX,
             selection: Selection::None,
         }
     }
+
+    pub fn with_pause(mut self, pause: Box<PauseScreen>) -> Self {
+        self.pause = Some(pause);
+        self
+    }
+
+    fn game(&self) -> Option<&Game> {
+        self.games.get(self.selected_game?)
+    }
+
+    fn has_boot(&self) -> bool {
+        self.game().is_some_and(|game| game.console.is_gameboy())
+    }
+
+    fn draw_entry<D>(
+        &mut self,
+        entry: &Selection,
+        selected: bool,
+        display: &mut D,
+    ) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565> + OriginDimensions,
+    {
+        let size = display.size();
+        let coord = entry.point();
+        let (outline, fill, text) = if selected {
+            (SELECTED, SELECTED_FILL, WHITE_CHAR)
+        } else {
+            (SLIDER, SLIDER_FILL, GREY_CHAR)
+        };
+
+        match entry {
+            Selection::Brightness => {
+                Rectangle::new(coord, Size::new(size.width - 24, 6))
+                    .into_styled(outline)
+                    .draw(display)?;
+
+                Rectangle::new(
+                    Point::new(coord.x + 1, coord.y + 1),
+                    Size::new(size.width - 26, 4),
+                )
+                .into_styled(BACKGROUND)
+                .draw(display)?;
+
+                Rectangle::new(
+                    Point::new(coord.x + 1, coord.y + 1),
+                    Size::new(
+                        (((size.width - 26) as f32 / u16::MAX as f32) * self.brightness as f32)
+                            as u32,
+                        4,
+                    ),
+                )
+                .into_styled(fill)
+                .draw(display)?;
+            }
+            Selection::BootRom | Selection::View | Selection::Sort | Selection::Filter => {
+                let value = match entry {
+                    Selection::View => self.prefs.view.label(),
+                    Selection::Sort => self.prefs.sort.label(),
+                    Selection::Filter => self.prefs.filter.label(),
+                    _ => match self.game() {
+                        Some(game) => self.prefs.boot(game).label(),
+                        None => return Ok(()),
+                    },
+                };
+                let width = size.width - coord.x as u32 - 20;
+
+                Rectangle::new(coord, Size::new(width, 9))
+                    .into_styled(outline)
+                    .draw(display)?;
+
+                Rectangle::new(
+                    Point::new(coord.x + 1, coord.y + 1),
+                    Size::new(width - 2, 7),
+                )
+                .into_styled(BACKGROUND)
+                .draw(display)?;
+
+                Text::with_text_style(
+                    value,
+                    Point::new(coord.x + width as i32 / 2, coord.y + 4),
+                    text,
+                    CENTERED_TEXT,
+                )
+                .draw(display)?;
+            }
+            Selection::None => {}
+        }
+
+        Ok(())
+    }
 }
 
 impl<D> Screen<D> for Settings
@@ -138,19 +254,26 @@ where
         Text::with_text_style("Brightness", Point::new(4, 21), WHITE_CHAR, NORMAL_TEXT)
             .draw(display)?;
 
-        Rectangle::new(Point::new(4, 31), Size::new(size.width - 24, 6))
-            .into_styled(SLIDER)
-            .draw(display)?;
+        self.draw_entry(&Selection::Brightness, false, display)?;
 
-        Rectangle::new(
-            Point::new(5, 32),
-            Size::new(
-                (((size.width - 26) as f32 / u16::MAX as f32) * self.brightness as f32) as u32,
-                4,
-            ),
-        )
-        .into_styled(SLIDER_FILL)
-        .draw(display)?;
+        if self.has_boot() {
+            Text::with_text_style("Boot ROM", Point::new(4, 45), WHITE_CHAR, NORMAL_TEXT)
+                .draw(display)?;
+
+            self.draw_entry(&Selection::BootRom, false, display)?;
+        }
+
+        for (label, entry) in [
+            ("View", Selection::View),
+            ("Sort", Selection::Sort),
+            ("Filter", Selection::Filter),
+        ] {
+            let coord = entry.point();
+            Text::with_text_style(label, Point::new(4, coord.y + 2), WHITE_CHAR, NORMAL_TEXT)
+                .draw(display)?;
+
+            self.draw_entry(&entry, false, display)?;
+        }
 
         //self.update(display, &InputStatus::default())?;
 
@@ -164,21 +287,26 @@ where
     ) -> Result<Option<Box<dyn Screen<D>>>, D::Error> {
         let mut dirty = false;
         let mut selection_dirty = false;
-        let mut prev = self.selection.clone();
+        let prev = self.selection.clone();
         let mut change = 0;
+        let has_boot = self.has_boot();
         if input.down.should_trigger() {
-            self.selection = self.selection.next();
+            self.selection = self.selection.next(has_boot);
             dirty = true;
             selection_dirty = true;
         }
         if input.up.should_trigger() {
-            self.selection = self.selection.previous();
+            self.selection = self.selection.previous(has_boot);
             dirty = true;
             selection_dirty = true;
         }
         if input.b.should_trigger() {
+            if let Some(pause) = self.pause.take() {
+                return Ok(Some(pause));
+            }
             return Ok(Some(Box::new(GamesScreen::with_game(
                 self.games.clone(),
+                self.prefs.clone(),
                 self.selected_game,
             ))));
         }
@@ -195,86 +323,45 @@ where
             return Ok(None);
         }
 
-        let size = display.size();
         //std::println!("brightness {:?}", self.brightness);
         if selection_dirty {
             if prev != Selection::None {
-                Rectangle::new(prev.point(), Size::new(size.width - 24, 6))
-                    .into_styled(SLIDER)
-                    .draw(display)?;
-
-                let value = match prev {
-                    Selection::Brightness => self.brightness,
-                    Selection::None => panic!(),
-                };
-                let coord = prev.point();
-
-                Rectangle::new(
-                    Point::new(coord.x + 1, coord.y + 1),
-                    Size::new(
-                        (((size.width - 26) as f32 / u16::MAX as f32) * value as f32) as u32,
-                        4,
-                    ),
-                )
-                .into_styled(SLIDER_FILL)
-                .draw(display)?;
+                self.draw_entry(&prev, false, display)?;
             }
             if self.selection != Selection::None {
-                Rectangle::new(self.selection.point(), Size::new(size.width - 24, 6))
-                    .into_styled(SELECTED)
-                    .draw(display)?;
-
-                let value = match self.selection {
-                    Selection::Brightness => self.brightness,
-                    Selection::None => panic!(),
-                };
-                let coord = self.selection.point();
-
-                Rectangle::new(
-                    Point::new(coord.x + 1, coord.y + 1),
-                    Size::new(
-                        (((size.width - 26) as f32 / u16::MAX as f32) * value as f32) as u32,
-                        4,
-                    ),
-                )
-                .into_styled(SELECTED_FILL)
-                .draw(display)?;
+                let selection = self.selection.clone();
+                self.draw_entry(&selection, true, display)?;
             }
-        } else {
-            if change != 0 && self.selection != Selection::None {
-                match self.selection {
-                    Selection::Brightness => {
-                        self.brightness = if change > 0 {
-                            self.brightness.saturating_add(change.abs() as u16)
-                        } else {
-                            self.brightness.saturating_sub(change.abs() as u16)
-                        };
-                        self.events
-                            .push(Event::BacklightBrightness(self.brightness));
+        } else if change != 0 && self.selection != Selection::None {
+            match self.selection {
+                Selection::Brightness => {
+                    self.brightness = if change > 0 {
+                        self.brightness.saturating_add(change.abs() as u16)
+                    } else {
+                        self.brightness.saturating_sub(change.abs() as u16)
+                    };
+                    self.events
+                        .push(Event::BacklightBrightness(self.brightness));
+                }
+                Selection::BootRom => {
+                    if let Some(game) = self.selected_game.and_then(|i| self.games.get(i)) {
+                        self.prefs.toggle_boot(game);
+                        self.events.push(Event::SavePrefs(self.prefs.clone()));
                     }
-                    Selection::None => panic!(),
                 }
-                let coord = self.selection.point();
-                if change < 0 {
-                    Rectangle::new(
-                        Point::new(coord.x + 1, coord.y + 1),
-                        Size::new(size.width - 26, 4),
-                    )
-                    .into_styled(BACKGROUND)
-                    .draw(display)?;
+                Selection::View | Selection::Sort | Selection::Filter => {
+                    let by = change.signum();
+                    match self.selection {
+                        Selection::View => self.prefs.view = self.prefs.view.cycle(by),
+                        Selection::Sort => self.prefs.sort = self.prefs.sort.cycle(by),
+                        _ => self.prefs.filter = self.prefs.filter.cycle(by),
+                    }
+                    self.events.push(Event::SavePrefs(self.prefs.clone()));
                 }
-
-                Rectangle::new(
-                    Point::new(coord.x + 1, coord.y + 1),
-                    Size::new(
-                        (((size.width - 26) as f32 / u16::MAX as f32) * self.brightness as f32)
-                            as u32,
-                        4,
-                    ),
-                )
-                .into_styled(SELECTED_FILL)
-                .draw(display)?;
+                Selection::None => panic!(),
             }
+            let selection = self.selection.clone();
+            self.draw_entry(&selection, true, display)?;
         }
 
         Ok(None)
@@ -0,0 +1,138 @@
+//! Just enough PNG and PPM writing for frame dumps, the PNGs use stored (uncompressed) deflate
+//! blocks so no compression library is needed.
+
+use alloc::{format, vec, vec::Vec};
+use embedded_graphics::pixelcolor::{Rgb565, RgbColor};
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+// the most a stored deflate block can hold
+const STORED_BLOCK: usize = 0xFFFF;
+
+/// CRC-32 as used by PNG and zip, also what the runner prints for each frame
+pub fn crc32(data: &[u8]) -> u32 {
+    !crc32_update(0xFFFF_FFFF, data)
+}
+
+fn crc32_update(mut crc: u32, data: &[u8]) -> u32 {
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for byte in data {
+        a = (a + *byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// The framebuffer as little endian RGB565, which is what the CRC is taken over
+pub fn rgb565_bytes(pixels: &[Rgb565]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| {
+            let raw = (pixel.r() as u16) << 11 | (pixel.g() as u16) << 5 | pixel.b() as u16;
+            raw.to_le_bytes()
+        })
+        .collect()
+}
+
+/// Expands RGB565 to 8 bits per channel
+pub fn rgb888(pixels: &[Rgb565]) -> Vec<u8> {
+    pixels
+        .iter()
+        .flat_map(|pixel| {
+            [
+                pixel.r() << 3 | pixel.r() >> 2,
+                pixel.g() << 2 | pixel.g() >> 4,
+                pixel.b() << 3 | pixel.b() >> 2,
+            ]
+        })
+        .collect()
+}
+
+/// Binary PPM (P6)
+pub fn ppm(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let mut out = format!("P6\n{} {}\n255\n", width, height).into_bytes();
+    out.extend_from_slice(rgb);
+    out
+}
+
+fn chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend((data.len() as u32).to_be_bytes());
+    out.extend(kind);
+    out.extend(data);
+    let crc = !crc32_update(crc32_update(0xFFFF_FFFF, kind), data);
+    out.extend(crc.to_be_bytes());
+}
+
+/// 8 bit RGB PNG
+pub fn png(width: u32, height: u32, rgb: &[u8]) -> Vec<u8> {
+    let mut out = PNG_SIGNATURE.to_vec();
+
+    let mut header = Vec::new();
+    header.extend(width.to_be_bytes());
+    header.extend(height.to_be_bytes());
+    // 8 bits, truecolour, deflate, no filter, no interlacing
+    header.extend([8, 2, 0, 0, 0]);
+    chunk(&mut out, b"IHDR", &header);
+
+    // every row starts with its filter type, always none
+    let stride = width as usize * 3;
+    let mut raw = Vec::with_capacity((stride + 1) * height as usize);
+    for row in rgb.chunks(stride) {
+        raw.push(0);
+        raw.extend(row);
+    }
+
+    let mut zlib = vec![0x78, 0x01];
+    let blocks = raw.chunks(STORED_BLOCK).count();
+    for (i, block) in raw.chunks(STORED_BLOCK).enumerate() {
+        zlib.push((i + 1 == blocks) as u8);
+        zlib.extend((block.len() as u16).to_le_bytes());
+        zlib.extend((!(block.len() as u16)).to_le_bytes());
+        zlib.extend(block);
+    }
+    zlib.extend(adler32(&raw).to_be_bytes());
+    chunk(&mut out, b"IDAT", &zlib);
+
+    chunk(&mut out, b"IEND", &[]);
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_checksums() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn test_png_layout() {
+        let rgb = [0xFF; 4 * 2 * 3];
+        let png = png(4, 2, &rgb);
+
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(png[16..20], 4u32.to_be_bytes());
+        assert_eq!(png[20..24], 2u32.to_be_bytes());
+        // 2 rows of a filter byte and 12 pixel bytes in one final stored block
+        assert_eq!(&png[37..41], b"IDAT");
+        assert_eq!(png[43], 1);
+        assert_eq!(png[44..46], 26u16.to_le_bytes());
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+    }
+}
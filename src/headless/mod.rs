@@ -0,0 +1,376 @@
+//! Runs a ROM without a window, for regression tests in CI:
+//!
+//! ```text
+//! egb-headless <rom> [--frames N] [--input FILE] [--every N] [--out DIR] [--format png|ppm]
+//!     [--expect-crc HEX] [--ppu] [--ppu-palette N] [--cheats FILE] [--movie FILE]
+//!     [--record FILE]
+//! ```
+//!
+//! Frames are what the Sprig's screen would show. The final frame (and every Nth one with
+//! `--every`) is written to the output directory and its CRC-32 printed, `--expect-crc` makes
+//! the run fail if the final frame doesn't match. `--ppu` also writes the NES PPU viewers after
+//! the last frame. NES games load the cheats next to the ROM (`game.cht` for `game.nes`), or
+//! the file given with `--cheats`.
+//!
+//! `--movie` plays an input movie instead of an input script, including FCEUX `.fm2` files, and
+//! runs for as long as the movie unless `--frames` says otherwise. `--record` writes whatever
+//! input was used as a movie, so a scripted run can be replayed later.
+
+pub mod image;
+pub mod script;
+
+use alloc::{
+    format,
+    string::{String, ToString},
+};
+use std::{eprintln, println};
+
+use crate::{
+    buffer::Buffer,
+    emu::Machine,
+    games::GameConsole,
+    gb::boot::BootMode,
+    input::InputStatus,
+    movie::{self, load_movie, Command, Movie, Start},
+    nes::{
+        cheats::{cheat_path, load_cheats},
+        viewer,
+    },
+};
+
+use script::InputScript;
+
+const WIDTH: u32 = 160;
+const HEIGHT: u32 = 128;
+
+const USAGE: &str = "usage: egb-headless <rom> [--frames N] [--input FILE] [--every N] \
+[--out DIR] [--format png|ppm] [--expect-crc HEX] [--ppu] [--ppu-palette N] [--cheats FILE] \
+[--movie FILE] [--record FILE]";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Png,
+    Ppm,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Ppm => "ppm",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Options {
+    pub rom: String,
+    /// Defaults to the movie's length, or a second without one
+    pub frames: Option<u32>,
+    pub input: Option<String>,
+    /// Dump every Nth frame as well as the last
+    pub every: Option<u32>,
+    pub out: String,
+    pub format: Format,
+    pub expect_crc: Option<u32>,
+    /// Write the PPU viewers at the end, NES only
+    pub ppu: bool,
+    /// The palette the pattern tables are drawn in, 0-7
+    pub ppu_palette: u8,
+    /// A cheat file, NES only
+    pub cheats: Option<String>,
+    /// An input movie to play instead of a script
+    pub movie: Option<String>,
+    /// Where to save the input as a movie
+    pub record: Option<String>,
+}
+
+impl Options {
+    pub fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            rom: String::new(),
+            frames: None,
+            input: None,
+            every: None,
+            out: ".".to_string(),
+            format: Format::Png,
+            expect_crc: None,
+            ppu: false,
+            ppu_palette: 0,
+            cheats: None,
+            movie: None,
+            record: None,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or(format!("{} needs a value", arg))
+                    .map(String::as_str)
+            };
+            let number = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("{} expects a number", arg))
+            };
+
+            match arg.as_str() {
+                "--frames" => options.frames = Some(number(value()?)?),
+                "--input" => options.input = Some(value()?.to_string()),
+                "--every" => options.every = Some(number(value()?)?).filter(|n| *n > 0),
+                "--out" => options.out = value()?.to_string(),
+                "--format" => {
+                    options.format = match value()? {
+                        "png" => Format::Png,
+                        "ppm" => Format::Ppm,
+                        other => return Err(format!("unknown format '{}'", other)),
+                    }
+                }
+                "--expect-crc" => {
+                    let crc = value()?;
+                    let crc = crc.trim_start_matches("0x");
+                    options.expect_crc = Some(
+                        u32::from_str_radix(crc, 16)
+                            .map_err(|_| format!("'{}' isn't a hex CRC", crc))?,
+                    );
+                }
+                "--ppu" => options.ppu = true,
+                "--ppu-palette" => {
+                    options.ppu_palette = number(value()?)?
+                        .try_into()
+                        .ok()
+                        .filter(|palette| *palette < 8)
+                        .ok_or("--ppu-palette expects 0-7")?
+                }
+                "--cheats" => options.cheats = Some(value()?.to_string()),
+                "--movie" => options.movie = Some(value()?.to_string()),
+                "--record" => options.record = Some(value()?.to_string()),
+                flag if flag.starts_with("--") => return Err(format!("unknown flag {}", flag)),
+                rom if options.rom.is_empty() => options.rom = rom.to_string(),
+                extra => return Err(format!("unexpected argument '{}'", extra)),
+            }
+        }
+
+        if options.rom.is_empty() {
+            return Err("no ROM given".to_string());
+        }
+        if options.movie.is_some() && options.input.is_some() {
+            return Err("--movie and --input can't be used together".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// Starts a ROM file on the emulator for its console
+fn load(path: &str) -> Result<Machine, String> {
+    let data = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    let console = GameConsole::detect(&data, path).ok_or(format!("{}: unknown ROM type", path))?;
+    // a boot ROM would only change the first few seconds of every run
+    Machine::from_rom(console, data, BootMode::Skip)
+}
+
+/// Writes a frame and returns its CRC
+fn dump(options: &Options, frame: u32, display: &Buffer) -> Result<u32, String> {
+    let pixels = display.data();
+    let crc = image::crc32(&image::rgb565_bytes(&pixels));
+
+    let rgb = image::rgb888(&pixels);
+    let data = match options.format {
+        Format::Png => image::png(WIDTH, HEIGHT, &rgb),
+        Format::Ppm => image::ppm(WIDTH, HEIGHT, &rgb),
+    };
+    let path = format!(
+        "{}/frame_{:05}.{}",
+        options.out,
+        frame,
+        options.format.extension()
+    );
+    std::fs::write(&path, data).map_err(|e| format!("{}: {}", path, e))?;
+
+    println!("frame {}: {} crc32 {:08x}", frame, path, crc);
+    Ok(crc)
+}
+
+pub fn run_with(options: &Options) -> Result<u32, String> {
+    let script = match &options.input {
+        Some(path) => InputScript::parse(
+            &std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?,
+        )
+        .map_err(|e| format!("{}: {}", path, e))?,
+        None => InputScript::default(),
+    };
+    let movie = options.movie.as_deref().map(load_movie).transpose()?;
+    let mut machine = load(&options.rom)?;
+    match (&mut machine, &options.cheats) {
+        (Machine::Nes(nes), Some(path)) => nes.set_cheats(load_cheats(path)?),
+        (Machine::Nes(nes), None) => {
+            let path = cheat_path(&options.rom);
+            if std::path::Path::new(&path).exists() {
+                nes.set_cheats(load_cheats(&path)?);
+            }
+        }
+        (_, Some(_)) => return Err("--cheats needs a NES ROM".to_string()),
+        (_, None) => {}
+    }
+    match (&mut machine, &movie) {
+        (Machine::Nes(nes), Some(movie)) => movie.begin(nes)?,
+        // other consoles can only follow the buttons
+        (_, Some(movie))
+            if movie.start != Start::PowerOn
+                || movie.frames.iter().any(|f| f.command != Command::None) =>
+        {
+            return Err("the movie needs a NES ROM".to_string())
+        }
+        _ => {}
+    }
+    std::fs::create_dir_all(&options.out).map_err(|e| format!("{}: {}", options.out, e))?;
+
+    let frames = options
+        .frames
+        .or(movie.as_ref().map(|movie| movie.frames.len() as u32))
+        .unwrap_or(60);
+    let mut recording = Movie::new(Start::PowerOn);
+    let mut display = Buffer::new();
+    let mut input = InputStatus::default();
+    let mut crc = 0;
+
+    for frame in 1..=frames {
+        let index = frame as usize - 1;
+        match &movie {
+            Some(movie) => {
+                if let Machine::Nes(nes) = &mut machine {
+                    movie.play(index, nes);
+                }
+                let held = movie.frames.get(index).map_or(0, |f| f.buttons);
+                movie::apply(held, &mut input);
+            }
+            None => script.apply(frame - 1, &mut input),
+        }
+        recording.record(&input);
+        machine.frame(&input);
+        // the buffer can't fail to draw
+        machine.present(&mut display).unwrap();
+
+        let last = frame == frames;
+        if last || options.every.is_some_and(|every| frame % every == 0) {
+            crc = dump(options, frame, &display)?;
+        }
+    }
+
+    if options.ppu {
+        let Machine::Nes(nes) = &machine else {
+            return Err("--ppu needs a NES ROM".to_string());
+        };
+        for path in viewer::save(&nes.cpu().bus.ppu, &options.out, options.ppu_palette)? {
+            println!("{}", path);
+        }
+    }
+
+    if let Some(path) = &options.record {
+        std::fs::write(path, recording.to_text()).map_err(|e| format!("{}: {}", path, e))?;
+    }
+
+    Ok(crc)
+}
+
+/// Entry point for `egb-headless`, returns the exit code
+pub fn run(args: &[String]) -> i32 {
+    let options = match Options::parse(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            return 2;
+        }
+    };
+
+    match run_with(&options) {
+        Ok(crc) => match options.expect_crc {
+            Some(expected) if expected != crc => {
+                eprintln!("crc32 mismatch: got {:08x}, expected {:08x}", crc, expected);
+                1
+            }
+            _ => 0,
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_options() {
+        let options = Options::parse(&args(&[
+            "game.gba",
+            "--frames",
+            "120",
+            "--every",
+            "30",
+            "--format",
+            "ppm",
+            "--expect-crc",
+            "0xdeadbeef",
+            "--ppu",
+            "--ppu-palette",
+            "5",
+            "--cheats",
+            "game.cht",
+        ]))
+        .unwrap();
+        assert_eq!(options.rom, "game.gba");
+        assert_eq!(options.frames, Some(120));
+        assert_eq!(options.every, Some(30));
+        assert_eq!(options.format, Format::Ppm);
+        assert_eq!(options.expect_crc, Some(0xDEADBEEF));
+        assert!(options.ppu);
+        assert_eq!(options.ppu_palette, 5);
+        assert_eq!(options.cheats.as_deref(), Some("game.cht"));
+
+        assert!(Options::parse(&args(&["--frames", "10"])).is_err());
+        assert!(Options::parse(&args(&["game.nes", "--frames"])).is_err());
+        assert!(Options::parse(&args(&["game.nes", "--bogus"])).is_err());
+        assert!(Options::parse(&args(&["game.nes", "--ppu-palette", "8"])).is_err());
+        assert!(Options::parse(&args(&["game.nes", "--movie", "a.fm2", "--input", "b"])).is_err());
+    }
+
+    #[test]
+    fn test_sprig_run() {
+        let dir = std::env::temp_dir().join(format!("egb_headless_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let game = dir.join("demo.sprig");
+        std::fs::write(&game, include_str!("../assets/games/crate_push.sprig")).unwrap();
+        let script = dir.join("input.txt");
+        std::fs::write(&script, "0 right\n1\n").unwrap();
+
+        let mut options = Options::parse(&args(&[
+            game.to_str().unwrap(),
+            "--frames",
+            "3",
+            "--input",
+            script.to_str().unwrap(),
+            "--out",
+            dir.to_str().unwrap(),
+        ]))
+        .unwrap();
+        let moved = run_with(&options).unwrap();
+        assert!(dir.join("frame_00003.png").exists());
+
+        // the same run gives the same frame, without the input it doesn't
+        assert_eq!(run_with(&options).unwrap(), moved);
+        options.input = None;
+        assert_ne!(run_with(&options).unwrap(), moved);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,102 @@
+//! Scripted input for headless runs. Each line is a frame number followed by the buttons held
+//! from that frame on, a frame with no buttons releases everything:
+//!
+//! ```text
+//! # hold right for a second, then press start
+//! 0 right
+//! 60
+//! 90 start
+//! 95
+//! ```
+
+use alloc::{format, string::String, vec::Vec};
+
+use crate::input::InputStatus;
+
+const BUTTONS: [&str; 8] = ["up", "down", "left", "right", "a", "b", "start", "select"];
+
+#[derive(Default)]
+pub struct InputScript {
+    /// Frames where the held buttons change, in order
+    changes: Vec<(u32, [bool; 8])>,
+}
+
+impl InputScript {
+    pub fn parse(src: &str) -> Result<InputScript, String> {
+        let mut script = InputScript::default();
+
+        for (n, line) in src.lines().enumerate() {
+            let n = n + 1;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let frame = words
+                .next()
+                .and_then(|word| word.parse::<u32>().ok())
+                .ok_or(format!("line {}: expected a frame number", n))?;
+            if script
+                .changes
+                .last()
+                .is_some_and(|(last, _)| *last >= frame)
+            {
+                return Err(format!("line {}: frames must go up", n));
+            }
+
+            let mut held = [false; 8];
+            for word in words {
+                let i = BUTTONS
+                    .iter()
+                    .position(|button| button.eq_ignore_ascii_case(word))
+                    .ok_or(format!("line {}: unknown button '{}'", n, word))?;
+                held[i] = true;
+            }
+            script.changes.push((frame, held));
+        }
+
+        Ok(script)
+    }
+
+    /// Which buttons are down on a frame, in `BUTTONS` order
+    pub fn held(&self, frame: u32) -> [bool; 8] {
+        self.changes
+            .iter()
+            .take_while(|(start, _)| *start <= frame)
+            .last()
+            .map(|(_, held)| *held)
+            .unwrap_or_default()
+    }
+
+    pub fn apply(&self, frame: u32, input: &mut InputStatus) {
+        let [up, down, left, right, a, b, start, select] = self.held(frame);
+        input.update(up, down, left, right, a, b);
+        input.start.update(start);
+        input.select.update(select);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_script() {
+        let script = InputScript::parse("# comment\n0 right\n\n60 A start\n90\n").unwrap();
+        assert!(script.held(0)[3]);
+        assert_eq!(
+            script.held(59),
+            [false, false, false, true, false, false, false, false]
+        );
+        assert_eq!(
+            script.held(60),
+            [false, false, false, false, true, false, true, false]
+        );
+        assert_eq!(script.held(1000), [false; 8]);
+
+        assert!(InputScript::parse("10 up\n5 down").is_err());
+        assert!(InputScript::parse("10 jump").is_err());
+        assert!(InputScript::parse("up").is_err());
+    }
+}
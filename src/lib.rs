@@ -0,0 +1,48 @@
+//! The emulators, menus and devices. `egb` runs them on the Sprig or in the simulator, and
+//! `egb-headless` without a window, which is why SDL is behind the `sdl` feature.
+
+#![no_std]
+
+#[cfg(target_arch = "x86_64")]
+extern crate std;
+
+extern crate alloc;
+
+mod buffer;
+pub mod device;
+mod emu;
+mod events;
+#[cfg(target_arch = "x86_64")]
+mod font;
+mod games;
+mod gb;
+#[cfg(target_arch = "x86_64")]
+mod gba;
+pub mod gui;
+#[cfg(target_arch = "x86_64")]
+pub mod headless;
+pub mod input;
+pub mod library;
+mod movie;
+pub mod nes;
+mod pacer;
+pub mod prefs;
+#[cfg(target_arch = "arm")]
+pub mod rp2040;
+mod session;
+#[cfg(all(target_arch = "x86_64", feature = "sdl"))]
+pub mod simulator;
+mod sprig;
+pub mod storage;
+#[cfg(all(target_arch = "x86_64", feature = "sdl"))]
+mod window;
+
+#[cfg(target_arch = "arm")]
+pub mod simulator {
+    pub struct Simulator;
+}
+
+#[cfg(target_arch = "x86_64")]
+pub mod rp2040 {
+    pub struct Sprig;
+}
@@ -0,0 +1,296 @@
+//! The game library: ROMs found on storage, with the console worked out from their headers and
+//! cover art paired by name, so `roms/Tetris.gb` shows `roms/Tetris.tga` on its cartridge.
+//! Folders are searched too, so games can be sorted into one per console.
+
+use alloc::{
+    format,
+    rc::Rc,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::games::{Cover, Game, GameConsole, RomHandle};
+
+/// Where games are looked for
+pub const ROM_DIR: &str = "roms";
+/// Enough for every header `GameConsole::detect` looks at
+const HEADER_LEN: usize = 0x150;
+/// How deep into folders to look
+const MAX_DEPTH: u8 = 4;
+/// Bigger art wouldn't fit on a cartridge and could run the Sprig out of memory
+const MAX_COVER: u64 = 16 * 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DirEntry {
+    pub name: String,
+    pub size: u64,
+    pub dir: bool,
+}
+
+/// Somewhere games can be read from, a folder on the host or the Sprig's SD card. Paths use
+/// `/` and are relative to the storage's root.
+pub trait Storage {
+    fn list(&mut self, dir: &str) -> Result<Vec<DirEntry>, String>;
+    /// Reads from `offset` into `buf`, returning how much was read
+    fn read_at(&mut self, path: &str, offset: u64, buf: &mut [u8]) -> Result<usize, String>;
+
+    /// Reads a whole file
+    fn read(&mut self, path: &str) -> Result<Vec<u8>, String> {
+        let mut data = Vec::new();
+        let mut chunk = [0; 512];
+        loop {
+            let read = self.read_at(path, data.len() as u64, &mut chunk)?;
+            if read == 0 {
+                return Ok(data);
+            }
+            data.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+fn join(dir: &str, name: &str) -> String {
+    if dir.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
+
+/// `Super_Mario_Bros (USA).nes` is shown as `Super Mario Bros`
+pub fn title(name: &str) -> String {
+    let stem = name.rsplit_once('.').map_or(name, |(stem, _)| stem);
+    let stem = stem.split(['(', '[']).next().unwrap_or(stem);
+    stem.replace('_', " ").trim().to_string()
+}
+
+/// Finds every game under `dir`, sorted by title. Files that can't be read are skipped, only
+/// a folder that can't be listed is an error.
+pub fn scan(storage: &mut dyn Storage, dir: &str) -> Result<Vec<Game>, String> {
+    let mut games = Vec::new();
+    scan_dir(storage, dir, 0, &mut games)?;
+    games.sort_by_key(|game| game.title.to_lowercase());
+    Ok(games)
+}
+
+fn scan_dir(
+    storage: &mut dyn Storage,
+    dir: &str,
+    depth: u8,
+    games: &mut Vec<Game>,
+) -> Result<(), String> {
+    let entries = storage.list(dir)?;
+
+    for entry in &entries {
+        let path = join(dir, &entry.name);
+        if entry.dir {
+            if depth < MAX_DEPTH && !entry.name.starts_with('.') {
+                scan_dir(storage, &path, depth + 1, games)?;
+            }
+            continue;
+        }
+        if entry.name.to_ascii_lowercase().ends_with(".tga") {
+            continue;
+        }
+
+        let mut header = vec![0; HEADER_LEN];
+        let Ok(read) = storage.read_at(&path, 0, &mut header) else {
+            continue;
+        };
+        let Some(console) = GameConsole::detect(&header[..read], &entry.name) else {
+            continue;
+        };
+
+        let stem = entry
+            .name
+            .rsplit_once('.')
+            .map_or(&*entry.name, |(stem, _)| stem);
+        let cover = entries
+            .iter()
+            .find(|art| {
+                !art.dir
+                    && art.size <= MAX_COVER
+                    && art.name.eq_ignore_ascii_case(&format!("{}.tga", stem))
+            })
+            .and_then(|art| storage.read(&join(dir, &art.name)).ok())
+            .map(|data| Cover::Loaded(Rc::from(data)));
+
+        games.push(Game::new(
+            title(&entry.name),
+            console,
+            cover,
+            Some(RomHandle::File(path)),
+        ));
+    }
+    Ok(())
+}
+
+/// What's shown when there's nothing on storage
+pub fn builtin() -> Vec<Game> {
+    let mut crate_push =
+        Game::new_sprig("Crate Push", include_bytes!("assets/games/crate_push.tga"));
+    crate_push.rom = Some(RomHandle::Builtin(include_bytes!(
+        "assets/games/crate_push.sprig"
+    )));
+    let snake = Game::new(
+        "Snake".to_string(),
+        GameConsole::NES,
+        None,
+        Some(RomHandle::Builtin(include_bytes!("snake.nes"))),
+    );
+
+    vec![
+        snake,
+        Game::new_gameboy_advanced(
+            "Super Mario Advanced",
+            include_bytes!("assets/games/super_mario_advanced.tga"),
+        ),
+        Game::new_gameboy(
+            "Super Mario Land",
+            include_bytes!("assets/games/super_mario_land.tga"),
+        ),
+        crate_push,
+    ]
+}
+
+/// The games on storage, or the built in ones if there aren't any
+pub fn load(storage: Option<&mut dyn Storage>) -> Vec<Game> {
+    let games = storage
+        .and_then(|storage| scan(storage, ROM_DIR).ok())
+        .unwrap_or_default();
+    if games.is_empty() {
+        builtin()
+    } else {
+        games
+    }
+}
+
+/// A folder on the host standing in for the SD card
+#[cfg(target_arch = "x86_64")]
+pub struct HostStorage {
+    root: std::path::PathBuf,
+}
+
+#[cfg(target_arch = "x86_64")]
+impl HostStorage {
+    pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+impl Storage for HostStorage {
+    fn list(&mut self, dir: &str) -> Result<Vec<DirEntry>, String> {
+        let path = self.root.join(dir);
+        let entries = std::fs::read_dir(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        let mut list = Vec::new();
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            list.push(DirEntry {
+                name: entry.file_name().to_string_lossy().to_string(),
+                size: meta.len(),
+                dir: meta.is_dir(),
+            });
+        }
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(list)
+    }
+
+    fn read_at(&mut self, path: &str, offset: u64, buf: &mut [u8]) -> Result<usize, String> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let path = self.root.join(path);
+        let error = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let mut file = std::fs::File::open(&path).map_err(error)?;
+        file.seek(SeekFrom::Start(offset)).map_err(error)?;
+
+        // read can stop short, fill as much of the buffer as there is
+        let mut filled = 0;
+        while filled < buf.len() {
+            match file.read(&mut buf[filled..]).map_err(error)? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        Ok(filled)
+    }
+
+    fn read(&mut self, path: &str) -> Result<Vec<u8>, String> {
+        let path = self.root.join(path);
+        std::fs::read(&path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        let mut gb = vec![0; HEADER_LEN];
+        gb[0x104..0x108].copy_from_slice(&[0xCE, 0xED, 0x66, 0x66]);
+        assert_eq!(
+            GameConsole::detect(&gb, "tetris.bin"),
+            Some(GameConsole::GameBoy)
+        );
+        gb[0x143] = 0xC0;
+        assert_eq!(
+            GameConsole::detect(&gb, "x.gb"),
+            Some(GameConsole::GameBoyColor)
+        );
+
+        // the header wins over a wrong extension
+        assert_eq!(
+            GameConsole::detect(b"NES\x1A\x02\x01", "game.gb"),
+            Some(GameConsole::NES)
+        );
+        assert_eq!(
+            GameConsole::detect(b"title: hi", "crate.sprig"),
+            Some(GameConsole::Sprig)
+        );
+        assert_eq!(GameConsole::detect(b"notes", "readme.txt"), None);
+
+        assert_eq!(title("Super_Mario_Bros (USA) [!].nes"), "Super Mario Bros");
+    }
+
+    #[test]
+    fn test_scan() {
+        let root = std::env::temp_dir().join(format!("egb_library_{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let roms = root.join(ROM_DIR);
+        std::fs::create_dir_all(roms.join("nes")).unwrap();
+
+        std::fs::write(roms.join("nes/Zelda.nes"), b"NES\x1A\x08\x00").unwrap();
+        let cover = include_bytes!("assets/games/super_mario_bros.tga");
+        std::fs::write(roms.join("nes/zelda.TGA"), cover).unwrap();
+        std::fs::write(
+            roms.join("crate_push.sprig"),
+            include_str!("assets/games/crate_push.sprig"),
+        )
+        .unwrap();
+        std::fs::write(roms.join("notes.txt"), "not a game").unwrap();
+
+        let games = scan(&mut HostStorage::new(&root), ROM_DIR).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[0].title, "crate push");
+        assert_eq!(games[0].console, GameConsole::Sprig);
+        assert!(games[0].get_image().is_none());
+        assert_eq!(games[1].title, "Zelda");
+        assert_eq!(games[1].console, GameConsole::NES);
+        assert_eq!(
+            games[1].rom,
+            Some(RomHandle::File("roms/nes/Zelda.nes".to_string()))
+        );
+        assert!(games[1].get_image().is_some());
+
+        // nothing there falls back to the built in games
+        let empty = root.join("empty");
+        std::fs::create_dir_all(&empty).unwrap();
+        assert_eq!(load(Some(&mut HostStorage::new(&empty))).len(), 4);
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
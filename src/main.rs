@@ -6,15 +6,14 @@ extern crate std;
 
 use core::panic;
 
+use alloc::boxed::Box;
 use alloc::vec::Vec;
-use alloc::{boxed::Box, vec};
 #[cfg(target_arch = "arm")]
 use defmt_rtt as _;
-use games::Game;
-use gui::{core::Gui, screens::games::GamesScreen};
+use egb::gui::{self, core::Gui, screens::games::GamesScreen};
+use egb::input::InputStatus;
 #[cfg(target_arch = "arm")]
 use hal::entry;
-use input::InputStatus;
 #[cfg(target_arch = "arm")]
 use rp2040_hal as hal;
 
@@ -26,7 +25,7 @@ use embedded_alloc::Heap;
 #[global_allocator]
 static HEAP: Heap = Heap::empty();
 
-use device::Device;
+use egb::device::Device;
 use embedded_graphics::{
     geometry::Point,
     image::Image,
@@ -39,34 +38,7 @@ use embedded_graphics::draw_target::DrawTarget;
 
 //use st7735_lcd::Orientation;
 
-mod device;
-#[cfg(target_arch = "arm")]
-mod rp2040;
-#[cfg(target_arch = "x86_64")]
-mod simulator;
-use simulator::Simulator;
-use tinytga::Tga;
-mod buffer;
-mod emu;
-mod events;
-#[cfg(target_arch = "x86_64")]
-mod font;
-mod games;
-mod gui;
-mod input;
-mod nes;
-
-use rp2040::Sprig;
-
-#[cfg(target_arch = "arm")]
-mod simulator {
-    pub struct Simulator;
-}
-
-#[cfg(target_arch = "x86_64")]
-mod rp2040 {
-    pub struct Sprig;
-}
+use egb::{library, nes, prefs, rp2040::Sprig, simulator::Simulator, storage};
 
 #[cfg(target_arch = "arm")]
 #[link_section = ".boot2"]
@@ -76,8 +48,6 @@ pub static BOOT2: [u8; 256] = rp2040_boot2::BOOT_LOADER_W25Q080;
 #[cfg(target_arch = "arm")]
 #[entry]
 fn main() -> ! {
-    use input::InputStatus;
-
     let mut sprig = shared().0.unwrap();
     let mut input = InputStatus::default();
 
@@ -99,25 +69,70 @@ fn shared() -> (Option<Sprig>, Option<Simulator>) {
         unsafe { HEAP.init(HEAP_MEM.as_ptr() as usize, HEAP_SIZE) }
     }
 
-    let mut games: Vec<Game> = vec![];
-    games.push(Game::new_nes(
-        "Super Mario Bros",
-        Tga::from_slice(include_bytes!("assets/games/super_mario_bros.tga")).unwrap(),
-    ));
-    games.push(Game::new_gameboy_advanced(
-        "Super Mario Advanced",
-        Tga::from_slice(include_bytes!("assets/games/super_mario_advanced.tga")).unwrap(),
-    ));
-    games.push(Game::new_gameboy(
-        "Super Mario Land",
-        Tga::from_slice(include_bytes!("assets/games/super_mario_land.tga")).unwrap(),
-    ));
+    // the Sprig reads its games from the SD card, it's brought up before anything is shown
+    #[cfg(target_arch = "arm")]
+    let mut device = Sprig::start();
+    #[cfg(target_arch = "arm")]
+    let games = library::load(device.storage());
+    // `egb <folder>` looks for games in <folder>/roms instead of ./roms, and `egb card.img`
+    // in the roms folder of a FAT disk image like the Sprig's SD card
+    #[cfg(target_arch = "x86_64")]
+    let mut storage: Box<dyn library::Storage> = match std::env::args().nth(1) {
+        Some(path) if path.ends_with(".img") => {
+            let volume =
+                storage::image::DiskImage::open(&path).and_then(storage::fat::Volume::mount);
+            match volume {
+                Ok(volume) => Box::new(volume),
+                Err(e) => panic!("{}", e),
+            }
+        }
+        Some(path) if std::path::Path::new(&path).is_dir() => {
+            Box::new(library::HostStorage::new(path))
+        }
+        _ => Box::new(library::HostStorage::new(".")),
+    };
+    #[cfg(target_arch = "x86_64")]
+    let games = library::load(Some(storage.as_mut()));
+
+    // the view, favourites and recently played games from last time
+    #[cfg(target_arch = "x86_64")]
+    let prefs = prefs::load_prefs(prefs::PREFS_PATH).unwrap_or_default();
+    #[cfg(target_arch = "arm")]
+    let prefs = device
+        .storage()
+        .and_then(|storage| prefs::read_prefs(storage, prefs::PREFS_PATH).ok())
+        .unwrap_or_default();
+    // kept by the device too, for going back to the library from the pause menu
+    let (library, library_prefs) = (games.clone(), prefs.clone());
 
     // TODO: maybe add a startup screen?
+    // `egb tune.nsf` opens the NSF player instead
     #[cfg(target_arch = "x86_64")]
-    let mut device = Simulator::init(Box::new(GamesScreen::new(games)));
+    let mut device = match std::env::args().nth(1).filter(|arg| arg.ends_with(".nsf")) {
+        Some(path) => {
+            let player = nes::nsf::load_nsf(&path).and_then(nes::nsf::NsfPlayer::new);
+            match player {
+                Ok(player) => Simulator::init(Box::new(gui::screens::nsf::NsfScreen::new(
+                    player, games, prefs,
+                ))),
+                // B on the error goes on to the library
+                Err(e) => Simulator::init(Box::new(gui::screens::error::ErrorScreen::new(
+                    &path,
+                    &e,
+                    Box::new(GamesScreen::new(games, prefs)),
+                ))),
+            }
+        }
+        None => Simulator::init(Box::new(GamesScreen::new(games, prefs))),
+    };
     #[cfg(target_arch = "arm")]
-    let mut device = Sprig::init(Box::new(GamesScreen::new(games)));
+    device.show(Box::new(GamesScreen::new(games, prefs)));
+
+    device.set_library(library, library_prefs);
+
+    // games are read from the same place when they're launched
+    #[cfg(target_arch = "x86_64")]
+    device.set_storage(storage);
 
     let disp = device.display();
 
@@ -134,6 +149,15 @@ fn shared() -> (Option<Sprig>, Option<Simulator>) {
 fn main() {
     //font::write_font();
 
+    let args: Vec<alloc::string::String> = std::env::args().collect();
+    match args.get(1).map(|arg| arg.as_str()) {
+        Some("blargg") => std::process::exit(nes::blargg::main(&args[2..])),
+        Some("disasm") => std::process::exit(nes::disasm::main(&args[2..])),
+        Some("debug") => std::process::exit(nes::debugger::main(&args[2..])),
+        Some("gdb") => std::process::exit(nes::gdbstub::main(&args[2..])),
+        _ => {}
+    }
+
     let mut sim = shared().1.unwrap();
     let mut input = InputStatus::default();
     let mut val = 0;
@@ -0,0 +1,381 @@
+//! Input movies: the buttons held on every frame, from power-on or a save state, so a run can
+//! be played back exactly. Frames are written like FCEUX's `.fm2` logs, which can be imported:
+//!
+//! ```text
+//! egb-movie 1
+//! start power-on
+//! |0|........|
+//! |0|....T...|
+//! |1|.......A|
+//! ```
+//!
+//! The first field is 1 for a reset and 2 for a power cycle before the frame, the second is
+//! right, left, down, up, start, select, B and A, with a dot for a button that isn't held.
+//! `start state <hex>` starts from an NES save state instead of power-on.
+//! https://fceux.com/web/help/fm2.html
+//!
+//! Only `egb-headless` records and plays movies, the simulator and the Sprig don't yet.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    input::InputStatus,
+    nes::{emu::NesEmulator, joypad},
+};
+
+const HEADER: &str = "egb-movie 1";
+/// The order buttons are written in, the bit each one is
+const COLUMNS: [(char, u8); 8] = [
+    ('R', joypad::RIGHT),
+    ('L', joypad::LEFT),
+    ('D', joypad::DOWN),
+    ('U', joypad::UP),
+    ('T', joypad::START),
+    ('S', joypad::SELECT),
+    ('B', joypad::B),
+    ('A', joypad::A),
+];
+
+/// The buttons held in `input`, as NES controller bits
+pub fn buttons(input: &InputStatus) -> u8 {
+    [
+        (input.a.pressed, joypad::A),
+        (input.b.pressed, joypad::B),
+        (input.select.pressed, joypad::SELECT),
+        (input.start.pressed, joypad::START),
+        (input.up.pressed, joypad::UP),
+        (input.down.pressed, joypad::DOWN),
+        (input.left.pressed, joypad::LEFT),
+        (input.right.pressed, joypad::RIGHT),
+    ]
+    .iter()
+    .filter(|(pressed, _)| *pressed)
+    .fold(0, |bits, (_, bit)| bits | bit)
+}
+
+/// Holds the buttons in `bits` for a frame
+pub fn apply(bits: u8, input: &mut InputStatus) {
+    let held = |bit: u8| bits & bit != 0;
+    input.update(
+        held(joypad::UP),
+        held(joypad::DOWN),
+        held(joypad::LEFT),
+        held(joypad::RIGHT),
+        held(joypad::A),
+        held(joypad::B),
+    );
+    input.start.update(held(joypad::START));
+    input.select.update(held(joypad::SELECT));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    None,
+    Reset,
+    Power,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frame {
+    pub buttons: u8,
+    /// Happens before the frame runs
+    pub command: Command,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Start {
+    PowerOn,
+    /// An NES save state
+    State(Vec<u8>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Movie {
+    pub start: Start,
+    pub frames: Vec<Frame>,
+}
+
+/// `|commands|RLDUTSBA|...`, anything after the first controller is ignored
+fn parse_frame(line: &str) -> Result<Frame, String> {
+    let mut fields = line
+        .strip_prefix('|')
+        .ok_or("frames start with '|'")?
+        .split('|');
+    let command = fields.next().unwrap_or("").trim();
+    let command = match command.parse::<u8>() {
+        Ok(bits) if bits & 2 != 0 => Command::Power,
+        Ok(bits) if bits & 1 != 0 => Command::Reset,
+        Ok(_) => Command::None,
+        Err(_) if command.is_empty() => Command::None,
+        Err(_) => return Err(format!("'{}' isn't a command", command)),
+    };
+
+    let pad = fields.next().ok_or("missing the controller")?;
+    if !pad.is_empty() && pad.chars().count() != COLUMNS.len() {
+        return Err(format!("'{}' should be 8 buttons", pad));
+    }
+    let buttons = pad
+        .chars()
+        .zip(COLUMNS)
+        .filter(|(c, _)| *c != '.' && *c != ' ')
+        .fold(0, |bits, (_, (_, bit))| bits | bit);
+
+    Ok(Frame { buttons, command })
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn unhex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) {
+        return Err("the state has an odd number of hex digits".to_string());
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| {
+            text.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(format!("bad hex in the state at {}", i))
+        })
+        .collect()
+}
+
+impl Movie {
+    pub fn new(start: Start) -> Self {
+        Self {
+            start,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Adds a frame with what's held in `input`
+    pub fn record(&mut self, input: &InputStatus) {
+        self.frames.push(Frame {
+            buttons: buttons(input),
+            command: Command::None,
+        });
+    }
+
+    pub fn parse(src: &str) -> Result<Movie, String> {
+        let mut lines = src
+            .lines()
+            .enumerate()
+            .map(|(n, line)| (n + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        if lines.next().map(|(_, line)| line) != Some(HEADER) {
+            return Err(format!("not a movie, it should start with '{}'", HEADER));
+        }
+
+        let mut movie = Movie::new(Start::PowerOn);
+        for (n, line) in lines {
+            if let Some(start) = line.strip_prefix("start ") {
+                movie.start = match start.split_once(' ') {
+                    None if start == "power-on" => Start::PowerOn,
+                    Some(("state", data)) => {
+                        Start::State(unhex(data).map_err(|e| format!("line {}: {}", n, e))?)
+                    }
+                    _ => return Err(format!("line {}: unknown start '{}'", n, start)),
+                };
+            } else {
+                let frame = parse_frame(line).map_err(|e| format!("line {}: {}", n, e))?;
+                movie.frames.push(frame);
+            }
+        }
+        Ok(movie)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut out = format!("{}\n", HEADER);
+        match &self.start {
+            Start::PowerOn => out += "start power-on\n",
+            Start::State(state) => out += &format!("start state {}\n", hex(state)),
+        }
+        for frame in &self.frames {
+            let command = match frame.command {
+                Command::None => 0,
+                Command::Reset => 1,
+                Command::Power => 2,
+            };
+            let pad: String = COLUMNS
+                .iter()
+                .map(|(c, bit)| if frame.buttons & bit != 0 { *c } else { '.' })
+                .collect();
+            out += &format!("|{}|{}|\n", command, pad);
+        }
+        out
+    }
+
+    /// Imports an FCEUX text movie. Only the first controller is used, and movies that start
+    /// from one of FCEUX's save states can't be played.
+    pub fn from_fm2(src: &str) -> Result<Movie, String> {
+        let mut movie = Movie::new(Start::PowerOn);
+        for (n, line) in src.lines().enumerate() {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('|') {
+                let frame = parse_frame(line).map_err(|e| format!("line {}: {}", n + 1, e))?;
+                movie.frames.push(frame);
+                continue;
+            }
+
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            match (key, value.trim()) {
+                ("binary", "1") => return Err("binary fm2 movies aren't supported".to_string()),
+                ("savestate", state) if !state.is_empty() => {
+                    return Err("fm2 movies that start from a save state aren't supported".into())
+                }
+                ("port0", device) if device != "1" => {
+                    return Err("the fm2 movie has no controller in the first port".into())
+                }
+                _ => {}
+            }
+        }
+        Ok(movie)
+    }
+
+    /// Sets the NES up to play the movie from the start
+    pub fn begin(&self, emu: &mut NesEmulator) -> Result<(), String> {
+        match &self.start {
+            Start::PowerOn => Ok(()),
+            Start::State(state) => emu.load_state(state),
+        }
+    }
+
+    /// Does frame `index`'s reset and sets its buttons, false once the movie's over
+    pub fn play(&self, index: usize, emu: &mut NesEmulator) -> bool {
+        let Some(frame) = self.frames.get(index) else {
+            return false;
+        };
+        match frame.command {
+            Command::None => {}
+            Command::Reset => emu.reset(),
+            Command::Power => emu.power(),
+        }
+        emu.set_buttons(frame.buttons);
+        true
+    }
+}
+
+/// Reads a movie, `.fm2` files are imported from FCEUX
+#[cfg(target_arch = "x86_64")]
+pub fn load_movie(path: &str) -> Result<Movie, String> {
+    let src = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    if path.ends_with(".fm2") {
+        Movie::from_fm2(&src)
+    } else {
+        Movie::parse(&src)
+    }
+    .map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nes::{
+        cartridge::{Mirroring, Rom},
+        cpu::Mem,
+    };
+    use alloc::vec;
+
+    /// Reads the controller into $02 over and over, and counts reads in $01
+    fn reader() -> NesEmulator {
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x01, 0x8D, 0x16, 0x40, // LDA #1, STA $4016
+            0xA9, 0x00, 0x8D, 0x16, 0x40, // LDA #0, STA $4016
+            0xA2, 0x08,                   // LDX #8
+            0xAD, 0x16, 0x40,             // loop: LDA $4016
+            0x4A, 0x26, 0x00,             // LSR A, ROL $00
+            0xCA, 0xD0, 0xF7,             // DEX, BNE loop
+            0xA5, 0x00, 0x85, 0x02,       // LDA $00, STA $02
+            0xE6, 0x01,                   // INC $01
+            0x4C, 0x00, 0x80,             // JMP $8000
+        ];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        prg_rom[0x3FFD] = 0x80;
+        NesEmulator::with_rom(Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        })
+    }
+
+    #[test]
+    fn test_text() {
+        let mut input = InputStatus::default();
+        let mut movie = Movie::new(Start::State(vec![0x01, 0xAB]));
+        movie.record(&input);
+        apply(joypad::START | joypad::A, &mut input);
+        movie.record(&input);
+        movie.frames[0].command = Command::Reset;
+
+        let text = movie.to_text();
+        assert!(text.ends_with("start state 01ab\n|1|........|\n|0|....T..A|\n"));
+        assert_eq!(Movie::parse(&text), Ok(movie));
+
+        assert!(Movie::parse("|0|........|").is_err());
+        assert!(Movie::parse("egb-movie 1\n|0|...|").is_err());
+    }
+
+    #[test]
+    fn test_fm2() {
+        let fm2 = "version 3\nemuVersion 22020\nport0 1\nport1 0\nport2 0\n\
+            romFilename smb\n|2|........|||\n|0|R......A|||\n|0|....T...|||\n";
+        let movie = Movie::from_fm2(fm2).unwrap();
+        assert_eq!(movie.frames.len(), 3);
+        assert_eq!(movie.frames[0].command, Command::Power);
+        assert_eq!(movie.frames[1].buttons, joypad::RIGHT | joypad::A);
+        assert_eq!(movie.frames[2].buttons, joypad::START);
+
+        assert!(Movie::from_fm2("binary 1\n").is_err());
+        assert!(Movie::from_fm2("savestate AAAA\n").is_err());
+    }
+
+    #[test]
+    fn test_playback() {
+        let mut movie = Movie::new(Start::PowerOn);
+        for bits in [joypad::A, joypad::A | joypad::RIGHT, 0, joypad::START] {
+            movie.frames.push(Frame {
+                buttons: bits,
+                command: Command::None,
+            });
+        }
+
+        let run = |movie: &Movie| {
+            let mut emu = reader();
+            movie.begin(&mut emu).unwrap();
+            let mut seen = Vec::new();
+            let mut i = 0;
+            while movie.play(i, &mut emu) {
+                emu.run_frame();
+                seen.push(emu.cpu().mem_read(0x02));
+                i += 1;
+            }
+            (seen, emu.cpu().mem_read(0x01))
+        };
+        let (seen, counted) = run(&movie);
+        // read A first, so it ends up in the high bit
+        assert_eq!(seen, [0x80, 0x81, 0x00, 0x10]);
+        assert_eq!(run(&movie), (seen, counted), "playback is deterministic");
+
+        // from a state partway through
+        let mut emu = reader();
+        movie.play(0, &mut emu);
+        emu.run_frame();
+        let mut state = Vec::new();
+        emu.save_state(&mut state);
+        let from_state = Movie {
+            start: Start::State(state),
+            frames: movie.frames[1..].to_vec(),
+        };
+        let (rest, _) = run(&from_state);
+        assert_eq!(rest, [0x81, 0x00, 0x10]);
+    }
+}
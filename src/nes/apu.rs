@@ -0,0 +1,643 @@
+use alloc::vec::Vec;
+
+pub const NTSC_CPU_HZ: u32 = 1_789_773;
+pub const PAL_CPU_HZ: u32 = 1_662_607;
+pub const SAMPLE_RATE: u32 = 22_050;
+
+#[rustfmt::skip]
+const LENGTH_TABLE: [u8; 32] = [
+    10, 254, 20, 2, 40, 4, 80, 6, 160, 8, 60, 10, 14, 12, 26, 14,
+    12, 16, 24, 18, 48, 20, 96, 22, 192, 24, 72, 26, 16, 28, 32, 30,
+];
+
+const DUTY_TABLE: [[u8; 8]; 4] = [
+    [0, 1, 0, 0, 0, 0, 0, 0],
+    [0, 1, 1, 0, 0, 0, 0, 0],
+    [0, 1, 1, 1, 1, 0, 0, 0],
+    [1, 0, 0, 1, 1, 1, 1, 1],
+];
+
+#[rustfmt::skip]
+const TRIANGLE_TABLE: [u8; 32] = [
+    15, 14, 13, 12, 11, 10, 9, 8, 7, 6, 5, 4, 3, 2, 1, 0,
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+
+const NOISE_PERIODS: [u16; 16] = [
+    4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
+];
+
+const DMC_PERIODS: [u16; 16] = [
+    428, 380, 340, 320, 286, 254, 226, 214, 190, 160, 142, 128, 106, 84, 72, 54,
+];
+
+// frame counter steps in CPU cycles
+const STEP_1: u32 = 7457;
+const STEP_2: u32 = 14913;
+const STEP_3: u32 = 22371;
+const STEP_4: u32 = 29829;
+const STEP_5: u32 = 37281;
+
+#[derive(Default)]
+struct Envelope {
+    start: bool,
+    looping: bool,
+    constant: bool,
+    period: u8,
+    divider: u8,
+    decay: u8,
+}
+
+impl Envelope {
+    fn write(&mut self, data: u8) {
+        self.looping = data & 0x20 != 0;
+        self.constant = data & 0x10 != 0;
+        self.period = data & 0x0F;
+    }
+
+    fn clock(&mut self) {
+        if self.start {
+            self.start = false;
+            self.decay = 15;
+            self.divider = self.period;
+        } else if self.divider == 0 {
+            self.divider = self.period;
+            if self.decay > 0 {
+                self.decay -= 1;
+            } else if self.looping {
+                self.decay = 15;
+            }
+        } else {
+            self.divider -= 1;
+        }
+    }
+
+    fn volume(&self) -> u8 {
+        if self.constant {
+            self.period
+        } else {
+            self.decay
+        }
+    }
+}
+
+#[derive(Default)]
+struct Pulse {
+    /// Pulse 1 negates its sweep with one's complement
+    ones_complement: bool,
+    enabled: bool,
+    duty: u8,
+    step: u8,
+    timer: u16,
+    period: u16,
+    length: u8,
+    envelope: Envelope,
+    sweep_enabled: bool,
+    sweep_period: u8,
+    sweep_negate: bool,
+    sweep_shift: u8,
+    sweep_divider: u8,
+    sweep_reload: bool,
+}
+
+impl Pulse {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.duty = data >> 6;
+                self.envelope.write(data);
+            }
+            1 => {
+                self.sweep_enabled = data & 0x80 != 0;
+                self.sweep_period = (data >> 4) & 7;
+                self.sweep_negate = data & 0x08 != 0;
+                self.sweep_shift = data & 7;
+                self.sweep_reload = true;
+            }
+            2 => self.period = (self.period & 0x700) | data as u16,
+            _ => {
+                self.period = (self.period & 0xFF) | ((data as u16 & 7) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.step = 0;
+                self.envelope.start = true;
+            }
+        }
+    }
+
+    fn sweep_target(&self) -> u16 {
+        let change = self.period >> self.sweep_shift;
+        if self.sweep_negate {
+            self.period
+                .saturating_sub(change + self.ones_complement as u16)
+        } else {
+            self.period + change
+        }
+    }
+
+    fn muted(&self) -> bool {
+        self.period < 8 || self.sweep_target() > 0x7FF
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            self.step = (self.step + 1) % 8;
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_half(&mut self) {
+        if self.length > 0 && !self.envelope.looping {
+            self.length -= 1;
+        }
+
+        if self.sweep_divider == 0 && self.sweep_enabled && self.sweep_shift > 0 && !self.muted() {
+            self.period = self.sweep_target();
+        }
+        if self.sweep_divider == 0 || self.sweep_reload {
+            self.sweep_divider = self.sweep_period;
+            self.sweep_reload = false;
+        } else {
+            self.sweep_divider -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0
+            || self.muted()
+            || DUTY_TABLE[self.duty as usize][self.step as usize] == 0
+        {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Triangle {
+    enabled: bool,
+    control: bool,
+    step: u8,
+    timer: u16,
+    period: u16,
+    length: u8,
+    linear: u8,
+    linear_reload: u8,
+    reload: bool,
+}
+
+impl Triangle {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.control = data & 0x80 != 0;
+                self.linear_reload = data & 0x7F;
+            }
+            2 => self.period = (self.period & 0x700) | data as u16,
+            3 => {
+                self.period = (self.period & 0xFF) | ((data as u16 & 7) << 8);
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.reload = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period;
+            if self.length > 0 && self.linear > 0 {
+                self.step = (self.step + 1) % 32;
+            }
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_quarter(&mut self) {
+        if self.reload {
+            self.linear = self.linear_reload;
+        } else if self.linear > 0 {
+            self.linear -= 1;
+        }
+        if !self.control {
+            self.reload = false;
+        }
+    }
+
+    fn clock_half(&mut self) {
+        if self.length > 0 && !self.control {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        // really low periods are ultrasonic, real hardware outputs them but they just pop
+        if self.period < 2 {
+            7
+        } else {
+            TRIANGLE_TABLE[self.step as usize]
+        }
+    }
+}
+
+struct Noise {
+    enabled: bool,
+    short_mode: bool,
+    shift: u16,
+    timer: u16,
+    period: u16,
+    length: u8,
+    envelope: Envelope,
+}
+
+impl Default for Noise {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            short_mode: false,
+            shift: 1,
+            timer: 0,
+            period: NOISE_PERIODS[0],
+            length: 0,
+            envelope: Envelope::default(),
+        }
+    }
+}
+
+impl Noise {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => self.envelope.write(data),
+            2 => {
+                self.short_mode = data & 0x80 != 0;
+                self.period = NOISE_PERIODS[(data & 0x0F) as usize];
+            }
+            3 => {
+                if self.enabled {
+                    self.length = LENGTH_TABLE[(data >> 3) as usize];
+                }
+                self.envelope.start = true;
+            }
+            _ => {}
+        }
+    }
+
+    /// Clocked every CPU cycle, the period table is in CPU cycles
+    fn clock_timer(&mut self) {
+        if self.timer == 0 {
+            self.timer = self.period - 1;
+            let tap = if self.short_mode { 6 } else { 1 };
+            let feedback = (self.shift ^ (self.shift >> tap)) & 1;
+            self.shift = (self.shift >> 1) | (feedback << 14);
+        } else {
+            self.timer -= 1;
+        }
+    }
+
+    fn clock_half(&mut self) {
+        if self.length > 0 && !self.envelope.looping {
+            self.length -= 1;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        if self.length == 0 || self.shift & 1 != 0 {
+            0
+        } else {
+            self.envelope.volume()
+        }
+    }
+}
+
+#[derive(Default)]
+struct Dmc {
+    irq_enabled: bool,
+    irq: bool,
+    looping: bool,
+    period: u16,
+    timer: u16,
+    level: u8,
+    sample_addr: u16,
+    sample_len: u16,
+    addr: u16,
+    remaining: u16,
+    buffer: Option<u8>,
+    shift: u8,
+    bits: u8,
+    silent: bool,
+}
+
+impl Dmc {
+    fn write(&mut self, reg: u16, data: u8) {
+        match reg {
+            0 => {
+                self.irq_enabled = data & 0x80 != 0;
+                if !self.irq_enabled {
+                    self.irq = false;
+                }
+                self.looping = data & 0x40 != 0;
+                self.period = DMC_PERIODS[(data & 0x0F) as usize];
+            }
+            1 => self.level = data & 0x7F,
+            2 => self.sample_addr = 0xC000 | (data as u16) << 6,
+            _ => self.sample_len = (data as u16) << 4 | 1,
+        }
+    }
+
+    fn restart(&mut self) {
+        self.addr = self.sample_addr;
+        self.remaining = self.sample_len;
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer > 0 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period.saturating_sub(1);
+
+        if !self.silent {
+            if self.shift & 1 != 0 {
+                if self.level <= 125 {
+                    self.level += 2;
+                }
+            } else if self.level >= 2 {
+                self.level -= 2;
+            }
+        }
+        self.shift >>= 1;
+
+        if self.bits > 0 {
+            self.bits -= 1;
+        }
+        if self.bits == 0 {
+            self.bits = 8;
+            match self.buffer.take() {
+                Some(byte) => {
+                    self.silent = false;
+                    self.shift = byte;
+                }
+                None => self.silent = true,
+            }
+        }
+    }
+
+    /// The address of the next sample byte if the buffer needs filling
+    fn request(&self) -> Option<u16> {
+        if self.buffer.is_none() && self.remaining > 0 {
+            Some(self.addr)
+        } else {
+            None
+        }
+    }
+
+    fn fill(&mut self, byte: u8) {
+        self.buffer = Some(byte);
+        self.addr = self.addr.checked_add(1).unwrap_or(0x8000);
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            if self.looping {
+                self.restart();
+            } else if self.irq_enabled {
+                self.irq = true;
+            }
+        }
+    }
+}
+
+/// The 2A03's sound hardware: two pulse channels, a triangle, noise and the DMC.
+/// https://www.nesdev.org/wiki/APU
+pub struct Apu {
+    pulse1: Pulse,
+    pulse2: Pulse,
+    triangle: Triangle,
+    noise: Noise,
+    dmc: Dmc,
+    five_step: bool,
+    irq_inhibit: bool,
+    frame_irq: bool,
+    frame_cycle: u32,
+    odd_cycle: bool,
+    cpu_hz: u32,
+    sample_timer: u32,
+    samples: Vec<i16>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Self::with_clock(NTSC_CPU_HZ)
+    }
+
+    pub fn with_clock(cpu_hz: u32) -> Self {
+        Self {
+            pulse1: Pulse {
+                ones_complement: true,
+                ..Default::default()
+            },
+            pulse2: Pulse::default(),
+            triangle: Triangle::default(),
+            noise: Noise::default(),
+            dmc: Dmc::default(),
+            five_step: false,
+            irq_inhibit: false,
+            frame_irq: false,
+            frame_cycle: 0,
+            odd_cycle: false,
+            cpu_hz,
+            sample_timer: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn set_clock(&mut self, cpu_hz: u32) {
+        self.cpu_hz = cpu_hz;
+    }
+
+    /// Handles writes to $4000-$4013, $4015 and $4017
+    pub fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4000..=0x4003 => self.pulse1.write(addr - 0x4000, data),
+            0x4004..=0x4007 => self.pulse2.write(addr - 0x4004, data),
+            0x4008..=0x400B => self.triangle.write(addr - 0x4008, data),
+            0x400C..=0x400F => self.noise.write(addr - 0x400C, data),
+            0x4010..=0x4013 => self.dmc.write(addr - 0x4010, data),
+            0x4015 => {
+                self.pulse1.enabled = data & 0x01 != 0;
+                self.pulse2.enabled = data & 0x02 != 0;
+                self.triangle.enabled = data & 0x04 != 0;
+                self.noise.enabled = data & 0x08 != 0;
+                if !self.pulse1.enabled {
+                    self.pulse1.length = 0;
+                }
+                if !self.pulse2.enabled {
+                    self.pulse2.length = 0;
+                }
+                if !self.triangle.enabled {
+                    self.triangle.length = 0;
+                }
+                if !self.noise.enabled {
+                    self.noise.length = 0;
+                }
+                if data & 0x10 == 0 {
+                    self.dmc.remaining = 0;
+                } else if self.dmc.remaining == 0 {
+                    self.dmc.restart();
+                }
+                self.dmc.irq = false;
+            }
+            0x4017 => {
+                self.five_step = data & 0x80 != 0;
+                self.irq_inhibit = data & 0x40 != 0;
+                if self.irq_inhibit {
+                    self.frame_irq = false;
+                }
+                self.frame_cycle = 0;
+                if self.five_step {
+                    self.clock_quarter();
+                    self.clock_half();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// $4015. Bus reads can't change state, so unlike the real thing reading it doesn't
+    /// acknowledge the frame interrupt.
+    pub fn status(&self) -> u8 {
+        (self.pulse1.length > 0) as u8
+            | ((self.pulse2.length > 0) as u8) << 1
+            | ((self.triangle.length > 0) as u8) << 2
+            | ((self.noise.length > 0) as u8) << 3
+            | ((self.dmc.remaining > 0) as u8) << 4
+            | (self.frame_irq as u8) << 6
+            | (self.dmc.irq as u8) << 7
+    }
+
+    pub fn irq(&self) -> bool {
+        self.frame_irq || self.dmc.irq
+    }
+
+    fn clock_quarter(&mut self) {
+        self.pulse1.envelope.clock();
+        self.pulse2.envelope.clock();
+        self.noise.envelope.clock();
+        self.triangle.clock_quarter();
+    }
+
+    fn clock_half(&mut self) {
+        self.pulse1.clock_half();
+        self.pulse2.clock_half();
+        self.triangle.clock_half();
+        self.noise.clock_half();
+    }
+
+    fn clock_frame_counter(&mut self) {
+        self.frame_cycle += 1;
+        match self.frame_cycle {
+            STEP_1 | STEP_3 => self.clock_quarter(),
+            STEP_2 => {
+                self.clock_quarter();
+                self.clock_half();
+            }
+            STEP_4 if !self.five_step => {
+                self.clock_quarter();
+                self.clock_half();
+                if !self.irq_inhibit {
+                    self.frame_irq = true;
+                }
+                self.frame_cycle = 0;
+            }
+            STEP_5 => {
+                self.clock_quarter();
+                self.clock_half();
+                self.frame_cycle = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Runs the APU for one CPU cycle. When the DMC needs another sample byte its address is
+    /// returned and the bus should answer with `dmc_fill`.
+    pub fn clock(&mut self) -> Option<u16> {
+        self.clock_frame_counter();
+
+        self.triangle.clock_timer();
+        self.noise.clock_timer();
+        self.dmc.clock_timer();
+        if self.odd_cycle {
+            self.pulse1.clock_timer();
+            self.pulse2.clock_timer();
+        }
+        self.odd_cycle = !self.odd_cycle;
+
+        self.sample_timer += SAMPLE_RATE;
+        if self.sample_timer >= self.cpu_hz {
+            self.sample_timer -= self.cpu_hz;
+            let sample = self.mix();
+            self.samples.push(((sample - 0.5) * 65535.0) as i16);
+        }
+
+        self.dmc.request()
+    }
+
+    pub fn dmc_fill(&mut self, byte: u8) {
+        self.dmc.fill(byte);
+    }
+
+    /// The current output of each channel: pulse 1, pulse 2, triangle and noise are 0-15,
+    /// the DMC is 0-127
+    pub fn levels(&self) -> [u8; 5] {
+        [
+            self.pulse1.output(),
+            self.pulse2.output(),
+            if self.triangle.length > 0 && self.triangle.linear > 0 {
+                self.triangle.output()
+            } else {
+                0
+            },
+            self.noise.output(),
+            self.dmc.level,
+        ]
+    }
+
+    /// Mixes the channels like the real DACs do, from 0.0 to 1.0
+    /// https://www.nesdev.org/wiki/APU_Mixer
+    pub fn mix(&self) -> f32 {
+        let pulse = (self.pulse1.output() + self.pulse2.output()) as f32;
+        let pulse_out = if pulse == 0.0 {
+            0.0
+        } else {
+            95.88 / (8128.0 / pulse + 100.0)
+        };
+
+        let triangle = self.triangle.output() as f32;
+        let noise = self.noise.output() as f32;
+        let dmc = self.dmc.level as f32;
+        let tnd = triangle / 8227.0 + noise / 12241.0 + dmc / 22638.0;
+        let tnd_out = if tnd == 0.0 {
+            0.0
+        } else {
+            159.79 / (1.0 / tnd + 100.0)
+        };
+
+        pulse_out + tnd_out
+    }
+
+    /// Everything generated since the last call, at `SAMPLE_RATE`
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        core::mem::take(&mut self.samples)
+    }
+}
@@ -0,0 +1,311 @@
+//! Runs test ROMs that report through $6000, like blargg's and most of nes-test-roms:
+//! $6000 holds the status, $6001-$6003 the signature DE B0 61 and $6004 on a zero terminated
+//! message.
+//! https://github.com/christopherpow/nes-test-roms
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use std::{panic, path::Path, println};
+
+use super::{cartridge::Rom, cpu::Mem, emu::NesEmulator};
+
+const STATUS: u16 = 0x6000;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const TEXT: u16 = 0x6004;
+const TEXT_MAX: u16 = 0x1000;
+
+const RUNNING: u8 = 0x80;
+const NEEDS_RESET: u8 = 0x81;
+
+/// Frames to wait before pressing reset, the ROMs ask for at least 100ms
+const RESET_DELAY: u32 = 6;
+/// Frames a result has to stay put before it's believed
+const SETTLE_FRAMES: u32 = 2;
+pub const DEFAULT_FRAMES: u32 = 60 * 60;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Passed,
+    /// The ROM's result code
+    Failed(u8),
+    /// Never wrote a result, or never wrote the signature at all
+    Timeout,
+    /// The emulator panicked
+    Crashed(String),
+    Unsupported(String),
+}
+
+impl Status {
+    /// Timeouts and unsupported ROMs are expected while the PPU and mappers are missing,
+    /// only real failures count
+    pub fn is_failure(&self) -> bool {
+        matches!(self, Status::Failed(_) | Status::Crashed(_))
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Status::Passed => "pass".to_string(),
+            Status::Failed(code) => format!("FAIL {}", code),
+            Status::Timeout => "timeout".to_string(),
+            Status::Crashed(_) => "CRASH".to_string(),
+            Status::Unsupported(why) => format!("skip ({})", why),
+        }
+    }
+}
+
+pub struct TestResult {
+    pub name: String,
+    pub status: Status,
+    /// What the ROM printed to $6004
+    pub text: String,
+    /// How long it ran before the status settled
+    pub frames: u32,
+}
+
+fn signed(emu: &NesEmulator) -> bool {
+    (0..3).all(|i| emu.cpu().mem_read(STATUS + 1 + i) == SIGNATURE[i as usize])
+}
+
+fn text(emu: &NesEmulator) -> String {
+    let mut text = String::new();
+    for addr in TEXT..TEXT + TEXT_MAX {
+        match emu.cpu().mem_read(addr) {
+            0 => break,
+            byte => text.push(byte as char),
+        }
+    }
+    text.trim().to_string()
+}
+
+/// Runs one ROM until its status settles or `max_frames` pass
+pub fn run(rom: Rom, max_frames: u32) -> (Status, String, u32) {
+    if rom.mapper != 0 {
+        return (
+            Status::Unsupported(format!("mapper {}", rom.mapper)),
+            String::new(),
+            0,
+        );
+    }
+
+    let mut emu = NesEmulator::with_rom(rom);
+    let mut reset_at = None;
+    let mut settled = 0;
+    let mut last = None;
+
+    for frame in 1..=max_frames {
+        emu.run_frame();
+        if !signed(&emu) {
+            continue;
+        }
+
+        let status = emu.cpu().mem_read(STATUS);
+        match status {
+            RUNNING => {}
+            NEEDS_RESET => match reset_at {
+                None => reset_at = Some(frame + RESET_DELAY),
+                Some(at) if frame >= at => {
+                    emu.reset();
+                    reset_at = None;
+                }
+                Some(_) => {}
+            },
+            code => {
+                settled = if last == Some(code) { settled + 1 } else { 0 };
+                if settled >= SETTLE_FRAMES {
+                    let status = if code == 0 {
+                        Status::Passed
+                    } else {
+                        Status::Failed(code)
+                    };
+                    return (status, text(&emu), frame);
+                }
+            }
+        }
+        last = Some(status);
+    }
+
+    let text = if signed(&emu) {
+        text(&emu)
+    } else {
+        String::new()
+    };
+    (Status::Timeout, text, max_frames)
+}
+
+/// Runs a ROM file, a panic in the emulator is caught and reported as a crash
+pub fn run_file(path: &Path, max_frames: u32) -> TestResult {
+    let name = path.display().to_string();
+    let rom = match std::fs::read(path)
+        .map_err(|e| e.to_string())
+        .and_then(|raw| Rom::new(&raw))
+    {
+        Ok(rom) => rom,
+        Err(e) => {
+            return TestResult {
+                name,
+                status: Status::Unsupported(e),
+                text: String::new(),
+                frames: 0,
+            }
+        }
+    };
+
+    let (status, text, frames) = match panic::catch_unwind(|| run(rom, max_frames)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            (Status::Crashed(msg.clone()), msg, 0)
+        }
+    };
+    TestResult {
+        name,
+        status,
+        text,
+        frames,
+    }
+}
+
+/// Every .nes file under `dir`, sorted so the table is stable
+pub fn discover(dir: &Path) -> Result<Vec<std::path::PathBuf>, String> {
+    let mut roms = Vec::new();
+    let mut dirs = alloc::vec![dir.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = std::fs::read_dir(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            } else if path
+                .extension()
+                .is_some_and(|ext| ext.eq_ignore_ascii_case("nes"))
+            {
+                roms.push(path);
+            }
+        }
+    }
+
+    roms.sort();
+    Ok(roms)
+}
+
+pub fn print_table(results: &[TestResult]) {
+    let width = results.iter().map(|r| r.name.len()).max().unwrap_or(0);
+    for result in results {
+        // the first line of the message is usually the test's name, the rest says what broke
+        let detail = result.text.lines().last().unwrap_or("");
+        println!(
+            "{:width$}  {:<12} {:>11}  {}",
+            result.name,
+            result.status.label(),
+            format!("{} frames", result.frames),
+            detail,
+            width = width
+        );
+    }
+
+    let passed = results
+        .iter()
+        .filter(|r| r.status == Status::Passed)
+        .count();
+    let failed = results.iter().filter(|r| r.status.is_failure()).count();
+    println!(
+        "{} passed, {} failed, {} other",
+        passed,
+        failed,
+        results.len() - passed - failed
+    );
+}
+
+/// Entry point for `egb blargg <dir> [--frames N]`, returns the exit code
+pub fn main(args: &[String]) -> i32 {
+    let mut dir = None;
+    let mut frames = DEFAULT_FRAMES;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--frames" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => frames = n,
+                None => {
+                    std::eprintln!("--frames expects a number");
+                    return 2;
+                }
+            },
+            path => dir = Some(path.to_string()),
+        }
+    }
+
+    let Some(dir) = dir else {
+        std::eprintln!("usage: egb blargg <dir> [--frames N]");
+        return 2;
+    };
+    let roms = match discover(Path::new(&dir)) {
+        Ok(roms) => roms,
+        Err(e) => {
+            std::eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let results: Vec<_> = roms.iter().map(|rom| run_file(rom, frames)).collect();
+    print_table(&results);
+
+    if results.iter().any(|r| r.status.is_failure()) {
+        1
+    } else {
+        0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use super::*;
+    use crate::nes::cartridge::Mirroring;
+
+    /// Writes the signature, "ok" and a passing status, then spins
+    fn passing_rom(code: u8) -> Rom {
+        #[rustfmt::skip]
+        let program = [
+            0xA9, 0x80, 0x8D, 0x00, 0x60, // LDA #$80, STA $6000
+            0xA9, 0xDE, 0x8D, 0x01, 0x60, // signature
+            0xA9, 0xB0, 0x8D, 0x02, 0x60,
+            0xA9, 0x61, 0x8D, 0x03, 0x60,
+            0xA9, b'o', 0x8D, 0x04, 0x60, // "ok"
+            0xA9, b'k', 0x8D, 0x05, 0x60,
+            0xA9, 0x00, 0x8D, 0x06, 0x60,
+            0xA9, code, 0x8D, 0x00, 0x60, // status
+            0x4C, 0x28, 0x80,             // JMP to itself
+        ];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        // reset vector, mirrored to $FFFC
+        prg_rom[0x3FFC] = 0x00;
+        prg_rom[0x3FFD] = 0x80;
+
+        Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        }
+    }
+
+    #[test]
+    fn test_protocol() {
+        let (status, text, _) = run(passing_rom(0), 10);
+        assert_eq!(status, Status::Passed);
+        assert_eq!(text, "ok");
+
+        let (status, _, _) = run(passing_rom(3), 10);
+        assert_eq!(status, Status::Failed(3));
+    }
+}
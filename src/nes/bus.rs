@@ -1,26 +1,152 @@
+use alloc::{string::String, vec::Vec};
+use core::cell::Cell;
+
 use crate::nes::cpu::Mem;
 
-use super::cartridge::Rom;
+use super::{apu::Apu, cartridge::Rom, cheats::Patch, joypad::Joypad, ppu::Ppu, state::Reader};
 
 const RAM: u16 = 0x0000;
 const RAM_MIRRORS_END: u16 = 0x1FFF;
 const PPU_REGISTERS: u16 = 0x2000;
 const PPU_REGISTERS_MIRRORS_END: u16 = 0x3FFF;
+const OAM_DMA: u16 = 0x4014;
+const APU_STATUS: u16 = 0x4015;
+const JOYPAD1: u16 = 0x4016;
+const JOYPAD2: u16 = 0x4017;
+const NSF_BANKS: u16 = 0x5FF8;
+const PRG_RAM: u16 = 0x6000;
+const PRG_RAM_END: u16 = 0x7FFF;
+
+/// Stops the debugger when the CPU touches `start..=end`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Watchpoint {
+    pub start: u16,
+    pub end: u16,
+    pub read: bool,
+    pub write: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WatchHit {
+    pub addr: u16,
+    pub value: u8,
+    pub write: bool,
+}
 
 pub struct Bus {
     cpu_vram: [u8; 2048],
+    prg_ram: [u8; 0x2000],
     rom: Rom,
+    pub ppu: Ppu,
+    pub apu: Apu,
+    pub joypad: Joypad,
+    /// NSF bankswitching, each register maps a 4K page of the tune into $8000-$FFFF
+    banks: Option<[u8; 8]>,
+    pub watchpoints: Vec<Watchpoint>,
+    /// Game Genie codes, applied to PRG-ROM reads
+    pub patches: Vec<Patch>,
+    /// The last access that hit a watchpoint, reads only get `&self` so it lives in a cell
+    watch_hit: Cell<Option<WatchHit>>,
 }
 
 impl Bus {
-    pub fn new(rom: Rom) -> Self {
+    pub fn new(mut rom: Rom) -> Self {
+        // the PPU owns CHR, the CPU can't see it
+        let ppu = Ppu::new(core::mem::take(&mut rom.chr_rom), rom.screen_mirroring);
         Bus {
             cpu_vram: [0; 2048],
+            prg_ram: [0; 0x2000],
             rom: rom,
+            ppu,
+            apu: Apu::new(),
+            joypad: Joypad::default(),
+            banks: None,
+            watchpoints: Vec::new(),
+            patches: Vec::new(),
+            watch_hit: Cell::new(None),
+        }
+    }
+
+    pub fn take_watch_hit(&mut self) -> Option<WatchHit> {
+        self.watch_hit.take()
+    }
+
+    fn watch(&self, addr: u16, value: u8, write: bool) {
+        let hit = self.watchpoints.iter().any(|watch| {
+            (watch.start..=watch.end).contains(&addr)
+                && if write { watch.write } else { watch.read }
+        });
+        if hit {
+            self.watch_hit.set(Some(WatchHit { addr, value, write }));
+        }
+    }
+
+    pub fn set_banks(&mut self, banks: Option<[u8; 8]>) {
+        self.banks = banks;
+    }
+
+    /// The 2K of work RAM, without the mirrors
+    pub fn ram(&self) -> &[u8; 2048] {
+        &self.cpu_vram
+    }
+
+    /// Clears the internal and cartridge RAM
+    pub fn clear_ram(&mut self) {
+        self.cpu_vram.fill(0);
+        self.prg_ram.fill(0);
+    }
+
+    /// Appends RAM, cartridge RAM, the NSF banks and the PPU to a save state
+    pub fn save(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.cpu_vram);
+        out.extend_from_slice(&self.prg_ram);
+        match self.banks {
+            Some(banks) => {
+                out.push(1);
+                out.extend_from_slice(&banks);
+            }
+            None => out.push(0),
+        }
+        self.ppu.save(out);
+    }
+
+    /// How many bytes `save` appends
+    pub fn state_len(&self) -> usize {
+        let banks = if self.banks.is_some() { 1 + 8 } else { 1 };
+        self.cpu_vram.len() + self.prg_ram.len() + banks + self.ppu.state_len()
+    }
+
+    pub fn load(&mut self, state: &mut Reader) -> Result<(), String> {
+        state.bytes(&mut self.cpu_vram)?;
+        state.bytes(&mut self.prg_ram)?;
+        self.banks = match state.u8()? {
+            0 => None,
+            _ => {
+                let mut banks = [0; 8];
+                state.bytes(&mut banks)?;
+                Some(banks)
+            }
+        };
+        self.ppu.load(state)
+    }
+
+    /// Runs the APU alongside the CPU, feeding the DMC its sample bytes
+    pub fn tick_apu(&mut self, cycles: u64) {
+        for _ in 0..cycles {
+            if let Some(addr) = self.apu.clock() {
+                let byte = self.mem_read(addr);
+                self.apu.dmc_fill(byte);
+            }
         }
     }
 
     fn read_prg_rom(&self, mut addr: u16) -> u8 {
+        if let Some(banks) = self.banks {
+            let bank = banks[(addr as usize - 0x8000) / 0x1000] as usize;
+            let offset = bank * 0x1000 + (addr as usize & 0xFFF);
+            return self.rom.prg_rom.get(offset).copied().unwrap_or(0);
+        }
+
         addr -= 0x8000;
         if self.rom.prg_rom.len() == 0x4000 && addr >= 0x4000 {
             //mirror if needed
@@ -28,21 +154,27 @@ impl Bus {
         }
         self.rom.prg_rom[addr as usize]
     }
-}
 
-impl Mem for Bus {
-    fn mem_read(&self, addr: u16) -> u8 {
+    /// Reads without tripping watchpoints, for debuggers and viewers
+    pub fn peek(&self, addr: u16) -> u8 {
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b00000111_11111111;
                 self.cpu_vram[mirror_down_addr as usize]
             }
-            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
-                let _mirror_down_addr = addr & 0b00100000_00000111;
-                //todo!("PPU is not supported yet")
-                0
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.peek_register(addr),
+            APU_STATUS => self.apu.status(),
+            JOYPAD1 => self.joypad.peek(),
+            // nothing's plugged into the second port
+            JOYPAD2 => 0,
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize],
+            0x8000..=0xFFFF => {
+                let value = self.read_prg_rom(addr);
+                self.patches
+                    .iter()
+                    .find_map(|patch| patch.apply(addr, value))
+                    .unwrap_or(value)
             }
-            0x8000..=0xFFFF => self.read_prg_rom(addr),
             _ => {
                 #[cfg(all(target_arch = "x86_64", feature = "std_x86_64"))]
                 println!("Ignoring mem access at 0x{:x}.", addr);
@@ -51,16 +183,53 @@ impl Mem for Bus {
         }
     }
 
+    /// Reads an opcode or operand. It's a read like any other, but watchpoints are for the
+    /// data a program touches, not the program itself
+    pub fn fetch(&self, addr: u16) -> u8 {
+        match addr {
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.read_register(addr),
+            JOYPAD1 => self.joypad.read(),
+            _ => self.peek(addr),
+        }
+    }
+}
+
+impl Mem for Bus {
+    fn mem_read(&self, addr: u16) -> u8 {
+        let value = self.fetch(addr);
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, value, false);
+        }
+        value
+    }
+
     fn mem_write(&mut self, addr: u16, data: u8) {
+        if !self.watchpoints.is_empty() {
+            self.watch(addr, data, true);
+        }
+
         match addr {
             RAM..=RAM_MIRRORS_END => {
                 let mirror_down_addr = addr & 0b00000111_11111111;
                 self.cpu_vram[mirror_down_addr as usize] = data;
             }
-            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => {
-                let _mirror_down_addr = addr & 0b00100000_00000111;
-                //todo!("PPU is not supported yet")
+            PPU_REGISTERS..=PPU_REGISTERS_MIRRORS_END => self.ppu.write_register(addr, data),
+            // TODO: the CPU should stall for 513 cycles
+            OAM_DMA => {
+                let mut page = [0; 256];
+                for (i, byte) in page.iter_mut().enumerate() {
+                    *byte = self.peek((data as u16) << 8 | i as u16);
+                }
+                self.ppu.dma(&page);
+            }
+            JOYPAD1 => self.joypad.write(data),
+            0x4000..=0x4013 | APU_STATUS | JOYPAD2 => self.apu.write(addr, data),
+            NSF_BANKS..=0x5FFF if self.banks.is_some() => {
+                if let Some(banks) = self.banks.as_mut() {
+                    banks[(addr - NSF_BANKS) as usize] = data;
+                }
             }
+            PRG_RAM..=PRG_RAM_END => self.prg_ram[(addr - PRG_RAM) as usize] = data,
             _ => {
                 #[cfg(all(target_arch = "x86_64", feature = "std_x86_64"))]
                 println!("Ignoring mem write-access at 0x{:x}.", addr);
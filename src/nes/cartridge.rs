@@ -3,11 +3,15 @@ use alloc::{
     vec::Vec,
 };
 
+use crate::pacer::Region;
+
 const NES_TAG: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
 const PRG_ROM_PAGE_SIZE: usize = 16384;
 const CHR_ROM_PAGE_SIZE: usize = 8192;
+/// Bit 0 of flags 9 is set for PAL cartridges
+const TV_SYSTEM: usize = 9;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Mirroring {
     VERTICAL,
     HORIZONTAL,
@@ -59,6 +63,15 @@ impl Rom {
     }
 }
 
+/// What the header says the cartridge was made for. Few dumps set the PAL bit, so most PAL
+/// games come out as NTSC.
+pub fn region(raw: &[u8]) -> Region {
+    match raw.get(TV_SYSTEM) {
+        Some(flags) if flags & 1 != 0 => Region::Pal,
+        _ => Region::Ntsc,
+    }
+}
+
 pub mod test {
 
     use alloc::vec;
@@ -0,0 +1,255 @@
+//! Game Genie codes and RAM freezes. A cheat file has one cheat a line, a code then its name,
+//! and a `-` in front turns it off:
+//!
+//! ```text
+//! # Super Mario Bros
+//! SXIOPO      Infinite lives
+//! -YSAOPE     Start on world 8
+//! 075A=09     Always 9 lives
+//! ```
+//!
+//! Game Genie codes patch what the CPU reads from PRG-ROM, `ADDR=VALUE` is written to RAM every
+//! frame.
+//! https://www.nesdev.org/wiki/Game_Genie
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::cpu::Mem;
+
+const LETTERS: &str = "APZLGITYEOXUKSVN";
+
+/// A Game Genie patch on a PRG-ROM read
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Patch {
+    pub addr: u16,
+    pub value: u8,
+    /// 8 letter codes only patch when the ROM has this value, so they survive bankswitching
+    pub compare: Option<u8>,
+}
+
+impl Patch {
+    pub fn decode(code: &str) -> Result<Patch, String> {
+        let n = code
+            .chars()
+            .map(|c| {
+                LETTERS
+                    .find(c.to_ascii_uppercase())
+                    .map(|n| n as u16)
+                    .ok_or_else(|| format!("'{}' isn't a Game Genie letter", c))
+            })
+            .collect::<Result<Vec<u16>, String>>()?;
+        if n.len() != 6 && n.len() != 8 {
+            return Err(format!("'{}' should be 6 or 8 letters", code));
+        }
+
+        let addr = 0x8000
+            | (n[3] & 7) << 12
+            | (n[5] & 7) << 8
+            | (n[4] & 8) << 8
+            | (n[2] & 7) << 4
+            | (n[1] & 8) << 4
+            | (n[4] & 7)
+            | (n[3] & 8);
+        // the last letter's high bit moves to the compare byte in 8 letter codes
+        let last = n[n.len() - 1];
+        let value = (n[1] & 7) << 4 | (n[0] & 8) << 4 | (n[0] & 7) | (last & 8);
+        let compare = (n.len() == 8)
+            .then(|| ((n[7] & 7) << 4 | (n[6] & 8) << 4 | (n[6] & 7) | (n[5] & 8)) as u8);
+
+        Ok(Patch {
+            addr,
+            value: value as u8,
+            compare,
+        })
+    }
+
+    /// What the CPU reads from `addr` when the ROM has `value` there
+    pub fn apply(&self, addr: u16, value: u8) -> Option<u8> {
+        (addr == self.addr && self.compare.is_none_or(|compare| compare == value))
+            .then_some(self.value)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Code {
+    GameGenie(Patch),
+    /// Written to RAM every frame
+    Freeze {
+        addr: u16,
+        value: u8,
+    },
+}
+
+impl Code {
+    pub fn parse(text: &str) -> Result<Code, String> {
+        let Some((addr, value)) = text.split_once('=') else {
+            return Ok(Code::GameGenie(Patch::decode(text)?));
+        };
+        let hex = |text: &str| {
+            u16::from_str_radix(text.trim_start_matches('$'), 16)
+                .map_err(|_| format!("'{}' isn't a hex number", text))
+        };
+        let value = hex(value)?;
+        if value > 0xFF {
+            return Err(format!("{} doesn't fit in a byte", value));
+        }
+        Ok(Code::Freeze {
+            addr: hex(addr)?,
+            value: value as u8,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub name: String,
+    /// As it was written, to show and save it
+    pub code: String,
+    pub kind: Code,
+    pub enabled: bool,
+}
+
+impl Cheat {
+    /// Holds a RAM byte at `value`, named after its address
+    pub fn freeze(addr: u16, value: u8) -> Cheat {
+        Cheat {
+            name: format!("RAM ${:04X}", addr),
+            code: format!("{:04X}={:02X}", addr, value),
+            kind: Code::Freeze { addr, value },
+            enabled: true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Cheats {
+    pub list: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn parse(src: &str) -> Result<Cheats, String> {
+        let mut cheats = Cheats::default();
+        for (n, line) in src.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let (enabled, line) = match line.strip_prefix('-') {
+                Some(line) => (false, line),
+                None => (true, line),
+            };
+            let (code, name) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let kind = Code::parse(code).map_err(|e| format!("line {}: {}", n + 1, e))?;
+            cheats.list.push(Cheat {
+                name: name.trim().to_string(),
+                code: code.to_string(),
+                kind,
+                enabled,
+            });
+        }
+        Ok(cheats)
+    }
+
+    /// The Game Genie codes that are on, for the bus
+    pub fn patches(&self) -> Vec<Patch> {
+        self.list
+            .iter()
+            .filter(|cheat| cheat.enabled)
+            .filter_map(|cheat| match cheat.kind {
+                Code::GameGenie(patch) => Some(patch),
+                Code::Freeze { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Writes the freezes that are on
+    pub fn freeze<M: Mem>(&self, mem: &mut M) {
+        for cheat in self.list.iter().filter(|cheat| cheat.enabled) {
+            if let Code::Freeze { addr, value } = cheat.kind {
+                mem.mem_write(addr, value);
+            }
+        }
+    }
+}
+
+/// Where a ROM's cheats live: `game.nes` has `game.cht`
+pub fn cheat_path(rom: &str) -> String {
+    match rom.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => format!("{}.cht", stem),
+        _ => format!("{}.cht", rom),
+    }
+}
+
+/// Reads a cheat file, on the simulator from a path on disk
+#[cfg(target_arch = "x86_64")]
+pub fn load_cheats(path: &str) -> Result<Cheats, String> {
+    let src = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Cheats::parse(&src).map_err(|e| format!("{}: {}", path, e))
+}
+
+// TODO: read it from the sd card
+#[cfg(not(target_arch = "x86_64"))]
+pub fn load_cheats(_path: &str) -> Result<Cheats, String> {
+    Err(String::from("No storage to load cheats from"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_game_genie() {
+        assert_eq!(
+            Patch::decode("GOSSIP"),
+            Ok(Patch {
+                addr: 0xD1DD,
+                value: 0x14,
+                compare: None
+            })
+        );
+        let patch = Patch::decode("zexpygla").unwrap();
+        assert_eq!(
+            patch,
+            Patch {
+                addr: 0x94A7,
+                value: 0x02,
+                compare: Some(0x03)
+            }
+        );
+        assert_eq!(patch.apply(0x94A7, 0x03), Some(0x02));
+        assert_eq!(patch.apply(0x94A7, 0x04), None);
+        assert_eq!(patch.apply(0x94A8, 0x03), None);
+
+        assert!(Patch::decode("GOSSI").is_err());
+        assert!(Patch::decode("GOSSIB").is_err());
+    }
+
+    #[test]
+    fn test_file() {
+        let cheats =
+            Cheats::parse("# lives\nGOSSIP  Lives\n-ZEXPYGLA Jump\n075a=$09 Nine\n").unwrap();
+        assert_eq!(cheats.list.len(), 3);
+        assert_eq!(cheats.list[0].name, "Lives");
+        assert!(!cheats.list[1].enabled);
+        assert_eq!(
+            cheats.list[2].kind,
+            Code::Freeze {
+                addr: 0x075A,
+                value: 0x09
+            }
+        );
+        assert_eq!(cheats.patches().len(), 1);
+
+        assert_eq!(
+            Cheats::parse("075A=09 RAM $075A").unwrap().list[0],
+            Cheat::freeze(0x075A, 0x09)
+        );
+        assert!(Cheats::parse("075A=100").is_err());
+        assert_eq!(cheat_path("roms/smb.nes"), "roms/smb.cht");
+    }
+}
@@ -1,6 +1,7 @@
 use crate::nes::opcodes;
 use alloc::{format, vec::Vec};
 use bitflags::bitflags;
+use core::ops::Range;
 use hashbrown::HashMap;
 
 use super::{bus::Bus, cartridge::Rom};
@@ -57,6 +58,11 @@ pub struct CPU {
     pub program_counter: u16,
     pub stack_pointer: u8,
     pub bus: Bus,
+    /// Base cycles of every instruction run so far, page crossings and taken branches aren't
+    /// counted
+    pub cycles: u64,
+    /// Where the running instruction's operand bytes are, reading them is a fetch
+    operands: Range<u16>,
 }
 
 pub trait Mem {
@@ -80,15 +86,16 @@ pub trait Mem {
 
 impl Mem for CPU {
     fn mem_read(&self, addr: u16) -> u8 {
-        self.bus.mem_read(addr)
+        if self.operands.contains(&addr) {
+            self.bus.fetch(addr)
+        } else {
+            self.bus.mem_read(addr)
+        }
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
         self.bus.mem_write(addr, data)
     }
-    fn mem_read_u16(&self, pos: u16) -> u16 {
-        self.bus.mem_read_u16(pos)
-    }
 
     fn mem_write_u16(&mut self, pos: u16, data: u16) {
         self.bus.mem_write_u16(pos, data)
@@ -106,6 +113,8 @@ impl CPU {
             program_counter: 0,
             stack_pointer: STACK_RESET,
             bus: Bus::new(rom),
+            cycles: 0,
+            operands: 0..0,
         }
     }
 
@@ -118,6 +127,8 @@ impl CPU {
             program_counter: 0,
             stack_pointer: STACK_RESET,
             bus: bus,
+            cycles: 0,
+            operands: 0..0,
         }
     }
 
@@ -311,6 +322,12 @@ impl CPU {
         self.run()
     }
 
+    /// Jumps to a subroutine like JSR would, its RTS lands on `ret`
+    pub fn call(&mut self, addr: u16, ret: u16) {
+        self.stack_push_u16(ret.wrapping_sub(1));
+        self.program_counter = addr;
+    }
+
     pub fn load(&mut self, program: Vec<u8>) {
         for i in 0..(program.len() as u16) {
             self.mem_write(0x0600 + i, program[i as usize]);
@@ -718,13 +735,16 @@ impl CPU {
 
     pub fn tick(&mut self) {
         let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
-        let code = self.mem_read(self.program_counter);
+        let code = self.bus.fetch(self.program_counter);
         self.program_counter += 1;
         let program_counter_state = self.program_counter;
 
         let opcode = opcodes
             .get(&code)
             .expect(&format!("OpCode {:x} is not recognized", code));
+        self.operands =
+            self.program_counter..self.program_counter.saturating_add(opcode.len as u16 - 1);
+        self.cycles += opcode.cycles as u64;
 
         match code {
             // #region Load/Store Operations
@@ -943,6 +963,7 @@ impl CPU {
             // #endregion
             _ => todo!(),
         }
+        self.operands = 0..0;
 
         if program_counter_state == self.program_counter {
             self.program_counter += (opcode.len - 1) as u16;
@@ -0,0 +1,592 @@
+//! Breakpoints, watchpoints and stepping over the NES core. `Debugger::command` takes the same
+//! lines as the REPL, which `egb debug` and the simulator's B key both open, so they stay in step.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use super::{
+    bus::{WatchHit, Watchpoint},
+    cpu::CPU,
+    disasm,
+    emu::{NesEmulator, FRAME_CYCLES},
+};
+
+/// Instructions to run before giving up on `continue`
+const RUN_LIMIT: u64 = 50_000_000;
+
+const JSR: u8 = 0x20;
+const RTS: u8 = 0x60;
+const RTI: u8 = 0x40;
+
+const HELP: &str = "\
+s [n]                step n instructions
+n                    step over a JSR
+o                    run until the current subroutine returns
+c                    continue until a breakpoint or watchpoint
+f [n]                run to the start of frame n (default: the next one)
+b ADDR [if REG OP V] add a breakpoint, e.g. `b 8000 if a == 3f`
+bd ADDR              delete a breakpoint
+w ADDR[-END] [r|w]   watch reads and/or writes (default both)
+wd ADDR              delete a watchpoint
+l                    list breakpoints and watchpoints
+r                    show registers
+set REG V            set a register
+m ADDR [LEN]         dump memory
+poke ADDR V          write memory
+d [ADDR] [N]         disassemble (default: from PC)
+q                    quit
+Numbers are hex.";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Register {
+    A,
+    X,
+    Y,
+    Sp,
+    P,
+    Pc,
+}
+
+impl Register {
+    fn parse(name: &str) -> Result<Register, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "a" => Ok(Register::A),
+            "x" => Ok(Register::X),
+            "y" => Ok(Register::Y),
+            "sp" => Ok(Register::Sp),
+            "p" => Ok(Register::P),
+            "pc" => Ok(Register::Pc),
+            _ => Err(format!("unknown register '{}'", name)),
+        }
+    }
+
+    pub fn get(&self, cpu: &CPU) -> u16 {
+        match self {
+            Register::A => cpu.register_a as u16,
+            Register::X => cpu.register_x as u16,
+            Register::Y => cpu.register_y as u16,
+            Register::Sp => cpu.stack_pointer as u16,
+            Register::P => cpu.status.bits() as u16,
+            Register::Pc => cpu.program_counter,
+        }
+    }
+
+    pub fn set(&self, cpu: &mut CPU, value: u16) {
+        match self {
+            Register::A => cpu.register_a = value as u8,
+            Register::X => cpu.register_x = value as u8,
+            Register::Y => cpu.register_y = value as u8,
+            Register::Sp => cpu.stack_pointer = value as u8,
+            Register::P => cpu.status = super::cpu::CpuFlags::from_bits_truncate(value as u8),
+            Register::Pc => cpu.program_counter = value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Condition {
+    pub register: Register,
+    pub comparison: Comparison,
+    pub value: u16,
+}
+
+impl Condition {
+    pub fn matches(&self, cpu: &CPU) -> bool {
+        let register = self.register.get(cpu);
+        match self.comparison {
+            Comparison::Eq => register == self.value,
+            Comparison::Ne => register != self.value,
+            Comparison::Lt => register < self.value,
+            Comparison::Le => register <= self.value,
+            Comparison::Gt => register > self.value,
+            Comparison::Ge => register >= self.value,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Breakpoint {
+    pub addr: u16,
+    pub condition: Option<Condition>,
+}
+
+/// Why execution stopped
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stop {
+    /// The step, frame or subroutine asked for finished
+    Done,
+    Breakpoint(u16),
+    Watchpoint(WatchHit),
+    /// The instruction limit ran out without anything happening
+    Limit,
+}
+
+pub struct Debugger {
+    pub emu: NesEmulator,
+    pub breakpoints: Vec<Breakpoint>,
+}
+
+fn number(text: &str) -> Result<u16, String> {
+    let digits = text.trim_start_matches('$').trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("'{}' isn't a hex number", text))
+}
+
+impl Debugger {
+    pub fn new(emu: NesEmulator) -> Self {
+        Self {
+            emu,
+            breakpoints: Vec::new(),
+        }
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        self.emu.cpu()
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.emu.frame()
+    }
+
+    fn at_breakpoint(&self) -> bool {
+        let cpu = self.cpu();
+        self.breakpoints.iter().any(|breakpoint| {
+            breakpoint.addr == cpu.program_counter
+                && breakpoint
+                    .condition
+                    .as_ref()
+                    .is_none_or(|condition| condition.matches(cpu))
+        })
+    }
+
+    /// Runs until `done` says so, checking watchpoints after every instruction and breakpoints
+    /// before every one but the first. `done` gets the CPU afterwards, and the opcode and stack
+    /// pointer from before.
+    fn run(&mut self, limit: u64, mut done: impl FnMut(&CPU, u8, u8) -> bool) -> Stop {
+        for i in 0..limit {
+            if i > 0 && self.at_breakpoint() {
+                return Stop::Breakpoint(self.cpu().program_counter);
+            }
+
+            let cpu = self.emu.cpu_mut();
+            let opcode = cpu.bus.peek(cpu.program_counter);
+            let stack_pointer = cpu.stack_pointer;
+            cpu.tick();
+
+            if let Some(hit) = cpu.bus.take_watch_hit() {
+                return Stop::Watchpoint(hit);
+            }
+            if done(cpu, opcode, stack_pointer) {
+                return Stop::Done;
+            }
+        }
+        Stop::Limit
+    }
+
+    pub fn step(&mut self) -> Stop {
+        self.run(1, |_, _, _| true)
+    }
+
+    /// Steps, but runs a JSR until it comes back
+    pub fn step_over(&mut self) -> Stop {
+        let cpu = self.cpu();
+        if cpu.bus.peek(cpu.program_counter) != JSR {
+            return self.step();
+        }
+
+        let ret = cpu.program_counter.wrapping_add(3);
+        let stack_pointer = cpu.stack_pointer;
+        self.run(RUN_LIMIT, move |cpu, _, _| {
+            cpu.program_counter == ret && cpu.stack_pointer >= stack_pointer
+        })
+    }
+
+    /// Runs until the current subroutine (or interrupt handler) returns
+    pub fn step_out(&mut self) -> Stop {
+        let start = self.cpu().stack_pointer;
+        // nested calls run with a lower stack pointer
+        self.run(RUN_LIMIT, move |_, opcode, stack_pointer| {
+            (opcode == RTS || opcode == RTI) && stack_pointer >= start
+        })
+    }
+
+    pub fn resume(&mut self) -> Stop {
+        self.resume_for(RUN_LIMIT)
+    }
+
+    /// Continues for at most `limit` instructions, so callers can check for an interrupt
+    pub fn resume_for(&mut self, limit: u64) -> Stop {
+        self.run(limit, |_, _, _| false)
+    }
+
+    pub fn run_to_frame(&mut self, frame: u64) -> Stop {
+        let target = frame * FRAME_CYCLES;
+        if self.cpu().cycles >= target {
+            return Stop::Done;
+        }
+        self.run(RUN_LIMIT, move |cpu, _, _| cpu.cycles >= target)
+    }
+
+    pub fn watch(&mut self, watchpoint: Watchpoint) {
+        self.emu.cpu_mut().bus.watchpoints.push(watchpoint);
+    }
+
+    pub fn registers(&self) -> String {
+        let cpu = self.cpu();
+        format!(
+            "PC:{:04X} A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} CYC:{} FRAME:{}",
+            cpu.program_counter,
+            cpu.register_a,
+            cpu.register_x,
+            cpu.register_y,
+            cpu.status.bits(),
+            cpu.stack_pointer,
+            cpu.cycles,
+            self.frame()
+        )
+    }
+
+    /// A hex dump, 16 bytes a line
+    pub fn memory(&self, addr: u16, len: u16) -> String {
+        let bus = &self.cpu().bus;
+        let mut out = String::new();
+        for line in (0..len).step_by(16) {
+            let start = addr.wrapping_add(line);
+            out += &format!("{:04X}:", start);
+            for i in 0..16.min(len - line) {
+                out += &format!(" {:02X}", bus.peek(start.wrapping_add(i)));
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    fn describe(&self, stop: Stop) -> String {
+        let what = match stop {
+            Stop::Done => String::new(),
+            Stop::Breakpoint(addr) => format!("breakpoint at {:04X}\n", addr),
+            Stop::Watchpoint(hit) => format!(
+                "watchpoint: {} {:04X} = {:02X}\n",
+                if hit.write { "write" } else { "read" },
+                hit.addr,
+                hit.value
+            ),
+            Stop::Limit => format!("stopped after {} instructions\n", RUN_LIMIT),
+        };
+        let cpu = self.cpu();
+        let next = disasm::decode(&cpu.bus, cpu.program_counter, 0xFFFF);
+        format!("{}{}\n{}", what, self.registers(), next)
+    }
+
+    /// Runs one line of the REPL and returns what to print
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(String::new());
+        };
+        let args: Vec<&str> = words.collect();
+        let arg = |i: usize| args.get(i).copied().map(number).transpose();
+
+        let stop = match command {
+            "s" | "step" => {
+                let mut stop = Stop::Done;
+                for _ in 0..arg(0)?.unwrap_or(1) {
+                    stop = self.step();
+                    if stop != Stop::Done {
+                        break;
+                    }
+                }
+                stop
+            }
+            "n" | "next" => self.step_over(),
+            "o" | "out" => self.step_out(),
+            "c" | "continue" => self.resume(),
+            "f" | "frame" => {
+                let frame = match arg(0)? {
+                    Some(frame) => frame as u64,
+                    None => self.frame() + 1,
+                };
+                self.run_to_frame(frame)
+            }
+            "b" | "break" => {
+                let addr = arg(0)?.ok_or("b needs an address")?;
+                let condition = match args.get(1..) {
+                    Some(["if", register, comparison, value]) => Some(Condition {
+                        register: Register::parse(register)?,
+                        comparison: match *comparison {
+                            "==" => Comparison::Eq,
+                            "!=" => Comparison::Ne,
+                            "<" => Comparison::Lt,
+                            "<=" => Comparison::Le,
+                            ">" => Comparison::Gt,
+                            ">=" => Comparison::Ge,
+                            other => return Err(format!("unknown comparison '{}'", other)),
+                        },
+                        value: number(value)?,
+                    }),
+                    Some([]) | None => None,
+                    _ => return Err("expected `b ADDR if REG OP VALUE`".to_string()),
+                };
+                self.breakpoints.push(Breakpoint { addr, condition });
+                return Ok(format!("breakpoint {:04X}", addr));
+            }
+            "bd" => {
+                let addr = arg(0)?.ok_or("bd needs an address")?;
+                self.breakpoints
+                    .retain(|breakpoint| breakpoint.addr != addr);
+                return Ok(String::new());
+            }
+            "w" | "watch" => {
+                let range = args.first().ok_or("w needs an address")?;
+                let (start, end) = match range.split_once('-') {
+                    Some((start, end)) => (number(start)?, number(end)?),
+                    None => (number(range)?, number(range)?),
+                };
+                let (read, write) = match args.get(1).copied() {
+                    Some("r") => (true, false),
+                    Some("w") => (false, true),
+                    Some("rw") | None => (true, true),
+                    Some(other) => return Err(format!("expected r, w or rw, not '{}'", other)),
+                };
+                self.watch(Watchpoint {
+                    start,
+                    end,
+                    read,
+                    write,
+                });
+                return Ok(format!("watching {:04X}-{:04X}", start, end));
+            }
+            "wd" => {
+                let addr = arg(0)?.ok_or("wd needs an address")?;
+                self.emu
+                    .cpu_mut()
+                    .bus
+                    .watchpoints
+                    .retain(|watch| watch.start != addr);
+                return Ok(String::new());
+            }
+            "l" | "list" => {
+                let mut out = String::new();
+                for breakpoint in &self.breakpoints {
+                    out += &format!("break {:04X}", breakpoint.addr);
+                    if let Some(condition) = &breakpoint.condition {
+                        out += &format!(
+                            " if {:?} {:?} {:X}",
+                            condition.register, condition.comparison, condition.value
+                        );
+                    }
+                    out.push('\n');
+                }
+                for watch in &self.cpu().bus.watchpoints {
+                    out += &format!(
+                        "watch {:04X}-{:04X}{}{}\n",
+                        watch.start,
+                        watch.end,
+                        if watch.read { " r" } else { "" },
+                        if watch.write { " w" } else { "" }
+                    );
+                }
+                return Ok(out);
+            }
+            "r" | "regs" => return Ok(self.registers()),
+            "set" => {
+                let register = Register::parse(args.first().ok_or("set needs a register")?)?;
+                let value = arg(1)?.ok_or("set needs a value")?;
+                register.set(self.emu.cpu_mut(), value);
+                return Ok(self.registers());
+            }
+            "m" | "mem" => {
+                let addr = arg(0)?.ok_or("m needs an address")?;
+                return Ok(self.memory(addr, arg(1)?.unwrap_or(0x40)));
+            }
+            "poke" => {
+                let addr = arg(0)?.ok_or("poke needs an address")?;
+                let value = arg(1)?.ok_or("poke needs a value")?;
+                use super::cpu::Mem;
+                self.emu.cpu_mut().bus.mem_write(addr, value as u8);
+                // pokes aren't the CPU's doing
+                self.emu.cpu_mut().bus.take_watch_hit();
+                return Ok(String::new());
+            }
+            "d" | "disasm" => {
+                let addr = arg(0)?.unwrap_or(self.cpu().program_counter);
+                let count = arg(1)?.unwrap_or(10);
+                let bus = &self.cpu().bus;
+                let mut out = String::new();
+                let mut addr = addr;
+                for _ in 0..count {
+                    let instruction = disasm::decode(bus, addr, 0xFFFF);
+                    addr = addr.wrapping_add(instruction.size());
+                    out += &format!("{}\n", instruction);
+                }
+                return Ok(out);
+            }
+            "h" | "help" => return Ok(HELP.to_string()),
+            _ => return Err(format!("unknown command '{}', try h", command)),
+        };
+
+        Ok(self.describe(stop))
+    }
+}
+
+/// Entry point for `egb debug <rom.nes>`, a line-oriented REPL on stdin
+#[cfg(target_arch = "x86_64")]
+pub fn main(args: &[String]) -> i32 {
+    let Some(path) = args.first() else {
+        std::eprintln!("usage: egb debug <rom.nes>");
+        return 2;
+    };
+    let rom = match std::fs::read(path)
+        .map_err(|e| format!("{}: {}", path, e))
+        .and_then(|raw| super::cartridge::Rom::new(&raw))
+    {
+        Ok(rom) => rom,
+        Err(e) => {
+            std::eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    repl(&mut Debugger::new(NesEmulator::with_rom(rom)));
+    0
+}
+
+/// Reads commands from stdin until `q` or the end of input
+#[cfg(target_arch = "x86_64")]
+pub fn repl(debugger: &mut Debugger) {
+    use std::io::{BufRead, Write};
+
+    std::println!("{}", debugger.describe(Stop::Done));
+
+    let stdin = std::io::stdin();
+    let mut last = String::new();
+    loop {
+        std::print!("> ");
+        std::io::stdout().flush().ok();
+
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+            return;
+        }
+        // an empty line repeats the last command, like gdb
+        let line = match line.trim() {
+            "" => last.clone(),
+            line => line.to_string(),
+        };
+        if line == "q" || line == "quit" {
+            return;
+        }
+
+        let result =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| debugger.command(&line)));
+        match result {
+            Ok(Ok(out)) => std::println!("{}", out.trim_end()),
+            Ok(Err(e)) => std::println!("error: {}", e),
+            Err(_) => std::println!("the emulator crashed, state may be inconsistent"),
+        }
+        last = line;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec;
+
+    use super::*;
+    use crate::nes::cartridge::{Mirroring, Rom};
+
+    fn debugger() -> Debugger {
+        #[rustfmt::skip]
+        let program = [
+            0xA2, 0x00,       // 8000: LDX #$00
+            0xE8,             // 8002: INX
+            0x20, 0x0A, 0x80, // 8003: JSR $800A
+            0x4C, 0x02, 0x80, // 8006: JMP $8002
+            0x00,
+            0x8E, 0x00, 0x02, // 800A: STX $0200
+            0x20, 0x11, 0x80, // 800D: JSR $8011
+            0x60,             // 8010: RTS
+            0xEA,             // 8011: NOP
+            0x60,             // 8012: RTS
+        ];
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(&program);
+        prg_rom[0x3FFD] = 0x80;
+        let rom = Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        };
+        Debugger::new(NesEmulator::with_rom(rom))
+    }
+
+    #[test]
+    fn test_stepping() {
+        let mut debugger = debugger();
+        debugger.step();
+        debugger.step();
+        assert_eq!(debugger.cpu().program_counter, 0x8003);
+
+        // over the whole subroutine
+        assert_eq!(debugger.step_over(), Stop::Done);
+        assert_eq!(debugger.cpu().program_counter, 0x8006);
+
+        debugger.command("s 3").unwrap();
+        assert_eq!(debugger.cpu().program_counter, 0x800A);
+        debugger.command("s 2").unwrap();
+        assert_eq!(debugger.cpu().program_counter, 0x8011);
+        // out of the inner call, then the outer one
+        assert_eq!(debugger.step_out(), Stop::Done);
+        assert_eq!(debugger.cpu().program_counter, 0x8010);
+        debugger.command("o").unwrap();
+        assert_eq!(debugger.cpu().program_counter, 0x8006);
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let mut debugger = debugger();
+        debugger.command("b 8003 if x == 3").unwrap();
+        assert_eq!(debugger.resume(), Stop::Breakpoint(0x8003));
+        assert_eq!(debugger.cpu().register_x, 3);
+
+        debugger.command("bd 8003").unwrap();
+        debugger.command("w 0200 w").unwrap();
+        assert_eq!(
+            debugger.resume(),
+            Stop::Watchpoint(WatchHit {
+                addr: 0x0200,
+                value: 3,
+                write: true
+            })
+        );
+
+        // peeking doesn't trip it
+        assert!(debugger
+            .command("m 0200 4")
+            .unwrap()
+            .starts_with("0200: 03"));
+        debugger.command("wd 0200").unwrap();
+        assert_eq!(debugger.command("f 2").unwrap().lines().count(), 2);
+        assert_eq!(debugger.frame(), 2);
+
+        assert!(debugger.command("b").is_err());
+        assert!(debugger.command("b 8000 if q == 1").is_err());
+
+        // running the program isn't reading it
+        debugger.command("w 8000-8012 r").unwrap();
+        assert_eq!(debugger.command("s 20").unwrap().lines().count(), 2);
+    }
+}
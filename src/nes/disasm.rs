@@ -0,0 +1,288 @@
+//! A linear sweep 6502 disassembler built from the opcode table. Bytes that aren't a known
+//! opcode are listed as `.db`, the vectors at $FFFA as `.dw`.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use core::fmt;
+
+use super::{
+    bus::Bus,
+    cartridge::Rom,
+    cpu::{AddressingMode, Mem},
+    opcodes::{self, OpCode},
+};
+
+pub const VECTORS: [(&str, u16); 3] = [("nmi", 0xFFFA), ("reset", 0xFFFC), ("irq", 0xFFFE)];
+
+pub struct Instruction {
+    pub addr: u16,
+    pub bytes: Vec<u8>,
+    /// `.db`/`.dw` for data
+    pub mnemonic: &'static str,
+    pub operand: String,
+    /// Where a branch, JMP or JSR goes
+    pub target: Option<u16>,
+    pub label: Option<String>,
+}
+
+impl Instruction {
+    /// How many bytes it takes up
+    pub fn size(&self) -> u16 {
+        self.bytes.len() as u16
+    }
+
+    fn data(addr: u16, byte: u8) -> Self {
+        Self {
+            addr,
+            bytes: vec![byte],
+            mnemonic: ".db",
+            operand: format!("${:02X}", byte),
+            target: None,
+            label: None,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = self
+            .bytes
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let line = format!(
+            "{:04X}  {:8}  {} {}",
+            self.addr, bytes, self.mnemonic, self.operand
+        );
+        f.write_str(line.trim_end())
+    }
+}
+
+fn operand_text(op: &OpCode, addr: u16, lo: u8, word: u16) -> (String, Option<u16>) {
+    match (&op.mode, op.len) {
+        (AddressingMode::Immediate, _) => (format!("#${:02X}", lo), None),
+        (AddressingMode::ZeroPage, _) => (format!("${:02X}", lo), None),
+        (AddressingMode::ZeroPage_X, _) => (format!("${:02X},X", lo), None),
+        (AddressingMode::ZeroPage_Y, _) => (format!("${:02X},Y", lo), None),
+        (AddressingMode::Indirect_X, _) => (format!("(${:02X},X)", lo), None),
+        (AddressingMode::Indirect_Y, _) => (format!("(${:02X}),Y", lo), None),
+        (AddressingMode::Absolute, _) => {
+            let target = matches!(op.code, 0x4C | 0x20).then_some(word);
+            (format!("${:04X}", word), target)
+        }
+        (AddressingMode::Absolute_X, _) => (format!("${:04X},X", word), None),
+        (AddressingMode::Absolute_Y, _) => (format!("${:04X},Y", word), None),
+        // everything else is implied, a branch or JMP ($nnnn)
+        (AddressingMode::NoneAddressing, 1) => match op.code {
+            0x0A | 0x4A | 0x2A | 0x6A => ("A".to_string(), None),
+            _ => (String::new(), None),
+        },
+        (AddressingMode::NoneAddressing, 2) => {
+            let target = addr.wrapping_add(2).wrapping_add(lo as i8 as u16);
+            (format!("${:04X}", target), Some(target))
+        }
+        (AddressingMode::NoneAddressing, _) => (format!("(${:04X})", word), None),
+    }
+}
+
+/// Decodes the instruction at `addr`, without going past `end`
+pub fn decode<M: Mem>(mem: &M, addr: u16, end: u16) -> Instruction {
+    let opcodes = &*opcodes::OPCODES_MAP;
+    let code = mem.mem_read(addr);
+
+    let Some(op) = opcodes.get(&code) else {
+        return Instruction::data(addr, code);
+    };
+    if (addr as u32 + op.len as u32 - 1) > end as u32 {
+        return Instruction::data(addr, code);
+    }
+
+    let bytes: Vec<u8> = (0..op.len as u16)
+        .map(|i| mem.mem_read(addr.wrapping_add(i)))
+        .collect();
+    let lo = bytes.get(1).copied().unwrap_or(0);
+    let word = u16::from_le_bytes([lo, bytes.get(2).copied().unwrap_or(0)]);
+    let (operand, target) = operand_text(op, addr, lo, word);
+
+    Instruction {
+        addr,
+        bytes,
+        mnemonic: op.mnemonic,
+        operand,
+        target,
+        label: None,
+    }
+}
+
+/// Disassembles `start..=end` and labels every branch target inside it
+pub fn disassemble<M: Mem>(mem: &M, start: u16, end: u16) -> Vec<Instruction> {
+    let mut instructions = Vec::new();
+    let mut addr = start as u32;
+    while addr <= end as u32 {
+        let instruction = decode(mem, addr as u16, end);
+        addr += instruction.size() as u32;
+        instructions.push(instruction);
+    }
+
+    let targets: Vec<u16> = instructions.iter().filter_map(|i| i.target).collect();
+    for instruction in instructions.iter_mut() {
+        if targets.contains(&instruction.addr) {
+            instruction.label = Some(format!("L{:04X}", instruction.addr));
+        }
+    }
+    instructions
+}
+
+/// The whole of PRG-ROM as the CPU sees it, with the vectors labelled. Only the first 32K is
+/// mapped until there are mappers.
+pub fn disassemble_rom(rom: Rom) -> Vec<Instruction> {
+    let bus = Bus::new(rom);
+    let mut instructions = disassemble(&bus, 0x8000, VECTORS[0].1 - 1);
+
+    for (name, vector) in VECTORS {
+        let target = bus.mem_read_u16(vector);
+        // vectors sharing a handler keep the first name
+        if let Some(instruction) = instructions.iter_mut().find(|i| i.addr == target) {
+            if !instruction
+                .label
+                .as_ref()
+                .is_some_and(|label| VECTORS.iter().any(|(name, _)| name == label))
+            {
+                instruction.label = Some(name.to_string());
+            }
+        }
+        instructions.push(Instruction {
+            addr: vector,
+            bytes: target.to_le_bytes().to_vec(),
+            mnemonic: ".dw",
+            operand: format!("${:04X}", target),
+            target: Some(target),
+            label: Some(format!("{}_vector", name)),
+        });
+    }
+    instructions
+}
+
+/// Formats instructions one per line, with labels on their own line
+pub fn listing(instructions: &[Instruction]) -> String {
+    let mut out = String::new();
+    for instruction in instructions {
+        if let Some(label) = &instruction.label {
+            out += &format!("{}:\n", label);
+        }
+        out += &format!("    {}\n", instruction);
+    }
+    out
+}
+
+/// Entry point for `egb disasm <rom> [--from XXXX] [--to XXXX]`, returns the exit code
+#[cfg(target_arch = "x86_64")]
+pub fn main(args: &[String]) -> i32 {
+    let mut path = None;
+    let mut from = None;
+    let mut to = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut addr = || {
+            args.next()
+                .and_then(|value| u16::from_str_radix(value.trim_start_matches('$'), 16).ok())
+        };
+        match arg.as_str() {
+            "--from" => from = addr(),
+            "--to" => to = addr(),
+            rom => path = Some(rom.to_string()),
+        }
+    }
+
+    let Some(path) = path else {
+        std::eprintln!("usage: egb disasm <rom.nes> [--from XXXX] [--to XXXX]");
+        return 2;
+    };
+    let rom = match std::fs::read(&path)
+        .map_err(|e| format!("{}: {}", path, e))
+        .and_then(|raw| Rom::new(&raw))
+    {
+        Ok(rom) => rom,
+        Err(e) => {
+            std::eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let instructions = match (from, to) {
+        (None, None) => disassemble_rom(rom),
+        (from, to) => disassemble(&Bus::new(rom), from.unwrap_or(0x8000), to.unwrap_or(0xFFFF)),
+    };
+    std::print!("{}", listing(&instructions));
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nes::cartridge::Mirroring;
+
+    fn rom(program: &[u8]) -> Rom {
+        let mut prg_rom = vec![0xFF; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        // nmi and irq at $8000, reset at $8002
+        prg_rom[0x3FFA..].copy_from_slice(&[0x00, 0x80, 0x02, 0x80, 0x00, 0x80]);
+        Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        }
+    }
+
+    #[test]
+    fn test_decode() {
+        #[rustfmt::skip]
+        let bus = Bus::new(rom(&[
+            0x40,             // RTI
+            0xEA,             // NOP
+            0xA9, 0x10,       // LDA #$10
+            0xBD, 0x00, 0x02, // LDA $0200,X
+            0x0A,             // ASL A
+            0xD0, 0xF6,       // BNE $8000
+            0x6C, 0xFC, 0xFF, // JMP ($FFFC)
+            0x02,             // not an opcode
+        ]));
+        let instructions = disassemble(&bus, 0x8000, 0x800D);
+
+        let text: Vec<String> = instructions.iter().map(|i| i.to_string()).collect();
+        assert_eq!(
+            text,
+            [
+                "8000  40        RTI",
+                "8001  EA        NOP",
+                "8002  A9 10     LDA #$10",
+                "8004  BD 00 02  LDA $0200,X",
+                "8007  0A        ASL A",
+                "8008  D0 F6     BNE $8000",
+                "800A  6C FC FF  JMP ($FFFC)",
+                "800D  02        .db $02",
+            ]
+        );
+        assert_eq!(instructions[5].target, Some(0x8000));
+        assert_eq!(instructions[0].label.as_deref(), Some("L8000"));
+    }
+
+    #[test]
+    fn test_rom_listing() {
+        let instructions = disassemble_rom(rom(&[0x40, 0xEA, 0x4C, 0x02, 0x80]));
+        let listing = listing(&instructions);
+
+        assert!(listing.starts_with("nmi:\n    8000  40        RTI\n"));
+        assert!(listing.contains("reset:\n    8002  4C 02 80  JMP $8002\n"));
+        assert!(listing.ends_with("irq_vector:\n    FFFE  00 80     .dw $8000\n"));
+        // an instruction can't run into the vectors
+        let last = &instructions[instructions.len() - 4];
+        assert!(last.addr + last.size() <= 0xFFFA);
+    }
+}
@@ -1,4 +1,4 @@
-use alloc::vec;
+use alloc::{format, string::String, vec, vec::Vec};
 use embedded_graphics::draw_target::DrawTarget;
 use embedded_graphics::geometry::{Dimensions, Point};
 use embedded_graphics::image::{Image, ImageRaw};
@@ -7,10 +7,14 @@ use embedded_graphics::Drawable;
 
 use crate::buffer::Buffer;
 use crate::emu::Emulator;
-use crate::nes::cpu::CPU;
+use crate::nes::cpu::{CpuFlags, CPU};
+use crate::pacer::Region;
 
 use super::cartridge::Rom;
+use super::cheats::{Cheat, Cheats};
 use super::cpu::Mem;
+use super::search::RamSearch;
+use super::state::{self, write_u16, write_u64, Reader};
 
 fn color(byte: u8) -> Rgb565 {
     match byte {
@@ -26,8 +30,194 @@ fn color(byte: u8) -> Rgb565 {
     }
 }
 
+/// CPU cycles in an NTSC frame
+pub const FRAME_CYCLES: u64 = 29780;
+/// Scanlines in an NTSC frame, including vblank
+pub const SCANLINES: u64 = 262;
+
 pub struct NesEmulator {
     cpu: CPU,
+    cheats: Cheats,
+    /// Kept between visits to the RAM search screen, the game runs in between
+    search: Option<RamSearch>,
+    /// The frame rate the cartridge was made for
+    region: Region,
+}
+
+impl NesEmulator {
+    /// Starts a cartridge from its reset vector
+    pub fn with_rom(rom: Rom) -> Self {
+        let mut cpu = CPU::new(rom);
+        cpu.reset();
+        cpu.program_counter = cpu.mem_read_u16(0xFFFC);
+        Self {
+            cpu,
+            cheats: Cheats::default(),
+            search: None,
+            region: Region::Ntsc,
+        }
+    }
+
+    pub fn with_region(mut self, region: Region) -> Self {
+        self.region = region;
+        self
+    }
+
+    pub fn region(&self) -> Region {
+        self.region
+    }
+
+    /// The reset button: the CPU restarts from the reset vector but memory is kept
+    pub fn reset(&mut self) {
+        self.cpu.stack_pointer = self.cpu.stack_pointer.wrapping_sub(3);
+        self.cpu.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.cpu.program_counter = self.cpu.mem_read_u16(0xFFFC);
+    }
+
+    /// Turning it off and on: RAM is cleared and the CPU starts over from the reset vector
+    pub fn power(&mut self) {
+        self.cpu.bus.clear_ram();
+        self.cpu.reset();
+        self.cpu.program_counter = self.cpu.mem_read_u16(0xFFFC);
+    }
+
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn cpu_mut(&mut self) -> &mut CPU {
+        &mut self.cpu
+    }
+
+    /// The controller's buttons, see `joypad` for the bits
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.cpu.bus.joypad.buttons = buttons;
+    }
+
+    pub fn cheats(&self) -> &Cheats {
+        &self.cheats
+    }
+
+    pub fn set_cheats(&mut self, cheats: Cheats) {
+        self.cheats = cheats;
+        self.cpu.bus.patches = self.cheats.patches();
+    }
+
+    /// Turns a cheat on or off
+    pub fn toggle_cheat(&mut self, index: usize) {
+        if let Some(cheat) = self.cheats.list.get_mut(index) {
+            cheat.enabled = !cheat.enabled;
+            self.cpu.bus.patches = self.cheats.patches();
+        }
+    }
+
+    pub fn add_cheat(&mut self, cheat: Cheat) {
+        self.cheats.list.push(cheat);
+        self.cpu.bus.patches = self.cheats.patches();
+    }
+
+    /// The RAM search, started from the RAM as it is now the first time
+    pub fn ram_search(&mut self) -> &mut RamSearch {
+        let ram = self.cpu.bus.ram();
+        self.search.get_or_insert_with(|| RamSearch::new(ram))
+    }
+
+    /// Appends a save state of the running game to `out`
+    pub fn save_state(&self, out: &mut Vec<u8>) {
+        let cpu = &self.cpu;
+        out.push(state::VERSION);
+        out.extend_from_slice(&[
+            cpu.register_a,
+            cpu.register_x,
+            cpu.register_y,
+            cpu.status.bits(),
+            cpu.stack_pointer,
+        ]);
+        write_u16(out, cpu.program_counter);
+        write_u64(out, cpu.cycles);
+        cpu.bus.save(out);
+    }
+
+    /// How big `save_state` will be, without making one
+    pub fn state_len(&self) -> usize {
+        // version, A, X, Y, P, S, PC and the cycle count
+        1 + 5 + 2 + 8 + self.cpu.bus.state_len()
+    }
+
+    /// Loads a state from `save_state`, from the same game
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), String> {
+        let mut state = Reader::new(data);
+        let version = state.u8()?;
+        if version != state::VERSION {
+            return Err(format!(
+                "state is version {}, not {}",
+                version,
+                state::VERSION
+            ));
+        }
+        let cpu = &mut self.cpu;
+        cpu.register_a = state.u8()?;
+        cpu.register_x = state.u8()?;
+        cpu.register_y = state.u8()?;
+        cpu.status = CpuFlags::from_bits_truncate(state.u8()?);
+        cpu.stack_pointer = state.u8()?;
+        cpu.program_counter = state.u16()?;
+        cpu.cycles = state.u64()?;
+        cpu.bus.load(&mut state)?;
+        state.finish()
+    }
+
+    pub fn frame(&self) -> u64 {
+        self.cpu.cycles / FRAME_CYCLES
+    }
+
+    /// The scanline the PPU would be on, worked out from the CPU's cycles until there's a PPU
+    pub fn scanline(&self) -> u16 {
+        ((self.cpu.cycles % FRAME_CYCLES) * SCANLINES / FRAME_CYCLES) as u16
+    }
+
+    /// There's no PPU to wait for vblank on yet, so a frame is just a frame's worth of cycles
+    pub fn run_frame(&mut self) {
+        let end = self.cpu.cycles + FRAME_CYCLES;
+        while self.cpu.cycles < end {
+            self.cpu.tick();
+        }
+        self.cpu.bus.ppu.set_vblank();
+        self.cheats.freeze(&mut self.cpu);
+    }
+
+    /// Runs one instruction, when it finishes a frame vblank starts and the freezes are written
+    /// again like `run_frame` does
+    pub fn step(&mut self) {
+        let frame = self.frame();
+        self.cpu.tick();
+        if self.frame() != frame {
+            self.cpu.bus.ppu.set_vblank();
+            self.cheats.freeze(&mut self.cpu);
+        }
+    }
+
+    /// Draws $0200-$05FF as a 32x32 image, like the snake demo expects
+    pub fn draw<D>(&self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let mut frame_idx = 0;
+        let mut data = [0; 160 * 128 * 2];
+        for i in 0x0200..0x0600 {
+            let color_idx = self.cpu.mem_read(i as u16);
+            let color = color(color_idx);
+            let (b1, b2) = (
+                (color.r() << 3) | (color.g() >> 2),
+                (color.g() & 0b11) << 5 | color.b(),
+            );
+            data[frame_idx] = b1;
+            data[frame_idx + 1] = b2;
+            frame_idx += 2;
+        }
+
+        Image::new(&ImageRaw::<Rgb565>::new(&data, 32), Point::zero()).draw(display)
+    }
 }
 
 impl<D> Emulator<D> for NesEmulator
@@ -69,27 +259,19 @@ where
         cpu.load(game_code);
         cpu.reset();
 
-        Self { cpu }
+        Self {
+            cpu,
+            cheats: Cheats::default(),
+            search: None,
+            region: Region::Ntsc,
+        }
     }
 
     fn tick(&mut self, display: &mut D) -> Result<(), D::Error> {
-        self.cpu.tick();
+        self.step();
+        // the demo reads its random number from $FE
         self.cpu.mem_write(0xFE, 0x77);
 
-        let mut frame_idx = 0;
-        let mut data = [0; 160 * 128 * 2];
-        for i in 0x0200..0x0600 {
-            let color_idx = self.cpu.mem_read(i as u16);
-            let color = color(color_idx);
-            let (b1, b2) = (
-                (color.r() << 3) | (color.g() >> 2),
-                (color.g() & 0b11) << 5 | color.b(),
-            );
-            data[frame_idx] = b1;
-            data[frame_idx + 1] = b2;
-            frame_idx += 2;
-        }
-
-        Image::new(&ImageRaw::<Rgb565>::new(&data, 32), Point::zero()).draw(display)
+        self.draw(display)
     }
 }
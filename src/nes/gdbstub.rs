@@ -0,0 +1,545 @@
+//! A GDB remote serial protocol server over the debugger, so GDB (or anything else that speaks
+//! RSP) can attach to a running ROM:
+//!
+//! ```text
+//! egb gdb game.nes --port 2159
+//! (gdb) target remote :2159
+//! ```
+//!
+//! G in the simulator lends the game being played to GDB on the default port instead, it
+//! carries on once GDB detaches.
+//!
+//! GDB has no 6502 target, so registers go out in this order with no target description:
+//! A, X, Y, P and SP as a byte each, then PC as a little endian word. Memory is the CPU's view
+//! of the bus, breakpoints are `Z0` and watchpoints `Z2`-`Z4`.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+use std::{
+    eprintln,
+    io::{self, Read, Write},
+    net::{TcpListener, TcpStream},
+    println,
+};
+
+use super::{
+    bus::{WatchHit, Watchpoint},
+    cartridge::Rom,
+    cpu::Mem,
+    debugger::{Breakpoint, Debugger, Register, Stop},
+    emu::NesEmulator,
+};
+
+pub const DEFAULT_PORT: u16 = 2159;
+
+/// Instructions to run between checks for GDB's interrupt
+const CHUNK: u64 = 100_000;
+
+const REGISTERS: [Register; 6] = [
+    Register::A,
+    Register::X,
+    Register::Y,
+    Register::P,
+    Register::Sp,
+    Register::Pc,
+];
+
+const SIGINT: u8 = 2;
+const SIGTRAP: u8 = 5;
+
+/// What the server has to do after a packet
+#[derive(Debug, PartialEq)]
+pub enum Action {
+    Reply(String),
+    Step,
+    Continue,
+    /// Detach or kill, the connection is done
+    Close(Option<String>),
+}
+
+fn hex_bytes(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Goes by bytes, packets can hold anything and slicing the text could split a character
+fn parse_bytes(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.len().is_multiple_of(2) {
+        return Err(format!("odd length hex '{}'", hex));
+    }
+    let digit = |byte: u8| {
+        (byte as char)
+            .to_digit(16)
+            .ok_or_else(|| format!("bad hex '{}'", hex))
+    };
+    hex.as_bytes()
+        .chunks(2)
+        .map(|pair| Ok((digit(pair[0])? << 4 | digit(pair[1])?) as u8))
+        .collect()
+}
+
+fn parse_number(hex: &str) -> Result<u32, String> {
+    u32::from_str_radix(hex, 16).map_err(|_| format!("bad number '{}'", hex))
+}
+
+/// `ADDR,LEN` as used by `m`, `M` and `Z`
+fn parse_range(text: &str) -> Result<(u16, u32), String> {
+    let (addr, len) = text
+        .split_once(',')
+        .ok_or_else(|| format!("expected ADDR,LEN in '{}'", text))?;
+    Ok((parse_number(addr)? as u16, parse_number(len)?))
+}
+
+/// Wraps a reply as `$data#checksum`
+pub fn frame(data: &str) -> String {
+    let checksum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+    format!("${}#{:02x}", data, checksum)
+}
+
+/// What came in over the wire
+#[derive(Debug, PartialEq)]
+pub enum Incoming {
+    Packet(String),
+    /// Ctrl-C in GDB
+    Interrupt,
+    /// A packet with a bad checksum, GDB resends it after a `-`
+    Corrupt,
+}
+
+/// Reads the next packet, skipping acks. `None` when the connection closed.
+pub fn read_packet(stream: &mut impl Read) -> io::Result<Option<Incoming>> {
+    let mut byte = [0u8];
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        match byte[0] {
+            0x03 => return Ok(Some(Incoming::Interrupt)),
+            b'$' => break,
+            // acks, naks and noise between packets
+            _ => {}
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        if stream.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'#' {
+            break;
+        }
+        data.push(byte[0]);
+    }
+    let mut checksum = [0u8; 2];
+    stream.read_exact(&mut checksum)?;
+
+    let expected = core::str::from_utf8(&checksum)
+        .ok()
+        .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+    let sum = data.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+    if expected != Some(sum) {
+        return Ok(Some(Incoming::Corrupt));
+    }
+    Ok(Some(Incoming::Packet(
+        String::from_utf8_lossy(&data).into_owned(),
+    )))
+}
+
+pub struct GdbStub {
+    pub debugger: Debugger,
+}
+
+impl GdbStub {
+    pub fn new(debugger: Debugger) -> Self {
+        Self { debugger }
+    }
+
+    fn registers(&self) -> String {
+        let cpu = self.debugger.cpu();
+        let mut bytes: Vec<u8> = REGISTERS[..5]
+            .iter()
+            .map(|register| register.get(cpu) as u8)
+            .collect();
+        bytes.extend_from_slice(&cpu.program_counter.to_le_bytes());
+        hex_bytes(&bytes)
+    }
+
+    fn set_registers(&mut self, hex: &str) -> Result<(), String> {
+        let bytes = parse_bytes(hex)?;
+        if bytes.len() < 7 {
+            return Err("expected 7 bytes of registers".to_string());
+        }
+        let cpu = self.debugger.emu.cpu_mut();
+        for (register, byte) in REGISTERS[..5].iter().zip(&bytes) {
+            register.set(cpu, *byte as u16);
+        }
+        Register::Pc.set(cpu, u16::from_le_bytes([bytes[5], bytes[6]]));
+        Ok(())
+    }
+
+    fn register(&self, n: u32) -> Result<String, String> {
+        let register = REGISTERS
+            .get(n as usize)
+            .ok_or_else(|| format!("no register {}", n))?;
+        let value = register.get(self.debugger.cpu());
+        Ok(match register {
+            Register::Pc => hex_bytes(&value.to_le_bytes()),
+            _ => hex_bytes(&[value as u8]),
+        })
+    }
+
+    fn set_register(&mut self, text: &str) -> Result<(), String> {
+        let (n, value) = text
+            .split_once('=')
+            .ok_or_else(|| format!("expected N=VALUE in '{}'", text))?;
+        let register = REGISTERS
+            .get(parse_number(n)? as usize)
+            .ok_or_else(|| format!("no register {}", n))?;
+        let bytes = parse_bytes(value)?;
+        let value = u16::from_le_bytes([
+            bytes.first().copied().unwrap_or(0),
+            bytes.get(1).copied().unwrap_or(0),
+        ]);
+        register.set(self.debugger.emu.cpu_mut(), value);
+        Ok(())
+    }
+
+    fn read_memory(&self, text: &str) -> Result<String, String> {
+        let (addr, len) = parse_range(text)?;
+        let bus = &self.debugger.cpu().bus;
+        let bytes: Vec<u8> = (0..len.min(0x10000))
+            .map(|i| bus.peek(addr.wrapping_add(i as u16)))
+            .collect();
+        Ok(hex_bytes(&bytes))
+    }
+
+    fn write_memory(&mut self, text: &str) -> Result<(), String> {
+        let (range, data) = text
+            .split_once(':')
+            .ok_or_else(|| format!("expected ADDR,LEN:DATA in '{}'", text))?;
+        let (addr, _) = parse_range(range)?;
+        let bus = &mut self.debugger.emu.cpu_mut().bus;
+        for (i, byte) in parse_bytes(data)?.into_iter().enumerate() {
+            bus.mem_write(addr.wrapping_add(i as u16), byte);
+        }
+        // GDB's writes shouldn't trip watchpoints
+        bus.take_watch_hit();
+        Ok(())
+    }
+
+    /// `Z`/`z` packets: type 0 and 1 are breakpoints, 2 write, 3 read and 4 access watchpoints
+    fn set_point(&mut self, text: &str, insert: bool) -> Result<Option<()>, String> {
+        let (kind, range) = text
+            .split_once(',')
+            .ok_or_else(|| format!("expected TYPE,ADDR,KIND in '{}'", text))?;
+        let (addr, len) = parse_range(range)?;
+
+        match (kind, insert) {
+            ("0" | "1", true) => {
+                if !self.debugger.breakpoints.iter().any(|b| b.addr == addr) {
+                    self.debugger.breakpoints.push(Breakpoint {
+                        addr,
+                        condition: None,
+                    });
+                }
+            }
+            ("0" | "1", false) => self.debugger.breakpoints.retain(|b| b.addr != addr),
+            ("2" | "3" | "4", _) => {
+                // ranges running off the end of memory stop at $FFFF
+                let end = (addr as u32).saturating_add(len.max(1) - 1).min(0xFFFF);
+                let watchpoint = Watchpoint {
+                    start: addr,
+                    end: end as u16,
+                    read: kind != "2",
+                    write: kind != "3",
+                };
+                let watchpoints = &mut self.debugger.emu.cpu_mut().bus.watchpoints;
+                if insert {
+                    watchpoints.push(watchpoint);
+                } else {
+                    // only the one GDB set, a read and a write watchpoint can share an address
+                    watchpoints.retain(|watch| *watch != watchpoint);
+                }
+            }
+            _ => return Ok(None),
+        }
+        Ok(Some(()))
+    }
+
+    /// The reply to `?` and after a step or continue
+    pub fn stop_reply(stop: &Stop) -> String {
+        match stop {
+            Stop::Watchpoint(WatchHit { addr, write, .. }) => {
+                let kind = if *write { "watch" } else { "rwatch" };
+                format!("T{:02x}{}:{:04x};", SIGTRAP, kind, addr)
+            }
+            // the chunk ran out because GDB interrupted
+            Stop::Limit => format!("S{:02x}", SIGINT),
+            Stop::Done | Stop::Breakpoint(_) => format!("S{:02x}", SIGTRAP),
+        }
+    }
+
+    /// Handles one packet. Anything unsupported gets an empty reply, which GDB takes as "no".
+    pub fn handle(&mut self, packet: &str) -> Action {
+        let ok = |result: Result<(), String>| match result {
+            Ok(()) => "OK".to_string(),
+            Err(_) => "E01".to_string(),
+        };
+
+        let reply = match packet.as_bytes().first() {
+            None => String::new(),
+            Some(b'?') => Self::stop_reply(&Stop::Done),
+            Some(b'g') => self.registers(),
+            Some(b'G') => ok(self.set_registers(&packet[1..])),
+            Some(b'p') => parse_number(&packet[1..])
+                .and_then(|n| self.register(n))
+                .unwrap_or_else(|_| "E01".to_string()),
+            Some(b'P') => ok(self.set_register(&packet[1..])),
+            Some(b'm') => self
+                .read_memory(&packet[1..])
+                .unwrap_or_else(|_| "E01".to_string()),
+            Some(b'M') => ok(self.write_memory(&packet[1..])),
+            Some(b'Z' | b'z') => match self.set_point(&packet[1..], packet.starts_with('Z')) {
+                Ok(Some(())) => "OK".to_string(),
+                Ok(None) => String::new(),
+                Err(_) => "E01".to_string(),
+            },
+            // resuming somewhere else isn't supported, so the address is ignored
+            Some(b's') => return Action::Step,
+            Some(b'c') => return Action::Continue,
+            Some(b'D') => return Action::Close(Some("OK".to_string())),
+            Some(b'k') => return Action::Close(None),
+            // there's only the one thread
+            Some(b'H') => "OK".to_string(),
+            Some(b'T') => "OK".to_string(),
+            Some(b'q') => match packet {
+                "qC" => "QC1".to_string(),
+                "qAttached" => "1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                _ if packet.starts_with("qSupported") => "PacketSize=1000".to_string(),
+                _ => String::new(),
+            },
+            Some(_) => String::new(),
+        };
+        Action::Reply(reply)
+    }
+
+    /// Continues until something stops the CPU or GDB sends an interrupt
+    fn resume(&mut self, stream: &mut TcpStream) -> io::Result<Stop> {
+        loop {
+            let stop = self.debugger.resume_for(CHUNK);
+            if stop != Stop::Limit {
+                return Ok(stop);
+            }
+
+            stream.set_nonblocking(true)?;
+            let mut byte = [0u8];
+            let read = stream.read(&mut byte);
+            stream.set_nonblocking(false)?;
+            match read {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) if byte[0] == 0x03 => return Ok(Stop::Limit),
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Talks to one GDB until it detaches or disconnects
+    pub fn serve(&mut self, stream: &mut TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        while let Some(incoming) = read_packet(stream)? {
+            let packet = match incoming {
+                Incoming::Packet(packet) => packet,
+                Incoming::Corrupt => {
+                    stream.write_all(b"-")?;
+                    continue;
+                }
+                // an interrupt while stopped is already answered
+                Incoming::Interrupt => continue,
+            };
+            stream.write_all(b"+")?;
+
+            let reply = match self.handle(&packet) {
+                Action::Reply(reply) => reply,
+                Action::Step => Self::stop_reply(&self.debugger.step()),
+                Action::Continue => Self::stop_reply(&self.resume(stream)?),
+                Action::Close(reply) => {
+                    if let Some(reply) = reply {
+                        stream.write_all(frame(&reply).as_bytes())?;
+                    }
+                    return Ok(());
+                }
+            };
+            stream.write_all(frame(&reply).as_bytes())?;
+        }
+        Ok(())
+    }
+}
+
+/// Waits for GDB on `port` and serves that one connection, for lending a game that's already
+/// running
+pub fn serve_once(stub: &mut GdbStub, port: u16) -> Result<(), String> {
+    let listener =
+        TcpListener::bind(("127.0.0.1", port)).map_err(|e| format!("port {}: {}", port, e))?;
+    println!("waiting for gdb on 127.0.0.1:{}", port);
+    let (mut stream, _) = listener.accept().map_err(|e| e.to_string())?;
+    println!("gdb connected");
+    let result = stub.serve(&mut stream).map_err(|e| e.to_string());
+    println!("gdb disconnected");
+    result
+}
+
+/// Entry point for `egb gdb <rom> [--port N]`, returns the exit code
+pub fn main(args: &[String]) -> i32 {
+    let mut path = None;
+    let mut port = DEFAULT_PORT;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => match args.next().and_then(|n| n.parse().ok()) {
+                Some(n) => port = n,
+                None => {
+                    eprintln!("--port expects a number");
+                    return 2;
+                }
+            },
+            rom => path = Some(rom.to_string()),
+        }
+    }
+
+    let Some(path) = path else {
+        eprintln!("usage: egb gdb <rom.nes> [--port N]");
+        return 2;
+    };
+    let rom = match std::fs::read(&path)
+        .map_err(|e| format!("{}: {}", path, e))
+        .and_then(|raw| Rom::new(&raw))
+    {
+        Ok(rom) => rom,
+        Err(e) => {
+            eprintln!("{}", e);
+            return 1;
+        }
+    };
+
+    let listener = match TcpListener::bind(("127.0.0.1", port)) {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("port {}: {}", port, e);
+            return 1;
+        }
+    };
+    let mut stub = GdbStub::new(Debugger::new(NesEmulator::with_rom(rom)));
+    println!("waiting for gdb on 127.0.0.1:{}", port);
+
+    // keep the machine around between connections, like a real board
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            }
+        };
+        println!("gdb connected");
+        if let Err(e) = stub.serve(&mut stream) {
+            eprintln!("{}", e);
+        }
+        println!("gdb disconnected");
+    }
+    0
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nes::cartridge::Mirroring;
+    use alloc::vec;
+
+    fn stub(program: &[u8]) -> GdbStub {
+        let mut prg_rom = vec![0xEA; 0x4000];
+        prg_rom[..program.len()].copy_from_slice(program);
+        prg_rom[0x3FFC] = 0x00;
+        prg_rom[0x3FFD] = 0x80;
+        let rom = Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        };
+        GdbStub::new(Debugger::new(NesEmulator::with_rom(rom)))
+    }
+
+    fn reply(stub: &mut GdbStub, packet: &str) -> String {
+        match stub.handle(packet) {
+            Action::Reply(reply) => reply,
+            action => panic!("{:?}", action),
+        }
+    }
+
+    #[test]
+    fn test_framing() {
+        assert_eq!(frame("OK"), "$OK#9a");
+
+        let mut wire: &[u8] = b"+$qSupported:xmlRegisters=i386#c1\x03$g#00";
+        assert_eq!(
+            read_packet(&mut wire).unwrap(),
+            Some(Incoming::Packet("qSupported:xmlRegisters=i386".to_string()))
+        );
+        assert_eq!(read_packet(&mut wire).unwrap(), Some(Incoming::Interrupt));
+        assert_eq!(read_packet(&mut wire).unwrap(), Some(Incoming::Corrupt));
+        assert_eq!(read_packet(&mut wire).unwrap(), None);
+    }
+
+    #[test]
+    fn test_packets() {
+        #[rustfmt::skip]
+        let mut stub = stub(&[
+            0xA9, 0x42,       // LDA #$42
+            0x8D, 0x00, 0x02, // STA $0200
+            0x4C, 0x05, 0x80, // JMP $8005
+        ]);
+
+        assert_eq!(reply(&mut stub, "p5"), "0080");
+        assert_eq!(stub.handle("s"), Action::Step);
+        assert_eq!(GdbStub::stop_reply(&stub.debugger.step()), "S05");
+        assert_eq!(&reply(&mut stub, "g")[..2], "42");
+        assert_eq!(reply(&mut stub, "p5"), "0280");
+
+        assert_eq!(reply(&mut stub, "Z2,200,1"), "OK");
+        let stop = stub.debugger.resume();
+        assert_eq!(GdbStub::stop_reply(&stop), "T05watch:0200;");
+        assert_eq!(reply(&mut stub, "m200,2"), "4200");
+        assert_eq!(reply(&mut stub, "Z3,200,1"), "OK");
+        assert_eq!(reply(&mut stub, "z2,200,1"), "OK");
+        let watchpoints = &stub.debugger.cpu().bus.watchpoints;
+        assert_eq!(watchpoints.len(), 1);
+        assert!(watchpoints[0].read && !watchpoints[0].write);
+        assert_eq!(reply(&mut stub, "z3,200,1"), "OK");
+        assert_eq!(reply(&mut stub, "Z4,ff00,10000"), "OK");
+        assert_eq!(stub.debugger.cpu().bus.watchpoints[0].end, 0xFFFF);
+        assert_eq!(reply(&mut stub, "z4,ff00,10000"), "OK");
+
+        assert_eq!(reply(&mut stub, "M300,2:beef"), "OK");
+        assert_eq!(reply(&mut stub, "m300,2"), "beef");
+        assert_eq!(reply(&mut stub, "M300,2:\u{fffd}0"), "E01");
+        assert_eq!(reply(&mut stub, "P0=7f"), "OK");
+        assert_eq!(&reply(&mut stub, "g")[..2], "7f");
+
+        assert_eq!(reply(&mut stub, "Z0,8005,1"), "OK");
+        assert_eq!(
+            GdbStub::stop_reply(&stub.debugger.resume()),
+            "S05",
+            "stops at the JMP"
+        );
+        assert_eq!(stub.debugger.cpu().program_counter, 0x8005);
+        assert_eq!(reply(&mut stub, "vMustReplyEmpty"), "");
+        assert_eq!(stub.handle("k"), Action::Close(None));
+    }
+}
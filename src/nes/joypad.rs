@@ -0,0 +1,72 @@
+//! The standard controller on $4016: writing 1 then 0 latches the buttons, then each read
+//! shifts one out, A first.
+//! https://www.nesdev.org/wiki/Standard_controller
+
+use core::cell::Cell;
+
+pub const A: u8 = 0b0000_0001;
+pub const B: u8 = 0b0000_0010;
+pub const SELECT: u8 = 0b0000_0100;
+pub const START: u8 = 0b0000_1000;
+pub const UP: u8 = 0b0001_0000;
+pub const DOWN: u8 = 0b0010_0000;
+pub const LEFT: u8 = 0b0100_0000;
+pub const RIGHT: u8 = 0b1000_0000;
+
+#[derive(Default)]
+pub struct Joypad {
+    /// What's held, A in bit 0 up to right in bit 7
+    pub buttons: u8,
+    strobe: bool,
+    // reads shift the register, and the bus only reads through `&self`
+    index: Cell<u8>,
+}
+
+impl Joypad {
+    pub fn write(&mut self, data: u8) {
+        self.strobe = data & 1 != 0;
+        if self.strobe {
+            self.index.set(0);
+        }
+    }
+
+    /// What a read would return, without shifting
+    pub fn peek(&self) -> u8 {
+        match self.index.get() {
+            // while strobing it keeps reloading, so it's always A
+            _ if self.strobe => self.buttons & A,
+            index @ 0..=7 => (self.buttons >> index) & 1,
+            // official controllers read 1 once all 8 are out
+            _ => 1,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        let value = self.peek();
+        if !self.strobe && self.index.get() < 8 {
+            self.index.set(self.index.get() + 1);
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read() {
+        let mut joypad = Joypad {
+            buttons: A | START | RIGHT,
+            ..Default::default()
+        };
+
+        joypad.write(1);
+        assert_eq!(joypad.read(), 1);
+        assert_eq!(joypad.read(), 1, "strobing keeps returning A");
+        joypad.write(0);
+
+        let bits: [u8; 9] = core::array::from_fn(|_| joypad.read());
+        assert_eq!(bits, [1, 0, 0, 1, 0, 0, 0, 1, 1]);
+    }
+}
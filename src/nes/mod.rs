@@ -1,7 +1,22 @@
+pub mod apu;
+#[cfg(target_arch = "x86_64")]
+pub mod blargg;
 pub mod bus;
 pub mod cartridge;
+pub mod cheats;
 pub mod cpu;
+pub mod debugger;
+pub mod disasm;
 pub mod emu;
-pub mod opcodes;
 #[cfg(target_arch = "x86_64")]
+pub mod gdbstub;
+pub mod joypad;
+pub mod nsf;
+pub mod opcodes;
+pub mod ppu;
+pub mod rewind;
+pub mod search;
+pub mod state;
 pub mod trace;
+#[cfg(target_arch = "x86_64")]
+pub mod viewer;
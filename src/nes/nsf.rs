@@ -0,0 +1,337 @@
+//! NSF music files: the sound code and data ripped out of a game, with a header saying where
+//! to load it and which routines to call.
+//! https://www.nesdev.org/wiki/NSF
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use super::{
+    apu::{NTSC_CPU_HZ, PAL_CPU_HZ},
+    bus::Bus,
+    cartridge::{Mirroring, Rom},
+    cpu::{Mem, CPU},
+};
+
+const NSF_TAG: [u8; 5] = [0x4E, 0x45, 0x53, 0x4D, 0x1A];
+const HEADER_SIZE: usize = 0x80;
+const BANK_SIZE: usize = 0x1000;
+
+/// Where the init and play routines return to. Nothing is mapped there, so tunes can't
+/// jump to it by accident.
+const RETURN_ADDR: u16 = 0x5FF6;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+    /// Plays on either, the tune is told which at init
+    Dual,
+}
+
+pub struct Nsf {
+    pub songs: u8,
+    /// 1 based like the header
+    pub starting_song: u8,
+    pub load_addr: u16,
+    pub init_addr: u16,
+    pub play_addr: u16,
+    pub title: String,
+    pub artist: String,
+    pub copyright: String,
+    /// How often play is called, in microseconds
+    pub ntsc_speed: u16,
+    pub pal_speed: u16,
+    /// Only set when the tune uses bankswitching
+    pub banks: Option<[u8; 8]>,
+    pub region: Region,
+    pub data: Vec<u8>,
+}
+
+fn header_string(raw: &[u8]) -> String {
+    let end = raw.iter().position(|b| *b == 0).unwrap_or(raw.len());
+    raw[..end]
+        .iter()
+        .map(|b| if b.is_ascii() { *b as char } else { '?' })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+impl Nsf {
+    pub fn new(raw: &[u8]) -> Result<Nsf, String> {
+        if raw.len() < HEADER_SIZE || raw[0..5] != NSF_TAG {
+            return Err("File is not in NSF file format".to_string());
+        }
+
+        let word = |i: usize| u16::from_le_bytes([raw[i], raw[i + 1]]);
+
+        let mut banks = [0; 8];
+        banks.copy_from_slice(&raw[0x70..0x78]);
+        let banks = if banks.iter().any(|bank| *bank != 0) {
+            Some(banks)
+        } else {
+            None
+        };
+
+        let region = match raw[0x7A] & 3 {
+            0 => Region::Ntsc,
+            1 => Region::Pal,
+            _ => Region::Dual,
+        };
+
+        let nsf = Nsf {
+            songs: raw[6],
+            starting_song: raw[7],
+            load_addr: word(0x08),
+            init_addr: word(0x0A),
+            play_addr: word(0x0C),
+            title: header_string(&raw[0x0E..0x2E]),
+            artist: header_string(&raw[0x2E..0x4E]),
+            copyright: header_string(&raw[0x4E..0x6E]),
+            ntsc_speed: word(0x6E),
+            pal_speed: word(0x78),
+            banks,
+            region,
+            data: raw[HEADER_SIZE..].to_vec(),
+        };
+
+        if nsf.songs == 0 {
+            return Err("NSF has no songs".to_string());
+        }
+        if nsf.banks.is_none() && nsf.load_addr < 0x8000 {
+            return Err(format!(
+                "NSF load address {:04x} is below $8000",
+                nsf.load_addr
+            ));
+        }
+        Ok(nsf)
+    }
+
+    /// PAL only tunes are played at PAL speed, everything else as NTSC
+    pub fn is_pal(&self) -> bool {
+        self.region == Region::Pal
+    }
+
+    pub fn cpu_hz(&self) -> u32 {
+        if self.is_pal() {
+            PAL_CPU_HZ
+        } else {
+            NTSC_CPU_HZ
+        }
+    }
+
+    /// CPU cycles between calls to play
+    pub fn frame_cycles(&self) -> u64 {
+        let speed = match (self.is_pal(), self.ntsc_speed, self.pal_speed) {
+            (false, 0, _) => 16639,
+            (true, _, 0) => 19997,
+            (false, speed, _) => speed,
+            (true, _, speed) => speed,
+        };
+        speed as u64 * self.cpu_hz() as u64 / 1_000_000
+    }
+
+    /// Lays the data out as 4K pages for the bus. Bankswitched tunes start at the page offset
+    /// of the load address, the rest are placed at their load address in a 32K image.
+    fn rom(&self) -> Rom {
+        let offset = if self.banks.is_some() {
+            self.load_addr as usize % BANK_SIZE
+        } else {
+            self.load_addr as usize - 0x8000
+        };
+
+        let len = (offset + self.data.len()).max(8 * BANK_SIZE);
+        let mut prg_rom = vec![0; len.div_ceil(BANK_SIZE) * BANK_SIZE];
+        prg_rom[offset..offset + self.data.len()].copy_from_slice(&self.data);
+
+        Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        }
+    }
+
+    fn initial_banks(&self) -> [u8; 8] {
+        self.banks.unwrap_or([0, 1, 2, 3, 4, 5, 6, 7])
+    }
+}
+
+/// Reads an NSF, on the simulator from a path on disk
+#[cfg(target_arch = "x86_64")]
+pub fn load_nsf(path: &str) -> Result<Nsf, String> {
+    let raw = std::fs::read(path).map_err(|e| format!("{}: {}", path, e))?;
+    Nsf::new(&raw)
+}
+
+// TODO: read it from the sd card
+#[cfg(not(target_arch = "x86_64"))]
+pub fn load_nsf(_path: &str) -> Result<Nsf, String> {
+    Err(String::from("No storage to load NSF files from"))
+}
+
+/// Plays an NSF without a PPU: init is called once per song, then play once per frame while
+/// the APU runs alongside.
+pub struct NsfPlayer {
+    pub nsf: Nsf,
+    pub cpu: CPU,
+    /// 0 based
+    pub song: u8,
+}
+
+impl NsfPlayer {
+    pub fn new(nsf: Nsf) -> Result<Self, String> {
+        let mut bus = Bus::new(nsf.rom());
+        bus.apu.set_clock(nsf.cpu_hz());
+
+        let song = nsf.starting_song.saturating_sub(1).min(nsf.songs - 1);
+        let mut player = Self {
+            cpu: CPU::from_bus(bus),
+            nsf,
+            song,
+        };
+        player.play(song)?;
+        Ok(player)
+    }
+
+    /// Starts a song from the beginning
+    pub fn play(&mut self, song: u8) -> Result<(), String> {
+        self.song = song % self.nsf.songs;
+
+        self.cpu.bus.clear_ram();
+        self.cpu.bus.set_banks(Some(self.nsf.initial_banks()));
+        for addr in 0x4000..=0x4013 {
+            self.cpu.mem_write(addr, 0);
+        }
+        self.cpu.mem_write(0x4015, 0x0F);
+        self.cpu.mem_write(0x4017, 0x40);
+
+        self.cpu.reset();
+        self.cpu.register_a = self.song;
+        self.cpu.register_x = self.nsf.is_pal() as u8;
+        self.cpu.call(self.nsf.init_addr, RETURN_ADDR);
+        // init gets a whole second, some tunes decompress things first
+        self.run_routine(self.nsf.cpu_hz() as u64)
+            .map_err(|_| "NSF init routine never returned".to_string())
+    }
+
+    pub fn next_song(&mut self) -> Result<(), String> {
+        self.play((self.song + 1) % self.nsf.songs)
+    }
+
+    pub fn previous_song(&mut self) -> Result<(), String> {
+        self.play(self.song.checked_sub(1).unwrap_or(self.nsf.songs - 1))
+    }
+
+    /// Calls play and then lets the APU run until the next call is due
+    pub fn frame(&mut self) -> Result<(), String> {
+        let start = self.cpu.cycles;
+        let frame = self.nsf.frame_cycles();
+
+        self.cpu.call(self.nsf.play_addr, RETURN_ADDR);
+        self.run_routine(frame)
+            .map_err(|_| "NSF play routine took longer than a frame".to_string())?;
+
+        let spent = self.cpu.cycles - start;
+        if spent < frame {
+            self.cpu.bus.tick_apu(frame - spent);
+            self.cpu.cycles += frame - spent;
+        }
+        Ok(())
+    }
+
+    /// Steps the CPU until the routine returns to `RETURN_ADDR`
+    fn run_routine(&mut self, limit: u64) -> Result<(), ()> {
+        let start = self.cpu.cycles;
+        while self.cpu.program_counter != RETURN_ADDR {
+            if self.cpu.cycles - start > limit {
+                return Err(());
+            }
+            let before = self.cpu.cycles;
+            self.cpu.tick();
+            self.cpu.bus.tick_apu(self.cpu.cycles - before);
+        }
+        Ok(())
+    }
+
+    /// Samples generated since the last call, at `apu::SAMPLE_RATE`
+    pub fn take_samples(&mut self) -> Vec<i16> {
+        self.cpu.bus.apu.take_samples()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A two song tune: init stores the song number, play counts frames and keys pulse 1 on
+    fn test_nsf() -> Vec<u8> {
+        let mut raw = vec![0; HEADER_SIZE];
+        raw[0..5].copy_from_slice(&NSF_TAG);
+        raw[5] = 1;
+        raw[6] = 2;
+        raw[7] = 1;
+        raw[0x08..0x0A].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[0x0A..0x0C].copy_from_slice(&0x8000u16.to_le_bytes());
+        raw[0x0C..0x0E].copy_from_slice(&0x8003u16.to_le_bytes());
+        raw[0x0E..0x13].copy_from_slice(b"Tests");
+        raw[0x2E..0x31].copy_from_slice(b"EGB");
+        raw[0x6E..0x70].copy_from_slice(&16639u16.to_le_bytes());
+
+        #[rustfmt::skip]
+        raw.extend([
+            // init: STA $00, RTS
+            0x85, 0x00, 0x60,
+            // play: INC $01, LDA #$BF, STA $4000, LDA #$FD, STA $4002, LDA #$08, STA $4003, RTS
+            0xE6, 0x01,
+            0xA9, 0xBF, 0x8D, 0x00, 0x40,
+            0xA9, 0xFD, 0x8D, 0x02, 0x40,
+            0xA9, 0x08, 0x8D, 0x03, 0x40,
+            0x60,
+        ]);
+        raw
+    }
+
+    #[test]
+    fn test_header() {
+        let nsf = Nsf::new(&test_nsf()).unwrap();
+        assert_eq!(nsf.songs, 2);
+        assert_eq!(nsf.play_addr, 0x8003);
+        assert_eq!(nsf.title, "Tests");
+        assert_eq!(nsf.artist, "EGB");
+        assert_eq!(nsf.copyright, "");
+        assert_eq!(nsf.region, Region::Ntsc);
+        assert!(nsf.banks.is_none());
+        assert_eq!(nsf.frame_cycles(), 29780);
+
+        assert!(Nsf::new(&[0; 0x80]).is_err());
+    }
+
+    #[test]
+    fn test_play_loop() {
+        let mut player = NsfPlayer::new(Nsf::new(&test_nsf()).unwrap()).unwrap();
+        assert_eq!(player.cpu.mem_read(0x00), 0);
+
+        for _ in 0..10 {
+            player.frame().unwrap();
+        }
+        assert_eq!(player.cpu.mem_read(0x01), 10);
+        assert_eq!(player.cpu.bus.apu.status() & 1, 1);
+        let samples = player.take_samples();
+        assert!(samples.len() > 3000);
+        assert!(samples.iter().any(|s| *s != samples[0]));
+
+        player.next_song().unwrap();
+        assert_eq!(player.song, 1);
+        assert_eq!(player.cpu.mem_read(0x00), 1);
+        assert_eq!(player.cpu.mem_read(0x01), 0);
+        player.next_song().unwrap();
+        assert_eq!(player.song, 0);
+        player.previous_song().unwrap();
+        assert_eq!(player.song, 1);
+    }
+}
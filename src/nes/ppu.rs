@@ -0,0 +1,306 @@
+//! The PPU's side of the bus: its registers, pattern tables, nametables, palette RAM and OAM.
+//! Nothing is rendered yet, but games can fill its memory and the viewers can show it.
+//! https://www.nesdev.org/wiki/PPU_registers
+
+use alloc::{string::String, vec, vec::Vec};
+use core::cell::Cell;
+
+use super::{
+    cartridge::Mirroring,
+    state::{write_u16, Reader},
+};
+
+const CTRL: u16 = 0;
+const MASK: u16 = 1;
+const STATUS: u16 = 2;
+const OAM_ADDR: u16 = 3;
+const OAM_DATA: u16 = 4;
+const SCROLL: u16 = 5;
+const ADDR: u16 = 6;
+const DATA: u16 = 7;
+
+const VBLANK: u8 = 0b1000_0000;
+const CHR_RAM_SIZE: usize = 0x2000;
+
+pub struct Ppu {
+    /// Pattern tables, CHR-RAM when the cartridge has no CHR-ROM
+    pub chr: Vec<u8>,
+    chr_ram: bool,
+    pub vram: [u8; 2048],
+    pub palette: [u8; 32],
+    pub oam: [u8; 256],
+    pub mirroring: Mirroring,
+    pub ctrl: u8,
+    pub mask: u8,
+    pub oam_addr: u8,
+    pub scroll_x: u8,
+    pub scroll_y: u8,
+    // reads of $2002 and $2007 change these, and the bus only reads through `&self`
+    status: Cell<u8>,
+    addr: Cell<u16>,
+    /// The write toggle shared by $2005 and $2006
+    latch: Cell<bool>,
+    /// $2007 reads come back a read late, except from the palette
+    buffer: Cell<u8>,
+}
+
+impl Ppu {
+    pub fn new(chr: Vec<u8>, mirroring: Mirroring) -> Self {
+        let chr_ram = chr.is_empty();
+        Self {
+            chr: if chr_ram { vec![0; CHR_RAM_SIZE] } else { chr },
+            chr_ram,
+            vram: [0; 2048],
+            palette: [0; 32],
+            oam: [0; 256],
+            mirroring,
+            ctrl: 0,
+            mask: 0,
+            oam_addr: 0,
+            scroll_x: 0,
+            scroll_y: 0,
+            status: Cell::new(0),
+            addr: Cell::new(0),
+            latch: Cell::new(false),
+            buffer: Cell::new(0),
+        }
+    }
+
+    /// Nametable $2000-$2FFF to an offset into the 2K of VRAM
+    pub fn mirror_vram(&self, addr: u16) -> usize {
+        let table = (addr - 0x2000) / 0x400 % 4;
+        let offset = (addr % 0x400) as usize;
+        let page = match (&self.mirroring, table) {
+            (Mirroring::VERTICAL, 0 | 2) | (Mirroring::HORIZONTAL, 0 | 1) => 0,
+            (Mirroring::VERTICAL, _) | (Mirroring::HORIZONTAL, _) => 1,
+            // four screen needs VRAM on the cartridge, the last two share with the first two
+            (Mirroring::FOUR_SCREEN, table) => table as usize % 2,
+        };
+        page * 0x400 + offset
+    }
+
+    /// Palette $3F00-$3FFF to an index into palette RAM, sprite colour 0 is the backdrop's
+    fn mirror_palette(addr: u16) -> usize {
+        let index = (addr % 32) as usize;
+        match index {
+            0x10 | 0x14 | 0x18 | 0x1C => index - 0x10,
+            _ => index,
+        }
+    }
+
+    /// Reads the PPU's own address space
+    pub fn read_vram(&self, addr: u16) -> u8 {
+        match addr % 0x4000 {
+            addr @ 0x0000..=0x1FFF => self.chr[addr as usize % self.chr.len()],
+            addr @ 0x2000..=0x3EFF => {
+                self.vram[self.mirror_vram(0x2000 + (addr - 0x2000) % 0x1000)]
+            }
+            addr => self.palette[Self::mirror_palette(addr)],
+        }
+    }
+
+    pub fn write_vram(&mut self, addr: u16, data: u8) {
+        match addr % 0x4000 {
+            addr @ 0x0000..=0x1FFF => {
+                if self.chr_ram {
+                    self.chr[addr as usize] = data;
+                }
+            }
+            addr @ 0x2000..=0x3EFF => {
+                let index = self.mirror_vram(0x2000 + (addr - 0x2000) % 0x1000);
+                self.vram[index] = data;
+            }
+            addr => self.palette[Self::mirror_palette(addr)] = data & 0x3F,
+        }
+    }
+
+    fn increment(&self) {
+        let step = if self.ctrl & 0b100 != 0 { 32 } else { 1 };
+        self.addr.set(self.addr.get().wrapping_add(step) % 0x4000);
+    }
+
+    /// The base nametable, $2000 + 0x400 * n
+    pub fn nametable(&self) -> u16 {
+        0x2000 + (self.ctrl & 0b11) as u16 * 0x400
+    }
+
+    /// $0000 or $1000
+    pub fn background_table(&self) -> u16 {
+        if self.ctrl & 0b1_0000 != 0 {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    /// $0000 or $1000, ignored for 8x16 sprites
+    pub fn sprite_table(&self) -> u16 {
+        if self.ctrl & 0b1000 != 0 {
+            0x1000
+        } else {
+            0
+        }
+    }
+
+    pub fn tall_sprites(&self) -> bool {
+        self.ctrl & 0b10_0000 != 0
+    }
+
+    /// Called at the end of a frame until the PPU has timing of its own
+    pub fn set_vblank(&mut self) {
+        self.status.set(self.status.get() | VBLANK);
+    }
+
+    /// What a register read would return, without reading it
+    pub fn peek_register(&self, addr: u16) -> u8 {
+        match addr % 8 {
+            STATUS => self.status.get(),
+            OAM_DATA => self.oam[self.oam_addr as usize],
+            DATA => match self.addr.get() {
+                0x3F00..=0x3FFF => self.read_vram(self.addr.get()),
+                _ => self.buffer.get(),
+            },
+            // the rest are write only
+            _ => 0,
+        }
+    }
+
+    pub fn read_register(&self, addr: u16) -> u8 {
+        let value = self.peek_register(addr);
+        match addr % 8 {
+            STATUS => {
+                self.status.set(value & !VBLANK);
+                self.latch.set(false);
+            }
+            DATA => {
+                self.buffer.set(self.read_vram(self.addr.get()));
+                self.increment();
+            }
+            _ => {}
+        }
+        value
+    }
+
+    pub fn write_register(&mut self, addr: u16, data: u8) {
+        match addr % 8 {
+            CTRL => self.ctrl = data,
+            MASK => self.mask = data,
+            OAM_ADDR => self.oam_addr = data,
+            OAM_DATA => {
+                self.oam[self.oam_addr as usize] = data;
+                self.oam_addr = self.oam_addr.wrapping_add(1);
+            }
+            SCROLL => {
+                if self.latch.get() {
+                    self.scroll_y = data;
+                } else {
+                    self.scroll_x = data;
+                }
+                self.latch.set(!self.latch.get());
+            }
+            ADDR => {
+                let addr = self.addr.get();
+                self.addr.set(if self.latch.get() {
+                    (addr & 0xFF00) | data as u16
+                } else {
+                    ((data as u16 & 0x3F) << 8) | (addr & 0xFF)
+                });
+                self.latch.set(!self.latch.get());
+            }
+            DATA => {
+                self.write_vram(self.addr.get(), data);
+                self.increment();
+            }
+            // $2002 is read only
+            _ => {}
+        }
+    }
+
+    /// Appends the PPU's memory and registers to a save state, CHR only when it's RAM
+    pub fn save(&self, out: &mut Vec<u8>) {
+        if self.chr_ram {
+            out.extend_from_slice(&self.chr);
+        }
+        out.extend_from_slice(&self.vram);
+        out.extend_from_slice(&self.palette);
+        out.extend_from_slice(&self.oam);
+        out.extend_from_slice(&[
+            self.ctrl,
+            self.mask,
+            self.oam_addr,
+            self.scroll_x,
+            self.scroll_y,
+            self.status.get(),
+        ]);
+        write_u16(out, self.addr.get());
+        out.extend_from_slice(&[self.latch.get() as u8, self.buffer.get()]);
+    }
+
+    /// How many bytes `save` appends
+    pub fn state_len(&self) -> usize {
+        let chr = if self.chr_ram { self.chr.len() } else { 0 };
+        chr + self.vram.len() + self.palette.len() + self.oam.len() + 6 + 2 + 2
+    }
+
+    pub fn load(&mut self, state: &mut Reader) -> Result<(), String> {
+        if self.chr_ram {
+            state.bytes(&mut self.chr)?;
+        }
+        state.bytes(&mut self.vram)?;
+        state.bytes(&mut self.palette)?;
+        state.bytes(&mut self.oam)?;
+        self.ctrl = state.u8()?;
+        self.mask = state.u8()?;
+        self.oam_addr = state.u8()?;
+        self.scroll_x = state.u8()?;
+        self.scroll_y = state.u8()?;
+        self.status.set(state.u8()?);
+        self.addr.set(state.u16()?);
+        self.latch.set(state.u8()? != 0);
+        self.buffer.set(state.u8()?);
+        Ok(())
+    }
+
+    /// $4014, copies a page of CPU memory into OAM from `oam_addr` on
+    pub fn dma(&mut self, page: &[u8; 256]) {
+        for byte in page {
+            self.oam[self.oam_addr as usize] = *byte;
+            self.oam_addr = self.oam_addr.wrapping_add(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_registers() {
+        let mut ppu = Ppu::new(vec![], Mirroring::HORIZONTAL);
+
+        // $2400 mirrors $2000 horizontally
+        ppu.write_register(0x2006, 0x24);
+        ppu.write_register(0x2006, 0x05);
+        ppu.write_register(0x2007, 0x42);
+        assert_eq!(ppu.vram[5], 0x42);
+
+        ppu.write_register(0x2006, 0x20);
+        ppu.write_register(0x2006, 0x05);
+        ppu.read_register(0x2007);
+        assert_eq!(ppu.read_register(0x2007), 0x42, "reads are buffered");
+
+        // $3F10 mirrors the backdrop
+        ppu.write_register(0x2006, 0x3F);
+        ppu.write_register(0x2006, 0x10);
+        ppu.write_register(0x2007, 0x0F);
+        assert_eq!(ppu.palette[0], 0x0F);
+
+        ppu.set_vblank();
+        ppu.write_register(0x2005, 0x12);
+        assert_eq!(ppu.read_register(0x2002), VBLANK);
+        assert_eq!(ppu.read_register(0x2002), 0);
+        // reading the status reset the toggle, so this is X again
+        ppu.write_register(0x2005, 0x34);
+        assert_eq!(ppu.scroll_x, 0x34);
+    }
+}
@@ -0,0 +1,337 @@
+//! Rewinding: a save state is taken every few frames, and only the newest is kept whole. Older
+//! ones are kept as the XOR of each state with the one after it, run-length encoded, which is
+//! mostly zeros since little changes between frames. Stepping back XORs the newest delta into
+//! the newest state. When the deltas outgrow the memory budget the oldest are dropped, so a
+//! small heap just rewinds less far, and a budget too small for a state turns rewinding off.
+//! That's checked before a state is made, so a heap that can't hold one never has to.
+//!
+//! States don't include the APU (see `state`), so sound carries on from where it was instead of
+//! going back with the picture and the game's memory.
+
+use alloc::{collections::VecDeque, vec::Vec};
+
+use super::emu::NesEmulator;
+
+/// Frames between states unless told otherwise
+pub const DEFAULT_INTERVAL: u64 = 4;
+/// An NTSC frame
+const FRAME_US: u64 = 16_639;
+
+/// High bit set: a run of (n & 0x7F) + 1 zeros. Clear: n + 1 bytes follow as they are.
+const RUN: u8 = 0x80;
+const MAX_TOKEN: usize = 128;
+
+/// RLE encodes `a ^ b`
+fn encode_xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literal: Vec<u8> = Vec::new();
+    let mut zeros = 0;
+
+    let flush_literal = |out: &mut Vec<u8>, literal: &mut Vec<u8>| {
+        for chunk in literal.chunks(MAX_TOKEN) {
+            out.push(chunk.len() as u8 - 1);
+            out.extend_from_slice(chunk);
+        }
+        literal.clear();
+    };
+    let flush_zeros = |out: &mut Vec<u8>, zeros: &mut usize| {
+        while *zeros > 0 {
+            let run = (*zeros).min(MAX_TOKEN);
+            out.push(RUN | (run - 1) as u8);
+            *zeros -= run;
+        }
+    };
+
+    for byte in a.iter().zip(b).map(|(a, b)| a ^ b) {
+        if byte == 0 {
+            flush_literal(&mut out, &mut literal);
+            zeros += 1;
+        } else {
+            flush_zeros(&mut out, &mut zeros);
+            literal.push(byte);
+        }
+    }
+    flush_literal(&mut out, &mut literal);
+    flush_zeros(&mut out, &mut zeros);
+    out
+}
+
+/// XORs an encoded delta back into `state`
+fn apply_xor(delta: &[u8], state: &mut [u8]) {
+    let mut pos = 0;
+    let mut i = 0;
+    while i < delta.len() {
+        let token = delta[i];
+        i += 1;
+        let len = (token & !RUN) as usize + 1;
+        if token & RUN != 0 {
+            pos += len;
+        } else {
+            for (byte, change) in state[pos..pos + len].iter_mut().zip(&delta[i..i + len]) {
+                *byte ^= change;
+            }
+            pos += len;
+            i += len;
+        }
+    }
+}
+
+pub struct Rewind {
+    /// Frames between states
+    pub interval: u64,
+    budget: usize,
+    /// The newest state, whole
+    current: Vec<u8>,
+    current_frame: u64,
+    /// Where the next state is written before it's diffed, kept to avoid allocating each time
+    scratch: Vec<u8>,
+    /// Each takes the state after it back one step, newest at the back
+    deltas: VecDeque<Vec<u8>>,
+    delta_bytes: usize,
+    /// Set when a state didn't fit the budget
+    disabled: bool,
+    last_step_us: Option<u64>,
+}
+
+impl Rewind {
+    /// `budget` is how many bytes of heap rewinding may use, including the two whole states
+    pub fn new(budget: usize, interval: u64) -> Self {
+        Self {
+            interval: interval.max(1),
+            budget,
+            current: Vec::new(),
+            current_frame: 0,
+            scratch: Vec::new(),
+            deltas: VecDeque::new(),
+            delta_bytes: 0,
+            disabled: false,
+            last_step_us: None,
+        }
+    }
+
+    /// Forgets everything, for when a different game starts
+    pub fn clear(&mut self) {
+        self.current = Vec::new();
+        self.scratch = Vec::new();
+        self.deltas.clear();
+        self.delta_bytes = 0;
+        self.disabled = false;
+        self.last_step_us = None;
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.disabled
+    }
+
+    /// How many states back the game can go
+    pub fn depth(&self) -> usize {
+        if self.current.is_empty() {
+            0
+        } else {
+            self.deltas.len() + 1
+        }
+    }
+
+    /// Bytes in use, at most the budget
+    pub fn memory(&self) -> usize {
+        self.current.len() * 2 + self.delta_bytes
+    }
+
+    /// Call after every tick, takes a state every `interval` frames
+    pub fn record(&mut self, emu: &NesEmulator) {
+        let frame = emu.frame();
+        if self.disabled || (!self.current.is_empty() && frame < self.current_frame + self.interval)
+        {
+            return;
+        }
+
+        let len = emu.state_len();
+        if len != self.current.len() {
+            // the first state, or a different game
+            self.deltas.clear();
+            self.delta_bytes = 0;
+            if len * 2 > self.budget {
+                self.clear();
+                self.disabled = true;
+                return;
+            }
+        }
+
+        self.scratch.clear();
+        emu.save_state(&mut self.scratch);
+
+        if self.scratch.len() != self.current.len() {
+            self.scratch.shrink_to_fit();
+            self.current = self.scratch.clone();
+        } else {
+            let mut delta = encode_xor(&self.current, &self.scratch);
+            delta.shrink_to_fit();
+            core::mem::swap(&mut self.current, &mut self.scratch);
+            self.delta_bytes += delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.current_frame = frame;
+
+        while self.memory() > self.budget {
+            match self.deltas.pop_front() {
+                Some(delta) => self.delta_bytes -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Goes back to the newest state, or the one before if the game is already there. Returns
+    /// false when there's nothing further back.
+    pub fn step_back(&mut self, emu: &mut NesEmulator) -> bool {
+        if self.current.is_empty() {
+            return false;
+        }
+        if emu.frame() == self.current_frame {
+            let Some(delta) = self.deltas.pop_back() else {
+                return false;
+            };
+            self.delta_bytes -= delta.len();
+            apply_xor(&delta, &mut self.current);
+        }
+        // the states are our own, so they always load
+        if emu.load_state(&self.current).is_err() {
+            return false;
+        }
+        self.current_frame = emu.frame();
+        true
+    }
+
+    /// For holding the rewind button: steps back as fast as the states were taken
+    pub fn rewind(&mut self, emu: &mut NesEmulator, now_us: u64) -> bool {
+        if let Some(last) = self.last_step_us {
+            if now_us.saturating_sub(last) < self.interval * FRAME_US {
+                return false;
+            }
+        }
+        self.last_step_us = Some(now_us);
+        self.step_back(emu)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nes::{
+        cartridge::{Mirroring, Rom},
+        cpu::Mem,
+    };
+    use alloc::vec;
+
+    /// INC $00 forever
+    fn counter() -> NesEmulator {
+        let mut prg_rom = vec![0; 0x4000];
+        prg_rom[..5].copy_from_slice(&[0xE6, 0x00, 0x4C, 0x00, 0x80]);
+        prg_rom[0x3FFD] = 0x80;
+        NesEmulator::with_rom(Rom {
+            prg_rom,
+            chr_rom: vec![],
+            mapper: 0,
+            screen_mirroring: Mirroring::HORIZONTAL,
+        })
+    }
+
+    fn run(emu: &mut NesEmulator, rewind: &mut Rewind, frames: u64) {
+        for _ in 0..frames {
+            emu.run_frame();
+            rewind.record(emu);
+        }
+    }
+
+    #[test]
+    fn test_encoding() {
+        let a = vec![0u8; 1000];
+        let mut b = a.clone();
+        b[10] = 1;
+        b[11] = 2;
+        b[500..700].fill(0xFF);
+        let delta = encode_xor(&a, &b);
+        assert!(delta.len() < 220, "{} bytes", delta.len());
+
+        let mut state = a.clone();
+        apply_xor(&delta, &mut state);
+        assert_eq!(state, b);
+        apply_xor(&delta, &mut state);
+        assert_eq!(state, a);
+    }
+
+    #[test]
+    fn test_step_back() {
+        let mut emu = counter();
+        let mut rewind = Rewind::new(64 * 1024, 2);
+        rewind.record(&emu);
+        run(&mut emu, &mut rewind, 10);
+        assert_eq!(rewind.depth(), 6);
+
+        // a frame past the newest state goes back to it first
+        emu.run_frame();
+        let counted = emu.cpu().mem_read(0x00);
+        assert!(rewind.step_back(&mut emu));
+        assert_eq!(emu.frame(), 10);
+        assert_ne!(emu.cpu().mem_read(0x00), counted);
+
+        let mut previous = emu.cpu().mem_read(0x00);
+        for expected in [8, 6, 4, 2, 0] {
+            assert!(rewind.step_back(&mut emu));
+            assert_eq!(emu.frame(), expected);
+            assert_ne!(emu.cpu().mem_read(0x00), previous);
+            previous = emu.cpu().mem_read(0x00);
+        }
+        assert!(!rewind.step_back(&mut emu));
+
+        // and it carries on from there
+        run(&mut emu, &mut rewind, 4);
+        assert_eq!(rewind.depth(), 3);
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut emu = counter();
+        let mut state = Vec::new();
+        emu.save_state(&mut state);
+
+        assert_eq!(emu.state_len(), state.len());
+
+        // too small for even one state, like the Sprig's, and nothing is allocated finding out
+        let mut rewind = Rewind::new(8 * 1024, 1);
+        assert!(state.len() > 8 * 1024);
+        run(&mut emu, &mut rewind, 3);
+        assert!(!rewind.enabled());
+        assert_eq!(rewind.depth(), 0);
+        assert_eq!(rewind.scratch.capacity(), 0);
+        assert!(!rewind.step_back(&mut emu));
+
+        let mut rewind = Rewind::new(state.len(), 1);
+        run(&mut emu, &mut rewind, 3);
+        assert!(!rewind.enabled());
+
+        // room for the states and a handful of deltas, the oldest go first
+        let mut rewind = Rewind::new(state.len() * 2 + 200, 1);
+        run(&mut emu, &mut rewind, 100);
+        assert!(rewind.enabled());
+        assert!(rewind.memory() <= state.len() * 2 + 200);
+        let depth = rewind.depth();
+        assert!(depth > 1 && depth < 100, "depth {}", depth);
+
+        let mut roomy = Rewind::new(1024 * 1024, 1);
+        run(&mut emu, &mut roomy, 100);
+        assert_eq!(roomy.depth(), 100);
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut emu = counter();
+        let mut rewind = Rewind::new(64 * 1024, 1);
+        run(&mut emu, &mut rewind, 5);
+
+        assert!(rewind.rewind(&mut emu, 0));
+        // too soon
+        assert!(!rewind.rewind(&mut emu, FRAME_US / 2));
+        assert!(rewind.rewind(&mut emu, FRAME_US));
+        assert_eq!(emu.frame(), 3);
+    }
+}
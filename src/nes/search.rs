@@ -0,0 +1,143 @@
+//! Classic RAM search for finding cheats: start with every byte of work RAM as a candidate, then
+//! play a bit and keep the ones that changed the way the thing you're after did. A lives counter
+//! that went down by one is `ChangedBy(-1)`, and once a few bytes are left one can be frozen.
+
+use alloc::{format, string::String, vec::Vec};
+
+pub const RAM_SIZE: usize = 2048;
+
+/// How a byte compares to its value at the last search
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Filter {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+    /// Went up (or down) by exactly this much, wrapping like the byte would
+    ChangedBy(i8),
+}
+
+impl Filter {
+    pub fn matches(&self, previous: u8, current: u8) -> bool {
+        match self {
+            Filter::Equal => current == previous,
+            Filter::NotEqual => current != previous,
+            Filter::Greater => current > previous,
+            Filter::Less => current < previous,
+            Filter::ChangedBy(by) => current == previous.wrapping_add(*by as u8),
+        }
+    }
+
+    pub fn next(&self) -> Self {
+        match self {
+            Filter::Equal => Filter::NotEqual,
+            Filter::NotEqual => Filter::Greater,
+            Filter::Greater => Filter::Less,
+            Filter::Less => Filter::ChangedBy(1),
+            Filter::ChangedBy(_) => Filter::Equal,
+        }
+    }
+
+    pub fn previous(&self) -> Self {
+        match self {
+            Filter::Equal => Filter::ChangedBy(1),
+            Filter::NotEqual => Filter::Equal,
+            Filter::Greater => Filter::NotEqual,
+            Filter::Less => Filter::Greater,
+            Filter::ChangedBy(_) => Filter::Less,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Filter::Equal => String::from("Equal"),
+            Filter::NotEqual => String::from("Not equal"),
+            Filter::Greater => String::from("Greater"),
+            Filter::Less => String::from("Less"),
+            Filter::ChangedBy(by) => format!("Changed by {:+}", by),
+        }
+    }
+}
+
+pub struct RamSearch {
+    /// RAM as it was at the last search
+    snapshot: [u8; RAM_SIZE],
+    candidates: Vec<u16>,
+}
+
+impl RamSearch {
+    pub fn new(ram: &[u8; RAM_SIZE]) -> Self {
+        let mut search = Self {
+            snapshot: [0; RAM_SIZE],
+            candidates: Vec::new(),
+        };
+        search.reset(ram);
+        search
+    }
+
+    /// Starts over with every byte as a candidate
+    pub fn reset(&mut self, ram: &[u8; RAM_SIZE]) {
+        self.snapshot = *ram;
+        self.candidates = (0..RAM_SIZE as u16).collect();
+    }
+
+    /// Keeps the candidates that match, and takes a new snapshot to compare the next search to
+    pub fn filter(&mut self, filter: Filter, ram: &[u8; RAM_SIZE]) {
+        let snapshot = &self.snapshot;
+        self.candidates
+            .retain(|addr| filter.matches(snapshot[*addr as usize], ram[*addr as usize]));
+        self.snapshot = *ram;
+    }
+
+    /// Addresses still in the running, lowest first
+    pub fn candidates(&self) -> &[u16] {
+        &self.candidates
+    }
+
+    /// A byte's value at the last search
+    pub fn previous(&self, addr: u16) -> u8 {
+        self.snapshot[addr as usize % RAM_SIZE]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_search() {
+        let mut ram = [0; RAM_SIZE];
+        ram[0x10] = 3;
+        ram[0x20] = 3;
+        ram[0x30] = 7;
+        let mut search = RamSearch::new(&ram);
+        assert_eq!(search.candidates().len(), RAM_SIZE);
+
+        // lost a life
+        ram[0x10] = 2;
+        ram[0x20] = 5;
+        search.filter(Filter::NotEqual, &ram);
+        assert_eq!(search.candidates(), &[0x10, 0x20]);
+        assert_eq!(search.previous(0x10), 2);
+
+        // and another, then nothing happened
+        ram[0x10] = 1;
+        ram[0x20] = 4;
+        search.filter(Filter::Less, &ram);
+        search.filter(Filter::Equal, &ram);
+        assert_eq!(search.candidates(), &[0x10, 0x20]);
+
+        ram[0x10] = 0;
+        ram[0x20] = 2;
+        search.filter(Filter::ChangedBy(-1), &ram);
+        assert_eq!(search.candidates(), &[0x10]);
+
+        assert!(Filter::ChangedBy(1).matches(0xFF, 0x00));
+        assert!(Filter::Greater.matches(1, 2));
+        assert_eq!(Filter::Less.next(), Filter::ChangedBy(1));
+        assert_eq!(Filter::ChangedBy(-3).label(), "Changed by -3");
+
+        search.reset(&ram);
+        assert_eq!(search.candidates().len(), RAM_SIZE);
+    }
+}
@@ -0,0 +1,67 @@
+//! Save states: the CPU, work RAM, cartridge RAM and the PPU's memory as flat bytes. The APU
+//! isn't saved, sound picks up from where it was when a state is loaded.
+
+use alloc::{format, string::String, vec::Vec};
+
+/// Bumped when the layout changes, so old states are refused instead of misread
+pub const VERSION: u8 = 1;
+
+pub struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, pos: 0 }
+    }
+
+    pub fn bytes(&mut self, out: &mut [u8]) -> Result<(), String> {
+        let end = self.pos + out.len();
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(format!("state ends early at byte {}", self.pos))?;
+        out.copy_from_slice(bytes);
+        self.pos = end;
+        Ok(())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        let mut byte = [0];
+        self.bytes(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        let mut bytes = [0; 2];
+        self.bytes(&mut bytes)?;
+        Ok(u16::from_le_bytes(bytes))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        let mut bytes = [0; 8];
+        self.bytes(&mut bytes)?;
+        Ok(u64::from_le_bytes(bytes))
+    }
+
+    /// Errors if anything is left over, which means the state came from something else
+    pub fn finish(&self) -> Result<(), String> {
+        if self.pos == self.data.len() {
+            Ok(())
+        } else {
+            Err(format!(
+                "{} bytes left over in state",
+                self.data.len() - self.pos
+            ))
+        }
+    }
+}
+
+pub fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
+
+pub fn write_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_le_bytes());
+}
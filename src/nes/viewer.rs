@@ -0,0 +1,295 @@
+//! Pictures of the PPU's memory for chasing PPU bugs: the pattern tables, the four nametables
+//! with the scroll outlined, palette RAM and OAM. The simulator shows them in windows of their
+//! own, headless runs write them out as PNGs.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use crate::headless::image;
+
+use super::ppu::Ppu;
+
+/// The 2C02's colours
+#[rustfmt::skip]
+const COLOURS: [[u8; 3]; 64] = [
+    [84, 84, 84], [0, 30, 116], [8, 16, 144], [48, 0, 136], [68, 0, 100], [92, 0, 48],
+    [84, 4, 0], [60, 24, 0], [32, 42, 0], [8, 58, 0], [0, 64, 0], [0, 60, 0], [0, 50, 60],
+    [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [152, 150, 152], [8, 76, 196], [48, 50, 236], [92, 30, 228], [136, 20, 176], [160, 20, 100],
+    [152, 34, 32], [120, 60, 0], [84, 90, 0], [40, 114, 0], [8, 124, 0], [0, 118, 40],
+    [0, 102, 120], [0, 0, 0], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [76, 154, 236], [120, 124, 236], [176, 98, 236], [228, 84, 236],
+    [236, 88, 180], [236, 106, 100], [212, 136, 32], [160, 170, 0], [116, 196, 0], [76, 208, 32],
+    [56, 204, 108], [56, 180, 204], [60, 60, 60], [0, 0, 0], [0, 0, 0],
+    [236, 238, 236], [168, 204, 236], [188, 188, 236], [212, 178, 236], [236, 174, 236],
+    [236, 174, 212], [236, 180, 176], [228, 196, 144], [204, 210, 120], [180, 222, 120],
+    [168, 226, 144], [152, 226, 180], [160, 214, 228], [160, 162, 160], [0, 0, 0], [0, 0, 0],
+];
+
+const SCROLL_OUTLINE: [u8; 3] = [255, 0, 0];
+/// OAM previews are laid out 8 to a row in cells big enough for 8x16 sprites
+const OAM_CELL: (u32, u32) = (16, 24);
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    /// RGB888
+    pub rgb: Vec<u8>,
+}
+
+impl Image {
+    fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            rgb: vec![0; (width * height * 3) as usize],
+        }
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 3] {
+        let i = ((y * self.width + x) * 3) as usize;
+        [self.rgb[i], self.rgb[i + 1], self.rgb[i + 2]]
+    }
+
+    fn set(&mut self, x: u32, y: u32, colour: [u8; 3]) {
+        let i = ((y * self.width + x) * 3) as usize;
+        self.rgb[i..i + 3].copy_from_slice(&colour);
+    }
+
+    fn fill(&mut self, x: u32, y: u32, width: u32, height: u32, colour: [u8; 3]) {
+        for y in y..y + height {
+            for x in x..x + width {
+                self.set(x, y, colour);
+            }
+        }
+    }
+
+    pub fn png(&self) -> Vec<u8> {
+        image::png(self.width, self.height, &self.rgb)
+    }
+}
+
+/// The four colours of a palette, 0-3 are the background's and 4-7 the sprites'
+pub fn palette(ppu: &Ppu, palette: u8) -> [[u8; 3]; 4] {
+    let mut colours = [[0; 3]; 4];
+    for (i, colour) in colours.iter_mut().enumerate() {
+        // colour 0 is always the backdrop
+        let index = if i == 0 {
+            0
+        } else {
+            palette as usize % 8 * 4 + i
+        };
+        *colour = COLOURS[ppu.palette[index] as usize % 64];
+    }
+    colours
+}
+
+fn draw_tile(
+    ppu: &Ppu,
+    addr: u16,
+    colours: &[[u8; 3]; 4],
+    flip: (bool, bool),
+    image: &mut Image,
+    x: u32,
+    y: u32,
+) {
+    for row in 0..8u16 {
+        let low = ppu.read_vram(addr + row);
+        let high = ppu.read_vram(addr + row + 8);
+        let py = if flip.1 { 7 - row } else { row } as u32;
+        for column in 0..8u8 {
+            let bit = 7 - column;
+            let value = ((high >> bit) & 1) << 1 | (low >> bit) & 1;
+            let px = if flip.0 { 7 - column } else { column } as u32;
+            image.set(x + px, y + py, colours[value as usize]);
+        }
+    }
+}
+
+/// Both pattern tables side by side, 16x16 tiles each, in one of the eight palettes
+pub fn pattern_tables(ppu: &Ppu, palette_index: u8) -> Image {
+    let mut image = Image::new(256, 128);
+    let colours = palette(ppu, palette_index);
+    for table in 0..2u16 {
+        for tile in 0..256u16 {
+            let x = table as u32 * 128 + (tile % 16) as u32 * 8;
+            let y = (tile / 16) as u32 * 8;
+            draw_tile(
+                ppu,
+                table * 0x1000 + tile * 16,
+                &colours,
+                (false, false),
+                &mut image,
+                x,
+                y,
+            );
+        }
+    }
+    image
+}
+
+/// $2000, $2400, $2800 and $2C00 in a 2x2 grid, with the screen the scroll points at outlined
+pub fn nametables(ppu: &Ppu) -> Image {
+    let mut image = Image::new(512, 480);
+    for table in 0..4u16 {
+        let base = 0x2000 + table * 0x400;
+        for ty in 0..30u16 {
+            for tx in 0..32u16 {
+                let tile = ppu.read_vram(base + ty * 32 + tx) as u16;
+                let attribute = ppu.read_vram(base + 0x3C0 + ty / 4 * 8 + tx / 4);
+                let shift = (ty % 4 / 2) * 4 + (tx % 4 / 2) * 2;
+                let colours = palette(ppu, (attribute >> shift) & 0b11);
+                draw_tile(
+                    ppu,
+                    ppu.background_table() + tile * 16,
+                    &colours,
+                    (false, false),
+                    &mut image,
+                    (table % 2) as u32 * 256 + tx as u32 * 8,
+                    (table / 2) as u32 * 240 + ty as u32 * 8,
+                );
+            }
+        }
+    }
+
+    // the scroll is relative to the base nametable, and the screen wraps around the grid
+    let table = ((ppu.nametable() - 0x2000) / 0x400) as u32;
+    let left = table % 2 * 256 + ppu.scroll_x as u32;
+    let top = table / 2 * 240 + ppu.scroll_y as u32;
+    for i in 0..256 {
+        image.set((left + i) % 512, top % 480, SCROLL_OUTLINE);
+        image.set((left + i) % 512, (top + 239) % 480, SCROLL_OUTLINE);
+    }
+    for i in 0..240 {
+        image.set(left % 512, (top + i) % 480, SCROLL_OUTLINE);
+        image.set((left + 255) % 512, (top + i) % 480, SCROLL_OUTLINE);
+    }
+    image
+}
+
+/// Palette RAM as two rows of 16 swatches, background then sprites
+pub fn palettes(ppu: &Ppu) -> Image {
+    let mut image = Image::new(128, 16);
+    for (i, entry) in ppu.palette.iter().enumerate() {
+        let colour = COLOURS[*entry as usize % 64];
+        image.fill((i % 16) as u32 * 8, (i / 16) as u32 * 8, 8, 8, colour);
+    }
+    image
+}
+
+/// All 64 sprites, 8 to a row, as the PPU would draw them
+pub fn oam(ppu: &Ppu) -> Image {
+    let mut image = Image::new(OAM_CELL.0 * 8, OAM_CELL.1 * 8);
+    let backdrop = COLOURS[ppu.palette[0] as usize % 64];
+    image.fill(0, 0, image.width, image.height, backdrop);
+
+    for (i, sprite) in ppu.oam.chunks(4).enumerate() {
+        let (tile, attributes) = (sprite[1] as u16, sprite[2]);
+        let colours = palette(ppu, 4 + (attributes & 0b11));
+        let flip = (attributes & 0x40 != 0, attributes & 0x80 != 0);
+        let x = (i % 8) as u32 * OAM_CELL.0 + 4;
+        let y = (i / 8) as u32 * OAM_CELL.1 + 4;
+
+        if ppu.tall_sprites() {
+            // the low bit picks the table, and flipping swaps the halves
+            let addr = (tile & 1) * 0x1000 + (tile & 0xFE) * 16;
+            let (top, bottom) = if flip.1 { (16, 0) } else { (0, 16) };
+            draw_tile(ppu, addr + top, &colours, flip, &mut image, x, y);
+            draw_tile(ppu, addr + bottom, &colours, flip, &mut image, x, y + 8);
+        } else {
+            let addr = ppu.sprite_table() + tile * 16;
+            draw_tile(ppu, addr, &colours, flip, &mut image, x, y);
+        }
+    }
+    image
+}
+
+/// OAM as text, one sprite a line
+pub fn oam_table(ppu: &Ppu) -> String {
+    let mut out = String::from("#   x   y   tile pal flags\n");
+    for (i, sprite) in ppu.oam.chunks(4).enumerate() {
+        let attributes = sprite[2];
+        let mut flags = String::new();
+        for (bit, flag) in [(0x40, 'H'), (0x80, 'V'), (0x20, 'B')] {
+            if attributes & bit != 0 {
+                flags.push(flag);
+            }
+        }
+        out += &format!(
+            "{:02}  {:3} {:3} {:02X}   {}   {}\n",
+            i,
+            sprite[3],
+            sprite[0],
+            sprite[1],
+            attributes & 0b11,
+            flags
+        );
+    }
+    out
+}
+
+/// Writes every view into `dir` and returns the paths
+pub fn save(ppu: &Ppu, dir: &str, palette_index: u8) -> Result<Vec<String>, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir, e))?;
+
+    let files = [
+        ("ppu_patterns.png", pattern_tables(ppu, palette_index).png()),
+        ("ppu_nametables.png", nametables(ppu).png()),
+        ("ppu_palettes.png", palettes(ppu).png()),
+        ("ppu_oam.png", oam(ppu).png()),
+        ("ppu_oam.txt", oam_table(ppu).into_bytes()),
+    ];
+    let mut paths = Vec::new();
+    for (name, data) in files {
+        let path = format!("{}/{}", dir, name);
+        std::fs::write(&path, data).map_err(|e| format!("{}: {}", path, e))?;
+        paths.push(path.to_string());
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::nes::cartridge::Mirroring;
+
+    #[test]
+    fn test_views() {
+        let mut chr = vec![0; 0x2000];
+        // tile 1: the top row in colour 1, the left column in colour 2
+        chr[16] = 0xFF;
+        for row in 0..8 {
+            chr[16 + 8 + row] = 0x80;
+        }
+        let mut ppu = Ppu::new(chr, Mirroring::VERTICAL);
+        ppu.palette[..4].copy_from_slice(&[0x0F, 0x30, 0x16, 0x2A]);
+
+        let patterns = pattern_tables(&ppu, 0);
+        assert_eq!(patterns.pixel(8 + 1, 0), COLOURS[0x30]);
+        assert_eq!(patterns.pixel(8, 3), COLOURS[0x16]);
+        // both bits set
+        assert_eq!(patterns.pixel(8, 0), COLOURS[0x2A]);
+        assert_eq!(patterns.pixel(8 + 1, 1), COLOURS[0x0F]);
+
+        // tile 1 at the top left of $2000, which $2800 mirrors
+        ppu.vram[0] = 1;
+        ppu.scroll_x = 8;
+        let tables = nametables(&ppu);
+        assert_eq!(tables.pixel(1, 0), COLOURS[0x30]);
+        assert_eq!(tables.pixel(1, 240), COLOURS[0x30]);
+        assert_eq!(tables.pixel(8, 100), SCROLL_OUTLINE);
+        assert_eq!(tables.pixel(263, 100), SCROLL_OUTLINE);
+
+        ppu.palette[20..24].copy_from_slice(&[0x00, 0x11, 0x12, 0x13]);
+        ppu.oam[..4].copy_from_slice(&[10, 1, 0x41, 20]);
+        assert!(oam_table(&ppu).contains("00   20  10 01   1   H\n"));
+        // flipped, so the top row's last pixel is the one with both bits
+        let sprites = oam(&ppu);
+        assert_eq!(sprites.pixel(4 + 7, 4), COLOURS[0x13]);
+        assert_eq!(sprites.pixel(4 + 6, 4), COLOURS[0x11]);
+    }
+}
@@ -0,0 +1,270 @@
+//! Keeps games running at the console's frame rate on the device's clock. Fast-forward runs
+//! frames N times as often (or as often as the device can), slow motion N times less often,
+//! and when the device falls behind frames are run without being drawn to catch up.
+
+use alloc::{format, string::String};
+
+/// 60.0988 Hz
+pub const NTSC_FRAME_NS: u64 = 16_639_267;
+/// 50 Hz
+pub const PAL_FRAME_NS: u64 = 20_000_000;
+
+/// Frames skipped in a row before giving up on catching up
+const MAX_SKIP: u32 = 4;
+/// The most the audio backlog can stretch or squeeze a frame, in parts per thousand
+const MAX_NUDGE: i64 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Region {
+    Ntsc,
+    Pal,
+}
+
+impl Region {
+    pub fn frame_ns(&self) -> u64 {
+        match self {
+            Region::Ntsc => NTSC_FRAME_NS,
+            Region::Pal => PAL_FRAME_NS,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Speed {
+    Normal,
+    /// N times as fast
+    Fast(u32),
+    /// As fast as the device goes
+    Uncapped,
+    /// N times as slow
+    Slow(u32),
+}
+
+impl Speed {
+    /// The order the hotkey goes through them
+    pub fn next(&self) -> Self {
+        match self {
+            Speed::Normal => Speed::Fast(2),
+            Speed::Fast(2) => Speed::Fast(4),
+            Speed::Fast(_) => Speed::Uncapped,
+            Speed::Uncapped => Speed::Slow(2),
+            Speed::Slow(2) => Speed::Slow(4),
+            Speed::Slow(_) => Speed::Normal,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Speed::Normal => String::from("1X"),
+            Speed::Fast(n) => format!("{}X", n),
+            Speed::Uncapped => String::from("MAX"),
+            Speed::Slow(n) => format!("1/{}X", n),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Pace {
+    /// Too early for the next frame, it's due in this many microseconds
+    Wait(u64),
+    /// Run a frame, and draw it if `present`
+    Run { present: bool },
+}
+
+pub struct Pacer {
+    pub region: Region,
+    speed: Speed,
+    /// When the next frame is due
+    next_ns: Option<u64>,
+    skipped: u32,
+    /// From the audio backlog, in parts per thousand of a frame
+    nudge: i64,
+}
+
+impl Pacer {
+    pub fn new(region: Region) -> Self {
+        Self {
+            region,
+            speed: Speed::Normal,
+            next_ns: None,
+            skipped: 0,
+            nudge: 0,
+        }
+    }
+
+    /// For the game being started, starting the new pace from the next poll
+    pub fn set_region(&mut self, region: Region) {
+        self.region = region;
+        self.next_ns = None;
+        self.skipped = 0;
+    }
+
+    /// Changes speed, starting the new pace from the next poll
+    pub fn set_speed(&mut self, speed: Speed) {
+        self.speed = speed;
+        self.next_ns = None;
+        self.skipped = 0;
+    }
+
+    /// The hotkey: normal, 2x, 4x, uncapped, 1/2x, 1/4x and round again
+    pub fn cycle_speed(&mut self) -> Speed {
+        self.set_speed(self.speed.next());
+        self.speed
+    }
+
+    /// Sound only makes sense at normal speed, fast-forward and slow motion should drop it
+    pub fn mute_audio(&self) -> bool {
+        self.speed != Speed::Normal
+    }
+
+    /// Tells the pacer how many samples are waiting to be played. Clocks drift, so frames are
+    /// stretched a little while the backlog is over `target` and squeezed while it's under,
+    /// instead of the audio running dry or piling up.
+    pub fn audio_backlog(&mut self, queued: usize, target: usize) {
+        if target == 0 {
+            return;
+        }
+        let off = (queued as i64 - target as i64) * MAX_NUDGE / target as i64;
+        self.nudge = off.clamp(-MAX_NUDGE, MAX_NUDGE);
+    }
+
+    /// How long a frame lasts at this speed, None when uncapped
+    pub fn frame_ns(&self) -> Option<u64> {
+        let base = self.region.frame_ns() as i64;
+        let base = (base + base * self.nudge / 1000) as u64;
+        match self.speed {
+            Speed::Normal => Some(base),
+            Speed::Fast(n) => Some(base / n.max(1) as u64),
+            Speed::Uncapped => None,
+            Speed::Slow(n) => Some(base * n.max(1) as u64),
+        }
+    }
+
+    /// Call whenever a frame could be run
+    pub fn poll(&mut self, now_us: u64) -> Pace {
+        let now = now_us * 1000;
+
+        let Some(frame) = self.frame_ns() else {
+            // drawing every frame would eat into the speed, so only draw at the normal rate
+            let present = self.next_ns.is_none_or(|next| now >= next);
+            if present {
+                self.next_ns = Some(now + self.region.frame_ns());
+            }
+            return Pace::Run { present };
+        };
+
+        let next = *self.next_ns.get_or_insert(now);
+        if now < next {
+            return Pace::Wait((next - now).div_ceil(1000));
+        }
+
+        self.next_ns = Some(next + frame);
+        if now >= next + frame {
+            // a frame or more behind
+            if self.skipped < MAX_SKIP {
+                self.skipped += 1;
+                return Pace::Run { present: false };
+            }
+            // too far behind to catch up, carry on from here
+            self.next_ns = Some(now + frame);
+        }
+        self.skipped = 0;
+        Pace::Run { present: true }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const FRAME_US: u64 = NTSC_FRAME_NS / 1000;
+
+    #[test]
+    fn test_normal() {
+        let mut pacer = Pacer::new(Region::Ntsc);
+        assert_eq!(pacer.poll(0), Pace::Run { present: true });
+        assert_eq!(pacer.poll(1000), Pace::Wait(FRAME_US + 1 - 1000));
+        assert_eq!(pacer.poll(FRAME_US + 1), Pace::Run { present: true });
+
+        // 10 seconds is 601 frames
+        let mut pacer = Pacer::new(Region::Ntsc);
+        let (mut now, mut frames) = (0, 0);
+        while now < 10_000_000 {
+            match pacer.poll(now) {
+                Pace::Run { present } => {
+                    assert!(present);
+                    frames += 1;
+                }
+                Pace::Wait(us) => now += us,
+            }
+        }
+        assert_eq!(frames, 601);
+    }
+
+    #[test]
+    fn test_region() {
+        let mut pacer = Pacer::new(Region::Ntsc);
+        pacer.poll(0);
+        // a PAL game was launched, its frames start from the next poll
+        pacer.set_region(Region::Pal);
+        assert_eq!(pacer.poll(1_000), Pace::Run { present: true });
+        assert_eq!(pacer.poll(2_000), Pace::Wait(19_000));
+    }
+
+    #[test]
+    fn test_frame_skip() {
+        let mut pacer = Pacer::new(Region::Pal);
+        pacer.poll(0);
+        // the device stalled, three late frames run without drawing and the fourth is on time
+        let now = 80_000;
+        assert_eq!(pacer.poll(now), Pace::Run { present: false });
+        assert_eq!(pacer.poll(now), Pace::Run { present: false });
+        assert_eq!(pacer.poll(now), Pace::Run { present: false });
+        assert_eq!(pacer.poll(now), Pace::Run { present: true });
+        assert!(matches!(pacer.poll(now), Pace::Wait(_)));
+
+        // stalled for a second, it gives up after a few
+        let now = 1_080_000;
+        for _ in 0..MAX_SKIP {
+            assert_eq!(pacer.poll(now), Pace::Run { present: false });
+        }
+        assert_eq!(pacer.poll(now), Pace::Run { present: true });
+        assert_eq!(pacer.poll(now), Pace::Wait(20_000));
+    }
+
+    #[test]
+    fn test_speeds() {
+        let mut pacer = Pacer::new(Region::Pal);
+        assert_eq!(pacer.cycle_speed(), Speed::Fast(2));
+        assert!(pacer.mute_audio());
+        pacer.poll(0);
+        assert_eq!(pacer.poll(9_000), Pace::Wait(1_000));
+
+        pacer.set_speed(Speed::Slow(2));
+        pacer.poll(0);
+        assert_eq!(pacer.poll(20_000), Pace::Wait(20_000));
+
+        // uncapped runs every poll but only draws at the normal rate
+        pacer.set_speed(Speed::Uncapped);
+        assert_eq!(pacer.poll(0), Pace::Run { present: true });
+        assert_eq!(pacer.poll(5_000), Pace::Run { present: false });
+        assert_eq!(pacer.poll(20_000), Pace::Run { present: true });
+
+        assert_eq!(Speed::Slow(4).next(), Speed::Normal);
+        assert_eq!(Speed::Slow(4).label(), "1/4X");
+    }
+
+    #[test]
+    fn test_audio_backlog() {
+        let mut pacer = Pacer::new(Region::Pal);
+        pacer.audio_backlog(2048, 1024);
+        assert_eq!(
+            pacer.frame_ns(),
+            Some(PAL_FRAME_NS + PAL_FRAME_NS * 5 / 1000)
+        );
+        pacer.audio_backlog(1024, 1024);
+        assert_eq!(pacer.frame_ns(), Some(PAL_FRAME_NS));
+        pacer.audio_backlog(768, 1024);
+        assert!(pacer.frame_ns().unwrap() < PAL_FRAME_NS);
+    }
+}
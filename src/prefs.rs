@@ -0,0 +1,423 @@
+//! The settings store: how the game list is shown, favourite games and what was played last,
+//! kept in a text file so they're still there after a restart.
+//!
+//! ```text
+//! egb-prefs 1
+//! view list
+//! sort played
+//! filter nes
+//! favourite roms/nes/Zelda.nes
+//! recent roms/nes/Zelda.nes
+//! recent Crate Push
+//! boot roms/gb/Tetris.gb
+//! ```
+//!
+//! Games are remembered by their path, or by title for the built in ones. `recent` goes from the
+//! last game played back, and `boot` lists the Game Boy games started through a boot ROM.
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::{
+    games::{Game, GameConsole},
+    gb::boot::BootMode,
+    library::Storage,
+    storage::{fat::Volume, BlockDevice},
+};
+
+/// Where the store is kept, in the simulator's working directory or at the top of the SD card
+pub const PREFS_PATH: &str = "egb.prefs";
+/// How many games the recently played shelf remembers
+pub const MAX_RECENT: usize = 8;
+
+/// The value `by` places along from `current` in `all`, wrapping around
+fn cycle<T: Clone + PartialEq>(all: &[T], current: &T, by: i32) -> T {
+    let i = all.iter().position(|v| v == current).unwrap_or(0) as i32;
+    all[(i + by).rem_euclid(all.len() as i32) as usize].clone()
+}
+
+/// How the games screen shows the library
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum View {
+    #[default]
+    Carousel,
+    List,
+}
+
+impl View {
+    const ALL: [View; 2] = [View::Carousel, View::List];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            View::Carousel => "carousel",
+            View::List => "list",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            View::Carousel => "Carousel",
+            View::List => "List",
+        }
+    }
+
+    pub fn cycle(&self, by: i32) -> View {
+        cycle(&View::ALL, self, by)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Sort {
+    #[default]
+    Title,
+    /// Grouped by console, then by title
+    Console,
+    /// Most recently played first, then games that haven't been played by title
+    Played,
+}
+
+impl Sort {
+    const ALL: [Sort; 3] = [Sort::Title, Sort::Console, Sort::Played];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Sort::Title => "title",
+            Sort::Console => "console",
+            Sort::Played => "played",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Sort::Title => "Title",
+            Sort::Console => "Console",
+            Sort::Played => "Last played",
+        }
+    }
+
+    pub fn cycle(&self, by: i32) -> Sort {
+        cycle(&Sort::ALL, self, by)
+    }
+}
+
+/// Which games are shown
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Filter {
+    #[default]
+    All,
+    Favourites,
+    Console(GameConsole),
+}
+
+impl Filter {
+    fn all() -> Vec<Filter> {
+        let mut all = Vec::from([Filter::All, Filter::Favourites]);
+        all.extend(GameConsole::ALL.into_iter().map(Filter::Console));
+        all
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Filter::All => "all".to_string(),
+            Filter::Favourites => "favourites".to_string(),
+            Filter::Console(console) => console.name().to_lowercase(),
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Filter::All => "All",
+            Filter::Favourites => "Favourites",
+            Filter::Console(console) => console.name(),
+        }
+    }
+
+    pub fn cycle(&self, by: i32) -> Filter {
+        cycle(&Filter::all(), self, by)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Prefs {
+    pub view: View,
+    pub sort: Sort,
+    pub filter: Filter,
+    favourites: Vec<String>,
+    /// Keys of the games played last, the most recent first
+    recent: Vec<String>,
+    /// Keys of the games that run the boot ROM, the rest skip it
+    boot_rom: Vec<String>,
+}
+
+impl Prefs {
+    pub fn is_favourite(&self, game: &Game) -> bool {
+        self.favourites.iter().any(|key| key == game.key())
+    }
+
+    pub fn toggle_favourite(&mut self, game: &Game) {
+        if self.is_favourite(game) {
+            self.favourites.retain(|key| key != game.key());
+        } else {
+            self.favourites.push(game.key().to_string());
+        }
+    }
+
+    pub fn boot(&self, game: &Game) -> BootMode {
+        if self.boot_rom.iter().any(|key| key == game.key()) {
+            BootMode::BootRom
+        } else {
+            BootMode::Skip
+        }
+    }
+
+    pub fn toggle_boot(&mut self, game: &Game) {
+        match self.boot(game) {
+            BootMode::BootRom => self.boot_rom.retain(|key| key != game.key()),
+            BootMode::Skip => self.boot_rom.push(game.key().to_string()),
+        }
+    }
+
+    /// Puts a game at the front of the recently played shelf
+    pub fn played(&mut self, game: &Game) {
+        self.recent.retain(|key| key != game.key());
+        self.recent.insert(0, game.key().to_string());
+        self.recent.truncate(MAX_RECENT);
+    }
+
+    /// How many other games have been played since this one, None if it hasn't been lately
+    pub fn last_played(&self, game: &Game) -> Option<usize> {
+        self.recent.iter().position(|key| key == game.key())
+    }
+
+    /// The recently played games still in `games`, as indices into it, most recent first
+    pub fn recent(&self, games: &[Game]) -> Vec<usize> {
+        self.recent
+            .iter()
+            .filter_map(|key| games.iter().position(|game| game.key() == key))
+            .filter(|&i| self.shows(&games[i]))
+            .collect()
+    }
+
+    pub fn shows(&self, game: &Game) -> bool {
+        match &self.filter {
+            Filter::All => true,
+            Filter::Favourites => self.is_favourite(game),
+            Filter::Console(console) => game.console == *console,
+        }
+    }
+
+    /// The games that pass the filter, as indices into `games`, in the chosen order
+    pub fn order(&self, games: &[Game]) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..games.len())
+            .filter(|&i| self.shows(&games[i]))
+            .collect();
+        let console = |game: &Game| GameConsole::ALL.iter().position(|c| *c == game.console);
+
+        order.sort_by_cached_key(|&i| {
+            let game = &games[i];
+            let group = match self.sort {
+                Sort::Title => 0,
+                Sort::Console => console(game).unwrap_or(0),
+                Sort::Played => self.last_played(game).unwrap_or(MAX_RECENT),
+            };
+            (group, game.title.to_lowercase())
+        });
+        order
+    }
+
+    pub fn parse(src: &str) -> Result<Prefs, String> {
+        let mut lines = src.lines().map(str::trim).filter(|line| !line.is_empty());
+        match lines.next() {
+            Some("egb-prefs 1") => {}
+            _ => return Err(String::from("Not an egb settings file")),
+        }
+
+        let mut prefs = Prefs::default();
+        for line in lines {
+            let (key, value) = line.split_once(' ').unwrap_or((line, ""));
+            let value = value.trim();
+            match key {
+                "view" => {
+                    prefs.view = *View::ALL
+                        .iter()
+                        .find(|view| view.name() == value)
+                        .ok_or_else(|| format!("Unknown view {}", value))?;
+                }
+                "sort" => {
+                    prefs.sort = *Sort::ALL
+                        .iter()
+                        .find(|sort| sort.name() == value)
+                        .ok_or_else(|| format!("Unknown sort {}", value))?;
+                }
+                "filter" => {
+                    prefs.filter = Filter::all()
+                        .into_iter()
+                        .find(|filter| filter.name() == value)
+                        .ok_or_else(|| format!("Unknown filter {}", value))?;
+                }
+                "favourite" => prefs.favourites.push(value.to_string()),
+                "recent" if prefs.recent.len() < MAX_RECENT => prefs.recent.push(value.to_string()),
+                "recent" => {}
+                "boot" => prefs.boot_rom.push(value.to_string()),
+                // from a newer version
+                _ => {}
+            }
+        }
+        Ok(prefs)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!(
+            "egb-prefs 1\nview {}\nsort {}\nfilter {}\n",
+            self.view.name(),
+            self.sort.name(),
+            self.filter.name()
+        );
+        for key in &self.favourites {
+            text += &format!("favourite {}\n", key);
+        }
+        for key in &self.recent {
+            text += &format!("recent {}\n", key);
+        }
+        for key in &self.boot_rom {
+            text += &format!("boot {}\n", key);
+        }
+        text
+    }
+}
+
+/// Reads the settings store, on the simulator from a path on disk
+#[cfg(target_arch = "x86_64")]
+pub fn load_prefs(path: &str) -> Result<Prefs, String> {
+    let src = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    Prefs::parse(&src).map_err(|e| format!("{}: {}", path, e))
+}
+
+#[cfg(target_arch = "x86_64")]
+pub fn save_prefs(path: &str, prefs: &Prefs) -> Result<(), String> {
+    std::fs::write(path, prefs.to_text()).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Reads the store from the SD card
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub fn read_prefs(storage: &mut dyn Storage, path: &str) -> Result<Prefs, String> {
+    let data = storage.read(path)?;
+    let src = core::str::from_utf8(&data).map_err(|_| format!("{}: not text", path))?;
+    Prefs::parse(src).map_err(|e| format!("{}: {}", path, e))
+}
+
+/// Writes the store to the SD card, replacing the last one
+#[cfg_attr(target_arch = "x86_64", allow(dead_code))]
+pub fn write_prefs<D: BlockDevice>(
+    volume: &mut Volume<D>,
+    path: &str,
+    prefs: &Prefs,
+) -> Result<(), String> {
+    let mut file = volume.create(path)?;
+    volume.write(&mut file, prefs.to_text().as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{
+        games::RomHandle,
+        storage::{fat, image::DiskImage},
+    };
+
+    fn games() -> Vec<Game> {
+        let game = |title: &str, console, path: &str| {
+            Game::new(
+                title.to_string(),
+                console,
+                None,
+                Some(RomHandle::File(path.to_string())),
+            )
+        };
+        Vec::from([
+            game("Zelda", GameConsole::NES, "roms/zelda.nes"),
+            game("Metroid", GameConsole::NES, "roms/metroid.nes"),
+            game("Tetris", GameConsole::GameBoy, "roms/tetris.gb"),
+            game("Advance Wars", GameConsole::GameBoyAdvanced, "roms/aw.gba"),
+        ])
+    }
+
+    #[test]
+    fn test_order() {
+        let games = games();
+        let mut prefs = Prefs::default();
+        assert_eq!(prefs.order(&games), [3, 1, 2, 0]);
+
+        prefs.sort = Sort::Console;
+        assert_eq!(prefs.order(&games), [2, 3, 1, 0]);
+
+        prefs.played(&games[2]);
+        prefs.played(&games[0]);
+        prefs.sort = Sort::Played;
+        assert_eq!(prefs.order(&games), [0, 2, 3, 1]);
+        assert_eq!(prefs.recent(&games), [0, 2]);
+
+        prefs.filter = Filter::Console(GameConsole::NES);
+        assert_eq!(prefs.order(&games), [0, 1]);
+        assert_eq!(prefs.recent(&games), [0]);
+
+        prefs.filter = Filter::Favourites;
+        assert!(prefs.order(&games).is_empty());
+        prefs.toggle_favourite(&games[1]);
+        assert_eq!(prefs.order(&games), [1]);
+        prefs.toggle_favourite(&games[1]);
+        assert!(!prefs.is_favourite(&games[1]));
+    }
+
+    #[test]
+    fn test_text() {
+        let games = games();
+        let mut prefs = Prefs {
+            view: View::List,
+            sort: Sort::Played,
+            filter: Filter::Console(GameConsole::GameBoyColor),
+            ..Default::default()
+        };
+        prefs.toggle_favourite(&games[3]);
+        prefs.toggle_boot(&games[2]);
+        assert_eq!(prefs.boot(&games[2]), BootMode::BootRom);
+        assert_eq!(prefs.boot(&games[0]), BootMode::Skip);
+        for game in games.iter().cycle().take(MAX_RECENT + 3) {
+            prefs.played(game);
+        }
+
+        let text = prefs.to_text();
+        assert!(text.starts_with("egb-prefs 1\nview list\nsort played\nfilter gbc\n"));
+        assert_eq!(Prefs::parse(&text), Ok(prefs));
+
+        assert_eq!(Filter::All.cycle(-1), Filter::Console(GameConsole::Sprig));
+        assert!(Prefs::parse("view list").is_err());
+        assert!(Prefs::parse("egb-prefs 1\nsort colour").is_err());
+    }
+
+    #[test]
+    fn test_card() {
+        let path = std::env::temp_dir()
+            .join(format!("egb_prefs_{}.img", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let mut image = DiskImage::create(&path, 2048).unwrap();
+        fat::format(&mut image, "EGB").unwrap();
+        let mut volume = Volume::mount(image).unwrap();
+        assert!(read_prefs(&mut volume, PREFS_PATH).is_err());
+
+        let games = games();
+        let mut prefs = Prefs::default();
+        prefs.toggle_favourite(&games[0]);
+        write_prefs(&mut volume, PREFS_PATH, &prefs).unwrap();
+        // a shorter store over a longer one
+        prefs.toggle_favourite(&games[0]);
+        write_prefs(&mut volume, PREFS_PATH, &prefs).unwrap();
+        assert_eq!(read_prefs(&mut volume, PREFS_PATH), Ok(prefs));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,4 +1,5 @@
-use alloc::boxed::Box;
+use alloc::{boxed::Box, rc::Rc, vec::Vec};
+use core::{cell::RefCell, convert::Infallible};
 use cortex_m::delay::Delay;
 use embedded_graphics::{
     geometry::{Dimensions, Point},
@@ -22,6 +23,7 @@ use embedded_graphics::draw_target::DrawTarget;
 use embedded_hal::{
     digital::{InputPin, OutputPin},
     pwm::SetDutyCycle,
+    spi::{ErrorType, SpiBus},
 };
 
 // Provide an alias for our BSP so we can switch targets quickly.
@@ -34,52 +36,148 @@ use st7735_lcd::{Orientation, ST7735};
 use crate::{
     buffer::Buffer,
     device::Device,
-    emu::Emulator,
     events::Event,
-    games::GameConsole,
-    gui::{core::Gui, screen::Screen},
+    games::Game,
+    gui::screen::Screen,
     input::InputStatus,
-    nes::emu::NesEmulator,
+    library::Storage,
+    prefs::{self, Prefs},
+    session::{Screens, Session},
+    storage::{fat::Volume, sd::SdCard},
 };
 
 /// External high-speed crystal on the Raspberry Pi Pico board is 12 MHz. Adjust
 /// if your board has a different frequency
 const XTAL_FREQ_HZ: u32 = 12_000_000u32;
 
+/// Rewinding shares the heap with everything else, it rewinds less far (or not at all) when a
+/// state doesn't leave room
+const REWIND_BUDGET: usize = 8 * 1024;
+
+type Spi0 = rp2040_hal::Spi<
+    Enabled,
+    pac::SPI0,
+    (
+        Pin<Gpio19, FunctionSpi, PullDown>,
+        Pin<Gpio16, FunctionSpi, PullDown>,
+        Pin<Gpio18, FunctionSpi, PullDown>,
+    ),
+>;
+
 pub type Display = ST7735<
-    rp2040_hal::Spi<
-        Enabled,
-        pac::SPI0,
-        (
-            Pin<Gpio19, FunctionSpi, PullDown>,
-            Pin<Gpio16, FunctionSpi, PullDown>,
-            Pin<Gpio18, FunctionSpi, PullDown>,
-        ),
-    >,
+    DisplaySpi,
     Pin<rp2040_hal::gpio::bank0::Gpio22, FunctionSio<SioOutput>, PullDown>,
     Pin<rp2040_hal::gpio::bank0::Gpio26, FunctionSio<SioOutput>, PullDown>,
 >;
 
+/// The FAT filesystem on the SD card
+type Card = Volume<SdCard<CardSpi, Pin<Gpio21, FunctionSio<SioOutput>, PullDown>, hal::Timer>>;
+
+/// The display's end of the SPI bus it shares with the SD card, selecting it for each write
+pub struct DisplaySpi {
+    bus: Rc<RefCell<Spi0>>,
+    cs: Pin<Gpio20, FunctionSio<SioOutput>, PullDown>,
+}
+
+impl embedded_hal_0_2::blocking::spi::Write<u8> for DisplaySpi {
+    type Error = Infallible;
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+        let mut bus = self.bus.borrow_mut();
+        self.cs.set_low()?;
+        let result = embedded_hal_0_2::blocking::spi::Write::write(&mut *bus, words);
+        self.cs.set_high()?;
+        result
+    }
+}
+
+/// The SD card's end of the SPI bus, `SdCard` selects the card itself
+struct CardSpi {
+    bus: Rc<RefCell<Spi0>>,
+}
+
+impl ErrorType for CardSpi {
+    type Error = Infallible;
+}
+
+impl SpiBus for CardSpi {
+    fn read(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+        SpiBus::read(&mut *self.bus.borrow_mut(), words)
+    }
+
+    fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+        SpiBus::write(&mut *self.bus.borrow_mut(), words)
+    }
+
+    fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+        SpiBus::transfer(&mut *self.bus.borrow_mut(), read, write)
+    }
+
+    fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+        SpiBus::transfer_in_place(&mut *self.bus.borrow_mut(), words)
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        SpiBus::flush(&mut *self.bus.borrow_mut())
+    }
+}
+
 pub struct Sprig {
     clocks: ClocksManager,
     delay: Delay,
     led_l: Channel<Slice<Pwm6, FreeRunning>, A>,
     led_r: Channel<Slice<Pwm2, FreeRunning>, A>,
     lcd_backlight: Channel<Slice<Pwm0, FreeRunning>, B>,
-    display: Display,
     a: Pin<Gpio14, FunctionSio<SioInput>, PullUp>,
     b: Pin<Gpio15, FunctionSio<SioInput>, PullUp>,
     up: Pin<Gpio5, FunctionSio<SioInput>, PullUp>,
     down: Pin<Gpio7, FunctionSio<SioInput>, PullUp>,
     left: Pin<Gpio6, FunctionSio<SioInput>, PullUp>,
     right: Pin<Gpio8, FunctionSio<SioInput>, PullUp>,
-    gui: Option<Gui<Buffer>>,
+    select: Pin<Gpio13, FunctionSio<SioInput>, PullUp>,
+    timer: hal::Timer,
+    lcd: Lcd,
+    session: Session<Lcd>,
+    /// None without a card, or with one that couldn't be read
+    storage: Option<Card>,
+}
+
+/// The menus are drawn on a buffer that's sent to the display when it changes, games are drawn
+/// on the display itself
+struct Lcd {
+    display: Display,
     buf: Buffer,
-    nes_emu: Option<NesEmulator>,
 }
 
-impl Device<Display, Buffer> for Sprig {
-    fn init(screen: Box<dyn Screen<Buffer>>) -> Self {
+impl Lcd {
+    /// Sends the menus to the display if they've changed
+    fn flush(&mut self) {
+        if self.buf.dirty {
+            self.display
+                .fill_contiguous(&self.buf.bounding_box(), self.buf.data())
+                .unwrap();
+            self.buf.dirty = false;
+        }
+    }
+}
+
+impl Screens for Lcd {
+    type Gui = Buffer;
+    type Game = Display;
+
+    fn gui(&mut self) -> &mut Buffer {
+        &mut self.buf
+    }
+
+    fn game(&mut self) -> &mut Display {
+        &mut self.display
+    }
+}
+
+impl Sprig {
+    /// Brings up the hardware and the SD card without showing anything, so the library can be
+    /// read from the card before it's shown
+    pub fn start() -> Self {
         let mut pac = pac::Peripherals::take().unwrap();
         let core = pac::CorePeripherals::take().unwrap();
 
@@ -125,6 +223,8 @@ impl Device<Display, Buffer> for Sprig {
         let down = pins.gpio7.into_pull_up_input();
         let left = pins.gpio6.into_pull_up_input();
         let right = pins.gpio8.into_pull_up_input();
+        // J
+        let select = pins.gpio13.into_pull_up_input();
 
         let mut bclk = pins.gpio10.into_push_pull_output();
         bclk.set_low().unwrap();
@@ -136,17 +236,32 @@ impl Device<Display, Buffer> for Sprig {
         let dc = pins.gpio22.into_push_pull_output();
         let rst = pins.gpio26.into_push_pull_output();
 
-        // Exchange the uninitialised SPI driver for an initialised one
+        // Exchange the uninitialised SPI driver for an initialised one. The SD card has to be
+        // woken up at 400 kHz or less, the display gets 16 MHz after
         let spi = spi.init(
             &mut pac.RESETS,
             clocks.peripheral_clock.freq(),
-            16.MHz(),
+            400.kHz(),
             &embedded_hal::spi::MODE_0,
         );
+        let bus = Rc::new(RefCell::new(spi));
 
-        let mut disp = ST7735::new(spi, dc, rst, true, false, 160, 128);
+        // the display and the SD card share the bus, only one is selected at a time
         let mut disp_cs = pins.gpio20.into_push_pull_output();
-        disp_cs.set_low().unwrap();
+        disp_cs.set_high().unwrap();
+        let mut sd_cs = pins.gpio21.into_push_pull_output();
+        sd_cs.set_high().unwrap();
+
+        let timer = hal::Timer::new(pac.TIMER, &mut pac.RESETS, &clocks);
+        // without a card only the built in games are there
+        let storage = SdCard::new(CardSpi { bus: bus.clone() }, sd_cs, timer)
+            .and_then(Volume::mount)
+            .ok();
+        bus.borrow_mut()
+            .set_baudrate(clocks.peripheral_clock.freq(), 16.MHz());
+
+        let spi = DisplaySpi { bus, cs: disp_cs };
+        let mut disp = ST7735::new(spi, dc, rst, true, false, 160, 128);
 
         disp.init(&mut delay).unwrap();
         disp.set_orientation(&Orientation::Landscape).unwrap();
@@ -174,12 +289,11 @@ impl Device<Display, Buffer> for Sprig {
         led_r.output_to(pins.gpio4);
         led_r.set_duty_cycle(0).unwrap();
 
-        let mut buf = Buffer::new();
-
-        let gui = Some(Gui::new(screen, &mut buf).unwrap());
-        disp.fill_contiguous(&buf.bounding_box(), buf.data())
-            .unwrap();
-        buf.dirty = false;
+        let lcd = Lcd {
+            display: disp,
+            buf: Buffer::new(),
+        };
+        let session = Session::new(REWIND_BUDGET);
 
         //disp_cs.set_high().unwrap();
         //disp.set_offset(0, 25);
@@ -205,21 +319,46 @@ impl Device<Display, Buffer> for Sprig {
             led_l,
             led_r,
             lcd_backlight: lcd_led,
-            display: disp,
             a,
             b,
             up,
             down,
             left,
             right,
-            gui, //pwm: pwm_slices,
-            buf,
-            nes_emu: None,
+            select,
+            timer,
+            //pwm: pwm_slices,
+            lcd,
+            session,
+            storage,
         }
     }
 
+    /// The SD card, if there's one that could be read
+    pub fn storage(&mut self) -> Option<&mut dyn Storage> {
+        self.storage.as_mut().map(|card| card as &mut dyn Storage)
+    }
+
+    /// Puts a screen up, over the game if one's running
+    pub fn show(&mut self, screen: Box<dyn Screen<Buffer>>) {
+        self.session.show(screen, &mut self.lcd);
+        self.lcd.flush();
+    }
+}
+
+impl Device<Display, Buffer> for Sprig {
+    fn init(screen: Box<dyn Screen<Buffer>>) -> Self {
+        let mut sprig = Self::start();
+        sprig.show(screen);
+        sprig
+    }
+
+    fn set_library(&mut self, games: Vec<Game>, prefs: Prefs) {
+        self.session.set_library(games, prefs);
+    }
+
     fn display(&mut self) -> &mut Display {
-        &mut self.display
+        &mut self.lcd.display
     }
 
     fn set_backlight(&mut self, brightness: u16) {
@@ -242,6 +381,11 @@ impl Device<Display, Buffer> for Sprig {
         self.delay.delay_us(us);
     }
 
+    fn now_us(&mut self) -> u64 {
+        // the timer counts microseconds
+        self.timer.get_counter().ticks()
+    }
+
     fn update_input(&mut self, input: &mut InputStatus) -> InputStatus {
         let mut new = input.clone();
         new.update(
@@ -252,59 +396,32 @@ impl Device<Display, Buffer> for Sprig {
             self.a.is_low().unwrap(),
             self.b.is_low().unwrap(),
         );
+        new.select.update(self.select.is_low().unwrap());
 
         new
     }
 
     fn update(&mut self, input: &InputStatus) {
-        if self.gui.is_some() {
-            self.gui
-                .as_mut()
-                .unwrap()
-                .update(input, &mut self.buf)
-                .unwrap();
-
-            if self.buf.dirty {
-                self.display
-                    .fill_contiguous(&self.buf.bounding_box(), self.buf.data())
-                    .unwrap();
-                self.buf.dirty = false;
-            }
-
-            let events = self.gui.as_mut().unwrap().events();
-            for event in events {
-                match event {
-                    Event::BacklightBrightness(brightness) => self.set_backlight(brightness),
-                    Event::LedL(brightness) => self.set_led_r(brightness),
-                    Event::LedR(brightness) => self.set_led_r(brightness),
-                    Event::LaunchGame(console) => self.launch(console),
+        let now = self.now_us();
+        // the main loop comes back round in a few microseconds, so there's no need to wait
+        let storage = self.storage.as_mut().map(|card| card as &mut dyn Storage);
+        let update = self.session.update(&mut self.lcd, input, now, storage);
+        self.lcd.flush();
+
+        for event in update.events {
+            match event {
+                Event::BacklightBrightness(brightness) => self.set_backlight(brightness),
+                Event::LedL(brightness) => self.set_led_l(brightness),
+                Event::LedR(brightness) => self.set_led_r(brightness),
+                // without a card they only last until it's turned off, and there's nowhere to
+                // show an error
+                Event::SavePrefs(prefs) => {
+                    if let Some(card) = self.storage.as_mut() {
+                        let _ = prefs::write_prefs(card, prefs::PREFS_PATH, &prefs);
+                    }
                 }
+                _ => (),
             }
-        } else if self.nes_emu.is_some() {
-            self.nes_emu
-                .as_mut()
-                .unwrap()
-                .tick(&mut self.display)
-                .unwrap();
-
-            if self.buf.dirty {
-                self.display
-                    .fill_contiguous(&self.buf.bounding_box(), self.buf.data())
-                    .unwrap();
-                self.buf.dirty = false;
-            }
-        }
-        //self.window.update(&self.display);
-    }
-}
-
-impl Sprig {
-    fn launch(&mut self, console: GameConsole) {
-        if console == GameConsole::NES {
-            self.display.clear(Rgb565::BLACK).unwrap();
-            self.gui = None;
-            self.nes_emu = Some(NesEmulator::new(&mut self.display));
-            self.display.clear(Rgb565::BLUE).unwrap();
         }
     }
 }
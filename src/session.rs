@@ -0,0 +1,384 @@
+//! What the simulator and the Sprig have in common: the menus, the game being played and getting
+//! between them through the library and the pause menu, plus the debugging hotkeys. Devices
+//! feed it input and the time, and see to their own hardware.
+
+use alloc::{boxed::Box, vec::Vec};
+use core::fmt::Debug;
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::OriginDimensions,
+    pixelcolor::{Rgb565, RgbColor},
+};
+
+use crate::{
+    emu::Machine,
+    events::Event,
+    games::Game,
+    gui::{
+        core::Gui,
+        overlay::DebugOverlay,
+        screen::Screen,
+        screens::{
+            cheats::CheatsScreen, error::ErrorScreen, games::GamesScreen, memory::MemoryScreen,
+            pause::PauseScreen, search::RamSearchScreen, settings::Settings,
+        },
+    },
+    input::InputStatus,
+    library::Storage,
+    movie,
+    nes::{
+        emu::NesEmulator,
+        rewind::{Rewind, DEFAULT_INTERVAL},
+    },
+    pacer::{Pace, Pacer, Region},
+    prefs::Prefs,
+};
+
+/// Where a device draws, the menus and the games don't have to share a screen
+pub trait Screens {
+    /// What the menus are drawn on
+    type Gui: DrawTarget<Color = Rgb565, Error: Debug> + OriginDimensions + 'static;
+    /// What the games are drawn on
+    type Game: DrawTarget<Color = Rgb565, Error: Debug>;
+
+    fn gui(&mut self) -> &mut Self::Gui;
+    fn game(&mut self) -> &mut Self::Game;
+}
+
+/// What an update left for the device to do
+pub struct Update {
+    /// Events from the menus for the device's own hardware, and settings to write out
+    pub events: Vec<Event>,
+    /// What the pacer said about the game's next frame, None when there's no game running
+    pub pace: Option<Pace>,
+}
+
+pub struct Session<S: Screens> {
+    gui: Option<Gui<S::Gui>>,
+    nes_emu: Option<NesEmulator>,
+    /// Games for other consoles, NES games go in `nes_emu` for the debugging tools
+    machine: Option<Machine>,
+    /// The library, for going back to it from the pause menu
+    games: Vec<Game>,
+    prefs: Prefs,
+    /// The game that was launched last
+    playing: Option<Game>,
+    /// The pause menu's save state for the game being played
+    slot: Option<Vec<u8>>,
+    overlay: DebugOverlay,
+    rewind: Rewind,
+    pacer: Pacer,
+}
+
+impl<S: Screens> Session<S> {
+    /// Nothing is shown until `show`, `rewind_budget` is how many bytes of save states rewinding
+    /// can keep
+    pub fn new(rewind_budget: usize) -> Self {
+        Self {
+            gui: None,
+            nes_emu: None,
+            machine: None,
+            games: Vec::new(),
+            prefs: Prefs::default(),
+            playing: None,
+            slot: None,
+            overlay: DebugOverlay::new(),
+            rewind: Rewind::new(rewind_budget, DEFAULT_INTERVAL),
+            pacer: Pacer::new(Region::Ntsc),
+        }
+    }
+
+    /// The games and settings the library is shown with again after quitting a game
+    pub fn set_library(&mut self, games: Vec<Game>, prefs: Prefs) {
+        self.games = games;
+        self.prefs = prefs;
+    }
+
+    /// The NES game being played, for looking inside it
+    pub fn nes(&self) -> Option<&NesEmulator> {
+        self.nes_emu.as_ref()
+    }
+
+    /// Lends the NES game out to the debugger, nothing runs until `give_nes` hands it back
+    pub fn take_nes(&mut self) -> Option<NesEmulator> {
+        self.nes_emu.take()
+    }
+
+    pub fn give_nes(&mut self, nes: NesEmulator) {
+        self.nes_emu = Some(nes);
+    }
+
+    pub fn update(
+        &mut self,
+        screens: &mut S,
+        input: &InputStatus,
+        now: u64,
+        mut storage: Option<&mut dyn Storage>,
+    ) -> Update {
+        let mut update = Update {
+            events: Vec::new(),
+            pace: None,
+        };
+
+        if let Some(gui) = self.gui.as_mut() {
+            gui.update(input, now, screens.gui()).unwrap();
+
+            let events = gui.events();
+            for event in events {
+                let storage = storage
+                    .as_mut()
+                    .map(|storage| &mut **storage as &mut dyn Storage);
+                if let Some(event) = self.handle(event, screens, storage) {
+                    update.events.push(event);
+                }
+            }
+        } else if input.left.pressed && input.right.should_trigger()
+            || input.right.pressed && input.left.should_trigger()
+        {
+            // Left and Right together, Select+B is taken by the debug overlay
+            self.pause(screens);
+        } else if input.select.pressed && input.a.should_trigger() {
+            if let Some(nes) = self.nes_emu.take() {
+                self.show(Box::new(MemoryScreen::new(nes)), screens);
+            }
+        } else if input.select.pressed && input.up.should_trigger() {
+            if let Some(nes) = self.nes_emu.take() {
+                self.show(Box::new(CheatsScreen::new(nes)), screens);
+            }
+        } else if input.select.pressed && input.down.should_trigger() {
+            if let Some(nes) = self.nes_emu.take() {
+                self.show(Box::new(RamSearchScreen::new(nes)), screens);
+            }
+        } else if input.select.pressed && input.right.should_trigger() {
+            self.overlay.speed = self.pacer.cycle_speed();
+        } else if input.select.pressed && input.left.pressed {
+            if let Some(nes) = self.nes_emu.as_mut() {
+                if self.rewind.rewind(nes, now) {
+                    nes.draw(screens.game()).unwrap();
+                }
+            }
+        } else if let Some(nes) = self.nes_emu.as_mut() {
+            let pace = self.pacer.poll(now);
+            if let Pace::Run { present } = pace {
+                nes.set_buttons(movie::buttons(input));
+                let frame = nes.frame();
+                while nes.frame() == frame {
+                    self.overlay.record(nes.cpu());
+                    nes.step();
+                }
+                self.rewind.record(nes);
+                if present {
+                    nes.draw(screens.game()).unwrap();
+                }
+            }
+            update.pace = Some(pace);
+
+            self.overlay
+                .update(screens.game(), input, now, nes)
+                .unwrap();
+        } else if let Some(machine) = self.machine.as_mut() {
+            let pace = self.pacer.poll(now);
+            if let Pace::Run { present } = pace {
+                machine.frame(input);
+                if present {
+                    machine.present(screens.game()).unwrap();
+                }
+            }
+            update.pace = Some(pace);
+        }
+
+        update
+    }
+
+    /// Acts on an event from the menus, handing back the ones the device has to
+    fn handle(
+        &mut self,
+        event: Event,
+        screens: &mut S,
+        storage: Option<&mut dyn Storage>,
+    ) -> Option<Event> {
+        match event {
+            Event::LaunchGame(game) => self.launch(game, screens, storage),
+            Event::ResumeNes(nes) => self.resume(*nes, screens),
+            Event::ResumeGame(machine) => self.resume_game(*machine, screens),
+            Event::SaveState(state) => self.slot = Some(state),
+            Event::OpenSettings(pause) => {
+                let screen =
+                    Settings::new(self.games.clone(), self.prefs.clone(), self.playing_index())
+                        .with_pause(pause);
+                self.show(Box::new(screen), screens);
+            }
+            Event::QuitToLibrary => {
+                let screen = GamesScreen::with_game(
+                    self.games.clone(),
+                    self.prefs.clone(),
+                    self.playing_index(),
+                );
+                self.show(Box::new(screen), screens);
+            }
+            Event::SavePrefs(prefs) => {
+                self.prefs = prefs.clone();
+                return Some(Event::SavePrefs(prefs));
+            }
+            event => return Some(event),
+        }
+        None
+    }
+
+    /// Puts a screen up, over the game if one's running
+    pub fn show(&mut self, screen: Box<dyn Screen<S::Gui>>, screens: &mut S) {
+        self.gui = Some(Gui::new(screen, screens.gui()).unwrap());
+    }
+
+    fn resume(&mut self, nes: NesEmulator, screens: &mut S) {
+        screens.game().clear(Rgb565::BLACK).unwrap();
+        self.gui = None;
+        self.nes_emu = Some(nes);
+    }
+
+    fn resume_game(&mut self, mut machine: Machine, screens: &mut S) {
+        screens.game().clear(Rgb565::BLACK).unwrap();
+        self.gui = None;
+        machine.draw(screens.game()).unwrap();
+        match machine {
+            Machine::Nes(nes) => self.nes_emu = Some(*nes),
+            machine => self.machine = Some(machine),
+        }
+    }
+
+    /// Freezes the game under the pause menu
+    fn pause(&mut self, screens: &mut S) {
+        let machine = match (self.nes_emu.take(), self.machine.take()) {
+            (Some(nes), _) => Machine::Nes(Box::new(nes)),
+            (None, Some(machine)) => machine,
+            (None, None) => return,
+        };
+        let title = self
+            .playing
+            .as_ref()
+            .map_or("Paused", |game| game.get_title());
+        let screen = PauseScreen::new(machine, title, self.slot.clone());
+        self.show(Box::new(screen), screens);
+    }
+
+    /// Where the game being played is in the library
+    fn playing_index(&self) -> Option<usize> {
+        let playing = self.playing.as_ref()?;
+        self.games
+            .iter()
+            .position(|game| game.key() == playing.key())
+    }
+
+    fn launch(&mut self, game: Game, screens: &mut S, storage: Option<&mut dyn Storage>) {
+        match Machine::launch(&game, storage, self.prefs.boot(&game)) {
+            Ok(machine) => {
+                screens.game().clear(Rgb565::BLACK).unwrap();
+                self.gui = None;
+                self.rewind.clear();
+                self.slot = None;
+                self.pacer.set_region(machine.region());
+                self.playing = Some(game);
+                match machine {
+                    Machine::Nes(nes) => self.nes_emu = Some(*nes),
+                    machine => self.machine = Some(machine),
+                }
+            }
+            Err(e) => {
+                // stay in the library, behind the error
+                if let Some(gui) = self.gui.take() {
+                    let screen = ErrorScreen::new(game.get_title(), &e, gui.into_screen());
+                    self.show(Box::new(screen), screens);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::string::ToString;
+
+    use super::*;
+    use crate::{
+        buffer::Buffer,
+        games::{GameConsole, RomHandle},
+        nes::cpu::Mem,
+    };
+
+    impl Screens for Buffer {
+        type Gui = Buffer;
+        type Game = Buffer;
+
+        fn gui(&mut self) -> &mut Buffer {
+            self
+        }
+
+        fn game(&mut self) -> &mut Buffer {
+            self
+        }
+    }
+
+    #[test]
+    fn test_launch_and_pause() {
+        let nestest = Game::new(
+            "nestest".to_string(),
+            GameConsole::NES,
+            None,
+            Some(RomHandle::Builtin(include_bytes!("nestest.nes"))),
+        );
+        let art = Game::new_nes("Art", &[]);
+        let games = Vec::from([nestest.clone(), art.clone()]);
+
+        let mut buf = Buffer::new();
+        let screen = Box::new(GamesScreen::new(games.clone(), Prefs::default()));
+        let mut session = Session::new(64 * 1024);
+        session.show(screen, &mut buf);
+        session.set_library(games.clone(), Prefs::default());
+
+        // the error goes over the library
+        session.handle(Event::LaunchGame(art), &mut buf, None);
+        assert!(session.gui.is_some() && session.nes().is_none());
+
+        session.handle(Event::LaunchGame(nestest), &mut buf, None);
+        assert!(session.gui.is_none() && session.nes().is_some());
+        let update = session.update(&mut buf, &InputStatus::default(), 0, None);
+        assert_eq!(update.pace, Some(Pace::Run { present: true }));
+        assert_eq!(session.nes().unwrap().frame(), 1);
+
+        // Left and Right together
+        let mut input = InputStatus::default();
+        input.update(false, false, true, true, false, false);
+        let update = session.update(&mut buf, &input, 0, None);
+        assert_eq!(update.pace, None);
+        assert!(session.gui.is_some() && session.nes().is_none());
+
+        let mut prefs = Prefs::default();
+        prefs.toggle_favourite(&games[1]);
+        let event = session.handle(Event::SavePrefs(prefs.clone()), &mut buf, None);
+        assert!(matches!(event, Some(Event::SavePrefs(_))));
+        assert_eq!(session.prefs, prefs);
+        assert_eq!(session.playing_index(), Some(0));
+    }
+
+    #[test]
+    fn test_nes_input() {
+        let nestest = Game::new(
+            "nestest".to_string(),
+            GameConsole::NES,
+            None,
+            Some(RomHandle::Builtin(include_bytes!("nestest.nes"))),
+        );
+        let mut buf = Buffer::new();
+        let mut session = Session::new(64 * 1024);
+        session.handle(Event::LaunchGame(nestest), &mut buf, None);
+
+        let mut input = InputStatus::default();
+        input.update(false, false, false, false, true, false);
+        session.update(&mut buf, &input, 0, None);
+
+        // strobed, the first read is A
+        let mut nes = session.take_nes().unwrap();
+        nes.cpu_mut().mem_write(0x4016, 1);
+        assert_eq!(nes.cpu().mem_read(0x4016) & 1, 1);
+    }
+}
@@ -1,48 +1,75 @@
-use crate::emu::Emulator;
+use crate::device::Device;
 use crate::events::Event;
-use crate::games::GameConsole;
-use crate::gui::core::Gui;
+use crate::games::Game;
 use crate::gui::screen::Screen;
-use crate::nes::emu::NesEmulator;
-use embedded_graphics::draw_target::DrawTarget;
-use embedded_graphics::prelude::RgbColor;
+use crate::library::Storage;
+use crate::nes::debugger::{self, Breakpoint, Debugger};
+use crate::nes::gdbstub::{self, GdbStub};
+use crate::nes::viewer;
+use crate::pacer::Pace;
+use crate::prefs::{self, Prefs};
+use crate::session::{Screens, Session};
+use crate::window::Windows;
 use embedded_graphics::{geometry::Size, pixelcolor::Rgb565};
 use embedded_graphics_simulator::sdl2::Keycode;
-use embedded_graphics_simulator::SimulatorEvent;
-use embedded_graphics_simulator::{
-    BinaryColorTheme, OutputSettings, OutputSettingsBuilder, SimulatorDisplay, Window,
-};
-use std::boxed::Box;
+use embedded_graphics_simulator::{SimulatorDisplay, SimulatorEvent};
+use std::{boxed::Box, vec::Vec};
 type Display = SimulatorDisplay<Rgb565>;
 
+/// Plenty for minutes of rewinding
+const REWIND_BUDGET: usize = 8 * 1024 * 1024;
+
 use crate::input::InputStatus;
-use crate::Device;
 use core::time::Duration;
-use std::{println, thread};
+use std::{println, thread, time::Instant};
 
 pub struct Simulator {
     display: Display,
-    window: Window,
-    gui: Option<Gui<Display>>,
-    nes_emu: Option<NesEmulator>,
+    window: Windows,
+    session: Session<Display>,
+    /// Where the library came from, games are read from it when they're launched
+    storage: Option<Box<dyn Storage>>,
+    started: Instant,
+    /// The palette the PPU viewers draw the pattern tables in
+    ppu_palette: u8,
+    /// Kept for the next time the debugger's opened, watchpoints stay on the bus
+    breakpoints: Vec<Breakpoint>,
+}
+
+/// The menus and games share the window
+impl Screens for Display {
+    type Gui = Display;
+    type Game = Display;
+
+    fn gui(&mut self) -> &mut Display {
+        self
+    }
+
+    fn game(&mut self) -> &mut Display {
+        self
+    }
 }
 
 impl Device<Display, Display> for Simulator {
     fn init(screen: Box<dyn Screen<Display>>) -> Self {
         let mut display = SimulatorDisplay::<Rgb565>::new(Size::new(160, 128));
-        let settings = OutputSettingsBuilder::new()
-            .theme(BinaryColorTheme::Default)
-            .pixel_spacing(0)
-            .build();
-        let mut window = Window::new("EGB Simulator", &settings);
-        window.update(&display);
+        let mut window = Windows::new("EGB Simulator", 160, 128).unwrap();
+        window.update(&display).unwrap();
+        let mut session = Session::new(REWIND_BUDGET);
+        session.show(screen, &mut display);
         Self {
-            gui: Some(Gui::new(screen, &mut display).unwrap()),
+            session,
             display,
             window,
-            nes_emu: None,
+            storage: None,
+            started: Instant::now(),
+            ppu_palette: 0,
+            breakpoints: Vec::new(),
         }
     }
+    fn set_library(&mut self, games: Vec<Game>, prefs: Prefs) {
+        self.session.set_library(games, prefs);
+    }
     fn display(&mut self) -> &mut Display {
         &mut self.display
     }
@@ -61,9 +88,15 @@ impl Device<Display, Display> for Simulator {
     fn delay_us(&mut self, us: u32) {
         thread::sleep(Duration::from_micros(us as u64));
     }
+    fn now_us(&mut self) -> u64 {
+        self.started.elapsed().as_micros() as u64
+    }
 
     fn update_input(&mut self, input: &mut InputStatus) -> InputStatus {
         let mut new = input.clone();
+        let mut toggle_viewers = false;
+        let mut debug = false;
+        let mut gdb = false;
 
         for event in self.window.events() {
             match event {
@@ -75,6 +108,14 @@ impl Device<Display, Display> for Simulator {
                     Keycode::D => input.right.pressed = true,
                     Keycode::E => input.a.pressed = true,
                     Keycode::R => input.b.pressed = true,
+                    Keycode::Q => input.select.pressed = true,
+                    Keycode::P => toggle_viewers = true,
+                    Keycode::B => debug = true,
+                    Keycode::G => gdb = true,
+                    Keycode::O => {
+                        self.ppu_palette = (self.ppu_palette + 1) % 8;
+                        println!("PPU viewer palette {}", self.ppu_palette);
+                    }
                     _ => {}
                 },
                 SimulatorEvent::KeyUp { keycode, .. } => match keycode {
@@ -84,6 +125,7 @@ impl Device<Display, Display> for Simulator {
                     Keycode::D => input.right.pressed = false,
                     Keycode::E => input.a.pressed = false,
                     Keycode::R => input.b.pressed = false,
+                    Keycode::Q => input.select.pressed = false,
                     _ => {}
                 },
                 _ => {}
@@ -97,48 +139,128 @@ impl Device<Display, Display> for Simulator {
             input.a.pressed,
             input.b.pressed,
         );
+        new.select.update(input.select.pressed);
+        if toggle_viewers {
+            self.toggle_viewers();
+        }
+        if debug {
+            self.debug();
+        }
+        if gdb {
+            self.gdb();
+        }
 
         new
     }
 
     fn update(&mut self, input: &InputStatus) {
-        //self.window.update(&self.display);
-        if self.gui.is_some() {
-            self.gui
-                .as_mut()
-                .unwrap()
-                .update(input, &mut self.display)
-                .unwrap();
-
-            let events = self.gui.as_mut().unwrap().events();
-            for event in events {
-                match event {
-                    Event::LaunchGame(console) => self.launch(console),
-                    _ => (),
+        let now = self.now_us();
+        let storage = self
+            .storage
+            .as_mut()
+            .map(|storage| storage.as_mut() as &mut dyn Storage);
+        let update = self.session.update(&mut self.display, input, now, storage);
+
+        for event in update.events {
+            match event {
+                Event::BacklightBrightness(brightness) => self.set_backlight(brightness),
+                Event::LedL(brightness) => self.set_led_l(brightness),
+                Event::LedR(brightness) => self.set_led_r(brightness),
+                Event::SavePrefs(prefs) => {
+                    if let Err(e) = prefs::save_prefs(prefs::PREFS_PATH, &prefs) {
+                        println!("{}", e);
+                    }
                 }
+                _ => (),
             }
-        } else if self.nes_emu.is_some() {
-            self.nes_emu
-                .as_mut()
-                .unwrap()
-                .tick(&mut self.display)
-                .unwrap();
+        }
+
+        match update.pace {
+            Some(Pace::Run { present: true }) => {
+                self.update_window();
+                if self.window.viewers_open() {
+                    self.show_viewers();
+                }
+            }
+            Some(Pace::Wait(us)) => thread::sleep(Duration::from_micros(us.min(1000))),
+            _ => {}
         }
     }
 }
 
 impl Simulator {
+    pub fn set_storage(&mut self, storage: Box<dyn Storage>) {
+        self.storage = Some(storage);
+    }
+
     pub fn update_window(&mut self) {
-        self.window.update(&self.display);
+        if let Err(e) = self.window.update(&self.display) {
+            println!("{}", e);
+        }
+    }
+
+    /// Opens the PPU viewers next to the game, or closes them if they're open
+    fn toggle_viewers(&mut self) {
+        if self.window.viewers_open() {
+            self.window.close_viewers();
+        } else if self.session.nes().is_some() {
+            self.show_viewers();
+        } else {
+            println!("the PPU viewers need a NES game running");
+        }
     }
 
-    pub fn show_static(&mut self) {
-        self.window.show_static(&self.display);
+    /// Redraws the PPU viewers, they follow the game a frame at a time
+    fn show_viewers(&mut self) {
+        let Some(nes) = self.session.nes() else {
+            return;
+        };
+        let ppu = &nes.cpu().bus.ppu;
+        let views = [
+            (
+                "Pattern tables",
+                viewer::pattern_tables(ppu, self.ppu_palette),
+            ),
+            ("Nametables", viewer::nametables(ppu)),
+            ("Palettes", viewer::palettes(ppu)),
+            ("OAM", viewer::oam(ppu)),
+        ];
+        for (title, image) in views {
+            if let Err(e) = self.window.show(title, &image) {
+                println!("{}", e);
+            }
+        }
     }
 
-    fn launch(&mut self, console: GameConsole) {
-        self.display.clear(Rgb565::BLACK);
-        self.gui = None;
-        self.nes_emu = Some(NesEmulator::new(&mut self.display));
+    /// Opens the debugger's REPL on the terminal with the NES game, the window waits until it's
+    /// quit
+    fn debug(&mut self) {
+        let Some(nes) = self.session.take_nes() else {
+            println!("the debugger needs a NES game running");
+            return;
+        };
+        let mut debugger = Debugger::new(nes);
+        debugger.breakpoints = core::mem::take(&mut self.breakpoints);
+        debugger::repl(&mut debugger);
+        self.breakpoints = debugger.breakpoints;
+        self.session.give_nes(debugger.emu);
+        println!("back to the game");
+    }
+
+    /// Lends the NES game to GDB, like the debugger the window waits until GDB detaches
+    fn gdb(&mut self) {
+        let Some(nes) = self.session.take_nes() else {
+            println!("gdb needs a NES game running");
+            return;
+        };
+        let mut debugger = Debugger::new(nes);
+        debugger.breakpoints = core::mem::take(&mut self.breakpoints);
+        let mut stub = GdbStub::new(debugger);
+        if let Err(e) = gdbstub::serve_once(&mut stub, gdbstub::DEFAULT_PORT) {
+            println!("{}", e);
+        }
+        self.breakpoints = stub.debugger.breakpoints;
+        self.session.give_nes(stub.debugger.emu);
+        println!("back to the game");
     }
 }
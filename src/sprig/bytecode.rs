@@ -0,0 +1,193 @@
+//! Input handlers are compiled to a small bytecode when a game is loaded, so nothing has to be
+//! parsed while the game is running. Every instruction is an opcode byte followed by its operands.
+
+use alloc::{format, string::String, vec::Vec};
+
+use super::{game::SprigGame, world::World};
+
+/// `MOVE kind dir`, moves every `kind` sprite one tile
+pub const MOVE: u8 = 0x01;
+/// `RESET`, reloads the current map
+pub const RESET: u8 = 0x02;
+/// `NEXT`, goes to the next map
+pub const NEXT: u8 = 0x03;
+/// `LEVEL n`
+pub const LEVEL: u8 = 0x04;
+/// `REPLACE kind with`, turns every `kind` sprite into a `with`
+pub const REPLACE: u8 = 0x05;
+/// `REMOVE kind`
+pub const REMOVE: u8 = 0x06;
+/// `TEXT string`, shows a string from the string table
+pub const TEXT: u8 = 0x07;
+/// `CLEAR_TEXT`
+pub const CLEAR_TEXT: u8 = 0x08;
+/// `IF_ON a b len_lo len_hi`, skips `len` bytes unless an `a` shares a tile with a `b`
+pub const IF_ON: u8 = 0x09;
+/// `IF_ANY kind len_lo len_hi`, skips `len` bytes unless there's a `kind` on the map
+pub const IF_ANY: u8 = 0x0A;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    pub fn from_byte(byte: u8) -> Direction {
+        match byte {
+            0 => Direction::Up,
+            1 => Direction::Down,
+            2 => Direction::Left,
+            _ => Direction::Right,
+        }
+    }
+
+    pub fn offset(&self) -> (i32, i32) {
+        match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        }
+    }
+}
+
+fn operand(word: Option<&str>, line: usize) -> Result<u8, String> {
+    match word.map(str::as_bytes) {
+        Some([c]) => Ok(*c),
+        _ => Err(format!("line {}: expected a single character sprite", line)),
+    }
+}
+
+/// Compiles the body of an `on`/`after` block. Strings used by `text` go into `strings`.
+pub fn assemble(lines: &[(usize, &str)], strings: &mut Vec<String>) -> Result<Vec<u8>, String> {
+    let mut code = Vec::new();
+    // where the length of each open `if` needs patching
+    let mut open = Vec::new();
+
+    for (n, line) in lines {
+        let n = *n;
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let mut words = rest.split_whitespace();
+
+        match command {
+            "move" => {
+                let kind = operand(words.next(), n)?;
+                let dir = match words.next() {
+                    Some("up") => 0,
+                    Some("down") => 1,
+                    Some("left") => 2,
+                    Some("right") => 3,
+                    _ => return Err(format!("line {}: expected up, down, left or right", n)),
+                };
+                code.extend([MOVE, kind, dir]);
+            }
+            "reset" => code.push(RESET),
+            "next" => code.push(NEXT),
+            "level" => {
+                let level = rest
+                    .trim()
+                    .parse::<u8>()
+                    .map_err(|_| format!("line {}: expected a level number", n))?;
+                code.extend([LEVEL, level]);
+            }
+            "replace" => code.extend([
+                REPLACE,
+                operand(words.next(), n)?,
+                operand(words.next(), n)?,
+            ]),
+            "remove" => code.extend([REMOVE, operand(words.next(), n)?]),
+            "text" => {
+                if strings.len() > u8::MAX as usize {
+                    return Err(format!("line {}: too many strings", n));
+                }
+                code.extend([TEXT, strings.len() as u8]);
+                strings.push(String::from(rest.trim()));
+            }
+            "cleartext" => code.push(CLEAR_TEXT),
+            "if" => {
+                let a = operand(words.next(), n)?;
+                match (words.next(), words.next()) {
+                    (Some("on"), b) => code.extend([IF_ON, a, operand(b, n)?]),
+                    (None, _) => code.extend([IF_ANY, a]),
+                    _ => return Err(format!("line {}: expected `if a` or `if a on b`", n)),
+                }
+                open.push(code.len());
+                code.extend([0, 0]);
+            }
+            "end" => {
+                let patch = open
+                    .pop()
+                    .ok_or(format!("line {}: `end` without an `if`", n))?;
+                let len = code.len() - patch - 2;
+                if len > u16::MAX as usize {
+                    return Err(format!("line {}: `if` block is too long", n));
+                }
+                code[patch..patch + 2].copy_from_slice(&(len as u16).to_le_bytes());
+            }
+            _ => return Err(format!("line {}: unknown instruction '{}'", n, command)),
+        }
+    }
+
+    if !open.is_empty() {
+        return Err(String::from("`if` without an `end`"));
+    }
+    Ok(code)
+}
+
+/// Runs a compiled handler. The code was checked when it was assembled, so running off the
+/// end just stops.
+pub fn execute(code: &[u8], world: &mut World, game: &SprigGame) {
+    let mut pc = 0;
+    let arg = |pc: usize| code.get(pc).copied().unwrap_or(0);
+
+    while pc < code.len() {
+        let op = code[pc];
+        pc += 1;
+
+        match op {
+            MOVE => {
+                world.move_kind(game, arg(pc), Direction::from_byte(arg(pc + 1)));
+                pc += 2;
+            }
+            RESET => world.load(game, world.level),
+            NEXT => world.next_level(game),
+            LEVEL => {
+                world.load(game, arg(pc) as usize);
+                pc += 1;
+            }
+            REPLACE => {
+                world.replace(arg(pc), arg(pc + 1));
+                pc += 2;
+            }
+            REMOVE => {
+                world.remove(arg(pc));
+                pc += 1;
+            }
+            TEXT => {
+                world.text = game.strings.get(arg(pc) as usize).cloned();
+                pc += 1;
+            }
+            CLEAR_TEXT => world.text = None,
+            IF_ON | IF_ANY => {
+                let taken = if op == IF_ON {
+                    let taken = world.on(arg(pc), arg(pc + 1));
+                    pc += 2;
+                    taken
+                } else {
+                    let taken = world.any(arg(pc));
+                    pc += 1;
+                    taken
+                };
+                let len = u16::from_le_bytes([arg(pc), arg(pc + 1)]) as usize;
+                pc += 2;
+                if !taken {
+                    pc += len;
+                }
+            }
+            _ => return,
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+use embedded_graphics::pixelcolor::Rgb565;
+
+use super::bytecode;
+
+pub const TILE: usize = 16;
+/// Empty cells in maps
+pub const EMPTY: u8 = b'.';
+
+/// The Sprig buttons, the left pad is WASD and the right pad IJKL
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Key {
+    W,
+    A,
+    S,
+    D,
+    I,
+    J,
+    K,
+    L,
+}
+
+impl Key {
+    pub const ALL: [Key; 8] = [
+        Key::W,
+        Key::A,
+        Key::S,
+        Key::D,
+        Key::I,
+        Key::J,
+        Key::K,
+        Key::L,
+    ];
+
+    fn from_name(name: &str) -> Option<Key> {
+        Key::ALL
+            .into_iter()
+            .find(|key| key.name() == name.to_ascii_lowercase())
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Key::W => "w",
+            Key::A => "a",
+            Key::S => "s",
+            Key::D => "d",
+            Key::I => "i",
+            Key::J => "j",
+            Key::K => "k",
+            Key::L => "l",
+        }
+    }
+}
+
+/// The Sprig's 16 colour palette, `.` is transparent
+/// https://github.com/hackclub/sprig/blob/main/engine/src/base/palette.ts
+pub fn palette(c: u8) -> Result<Option<Rgb565>, String> {
+    let (r, g, b) = match c {
+        b'.' => return Ok(None),
+        b'0' => (0, 0, 0),
+        b'L' => (73, 80, 87),
+        b'1' => (145, 151, 156),
+        b'2' => (248, 249, 250),
+        b'3' => (235, 44, 71),
+        b'C' => (139, 65, 46),
+        b'7' => (25, 177, 248),
+        b'5' => (19, 21, 224),
+        b'6' => (254, 230, 16),
+        b'F' => (149, 140, 50),
+        b'4' => (45, 225, 62),
+        b'D' => (29, 148, 16),
+        b'8' => (245, 109, 187),
+        b'H' => (170, 58, 197),
+        b'9' => (245, 113, 23),
+        _ => return Err(format!("unknown colour '{}'", c as char)),
+    };
+    Ok(Some(Rgb565::new(r >> 3, g >> 2, b >> 3)))
+}
+
+/// A 16x16 sprite from the legend
+#[derive(Clone)]
+pub struct Bitmap {
+    pub kind: u8,
+    pub pixels: [Option<Rgb565>; TILE * TILE],
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WinCondition {
+    /// Every tile with the first sprite also has the second
+    All(u8, u8),
+    /// At least one tile has both sprites
+    Some(u8, u8),
+    /// The sprite isn't anywhere on the map
+    No(u8),
+}
+
+#[derive(Debug, Clone)]
+pub struct Map {
+    pub width: usize,
+    pub height: usize,
+    pub cells: Vec<u8>,
+}
+
+/// A Sprig-style tile game: sprites, levels and what the buttons do. Games are written in a
+/// small text format, see `assets/games/crate_push.sprig` for an example.
+pub struct SprigGame {
+    pub title: String,
+    /// Sprites in draw order, earlier ones are drawn on top
+    pub legend: Vec<Bitmap>,
+    pub background: Option<u8>,
+    pub solids: Vec<u8>,
+    /// Which sprites each sprite can push
+    pub pushables: Vec<(u8, Vec<u8>)>,
+    pub levels: Vec<Map>,
+    pub win: Vec<WinCondition>,
+    /// Bytecode to run for each `Key`
+    pub handlers: [Vec<u8>; 8],
+    /// Bytecode to run after every handler
+    pub after_input: Vec<u8>,
+    pub strings: Vec<String>,
+}
+
+fn kind(word: Option<&str>, line: usize) -> Result<u8, String> {
+    match word.map(str::as_bytes) {
+        Some([c]) => Ok(*c),
+        _ => Err(format!("line {}: expected a single character sprite", line)),
+    }
+}
+
+impl SprigGame {
+    pub fn parse(src: &str) -> Result<SprigGame, String> {
+        let mut game = SprigGame {
+            title: "Untitled".to_string(),
+            legend: vec![],
+            background: None,
+            solids: vec![],
+            pushables: vec![],
+            levels: vec![],
+            win: vec![],
+            handlers: Default::default(),
+            after_input: vec![],
+            strings: vec![],
+        };
+
+        let mut lines = src
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'));
+
+        while let Some((n, line)) = lines.next() {
+            let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+            let mut words = rest.split_whitespace();
+
+            match command {
+                "title" => game.title = rest.trim().to_string(),
+                "background" => game.background = Some(kind(words.next(), n)?),
+                "solids" => {
+                    for word in words {
+                        game.solids.push(kind(Some(word), n)?);
+                    }
+                }
+                "push" => {
+                    let pusher = kind(words.next(), n)?;
+                    let pushed = words
+                        .map(|word| kind(Some(word), n))
+                        .collect::<Result<_, _>>()?;
+                    game.pushables.push((pusher, pushed));
+                }
+                "win" => {
+                    let condition = match words.next() {
+                        Some("all") => {
+                            WinCondition::All(kind(words.next(), n)?, kind(words.next(), n)?)
+                        }
+                        Some("some") => {
+                            WinCondition::Some(kind(words.next(), n)?, kind(words.next(), n)?)
+                        }
+                        Some("no") => WinCondition::No(kind(words.next(), n)?),
+                        _ => return Err(format!("line {}: expected all, some or no", n)),
+                    };
+                    game.win.push(condition);
+                }
+                "bitmap" => {
+                    let kind = kind(words.next(), n)?;
+                    let mut pixels = [None; TILE * TILE];
+                    for row in 0..TILE {
+                        let (n, line) = lines
+                            .next()
+                            .ok_or(format!("line {}: bitmap is missing rows", n))?;
+                        if line.len() != TILE {
+                            return Err(format!("line {}: bitmap rows are 16 wide", n));
+                        }
+                        for (col, c) in line.bytes().enumerate() {
+                            pixels[row * TILE + col] =
+                                palette(c).map_err(|e| format!("line {}: {}", n, e))?;
+                        }
+                    }
+                    match lines.next() {
+                        Some((_, "end")) => {}
+                        _ => return Err(format!("line {}: bitmap has no end", n)),
+                    }
+                    game.legend.push(Bitmap { kind, pixels });
+                }
+                "map" => {
+                    let mut map = Map {
+                        width: 0,
+                        height: 0,
+                        cells: vec![],
+                    };
+                    loop {
+                        let (n, line) =
+                            lines.next().ok_or(format!("line {}: map has no end", n))?;
+                        if line == "end" {
+                            break;
+                        }
+                        if map.width != 0 && line.len() != map.width {
+                            return Err(format!("line {}: map rows must be the same width", n));
+                        }
+                        map.width = line.len();
+                        map.height += 1;
+                        map.cells.extend(line.bytes());
+                    }
+                    game.levels.push(map);
+                }
+                "on" | "after" => {
+                    let mut body = vec![];
+                    let mut depth = 0;
+                    loop {
+                        let (n, line) = lines
+                            .next()
+                            .ok_or(format!("line {}: handler has no end", n))?;
+                        match line {
+                            "end" if depth == 0 => break,
+                            "end" => depth -= 1,
+                            _ if line.starts_with("if ") => depth += 1,
+                            _ => {}
+                        }
+                        body.push((n, line));
+                    }
+                    let code = bytecode::assemble(&body, &mut game.strings)?;
+
+                    if command == "after" {
+                        game.after_input = code;
+                    } else {
+                        let key = Key::from_name(rest.trim())
+                            .ok_or(format!("line {}: unknown key '{}'", n, rest))?;
+                        game.handlers[key as usize] = code;
+                    }
+                }
+                _ => return Err(format!("line {}: unknown command '{}'", n, command)),
+            }
+        }
+
+        game.validate()?;
+        Ok(game)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.levels.is_empty() {
+            return Err("game has no maps".to_string());
+        }
+        for (i, map) in self.levels.iter().enumerate() {
+            for cell in &map.cells {
+                if *cell != EMPTY && self.bitmap(*cell).is_none() {
+                    return Err(format!("map {}: unknown sprite '{}'", i, *cell as char));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn bitmap(&self, kind: u8) -> Option<&Bitmap> {
+        self.legend.iter().find(|bitmap| bitmap.kind == kind)
+    }
+
+    pub fn is_solid(&self, kind: u8) -> bool {
+        self.solids.contains(&kind)
+    }
+
+    pub fn can_push(&self, pusher: u8, pushed: u8) -> bool {
+        self.pushables
+            .iter()
+            .any(|(kind, pushes)| *kind == pusher && pushes.contains(&pushed))
+    }
+}
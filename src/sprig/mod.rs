@@ -0,0 +1,4 @@
+pub mod bytecode;
+pub mod game;
+pub mod runtime;
+pub mod world;
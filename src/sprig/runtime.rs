@@ -0,0 +1,239 @@
+use embedded_graphics::{
+    draw_target::DrawTarget,
+    geometry::{Point, Size},
+    pixelcolor::{Rgb565, RgbColor},
+    primitives::{Primitive, PrimitiveStyle, Rectangle},
+    text::Text,
+    Drawable,
+};
+
+use crate::{
+    emu::Emulator,
+    gui::core::{CENTERED_TEXT, WHITE_CHAR},
+    input::InputStatus,
+};
+
+use super::{
+    bytecode,
+    game::{Key, SprigGame, TILE},
+    world::World,
+};
+
+const SCREEN_WIDTH: usize = 160;
+const SCREEN_HEIGHT: usize = 128;
+
+pub struct SprigRuntime {
+    game: SprigGame,
+    world: World,
+}
+
+impl SprigRuntime {
+    pub fn with_game(game: SprigGame) -> Self {
+        Self {
+            world: World::new(&game),
+            game,
+        }
+    }
+
+    /// Starts the game over from its first level
+    pub fn reset(&mut self) {
+        self.world = World::new(&self.game);
+    }
+
+    /// The pads map onto the Sprig buttons they sit on: WASD on the left and K/L for A/B.
+    /// I and J aren't wired up yet so they're read from start and select.
+    pub fn set_input(&mut self, input: &InputStatus) {
+        let buttons = [
+            (Key::W, &input.up),
+            (Key::A, &input.left),
+            (Key::S, &input.down),
+            (Key::D, &input.right),
+            (Key::I, &input.start),
+            (Key::J, &input.select),
+            (Key::K, &input.a),
+            (Key::L, &input.b),
+        ];
+        for (key, button) in buttons {
+            if button.should_trigger() {
+                self.press(key);
+            }
+        }
+    }
+
+    pub fn press(&mut self, key: Key) {
+        if self.world.finished {
+            return;
+        }
+
+        let level = self.world.level;
+        bytecode::execute(
+            &self.game.handlers[key as usize],
+            &mut self.world,
+            &self.game,
+        );
+        bytecode::execute(&self.game.after_input, &mut self.world, &self.game);
+
+        if self.world.level == level && self.world.won(&self.game) {
+            self.world.next_level(&self.game);
+        }
+    }
+
+    /// The topmost colour at a pixel of a tile, earlier legend entries are drawn on top
+    fn pixel(&self, tile: &[u8], px: usize, py: usize) -> Rgb565 {
+        let background = self.game.background.and_then(|kind| self.game.bitmap(kind));
+
+        self.game
+            .legend
+            .iter()
+            .filter(|bitmap| tile.contains(&bitmap.kind))
+            .chain(background)
+            .find_map(|bitmap| bitmap.pixels[py * TILE + px])
+            .unwrap_or(Rgb565::WHITE)
+    }
+
+    pub fn draw<D>(&mut self, display: &mut D) -> Result<(), D::Error>
+    where
+        D: DrawTarget<Color = Rgb565>,
+    {
+        let this = &*self;
+        let world = &self.world;
+        // scale the map to fit, like the Sprig does
+        let size = (SCREEN_WIDTH / world.width)
+            .min(SCREEN_HEIGHT / world.height)
+            .max(1);
+        let (offset_x, offset_y) = (
+            (SCREEN_WIDTH.saturating_sub(world.width * size)) / 2,
+            (SCREEN_HEIGHT.saturating_sub(world.height * size)) / 2,
+        );
+
+        let pixels = (0..SCREEN_HEIGHT).flat_map(|y| {
+            (0..SCREEN_WIDTH).map(move |x| {
+                let (mx, my) = (x.wrapping_sub(offset_x), y.wrapping_sub(offset_y));
+                if mx >= world.width * size || my >= world.height * size {
+                    return Rgb565::BLACK;
+                }
+                let tile = world.tile(mx / size, my / size);
+                this.pixel(tile, (mx % size) * TILE / size, (my % size) * TILE / size)
+            })
+        });
+        display.fill_contiguous(
+            &Rectangle::new(
+                Point::zero(),
+                Size::new(SCREEN_WIDTH as u32, SCREEN_HEIGHT as u32),
+            ),
+            pixels,
+        )?;
+
+        if let Some(text) = &self.world.text {
+            let width = text.len() as u32 * 4 + 4;
+            Rectangle::new(
+                Point::new((SCREEN_WIDTH as i32 - width as i32) / 2, 56),
+                Size::new(width, 12),
+            )
+            .into_styled(PrimitiveStyle::with_fill(Rgb565::BLACK))
+            .draw(display)?;
+            Text::with_text_style(
+                text,
+                Point::new(SCREEN_WIDTH as i32 / 2, 62),
+                WHITE_CHAR,
+                CENTERED_TEXT,
+            )
+            .draw(display)?;
+        }
+
+        self.world.dirty = false;
+        Ok(())
+    }
+}
+
+impl<D> Emulator<D> for SprigRuntime
+where
+    D: DrawTarget<Color = Rgb565>,
+{
+    fn new(_display: &mut D) -> Self {
+        let demo = include_str!("../assets/games/crate_push.sprig");
+        Self::with_game(SprigGame::parse(demo).unwrap())
+    }
+
+    fn tick(&mut self, display: &mut D) -> Result<(), D::Error> {
+        if self.world.dirty {
+            self.draw(display)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::vec::Vec;
+
+    use super::*;
+
+    const DEMO: &str = include_str!("../assets/games/crate_push.sprig");
+
+    fn runtime() -> SprigRuntime {
+        SprigRuntime::with_game(SprigGame::parse(DEMO).unwrap())
+    }
+
+    #[test]
+    fn test_push_crate() {
+        let mut runtime = runtime();
+        // the player starts left of a crate
+        runtime.press(Key::D);
+        assert!(runtime.world.tile(3, 2).contains(&b'p'));
+        assert!(runtime.world.tile(4, 2).contains(&b'b'));
+    }
+
+    #[test]
+    fn test_walls_block() {
+        let mut runtime = runtime();
+        runtime.press(Key::W);
+        runtime.press(Key::W);
+        assert!(runtime.world.tile(2, 1).contains(&b'p'));
+    }
+
+    #[test]
+    fn test_win_goes_to_next_level() {
+        let mut runtime = runtime();
+        runtime.press(Key::D);
+        runtime.press(Key::D);
+        assert_eq!(runtime.world.level, 1);
+    }
+
+    #[test]
+    fn test_reset() {
+        let mut runtime = runtime();
+        runtime.press(Key::D);
+        runtime.press(Key::K);
+        assert!(runtime.world.tile(2, 2).contains(&b'p'));
+    }
+
+    #[test]
+    fn test_bytecode_if() {
+        let mut strings = Vec::new();
+        let code = bytecode::assemble(
+            &[
+                (1, "if p on g"),
+                (2, "text on goal"),
+                (3, "end"),
+                (4, "remove g"),
+            ],
+            &mut strings,
+        )
+        .unwrap();
+        assert_eq!(
+            code,
+            [
+                bytecode::IF_ON,
+                b'p',
+                b'g',
+                2,
+                0,
+                bytecode::TEXT,
+                0,
+                bytecode::REMOVE,
+                b'g'
+            ]
+        );
+    }
+}
@@ -0,0 +1,163 @@
+use alloc::{string::String, vec, vec::Vec};
+
+use super::{
+    bytecode::Direction,
+    game::{SprigGame, WinCondition, EMPTY},
+};
+
+/// The map being played, every tile holds a stack of sprites
+pub struct World {
+    pub width: usize,
+    pub height: usize,
+    tiles: Vec<Vec<u8>>,
+    pub level: usize,
+    pub text: Option<String>,
+    /// Set once the last map has been beaten
+    pub finished: bool,
+    /// Set whenever something changes, so the runtime knows to redraw
+    pub dirty: bool,
+}
+
+impl World {
+    pub fn new(game: &SprigGame) -> Self {
+        let mut world = World {
+            width: 0,
+            height: 0,
+            tiles: vec![],
+            level: 0,
+            text: None,
+            finished: false,
+            dirty: true,
+        };
+        world.load(game, 0);
+        world
+    }
+
+    pub fn load(&mut self, game: &SprigGame, level: usize) {
+        let Some(map) = game.levels.get(level) else {
+            return;
+        };
+
+        self.width = map.width;
+        self.height = map.height;
+        self.tiles = map
+            .cells
+            .iter()
+            .map(|cell| if *cell == EMPTY { vec![] } else { vec![*cell] })
+            .collect();
+        self.level = level;
+        self.text = None;
+        self.dirty = true;
+    }
+
+    pub fn next_level(&mut self, game: &SprigGame) {
+        if self.level + 1 < game.levels.len() {
+            self.load(game, self.level + 1);
+        } else {
+            self.finished = true;
+            self.text = Some(String::from("You win!"));
+            self.dirty = true;
+        }
+    }
+
+    pub fn tile(&self, x: usize, y: usize) -> &[u8] {
+        &self.tiles[y * self.width + x]
+    }
+
+    fn positions(&self, kind: u8) -> Vec<(usize, usize)> {
+        (0..self.tiles.len())
+            .filter(|i| self.tiles[*i].contains(&kind))
+            .map(|i| (i % self.width, i / self.width))
+            .collect()
+    }
+
+    /// Moves every `kind` sprite, the ones furthest along go first so a line of them doesn't
+    /// block itself
+    pub fn move_kind(&mut self, game: &SprigGame, kind: u8, dir: Direction) {
+        let (dx, dy) = dir.offset();
+        let mut positions = self.positions(kind);
+        positions.sort_by_key(|(x, y)| -(*x as i32 * dx + *y as i32 * dy));
+
+        for (x, y) in positions {
+            self.try_move(game, x, y, kind, dx, dy);
+        }
+    }
+
+    fn try_move(
+        &mut self,
+        game: &SprigGame,
+        x: usize,
+        y: usize,
+        kind: u8,
+        dx: i32,
+        dy: i32,
+    ) -> bool {
+        let (nx, ny) = (x as i32 + dx, y as i32 + dy);
+        if nx < 0 || ny < 0 || nx >= self.width as i32 || ny >= self.height as i32 {
+            return false;
+        }
+        let (nx, ny) = (nx as usize, ny as usize);
+
+        // only solid sprites bump into things
+        if game.is_solid(kind) {
+            for other in self.tile(nx, ny).to_vec() {
+                if !game.is_solid(other) {
+                    continue;
+                }
+                if !game.can_push(kind, other) || !self.try_move(game, nx, ny, other, dx, dy) {
+                    return false;
+                }
+            }
+        }
+
+        let from = &mut self.tiles[y * self.width + x];
+        if let Some(i) = from.iter().position(|k| *k == kind) {
+            from.remove(i);
+            self.tiles[ny * self.width + nx].push(kind);
+            self.dirty = true;
+        }
+        true
+    }
+
+    pub fn replace(&mut self, kind: u8, with: u8) {
+        for tile in self.tiles.iter_mut() {
+            for sprite in tile.iter_mut().filter(|sprite| **sprite == kind) {
+                *sprite = with;
+                self.dirty = true;
+            }
+        }
+    }
+
+    pub fn remove(&mut self, kind: u8) {
+        for tile in self.tiles.iter_mut() {
+            let len = tile.len();
+            tile.retain(|sprite| *sprite != kind);
+            self.dirty |= tile.len() != len;
+        }
+    }
+
+    /// Whether an `a` shares a tile with a `b`
+    pub fn on(&self, a: u8, b: u8) -> bool {
+        self.tiles
+            .iter()
+            .any(|tile| tile.contains(&a) && tile.contains(&b))
+    }
+
+    pub fn any(&self, kind: u8) -> bool {
+        self.tiles.iter().any(|tile| tile.contains(&kind))
+    }
+
+    /// Games without win conditions have to use `next` themselves
+    pub fn won(&self, game: &SprigGame) -> bool {
+        !game.win.is_empty()
+            && game.win.iter().all(|condition| match condition {
+                WinCondition::All(a, b) => self
+                    .tiles
+                    .iter()
+                    .filter(|tile| tile.contains(a))
+                    .all(|tile| tile.contains(b)),
+                WinCondition::Some(a, b) => self.on(*a, *b),
+                WinCondition::No(a) => !self.any(*a),
+            })
+    }
+}
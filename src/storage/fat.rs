@@ -0,0 +1,1168 @@
+//! FAT12, FAT16 and FAT32, on a whole disk or the first FAT partition of an MBR. Long file
+//! names are read and written, names are matched without caring about case like Windows does.
+//! Files are read and written through a `File` the volume hands out, which remembers where in
+//! the cluster chain it is so reading a ROM front to back doesn't walk the chain every block.
+//! https://academy.cba.mit.edu/classes/networking_communications/SD/FAT.pdf
+
+use alloc::{
+    format,
+    string::{String, ToString},
+    vec,
+    vec::Vec,
+};
+
+use super::{Block, BlockDevice, BLOCK_SIZE};
+use crate::library::{DirEntry, Storage};
+
+const ENTRY_SIZE: usize = 32;
+const ATTR_READ_ONLY: u8 = 0x01;
+const ATTR_HIDDEN: u8 = 0x02;
+const ATTR_SYSTEM: u8 = 0x04;
+const ATTR_VOLUME: u8 = 0x08;
+const ATTR_DIR: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LFN: u8 = ATTR_READ_ONLY | ATTR_HIDDEN | ATTR_SYSTEM | ATTR_VOLUME;
+/// The first byte of a deleted entry
+const DELETED: u8 = 0xE5;
+/// Set on the sequence number of the last piece of a long name
+const LFN_LAST: u8 = 0x40;
+/// UCS-2 characters in each long name entry
+const LFN_CHARS: usize = 13;
+const LFN_OFFSETS: [usize; LFN_CHARS] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+/// Byte 12 of a short entry, Windows' way of keeping `readme.txt` lower case without a long name
+const LOWER_BASE: u8 = 0x08;
+const LOWER_EXT: u8 = 0x10;
+/// There's no clock, files are stamped 1 January 2024
+const DOS_DATE: u16 = (44 << 9) | (1 << 5) | 1;
+/// Partition types that can hold FAT
+const FAT_PARTITIONS: [u8; 6] = [0x01, 0x04, 0x06, 0x0B, 0x0C, 0x0E];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FatType {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+impl FatType {
+    /// The type is decided by the cluster count, nothing else
+    fn from_clusters(clusters: u32) -> FatType {
+        if clusters < 4085 {
+            FatType::Fat12
+        } else if clusters < 65525 {
+            FatType::Fat16
+        } else {
+            FatType::Fat32
+        }
+    }
+
+    /// Chain values from here up mean the end of the chain
+    fn end(&self) -> u32 {
+        match self {
+            FatType::Fat12 => 0xFF8,
+            FatType::Fat16 => 0xFFF8,
+            FatType::Fat32 => 0x0FFF_FFF8,
+        }
+    }
+
+    #[cfg(test)]
+    fn name(&self) -> &'static [u8; 8] {
+        match self {
+            FatType::Fat12 => b"FAT12   ",
+            FatType::Fat16 => b"FAT16   ",
+            FatType::Fat32 => b"FAT32   ",
+        }
+    }
+}
+
+fn u16_at(data: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([data[at], data[at + 1]])
+}
+
+fn u32_at(data: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]])
+}
+
+fn put_u16(data: &mut [u8], at: usize, value: u16) {
+    data[at..at + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+fn put_u32(data: &mut [u8], at: usize, value: u32) {
+    data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+/// A directory's entries live either in FAT12/16's fixed root or in a cluster chain
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Dir {
+    FixedRoot,
+    Chain(u32),
+}
+
+/// Where an entry is: its block, and its offset in the block
+type Slot = (u32, usize);
+
+/// A file or folder as found in its directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub dir: bool,
+    pub size: u32,
+    cluster: u32,
+    /// The block and offset of the short entry, where the size and first cluster are kept
+    slot: Slot,
+}
+
+pub struct File {
+    entry: Entry,
+    pos: u32,
+    /// The last cluster used and its index in the chain
+    current: Option<(u32, u32)>,
+}
+
+impl File {
+    pub fn size(&self) -> u32 {
+        self.entry.size
+    }
+
+    /// Moves to `pos`, which stops at the end of the file
+    pub fn seek(&mut self, pos: u32) {
+        self.pos = pos.min(self.entry.size);
+    }
+}
+
+pub struct Volume<D: BlockDevice> {
+    dev: D,
+    fat_type: FatType,
+    /// Where the filesystem starts on the device
+    start: u32,
+    sectors_per_cluster: u32,
+    fats: u32,
+    fat_start: u32,
+    fat_size: u32,
+    root_start: u32,
+    root_sectors: u32,
+    root_cluster: u32,
+    data_start: u32,
+    clusters: u32,
+    /// Where to start looking for a free cluster
+    next_free: u32,
+    /// The last block read, writes go straight through
+    cache: Block,
+    cached: Option<u32>,
+}
+
+impl<D: BlockDevice> Volume<D> {
+    pub fn mount(mut dev: D) -> Result<Self, String> {
+        let mut boot = [0; BLOCK_SIZE];
+        dev.read_block(0, &mut boot)?;
+        if boot[510..] != [0x55, 0xAA] {
+            return Err("no filesystem, the boot sector has no signature".to_string());
+        }
+
+        // a disk with a partition table, use its first FAT partition
+        let mut start = 0;
+        if !is_boot_sector(&boot) {
+            start = (0..4)
+                .map(|i| &boot[446 + i * 16..446 + (i + 1) * 16])
+                .find(|part| FAT_PARTITIONS.contains(&part[4]))
+                .map(|part| u32_at(part, 8))
+                .ok_or("no FAT partition")?;
+            dev.read_block(start, &mut boot)?;
+            if !is_boot_sector(&boot) {
+                return Err("the FAT partition has no boot sector".to_string());
+            }
+        }
+
+        let sectors_per_cluster = boot[13] as u32;
+        let reserved = u16_at(&boot, 14) as u32;
+        let fats = boot[16] as u32;
+        let root_entries = u16_at(&boot, 17) as u32;
+        let total = match u16_at(&boot, 19) {
+            0 => u32_at(&boot, 32),
+            total => total as u32,
+        };
+        let fat_size = match u16_at(&boot, 22) {
+            0 => u32_at(&boot, 36),
+            size => size as u32,
+        };
+        if sectors_per_cluster == 0 || fats == 0 || fat_size == 0 {
+            return Err("the boot sector is corrupt".to_string());
+        }
+
+        let root_sectors = (root_entries * ENTRY_SIZE as u32).div_ceil(BLOCK_SIZE as u32);
+        let fat_start = reserved;
+        let root_start = fat_start + fats * fat_size;
+        let data_start = root_start + root_sectors;
+        let clusters = total.saturating_sub(data_start) / sectors_per_cluster;
+        let fat_type = FatType::from_clusters(clusters);
+
+        Ok(Self {
+            dev,
+            fat_type,
+            start,
+            sectors_per_cluster,
+            fats,
+            fat_start,
+            fat_size,
+            root_start,
+            root_sectors,
+            root_cluster: match fat_type {
+                FatType::Fat32 => u32_at(&boot, 44),
+                _ => 0,
+            },
+            data_start,
+            clusters,
+            next_free: 2,
+            cache: [0; BLOCK_SIZE],
+            cached: None,
+        })
+    }
+
+    #[cfg(test)]
+    pub fn fat_type(&self) -> FatType {
+        self.fat_type
+    }
+
+    fn cluster_bytes(&self) -> u32 {
+        self.sectors_per_cluster * BLOCK_SIZE as u32
+    }
+
+    fn cluster_lba(&self, cluster: u32) -> u32 {
+        self.start + self.data_start + (cluster - 2) * self.sectors_per_cluster
+    }
+
+    fn block(&mut self, lba: u32) -> Result<&mut Block, String> {
+        if self.cached != Some(lba) {
+            self.cached = None;
+            self.dev.read_block(lba, &mut self.cache)?;
+            self.cached = Some(lba);
+        }
+        Ok(&mut self.cache)
+    }
+
+    /// Changes part of a block
+    fn patch(&mut self, lba: u32, at: usize, data: &[u8]) -> Result<(), String> {
+        self.block(lba)?[at..at + data.len()].copy_from_slice(data);
+        self.dev.write_block(lba, &self.cache)
+    }
+
+    fn zero_cluster(&mut self, cluster: u32) -> Result<(), String> {
+        let lba = self.cluster_lba(cluster);
+        for i in 0..self.sectors_per_cluster {
+            self.dev.write_block(lba + i, &[0; BLOCK_SIZE])?;
+        }
+        if self
+            .cached
+            .is_some_and(|cached| cached >= lba && cached < lba + self.sectors_per_cluster)
+        {
+            self.cached = None;
+        }
+        Ok(())
+    }
+
+    // #region FAT
+
+    fn fat_byte(&mut self, offset: u32) -> Result<u8, String> {
+        let lba = self.start + self.fat_start + offset / BLOCK_SIZE as u32;
+        Ok(self.block(lba)?[offset as usize % BLOCK_SIZE])
+    }
+
+    /// Writes a byte of the FAT to every copy
+    fn set_fat_byte(&mut self, offset: u32, value: u8) -> Result<(), String> {
+        for copy in 0..self.fats {
+            let lba =
+                self.start + self.fat_start + copy * self.fat_size + offset / BLOCK_SIZE as u32;
+            self.patch(lba, offset as usize % BLOCK_SIZE, &[value])?;
+        }
+        Ok(())
+    }
+
+    fn fat(&mut self, cluster: u32) -> Result<u32, String> {
+        Ok(match self.fat_type {
+            FatType::Fat12 => {
+                let offset = cluster + cluster / 2;
+                let pair = u16::from_le_bytes([self.fat_byte(offset)?, self.fat_byte(offset + 1)?]);
+                if cluster & 1 == 0 {
+                    pair as u32 & 0xFFF
+                } else {
+                    pair as u32 >> 4
+                }
+            }
+            FatType::Fat16 => {
+                let offset = cluster * 2;
+                u16::from_le_bytes([self.fat_byte(offset)?, self.fat_byte(offset + 1)?]) as u32
+            }
+            FatType::Fat32 => {
+                let offset = cluster * 4;
+                let mut bytes = [0; 4];
+                for (i, byte) in bytes.iter_mut().enumerate() {
+                    *byte = self.fat_byte(offset + i as u32)?;
+                }
+                u32::from_le_bytes(bytes) & 0x0FFF_FFFF
+            }
+        })
+    }
+
+    fn set_fat(&mut self, cluster: u32, value: u32) -> Result<(), String> {
+        match self.fat_type {
+            FatType::Fat12 => {
+                let offset = cluster + cluster / 2;
+                let pair = u16::from_le_bytes([self.fat_byte(offset)?, self.fat_byte(offset + 1)?]);
+                let value = value as u16 & 0xFFF;
+                let pair = if cluster & 1 == 0 {
+                    (pair & 0xF000) | value
+                } else {
+                    (pair & 0x000F) | (value << 4)
+                };
+                let [low, high] = pair.to_le_bytes();
+                self.set_fat_byte(offset, low)?;
+                self.set_fat_byte(offset + 1, high)
+            }
+            FatType::Fat16 => {
+                for (i, byte) in (value as u16).to_le_bytes().into_iter().enumerate() {
+                    self.set_fat_byte(cluster * 2 + i as u32, byte)?;
+                }
+                Ok(())
+            }
+            FatType::Fat32 => {
+                // the top 4 bits are reserved and kept
+                let value = (self.fat(cluster)? & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+                    self.set_fat_byte(cluster * 4 + i as u32, byte)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// The cluster after this one, None at the end of the chain
+    fn next(&mut self, cluster: u32) -> Result<Option<u32>, String> {
+        match self.fat(cluster)? {
+            next if next >= self.fat_type.end() => Ok(None),
+            next if next < 2 || next >= self.clusters + 2 => Err(format!(
+                "cluster {} links to {}, the FAT is corrupt",
+                cluster, next
+            )),
+            next => Ok(Some(next)),
+        }
+    }
+
+    /// Takes a free cluster, marking it as the end of a chain
+    fn allocate(&mut self) -> Result<u32, String> {
+        let last = self.clusters + 2;
+        let candidates = (self.next_free..last).chain(2..self.next_free);
+        for cluster in candidates {
+            if self.fat(cluster)? == 0 {
+                self.set_fat(cluster, self.fat_type.end() | 7)?;
+                self.next_free = if cluster + 1 < last { cluster + 1 } else { 2 };
+                return Ok(cluster);
+            }
+        }
+        Err("the disk is full".to_string())
+    }
+
+    fn free_chain(&mut self, first: u32) -> Result<(), String> {
+        let mut cluster = Some(first);
+        while let Some(current) = cluster.filter(|c| *c >= 2) {
+            cluster = self.next(current)?;
+            self.set_fat(current, 0)?;
+        }
+        self.next_free = self.next_free.min(first.max(2));
+        Ok(())
+    }
+
+    // #endregion
+
+    // #region Directories
+
+    fn root(&self) -> Dir {
+        match self.fat_type {
+            FatType::Fat32 => Dir::Chain(self.root_cluster),
+            _ => Dir::FixedRoot,
+        }
+    }
+
+    /// Every block a directory is made of
+    fn dir_blocks(&mut self, dir: Dir) -> Result<Vec<u32>, String> {
+        match dir {
+            Dir::FixedRoot => {
+                let start = self.start + self.root_start;
+                Ok((start..start + self.root_sectors).collect())
+            }
+            Dir::Chain(first) => {
+                let mut blocks = Vec::new();
+                let mut cluster = Some(first);
+                while let Some(current) = cluster {
+                    let lba = self.cluster_lba(current);
+                    blocks.extend(lba..lba + self.sectors_per_cluster);
+                    cluster = self.next(current)?;
+                }
+                Ok(blocks)
+            }
+        }
+    }
+
+    /// Every 32 byte slot in a directory, with the raw entry
+    fn slots(&mut self, dir: Dir) -> Result<Vec<(Slot, [u8; ENTRY_SIZE])>, String> {
+        let mut slots = Vec::new();
+        for lba in self.dir_blocks(dir)? {
+            let block = self.block(lba)?;
+            for at in (0..BLOCK_SIZE).step_by(ENTRY_SIZE) {
+                let mut raw = [0; ENTRY_SIZE];
+                raw.copy_from_slice(&block[at..at + ENTRY_SIZE]);
+                slots.push(((lba, at), raw));
+            }
+        }
+        Ok(slots)
+    }
+
+    fn entries(&mut self, dir: Dir) -> Result<Vec<Entry>, String> {
+        let mut entries = Vec::new();
+        // the long name pieces seen so far, and the checksum they expect
+        let mut long: Vec<u16> = Vec::new();
+        let mut checksum = None;
+
+        for (slot, raw) in self.slots(dir)? {
+            match raw[0] {
+                // nothing after this
+                0 => break,
+                DELETED => {
+                    checksum = None;
+                    continue;
+                }
+                _ => {}
+            }
+
+            if raw[11] & 0x3F == ATTR_LFN {
+                let seq = (raw[0] & 0x1F) as usize;
+                if raw[0] & LFN_LAST != 0 {
+                    long = vec![0xFFFF; seq * LFN_CHARS];
+                    checksum = Some(raw[13]);
+                }
+                if seq == 0 || seq * LFN_CHARS > long.len() || checksum != Some(raw[13]) {
+                    checksum = None;
+                    continue;
+                }
+                for (i, at) in LFN_OFFSETS.iter().enumerate() {
+                    long[(seq - 1) * LFN_CHARS + i] = u16_at(&raw, *at);
+                }
+                continue;
+            }
+
+            let short: [u8; 11] = raw[..11].try_into().unwrap();
+            let long_name = checksum
+                .take()
+                .filter(|sum| *sum == lfn_checksum(&short))
+                .map(|_| {
+                    let units = long.iter().copied().take_while(|c| *c != 0 && *c != 0xFFFF);
+                    char::decode_utf16(units)
+                        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                        .collect::<String>()
+                });
+            if raw[11] & ATTR_VOLUME != 0 || short[0] == b'.' {
+                continue;
+            }
+
+            entries.push(Entry {
+                name: long_name.unwrap_or_else(|| short_to_string(&short, raw[12])),
+                dir: raw[11] & ATTR_DIR != 0,
+                size: u32_at(&raw, 28),
+                cluster: ((u16_at(&raw, 20) as u32) << 16) | u16_at(&raw, 26) as u32,
+                slot,
+            });
+        }
+        Ok(entries)
+    }
+
+    fn dir_of(&self, entry: &Entry) -> Dir {
+        match entry.cluster {
+            0 => self.root(),
+            cluster => Dir::Chain(cluster),
+        }
+    }
+
+    /// Finds a path, `""` being the root
+    fn find(&mut self, path: &str) -> Result<Option<Entry>, String> {
+        let mut dir = self.root();
+        let mut found = None;
+        for part in path.split('/').filter(|part| !part.is_empty()) {
+            if found.as_ref().is_some_and(|entry: &Entry| !entry.dir) {
+                return Err(format!("{}: not a folder", path));
+            }
+            let Some(entry) = self
+                .entries(dir)?
+                .into_iter()
+                .find(|entry| entry.name.eq_ignore_ascii_case(part))
+            else {
+                return Ok(None);
+            };
+            dir = self.dir_of(&entry);
+            found = Some(entry);
+        }
+        Ok(found)
+    }
+
+    fn find_dir(&mut self, path: &str) -> Result<Dir, String> {
+        if path.split('/').all(|part| part.is_empty()) {
+            return Ok(self.root());
+        }
+        match self.find(path)? {
+            Some(entry) if entry.dir => Ok(self.dir_of(&entry)),
+            Some(_) => Err(format!("{}: not a folder", path)),
+            None => Err(format!("{}: not found", path)),
+        }
+    }
+
+    /// Adds an entry called `name` to `dir`, with a long name if it needs one
+    fn add_entry(&mut self, dir: Dir, name: &str, attr: u8, cluster: u32) -> Result<Entry, String> {
+        if name.is_empty()
+            || name.len() > 255
+            || name.contains(['/', '\\', ':', '*', '?', '"', '<', '>', '|'])
+        {
+            return Err(format!("'{}' can't be used as a name", name));
+        }
+        let existing = self.entries(dir)?;
+        if existing
+            .iter()
+            .any(|entry| entry.name.eq_ignore_ascii_case(name))
+        {
+            return Err(format!("{} already exists", name));
+        }
+
+        let (short, needs_long) = match exact_short_name(name) {
+            Some(short) => (short, false),
+            None => {
+                let taken: Vec<[u8; 11]> = self
+                    .slots(dir)?
+                    .iter()
+                    .map(|(_, raw)| raw[..11].try_into().unwrap())
+                    .collect();
+                (numbered_short_name(name, &taken)?, true)
+            }
+        };
+        let long: Vec<u16> = name.encode_utf16().collect();
+        let pieces = if needs_long {
+            long.len().div_ceil(LFN_CHARS)
+        } else {
+            0
+        };
+
+        let slot = self.free_slots(dir, pieces + 1)?;
+        let checksum = lfn_checksum(&short);
+        for (i, (lba, at)) in slot.iter().take(pieces).enumerate() {
+            // the last piece of the name comes first
+            let seq = pieces - i;
+            let mut raw = [0; ENTRY_SIZE];
+            raw[0] = seq as u8 | if i == 0 { LFN_LAST } else { 0 };
+            raw[11] = ATTR_LFN;
+            raw[13] = checksum;
+            for (j, offset) in LFN_OFFSETS.iter().enumerate() {
+                let unit = match (seq - 1) * LFN_CHARS + j {
+                    k if k < long.len() => long[k],
+                    k if k == long.len() => 0,
+                    _ => 0xFFFF,
+                };
+                put_u16(&mut raw, *offset, unit);
+            }
+            self.patch(*lba, *at, &raw)?;
+        }
+
+        let (lba, at) = slot[pieces];
+        let mut raw = [0; ENTRY_SIZE];
+        raw[..11].copy_from_slice(&short);
+        raw[11] = attr;
+        for date in [16, 18, 24] {
+            put_u16(&mut raw, date, DOS_DATE);
+        }
+        put_u16(&mut raw, 20, (cluster >> 16) as u16);
+        put_u16(&mut raw, 26, cluster as u16);
+        self.patch(lba, at, &raw)?;
+
+        Ok(Entry {
+            name: name.to_string(),
+            dir: attr & ATTR_DIR != 0,
+            size: 0,
+            cluster,
+            slot: (lba, at),
+        })
+    }
+
+    /// Finds `count` free slots in a row, growing the directory if there aren't any
+    fn free_slots(&mut self, dir: Dir, count: usize) -> Result<Vec<Slot>, String> {
+        loop {
+            let slots = self.slots(dir)?;
+            let mut run = Vec::new();
+            for (slot, raw) in &slots {
+                if raw[0] == 0 || raw[0] == DELETED {
+                    run.push(*slot);
+                    if run.len() == count {
+                        return Ok(run);
+                    }
+                } else {
+                    run.clear();
+                }
+            }
+
+            let Dir::Chain(first) = dir else {
+                return Err("the root folder is full".to_string());
+            };
+            let mut last = first;
+            while let Some(next) = self.next(last)? {
+                last = next;
+            }
+            let cluster = self.allocate()?;
+            self.zero_cluster(cluster)?;
+            self.set_fat(last, cluster)?;
+        }
+    }
+
+    /// Updates the size and first cluster in a file's short entry
+    fn save_entry(&mut self, entry: &Entry) -> Result<(), String> {
+        let (lba, at) = entry.slot;
+        let mut fields = [0; 12];
+        put_u16(&mut fields, 0, (entry.cluster >> 16) as u16);
+        put_u16(&mut fields, 2, DOS_DATE);
+        put_u16(&mut fields, 4, DOS_DATE);
+        put_u16(&mut fields, 6, entry.cluster as u16);
+        put_u32(&mut fields, 8, entry.size);
+        self.patch(lba, at + 20, &fields)
+    }
+
+    /// The files and folders in `path`
+    pub fn list(&mut self, path: &str) -> Result<Vec<Entry>, String> {
+        let dir = self.find_dir(path)?;
+        self.entries(dir)
+    }
+
+    #[cfg(test)]
+    pub fn create_dir(&mut self, path: &str) -> Result<(), String> {
+        let (parent, name) = path
+            .trim_end_matches('/')
+            .rsplit_once('/')
+            .unwrap_or(("", path));
+        let dir = self.find_dir(parent)?;
+
+        let cluster = self.allocate()?;
+        self.zero_cluster(cluster)?;
+        if let Err(e) = self.add_entry(dir, name, ATTR_DIR, cluster) {
+            self.free_chain(cluster)?;
+            return Err(e);
+        }
+
+        // the . and .. entries, .. is 0 for the root
+        let parent_cluster = match dir {
+            Dir::Chain(parent) if parent != self.root_cluster => parent,
+            _ => 0,
+        };
+        let lba = self.cluster_lba(cluster);
+        for (i, (name, target)) in [(b".          ", cluster), (b"..         ", parent_cluster)]
+            .into_iter()
+            .enumerate()
+        {
+            let mut raw = [0; ENTRY_SIZE];
+            raw[..11].copy_from_slice(name);
+            raw[11] = ATTR_DIR;
+            put_u16(&mut raw, 20, (target >> 16) as u16);
+            put_u16(&mut raw, 26, target as u16);
+            put_u16(&mut raw, 24, DOS_DATE);
+            self.patch(lba, i * ENTRY_SIZE, &raw)?;
+        }
+        Ok(())
+    }
+
+    // #endregion
+
+    // #region Files
+
+    pub fn open(&mut self, path: &str) -> Result<File, String> {
+        match self.find(path)? {
+            Some(entry) if !entry.dir => Ok(File {
+                entry,
+                pos: 0,
+                current: None,
+            }),
+            Some(_) => Err(format!("{}: is a folder", path)),
+            None => Err(format!("{}: not found", path)),
+        }
+    }
+
+    /// Opens a file to write, emptying it if it's already there
+    pub fn create(&mut self, path: &str) -> Result<File, String> {
+        let entry = match self.find(path)? {
+            Some(entry) if entry.dir => return Err(format!("{}: is a folder", path)),
+            Some(mut entry) => {
+                if entry.cluster != 0 {
+                    self.free_chain(entry.cluster)?;
+                }
+                entry.cluster = 0;
+                entry.size = 0;
+                self.save_entry(&entry)?;
+                entry
+            }
+            None => {
+                let (parent, name) = path.rsplit_once('/').unwrap_or(("", path));
+                let dir = self.find_dir(parent)?;
+                self.add_entry(dir, name, ATTR_ARCHIVE, 0)?
+            }
+        };
+        Ok(File {
+            entry,
+            pos: 0,
+            current: None,
+        })
+    }
+
+    /// The cluster holding byte `pos` of the file, growing the file's chain if `grow`
+    fn cluster_at(&mut self, file: &mut File, pos: u32, grow: bool) -> Result<u32, String> {
+        let index = pos / self.cluster_bytes();
+        let (mut cluster, mut at) = match file.current {
+            Some((cluster, at)) if at <= index => (cluster, at),
+            _ if file.entry.cluster == 0 => {
+                if !grow {
+                    return Err("read past the end of the file".to_string());
+                }
+                file.entry.cluster = self.allocate()?;
+                (file.entry.cluster, 0)
+            }
+            _ => (file.entry.cluster, 0),
+        };
+
+        while at < index {
+            cluster = match self.next(cluster)? {
+                Some(next) => next,
+                None if grow => {
+                    let next = self.allocate()?;
+                    self.set_fat(cluster, next)?;
+                    next
+                }
+                None => return Err("the file is shorter than its size".to_string()),
+            };
+            at += 1;
+        }
+        file.current = Some((cluster, at));
+        Ok(cluster)
+    }
+
+    /// Reads from where the file is up to, returning how much was read
+    pub fn read(&mut self, file: &mut File, buf: &mut [u8]) -> Result<usize, String> {
+        let mut read = 0;
+        while read < buf.len() && file.pos < file.entry.size {
+            let cluster = self.cluster_at(file, file.pos, false)?;
+            let offset = file.pos % self.cluster_bytes();
+            let lba = self.cluster_lba(cluster) + offset / BLOCK_SIZE as u32;
+            let at = offset as usize % BLOCK_SIZE;
+
+            let count = (BLOCK_SIZE - at)
+                .min(buf.len() - read)
+                .min((file.entry.size - file.pos) as usize);
+            let block = self.block(lba)?;
+            buf[read..read + count].copy_from_slice(&block[at..at + count]);
+            read += count;
+            file.pos += count as u32;
+        }
+        Ok(read)
+    }
+
+    /// Writes at where the file is up to, growing it as needed
+    pub fn write(&mut self, file: &mut File, data: &[u8]) -> Result<(), String> {
+        let mut written = 0;
+        while written < data.len() {
+            let cluster = self.cluster_at(file, file.pos, true)?;
+            let offset = file.pos % self.cluster_bytes();
+            let lba = self.cluster_lba(cluster) + offset / BLOCK_SIZE as u32;
+            let at = offset as usize % BLOCK_SIZE;
+
+            let count = (BLOCK_SIZE - at).min(data.len() - written);
+            self.patch(lba, at, &data[written..written + count])?;
+            written += count;
+            file.pos += count as u32;
+            file.entry.size = file.entry.size.max(file.pos);
+        }
+        self.save_entry(&file.entry)
+    }
+
+    // #endregion
+}
+
+fn is_boot_sector(boot: &Block) -> bool {
+    matches!(boot[0], 0xEB | 0xE9) && u16_at(boot, 11) == BLOCK_SIZE as u16
+}
+
+fn lfn_checksum(short: &[u8; 11]) -> u8 {
+    short
+        .iter()
+        .fold(0u8, |sum, c| sum.rotate_right(1).wrapping_add(*c))
+}
+
+fn short_to_string(short: &[u8; 11], case: u8) -> String {
+    let part = |bytes: &[u8], lower: bool| -> String {
+        let text = String::from_utf8_lossy(bytes);
+        let text = text.trim_end();
+        if lower {
+            text.to_ascii_lowercase()
+        } else {
+            text.to_string()
+        }
+    };
+    let base = part(&short[..8], case & LOWER_BASE != 0);
+    let ext = part(&short[8..], case & LOWER_EXT != 0);
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+fn short_char(c: char) -> bool {
+    c.is_ascii_uppercase() || c.is_ascii_digit() || "$%'-_@~`!(){}^#&".contains(c)
+}
+
+/// The short entry for names that already fit 8.3 in upper case, which need no long name
+fn exact_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.rsplit_once('.').unwrap_or((name, ""));
+    let fits = (1..=8).contains(&base.len())
+        && ext.len() <= 3
+        && base.chars().chain(ext.chars()).all(short_char);
+    if !fits {
+        return None;
+    }
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    Some(short)
+}
+
+/// A `BASIS~N.EXT` short name for a long one, that isn't taken in the folder
+fn numbered_short_name(name: &str, taken: &[[u8; 11]]) -> Result<[u8; 11], String> {
+    let clean = |text: &str| -> String {
+        text.chars()
+            .map(|c| c.to_ascii_uppercase())
+            .filter(|c| short_char(*c))
+            .collect()
+    };
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) if !base.is_empty() => (clean(base), clean(ext)),
+        _ => (clean(name), String::new()),
+    };
+    let base = if base.is_empty() {
+        "_".to_string()
+    } else {
+        base
+    };
+
+    for n in 1..1_000_000 {
+        let tail = format!("~{}", n);
+        let keep = (8 - tail.len()).min(base.len());
+        let mut short = [b' '; 11];
+        short[..keep].copy_from_slice(&base.as_bytes()[..keep]);
+        short[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        let ext = &ext.as_bytes()[..ext.len().min(3)];
+        short[8..8 + ext.len()].copy_from_slice(ext);
+        if !taken.contains(&short) {
+            return Ok(short);
+        }
+    }
+    Err(format!("no short name left for {}", name))
+}
+
+/// Makes an empty filesystem covering the whole device, FAT12 for small disks, FAT16 up to
+/// 512 MiB and FAT32 past that
+#[cfg(test)]
+pub fn format(dev: &mut impl BlockDevice, label: &str) -> Result<FatType, String> {
+    let blocks = dev.block_count()?;
+    let fat_type = if blocks < 8 * 1024 {
+        FatType::Fat12
+    } else if blocks < 1024 * 1024 {
+        FatType::Fat16
+    } else {
+        FatType::Fat32
+    };
+    format_as(dev, fat_type, label)?;
+    Ok(fat_type)
+}
+
+/// Makes an empty filesystem of a given type, which fails if the device is the wrong size
+#[cfg(test)]
+pub fn format_as(dev: &mut impl BlockDevice, fat_type: FatType, label: &str) -> Result<(), String> {
+    let blocks = dev.block_count()?;
+
+    let (reserved, root_sectors, entry_bits) = match fat_type {
+        FatType::Fat12 => (1, 32, 12),
+        FatType::Fat16 => (1, 32, 16),
+        FatType::Fat32 => (32, 0, 32),
+    };
+    let sectors_per_cluster: u32 = match fat_type {
+        FatType::Fat12 => (0..8)
+            .map(|shift| 1 << shift)
+            .find(|spc| blocks / spc < 4000)
+            .unwrap_or(128),
+        FatType::Fat16 => (0..8)
+            .map(|shift| 1 << shift)
+            .find(|spc| blocks / spc < 65000)
+            .unwrap_or(128),
+        FatType::Fat32 => match blocks {
+            0..=532_480 => 1,
+            532_481..=16_777_216 => 8,
+            16_777_217..=33_554_432 => 16,
+            33_554_433..=67_108_864 => 32,
+            _ => 64,
+        },
+    };
+
+    // the FATs take space from the clusters they describe, go until it settles
+    let mut fat_size = 1;
+    let clusters = loop {
+        let used = reserved + root_sectors + 2 * fat_size;
+        let clusters = blocks.saturating_sub(used) / sectors_per_cluster;
+        let needed = ((clusters as u64 + 2) * entry_bits).div_ceil(8 * BLOCK_SIZE as u64) as u32;
+        if needed <= fat_size {
+            break clusters;
+        }
+        fat_size = needed;
+    };
+    if FatType::from_clusters(clusters) != fat_type {
+        return Err(format!(
+            "{} blocks is the wrong size for {:?}",
+            blocks, fat_type
+        ));
+    }
+
+    let mut label_bytes = [b' '; 11];
+    for (byte, c) in label_bytes.iter_mut().zip(label.bytes()) {
+        *byte = c.to_ascii_uppercase();
+    }
+
+    let mut boot = [0; BLOCK_SIZE];
+    boot[..3].copy_from_slice(&[0xEB, 0x3C, 0x90]);
+    boot[3..11].copy_from_slice(b"EGB     ");
+    put_u16(&mut boot, 11, BLOCK_SIZE as u16);
+    boot[13] = sectors_per_cluster as u8;
+    put_u16(&mut boot, 14, reserved as u16);
+    boot[16] = 2;
+    put_u16(
+        &mut boot,
+        17,
+        (root_sectors * BLOCK_SIZE as u32 / ENTRY_SIZE as u32) as u16,
+    );
+    if blocks < 0x10000 {
+        put_u16(&mut boot, 19, blocks as u16);
+    } else {
+        put_u32(&mut boot, 32, blocks);
+    }
+    boot[21] = 0xF8;
+    put_u16(&mut boot, 24, 32);
+    put_u16(&mut boot, 26, 64);
+    // the extended boot record moves down for FAT32's extra fields
+    let ebr = match fat_type {
+        FatType::Fat32 => {
+            put_u32(&mut boot, 36, fat_size);
+            put_u32(&mut boot, 44, 2);
+            put_u16(&mut boot, 48, 1);
+            put_u16(&mut boot, 50, 6);
+            64
+        }
+        _ => {
+            put_u16(&mut boot, 22, fat_size as u16);
+            36
+        }
+    };
+    boot[ebr] = 0x80;
+    boot[ebr + 2] = 0x29;
+    put_u32(&mut boot, ebr + 3, blocks ^ 0xE6B0_0000);
+    boot[ebr + 7..ebr + 18].copy_from_slice(&label_bytes);
+    boot[ebr + 18..ebr + 26].copy_from_slice(fat_type.name());
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+
+    let empty = [0; BLOCK_SIZE];
+    for lba in 0..reserved + 2 * fat_size + root_sectors {
+        dev.write_block(lba, &empty)?;
+    }
+    dev.write_block(0, &boot)?;
+
+    if fat_type == FatType::Fat32 {
+        dev.write_block(6, &boot)?;
+        let mut info = [0; BLOCK_SIZE];
+        put_u32(&mut info, 0, 0x4161_5252);
+        put_u32(&mut info, 484, 0x6141_7272);
+        // free count and next free unknown
+        put_u32(&mut info, 488, 0xFFFF_FFFF);
+        put_u32(&mut info, 492, 0xFFFF_FFFF);
+        put_u32(&mut info, 508, 0xAA55_0000);
+        dev.write_block(1, &info)?;
+        dev.write_block(7, &info)?;
+    }
+
+    // the first two FAT entries are the media byte and an end of chain, FAT32 also ends the
+    // root folder's chain in cluster 2
+    let mut fat = [0; BLOCK_SIZE];
+    match fat_type {
+        FatType::Fat12 => fat[..3].copy_from_slice(&[0xF8, 0xFF, 0xFF]),
+        FatType::Fat16 => fat[..4].copy_from_slice(&[0xF8, 0xFF, 0xFF, 0xFF]),
+        FatType::Fat32 => {
+            put_u32(&mut fat, 0, 0x0FFF_FFF8);
+            put_u32(&mut fat, 4, 0x0FFF_FFFF);
+            put_u32(&mut fat, 8, 0x0FFF_FFFF);
+        }
+    }
+    for copy in 0..2 {
+        dev.write_block(reserved + copy * fat_size, &fat)?;
+    }
+    if fat_type == FatType::Fat32 {
+        let root = reserved + 2 * fat_size;
+        for lba in root..root + sectors_per_cluster {
+            dev.write_block(lba, &empty)?;
+        }
+    }
+    Ok(())
+}
+
+impl<D: BlockDevice> Storage for Volume<D> {
+    fn list(&mut self, dir: &str) -> Result<Vec<DirEntry>, String> {
+        Ok(Volume::list(self, dir)?
+            .into_iter()
+            .map(|entry| DirEntry {
+                name: entry.name,
+                size: entry.size as u64,
+                dir: entry.dir,
+            })
+            .collect())
+    }
+
+    fn read_at(&mut self, path: &str, offset: u64, buf: &mut [u8]) -> Result<usize, String> {
+        let mut file = self.open(path)?;
+        file.seek(offset.min(u32::MAX as u64) as u32);
+        Volume::read(self, &mut file, buf)
+    }
+
+    fn read(&mut self, path: &str) -> Result<Vec<u8>, String> {
+        let mut file = self.open(path)?;
+        let mut data = vec![0; file.size() as usize];
+        Volume::read(self, &mut file, &mut data)?;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::image::DiskImage;
+
+    /// The image is named after the process too, so test runs side by side don't share it
+    fn disk(name: &str, blocks: u32, fat_type: FatType) -> (Volume<DiskImage>, String) {
+        let path = std::env::temp_dir()
+            .join(format!("{}_{}.img", name, std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let mut image = DiskImage::create(&path, blocks).unwrap();
+        format_as(&mut image, fat_type, "EGB").unwrap();
+        let volume = Volume::mount(image).unwrap();
+        assert_eq!(volume.fat_type(), fat_type);
+        (volume, path)
+    }
+
+    /// Writes a file over a few clusters, reads it back with seeks, and checks long names.
+    /// Returns the volume mounted again from the image.
+    fn exercise(mut volume: Volume<DiskImage>, path: &str) -> Volume<DiskImage> {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 7 % 251) as u8).collect();
+        let name = "roms/nes/Super Mario Bros (USA).nes";
+
+        volume.create_dir("roms").unwrap();
+        volume.create_dir("roms/nes").unwrap();
+        let mut file = volume.create(name).unwrap();
+        // in uneven pieces so writes straddle blocks and clusters
+        for piece in data.chunks(777) {
+            volume.write(&mut file, piece).unwrap();
+        }
+        let mut short = volume.create("README.TXT").unwrap();
+        volume.write(&mut short, b"hello").unwrap();
+
+        // nothing cached is relied on
+        let mut volume = Volume::mount(DiskImage::open(path).unwrap()).unwrap();
+        let names: Vec<String> = volume
+            .list("")
+            .unwrap()
+            .into_iter()
+            .map(|e| e.name)
+            .collect();
+        assert_eq!(names, ["roms", "README.TXT"]);
+        let listed = volume.list("ROMS/NES").unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].name, "Super Mario Bros (USA).nes");
+        assert_eq!(listed[0].size, data.len() as u32);
+
+        let mut file = volume.open("roms/nes/super mario bros (usa).NES").unwrap();
+        let mut back = vec![0; data.len() + 10];
+        assert_eq!(volume.read(&mut file, &mut back).unwrap(), data.len());
+        assert_eq!(&back[..data.len()], &data[..]);
+
+        file.seek(12_345);
+        let mut piece = [0; 1000];
+        volume.read(&mut file, &mut piece).unwrap();
+        assert_eq!(&piece[..], &data[12_345..13_345]);
+        file.seek(3);
+        volume.read(&mut file, &mut piece[..4]).unwrap();
+        assert_eq!(&piece[..4], &data[3..7]);
+
+        // write over part of it, then make it again shorter
+        file.seek(100);
+        volume.write(&mut file, &[0xAA; 600]).unwrap();
+        let mut expected = data[99..701].to_vec();
+        expected[1..601].fill(0xAA);
+        assert_eq!(Storage::read(&mut volume, name).unwrap()[99..701], expected);
+
+        let mut file = volume.create(name).unwrap();
+        volume.write(&mut file, b"NES\x1A").unwrap();
+        assert_eq!(volume.list("roms/nes").unwrap()[0].size, 4);
+
+        assert!(volume.open("roms/missing.nes").is_err());
+        assert!(volume.open("roms").is_err());
+        assert!(volume.create_dir("roms").is_err());
+
+        Volume::mount(DiskImage::open(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_fat12() {
+        let (volume, path) = disk("egb_fat12", 2048, FatType::Fat12);
+        exercise(volume, &path);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_fat16() {
+        let (volume, path) = disk("egb_fat16", 16 * 1024, FatType::Fat16);
+        let mut volume = exercise(volume, &path);
+
+        // folders grow past their first cluster
+        for i in 0..100 {
+            let name = format!("roms/folder with a long name {}", i);
+            volume.create_dir(&name).unwrap();
+        }
+        assert_eq!(volume.list("roms").unwrap().len(), 101);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_fat32() {
+        let (volume, path) = disk("egb_fat32", 70_000, FatType::Fat32);
+        let mut volume = exercise(volume, &path);
+
+        // the library reads straight from the image
+        let games = crate::library::scan(&mut volume, "roms").unwrap();
+        assert_eq!(games.len(), 1);
+        assert_eq!(games[0].title, "Super Mario Bros");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(exact_short_name("README.TXT"), Some(*b"README  TXT"));
+        assert_eq!(exact_short_name("readme.txt"), None);
+        assert_eq!(
+            numbered_short_name("Super Mario Bros.nes", &[*b"SUPERM~1NES"]),
+            Ok(*b"SUPERM~2NES")
+        );
+        assert_eq!(short_to_string(b"README  TXT", LOWER_BASE), "readme.TXT");
+    }
+}
@@ -0,0 +1,81 @@
+//! A disk image file standing in for the SD card, e.g. one made with
+//! `mkfs.fat -C card.img 65536`, or by `fat::format` in the tests.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+};
+
+use alloc::{format, string::String};
+
+use super::{Block, BlockDevice, BLOCK_SIZE};
+
+pub struct DiskImage {
+    file: File,
+    path: String,
+}
+
+impl DiskImage {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self {
+            file,
+            path: path.into(),
+        })
+    }
+
+    /// Makes an empty image `blocks` long, replacing anything already there
+    #[cfg(test)]
+    pub fn create(path: &str, blocks: u32) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        file.set_len(blocks as u64 * BLOCK_SIZE as u64)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self {
+            file,
+            path: path.into(),
+        })
+    }
+
+    fn seek(&mut self, lba: u32) -> Result<(), String> {
+        self.file
+            .seek(SeekFrom::Start(lba as u64 * BLOCK_SIZE as u64))
+            .map(|_| ())
+            .map_err(|e| format!("{}: {}", self.path, e))
+    }
+}
+
+impl BlockDevice for DiskImage {
+    fn read_block(&mut self, lba: u32, block: &mut Block) -> Result<(), String> {
+        self.seek(lba)?;
+        self.file
+            .read_exact(block)
+            .map_err(|e| format!("{}: block {}: {}", self.path, lba, e))
+    }
+
+    fn write_block(&mut self, lba: u32, block: &Block) -> Result<(), String> {
+        self.seek(lba)?;
+        self.file
+            .write_all(block)
+            .map_err(|e| format!("{}: block {}: {}", self.path, lba, e))
+    }
+
+    #[cfg(test)]
+    fn block_count(&mut self) -> Result<u32, String> {
+        let len = self
+            .file
+            .metadata()
+            .map_err(|e| format!("{}: {}", self.path, e))?
+            .len();
+        Ok((len / BLOCK_SIZE as u64) as u32)
+    }
+}
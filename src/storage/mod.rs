@@ -0,0 +1,23 @@
+//! Storage for games and saves: a FAT filesystem on anything that reads and writes 512 byte
+//! blocks. On the Sprig that's the SD card over SPI, on the host a disk image file, so the same
+//! code can be tested without the hardware.
+
+pub mod fat;
+#[cfg(target_arch = "x86_64")]
+pub mod image;
+// only the Sprig has an SD card, the tests drive it with a fake one
+#[cfg(any(target_arch = "arm", test))]
+pub mod sd;
+
+use alloc::string::String;
+
+pub const BLOCK_SIZE: usize = 512;
+pub type Block = [u8; BLOCK_SIZE];
+
+pub trait BlockDevice {
+    fn read_block(&mut self, lba: u32, block: &mut Block) -> Result<(), String>;
+    fn write_block(&mut self, lba: u32, block: &Block) -> Result<(), String>;
+    /// Only needed for formatting, which only the tests do
+    #[cfg(test)]
+    fn block_count(&mut self) -> Result<u32, String>;
+}
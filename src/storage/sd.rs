@@ -0,0 +1,444 @@
+//! SD cards in SPI mode, as a block device. The bus should run at 400 kHz or less until
+//! `SdCard::new` returns, then it can be sped up to 25 MHz.
+//! http://elm-chan.org/docs/mmc/mmc_e.html
+
+use alloc::{format, string::String};
+use embedded_hal::{delay::DelayNs, digital::OutputPin, spi::SpiBus};
+
+use super::{Block, BlockDevice, BLOCK_SIZE};
+
+const GO_IDLE: u8 = 0;
+const SEND_IF_COND: u8 = 8;
+#[cfg(test)]
+const SEND_CSD: u8 = 9;
+const SET_BLOCKLEN: u8 = 16;
+const READ_BLOCK: u8 = 17;
+const WRITE_BLOCK: u8 = 24;
+const APP_CMD: u8 = 55;
+const READ_OCR: u8 = 58;
+/// Sent after APP_CMD
+const SD_SEND_OP_COND: u8 = 41;
+
+const R1_IDLE: u8 = 0x01;
+const R1_ILLEGAL: u8 = 0x04;
+const DATA_START: u8 = 0xFE;
+const DATA_ACCEPTED: u8 = 0x05;
+
+/// How many bytes to wait for a response or a data token
+const TIMEOUT: u32 = 50_000;
+/// Milliseconds to wait for the card to leave idle
+const INIT_TIMEOUT_MS: u32 = 1000;
+
+pub struct SdCard<SPI, CS, D> {
+    spi: SPI,
+    cs: CS,
+    delay: D,
+    /// SDHC and SDXC cards are addressed in blocks, older ones in bytes
+    block_addressed: bool,
+}
+
+impl<SPI: SpiBus, CS: OutputPin, D: DelayNs> SdCard<SPI, CS, D> {
+    /// Wakes the card up and puts it in SPI mode. A card that doesn't start is left deselected,
+    /// so whatever else is on the bus can carry on
+    pub fn new(spi: SPI, cs: CS, delay: D) -> Result<Self, String> {
+        let mut card = Self {
+            spi,
+            cs,
+            delay,
+            block_addressed: false,
+        };
+        if let Err(e) = card.init() {
+            let _ = card.deselect();
+            return Err(e);
+        }
+        Ok(card)
+    }
+
+    /// Gives the bus back, to share it or slow it down
+    pub fn release(self) -> (SPI, CS, D) {
+        (self.spi, self.cs, self.delay)
+    }
+
+    fn init(&mut self) -> Result<(), String> {
+        // at least 74 clocks with CS high to wake it up
+        self.deselect()?;
+        self.spi.write(&[0xFF; 10]).map_err(spi_error)?;
+
+        if self.command(GO_IDLE, 0)? != R1_IDLE {
+            return Err("no SD card".into());
+        }
+
+        // version 2 cards echo the check pattern, version 1 don't know the command
+        let v2 = match self.command(SEND_IF_COND, 0x1AA)? {
+            R1_IDLE => {
+                let mut echo = [0; 4];
+                self.receive(&mut echo)?;
+                if echo[3] != 0xAA {
+                    return Err("the SD card didn't echo its check pattern".into());
+                }
+                true
+            }
+            r1 if r1 & R1_ILLEGAL != 0 => false,
+            r1 => return Err(format!("SD card error {:#04x} checking its version", r1)),
+        };
+
+        let mut ready = false;
+        for _ in 0..INIT_TIMEOUT_MS {
+            self.command(APP_CMD, 0)?;
+            // high capacity support, only asked of version 2 cards
+            if self.command(SD_SEND_OP_COND, if v2 { 1 << 30 } else { 0 })? == 0 {
+                ready = true;
+                break;
+            }
+            self.delay.delay_ms(1);
+        }
+        if !ready {
+            return Err("the SD card didn't finish starting up".into());
+        }
+
+        if v2 {
+            if self.command(READ_OCR, 0)? != 0 {
+                return Err("the SD card didn't send its OCR".into());
+            }
+            let mut ocr = [0; 4];
+            self.receive(&mut ocr)?;
+            self.block_addressed = ocr[0] & 0x40 != 0;
+        }
+        if !self.block_addressed && self.command(SET_BLOCKLEN, BLOCK_SIZE as u32)? != 0 {
+            return Err("the SD card doesn't do 512 byte blocks".into());
+        }
+        self.deselect()
+    }
+
+    fn select(&mut self) -> Result<(), String> {
+        self.cs
+            .set_low()
+            .map_err(|_| String::from("SD card CS error"))
+    }
+
+    /// Lets go of the card, with a byte after so it releases the data line
+    fn deselect(&mut self) -> Result<(), String> {
+        self.cs
+            .set_high()
+            .map_err(|_| String::from("SD card CS error"))?;
+        self.spi.write(&[0xFF]).map_err(spi_error)
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let mut byte = [0xFF];
+        self.spi.transfer_in_place(&mut byte).map_err(spi_error)?;
+        Ok(byte[0])
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        buf.fill(0xFF);
+        self.spi.transfer_in_place(buf).map_err(spi_error)
+    }
+
+    /// Sends a command and returns its R1 response, leaving the card selected for the rest
+    fn command(&mut self, command: u8, arg: u32) -> Result<u8, String> {
+        self.deselect()?;
+        self.select()?;
+        // the card holds the line low while it's busy
+        let mut waited = 0;
+        while self.byte()? != 0xFF {
+            waited += 1;
+            if waited > TIMEOUT {
+                return Err("the SD card stayed busy".into());
+            }
+        }
+
+        // CRCs are only checked before SPI mode is on and for SEND_IF_COND
+        let crc = match command {
+            GO_IDLE => 0x95,
+            SEND_IF_COND => 0x87,
+            _ => 0x01,
+        };
+        let [a, b, c, d] = arg.to_be_bytes();
+        self.spi
+            .write(&[0x40 | command, a, b, c, d, crc])
+            .map_err(spi_error)?;
+
+        for _ in 0..10 {
+            let r1 = self.byte()?;
+            if r1 & 0x80 == 0 {
+                return Ok(r1);
+            }
+        }
+        Err(format!("the SD card didn't answer CMD{}", command))
+    }
+
+    fn wait_for_data(&mut self) -> Result<(), String> {
+        for _ in 0..TIMEOUT {
+            match self.byte()? {
+                DATA_START => return Ok(()),
+                0xFF => {}
+                error => return Err(format!("SD card read error {:#04x}", error)),
+            }
+        }
+        Err("the SD card never sent the data".into())
+    }
+
+    fn address(&self, lba: u32) -> u32 {
+        if self.block_addressed {
+            lba
+        } else {
+            lba * BLOCK_SIZE as u32
+        }
+    }
+}
+
+impl<SPI: SpiBus, CS: OutputPin, D: DelayNs> BlockDevice for SdCard<SPI, CS, D> {
+    fn read_block(&mut self, lba: u32, block: &mut Block) -> Result<(), String> {
+        let r1 = self.command(READ_BLOCK, self.address(lba))?;
+        if r1 != 0 {
+            self.deselect()?;
+            return Err(format!("SD card error {:#04x} reading block {}", r1, lba));
+        }
+        self.wait_for_data()?;
+        self.receive(block)?;
+        // the CRC, which isn't checked
+        self.receive(&mut [0; 2])?;
+        self.deselect()
+    }
+
+    fn write_block(&mut self, lba: u32, block: &Block) -> Result<(), String> {
+        let r1 = self.command(WRITE_BLOCK, self.address(lba))?;
+        if r1 != 0 {
+            self.deselect()?;
+            return Err(format!("SD card error {:#04x} writing block {}", r1, lba));
+        }
+        self.spi
+            .write(&[0xFF, DATA_START])
+            .and_then(|_| self.spi.write(block))
+            .and_then(|_| self.spi.write(&[0xFF, 0xFF]))
+            .map_err(spi_error)?;
+
+        let response = self.byte()?;
+        if response & 0x1F != DATA_ACCEPTED {
+            self.deselect()?;
+            return Err(format!("the SD card refused block {}", lba));
+        }
+        // busy while it writes
+        for _ in 0..TIMEOUT * 10 {
+            if self.byte()? != 0 {
+                return self.deselect();
+            }
+        }
+        Err("the SD card took too long to write".into())
+    }
+
+    #[cfg(test)]
+    fn block_count(&mut self) -> Result<u32, String> {
+        if self.command(SEND_CSD, 0)? != 0 {
+            self.deselect()?;
+            return Err("the SD card didn't send its CSD".into());
+        }
+        self.wait_for_data()?;
+        let mut csd = [0; 18];
+        self.receive(&mut csd)?;
+        self.deselect()?;
+
+        Ok(match csd[0] >> 6 {
+            // version 2: C_SIZE counts 512 KiB
+            1 => {
+                let size = ((csd[7] as u32 & 0x3F) << 16) | ((csd[8] as u32) << 8) | csd[9] as u32;
+                (size + 1) * 1024
+            }
+            _ => {
+                let size =
+                    ((csd[6] as u32 & 3) << 10) | ((csd[7] as u32) << 2) | (csd[8] as u32 >> 6);
+                let mult = ((csd[9] as u32 & 3) << 1) | (csd[10] as u32 >> 7);
+                let block_len = csd[5] as u32 & 0xF;
+                ((size + 1) << (mult + 2) << block_len) / BLOCK_SIZE as u32
+            }
+        })
+    }
+}
+
+fn spi_error<E: core::fmt::Debug>(e: E) -> String {
+    format!("SD card SPI error: {:?}", e)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::storage::fat::{self, Volume};
+    use alloc::{collections::VecDeque, vec, vec::Vec};
+    use core::convert::Infallible;
+
+    const BLOCKS: usize = 2048;
+
+    /// An SDHC card on the other end of the bus
+    struct FakeCard {
+        data: Vec<u8>,
+        /// What the card sends on the next clocks
+        out: VecDeque<u8>,
+        command: Vec<u8>,
+        started: bool,
+        /// Where a block being written goes, and what's arrived of it
+        writing: Option<(usize, Vec<u8>)>,
+    }
+
+    impl FakeCard {
+        fn new() -> Self {
+            Self {
+                data: vec![0; BLOCKS * BLOCK_SIZE],
+                out: VecDeque::new(),
+                command: Vec::new(),
+                started: false,
+                writing: None,
+            }
+        }
+
+        fn respond(&mut self, bytes: &[u8]) {
+            // one byte of nothing before the card answers
+            self.out.push_back(0xFF);
+            self.out.extend(bytes);
+        }
+
+        fn clock(&mut self, byte: u8) -> u8 {
+            let out = self.out.pop_front().unwrap_or(0xFF);
+
+            if let Some((lba, block)) = &mut self.writing {
+                if block.is_empty() && byte != DATA_START {
+                    return out;
+                }
+                block.push(byte);
+                // the token, the data and the CRC
+                if block.len() == 1 + BLOCK_SIZE + 2 {
+                    let at = *lba * BLOCK_SIZE;
+                    self.data[at..at + BLOCK_SIZE].copy_from_slice(&block[1..1 + BLOCK_SIZE]);
+                    self.writing = None;
+                    self.out.extend([DATA_ACCEPTED, 0, 0]);
+                }
+                return out;
+            }
+
+            if self.command.is_empty() && byte & 0xC0 != 0x40 {
+                return out;
+            }
+            self.command.push(byte);
+            if self.command.len() == 6 {
+                let arg = u32::from_be_bytes(self.command[1..5].try_into().unwrap());
+                let idle = if self.started { 0 } else { R1_IDLE };
+                match self.command[0] & 0x3F {
+                    GO_IDLE => self.respond(&[R1_IDLE]),
+                    SEND_IF_COND => self.respond(&[R1_IDLE, 0, 0, 1, 0xAA]),
+                    APP_CMD => self.respond(&[idle]),
+                    SD_SEND_OP_COND => {
+                        self.respond(&[idle]);
+                        self.started = true;
+                    }
+                    READ_OCR => self.respond(&[0, 0xC0, 0xFF, 0x80, 0]),
+                    SEND_CSD => {
+                        let size = (BLOCKS / 1024 - 1) as u8;
+                        let mut csd = [0; 16];
+                        csd[0] = 0x40;
+                        csd[9] = size;
+                        self.respond(&[0, 0xFF, DATA_START]);
+                        self.out.extend(csd);
+                        self.out.extend([0, 0]);
+                    }
+                    READ_BLOCK => {
+                        let at = arg as usize * BLOCK_SIZE;
+                        self.respond(&[0, 0xFF, DATA_START]);
+                        self.out.extend(self.data[at..at + BLOCK_SIZE].iter());
+                        self.out.extend([0, 0]);
+                    }
+                    WRITE_BLOCK => {
+                        self.respond(&[0]);
+                        self.writing = Some((arg as usize, Vec::new()));
+                    }
+                    _ => self.respond(&[R1_ILLEGAL]),
+                }
+                self.command.clear();
+            }
+            out
+        }
+    }
+
+    impl embedded_hal::spi::ErrorType for FakeCard {
+        type Error = Infallible;
+    }
+
+    impl SpiBus for FakeCard {
+        fn read(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+            words.iter_mut().for_each(|word| *word = self.clock(0xFF));
+            Ok(())
+        }
+
+        fn write(&mut self, words: &[u8]) -> Result<(), Infallible> {
+            words.iter().for_each(|word| {
+                self.clock(*word);
+            });
+            Ok(())
+        }
+
+        fn transfer(&mut self, read: &mut [u8], write: &[u8]) -> Result<(), Infallible> {
+            for i in 0..read.len().max(write.len()) {
+                let byte = self.clock(write.get(i).copied().unwrap_or(0xFF));
+                if let Some(slot) = read.get_mut(i) {
+                    *slot = byte;
+                }
+            }
+            Ok(())
+        }
+
+        fn transfer_in_place(&mut self, words: &mut [u8]) -> Result<(), Infallible> {
+            words.iter_mut().for_each(|word| *word = self.clock(*word));
+            Ok(())
+        }
+
+        fn flush(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    struct Pin;
+
+    impl embedded_hal::digital::ErrorType for Pin {
+        type Error = Infallible;
+    }
+
+    impl OutputPin for Pin {
+        fn set_low(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+
+        fn set_high(&mut self) -> Result<(), Infallible> {
+            Ok(())
+        }
+    }
+
+    struct NoDelay;
+
+    impl DelayNs for NoDelay {
+        fn delay_ns(&mut self, _ns: u32) {}
+    }
+
+    #[test]
+    fn test_card() {
+        let mut card = SdCard::new(FakeCard::new(), Pin, NoDelay).unwrap();
+        assert!(card.block_addressed);
+        assert_eq!(card.block_count().unwrap(), BLOCKS as u32);
+
+        let mut block = [0; BLOCK_SIZE];
+        block[..4].copy_from_slice(b"egb!");
+        block[511] = 0xFF;
+        card.write_block(5, &block).unwrap();
+        let mut back = [0; BLOCK_SIZE];
+        card.read_block(5, &mut back).unwrap();
+        assert_eq!(back, block);
+
+        // a whole filesystem on it
+        fat::format(&mut card, "SPRIG").unwrap();
+        let mut volume = Volume::mount(card).unwrap();
+        let mut file = volume.create("save.dat").unwrap();
+        volume.write(&mut file, &[7; 1000]).unwrap();
+        let mut file = volume.open("SAVE.DAT").unwrap();
+        let mut data = [0; 1000];
+        assert_eq!(volume.read(&mut file, &mut data).unwrap(), 1000);
+        assert_eq!(data, [7; 1000]);
+    }
+}
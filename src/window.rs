@@ -0,0 +1,169 @@
+//! The simulator's windows. embedded-graphics-simulator's `Window` starts SDL for itself and SDL
+//! can only be started once, so the game's window and the PPU viewers are all opened here and
+//! share one event pump.
+
+use embedded_graphics::{
+    geometry::{OriginDimensions, Point},
+    pixelcolor::{Rgb565, Rgb888, RgbColor},
+};
+use embedded_graphics_simulator::{SimulatorDisplay, SimulatorEvent};
+use sdl2::{
+    event::{Event, WindowEvent},
+    keyboard::Keycode,
+    pixels::PixelFormatEnum,
+    render::Canvas,
+    video::Window,
+    EventPump, VideoSubsystem,
+};
+use std::{
+    string::{String, ToString},
+    vec::Vec,
+};
+
+use crate::nes::viewer::Image;
+
+/// The viewers' images are small, they're drawn this many times bigger
+const VIEWER_SCALE: u32 = 2;
+
+pub struct Windows {
+    video: VideoSubsystem,
+    events: EventPump,
+    game: Canvas<Window>,
+    /// By title, closing one drops it
+    viewers: Vec<(String, Canvas<Window>)>,
+}
+
+impl Windows {
+    pub fn new(title: &str, width: u32, height: u32) -> Result<Self, String> {
+        let sdl = sdl2::init()?;
+        let video = sdl.video()?;
+        let events = sdl.event_pump()?;
+        let game = open(&video, title, width, height)?;
+        Ok(Self {
+            video,
+            events,
+            game,
+            viewers: Vec::new(),
+        })
+    }
+
+    /// Draws the display in the game's window
+    pub fn update(&mut self, display: &SimulatorDisplay<Rgb565>) -> Result<(), String> {
+        let size = display.size();
+        let mut rgb = Vec::with_capacity((size.width * size.height * 3) as usize);
+        for y in 0..size.height as i32 {
+            for x in 0..size.width as i32 {
+                let colour = Rgb888::from(display.get_pixel(Point::new(x, y)));
+                rgb.extend_from_slice(&[colour.r(), colour.g(), colour.b()]);
+            }
+        }
+        draw(&mut self.game, size.width, size.height, &rgb)
+    }
+
+    /// Opens a viewer the first time it's shown, after that it's redrawn
+    pub fn show(&mut self, title: &str, image: &Image) -> Result<(), String> {
+        let index = match self.viewers.iter().position(|(open, _)| open == title) {
+            Some(index) => index,
+            None => {
+                let canvas = open(
+                    &self.video,
+                    title,
+                    image.width * VIEWER_SCALE,
+                    image.height * VIEWER_SCALE,
+                )?;
+                self.viewers.push((title.to_string(), canvas));
+                self.viewers.len() - 1
+            }
+        };
+        draw(
+            &mut self.viewers[index].1,
+            image.width,
+            image.height,
+            &image.rgb,
+        )
+    }
+
+    pub fn viewers_open(&self) -> bool {
+        !self.viewers.is_empty()
+    }
+
+    pub fn close_viewers(&mut self) {
+        self.viewers.clear();
+    }
+
+    /// Input from any of the windows. Closing a viewer only closes it, closing the game's
+    /// window quits
+    pub fn events(&mut self) -> Vec<SimulatorEvent> {
+        let mut events = Vec::new();
+        for event in self.events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => events.push(SimulatorEvent::Quit),
+                Event::Window {
+                    window_id,
+                    win_event: WindowEvent::Close,
+                    ..
+                } => {
+                    if window_id == self.game.window().id() {
+                        events.push(SimulatorEvent::Quit);
+                    } else {
+                        self.viewers
+                            .retain(|(_, canvas)| canvas.window().id() != window_id);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat,
+                    ..
+                } => events.push(SimulatorEvent::KeyDown {
+                    keycode,
+                    keymod,
+                    repeat,
+                }),
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    keymod,
+                    repeat,
+                    ..
+                } => events.push(SimulatorEvent::KeyUp {
+                    keycode,
+                    keymod,
+                    repeat,
+                }),
+                _ => {}
+            }
+        }
+        events
+    }
+}
+
+fn open(
+    video: &VideoSubsystem,
+    title: &str,
+    width: u32,
+    height: u32,
+) -> Result<Canvas<Window>, String> {
+    let window = video
+        .window(title, width, height)
+        .build()
+        .map_err(|e| e.to_string())?;
+    window.into_canvas().build().map_err(|e| e.to_string())
+}
+
+/// Stretches RGB888 pixels over the whole window
+fn draw(canvas: &mut Canvas<Window>, width: u32, height: u32, rgb: &[u8]) -> Result<(), String> {
+    let creator = canvas.texture_creator();
+    let mut texture = creator
+        .create_texture_static(PixelFormatEnum::RGB24, width, height)
+        .map_err(|e| e.to_string())?;
+    texture
+        .update(None, rgb, width as usize * 3)
+        .map_err(|e| e.to_string())?;
+    canvas.copy(&texture, None, None)?;
+    canvas.present();
+    Ok(())
+}
@@ -0,0 +1,24 @@
+//! The NES test ROMs in `tests/roms/nes`. They aren't in the repo, so this is ignored unless
+//! asked for with `cargo test --test blargg -- --ignored`. Timeouts are only reported, most of
+//! them need the PPU.
+
+use std::{path::Path, vec::Vec};
+
+use egb::nes::blargg::{self, DEFAULT_FRAMES};
+
+#[test]
+#[ignore = "needs test ROMs in tests/roms/nes"]
+fn test_roms() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/roms/nes");
+    let roms = blargg::discover(&dir).unwrap();
+    assert!(!roms.is_empty(), "no test ROMs in {}", dir.display());
+
+    let results: Vec<_> = roms
+        .iter()
+        .map(|rom| blargg::run_file(rom, DEFAULT_FRAMES))
+        .collect();
+    blargg::print_table(&results);
+    for result in results {
+        assert!(!result.status.is_failure(), "{} failed", result.name);
+    }
+}